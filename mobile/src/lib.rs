@@ -88,12 +88,20 @@ pub async fn start_mycelium(peers: Vec<String>, tun_fd: i32, priv_key: Vec<u8>)
     let config = Config {
         node_key: secret_key,
         peers: endpoints,
+        peer_link_cost_offsets: Default::default(),
         no_tun: false,
         tcp_listen_port: DEFAULT_TCP_LISTEN_PORT,
         quic_listen_port: None,
+        ws_listen_port: None,
+        ws_proxy: None,
         peer_discovery_port: None, // disable multicast discovery
+        hole_punching: false,      // requires quic
+        peer_exchange_target: None,
         #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
         tun_name: "tun0".to_string(),
+        // The TUN interface is created by the app, which uses the default MTU.
+        tun_mtu: mycelium::DEFAULT_TUN_MTU,
+        tun_offload: false,
 
         metrics: NoMetrics,
         private_network_config: None,
@@ -101,6 +109,11 @@ pub async fn start_mycelium(peers: Vec<String>, tun_fd: i32, priv_key: Vec<u8>)
         #[cfg(any(target_os = "android", target_os = "ios"))]
         tun_fd: Some(tun_fd),
         update_workers: 1,
+        derived_subnets: 0,
+        router_config: Default::default(),
+        route_policy: Default::default(),
+        peer_acl: Default::default(),
+        legacy_peers: true,
     };
     let _node = match Node::new(config).await {
         Ok(node) => {
@@ -130,7 +143,7 @@ pub async fn start_mycelium(peers: Vec<String>, tun_fd: i32, priv_key: Vec<u8>)
                     CmdType::Status => {
                         let mut vec: Vec<String> = Vec::new();
                         for info in _node.peer_info() {
-                            vec.push(info.endpoint.proto().to_string() + ","+ info.endpoint.address().to_string().as_str()+","+ &info.connection_state.to_string());
+                            vec.push(info.endpoint.proto().to_string() + ","+ info.endpoint.host().to_string().as_str()+","+ &info.connection_state.to_string());
                         }
                         send_response(vec).await;
                     }
@@ -15,6 +15,7 @@ use mycelium::{
     crypto::PublicKey,
     endpoint::Endpoint,
     metrics::Metrics,
+    peer_acl::PeerAcl,
     peer_manager::{PeerExists, PeerNotFound, PeerStats},
 };
 
@@ -52,7 +53,11 @@ impl Http {
         let admin_routes = Router::new()
             .route("/admin", get(get_info))
             .route("/admin/peers", get(get_peers).post(add_peer))
-            .route("/admin/peers/:endpoint", delete(delete_peer))
+            .route(
+                "/admin/peers/:endpoint",
+                delete(delete_peer).put(update_peer),
+            )
+            .route("/admin/acl", get(get_peer_acl).put(set_peer_acl))
             .route("/admin/routes/selected", get(get_selected_routes))
             .route("/admin/routes/fallback", get(get_fallback_routes))
             .route("/pubkey/:ip", get(get_pubk_from_ip))
@@ -97,9 +102,13 @@ where
 
 /// Payload of an add_peer request
 #[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AddPeer {
     /// The endpoint used to connect to the peer
     pub endpoint: String,
+    /// Cost added to the link cost of the peer
+    #[serde(default)]
+    pub link_cost_offset: u16,
 }
 
 /// Add a new peer to the system
@@ -119,7 +128,12 @@ where
         Err(e) => return Err((StatusCode::BAD_REQUEST, e.to_string())),
     };
 
-    match state.node.lock().await.add_peer(endpoint) {
+    match state
+        .node
+        .lock()
+        .await
+        .add_peer(endpoint, payload.link_cost_offset)
+    {
         Ok(()) => Ok(StatusCode::NO_CONTENT),
         Err(PeerExists) => Err((
             StatusCode::CONFLICT,
@@ -151,6 +165,69 @@ where
     }
 }
 
+/// Payload of an update_peer request
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatePeer {
+    /// Cost added to the link cost of the peer
+    pub link_cost_offset: u16,
+}
+
+/// Update the settings of an existing peer
+async fn update_peer<M>(
+    State(state): State<HttpServerState<M>>,
+    Path(endpoint): Path<String>,
+    Json(payload): Json<UpdatePeer>,
+) -> Result<StatusCode, (StatusCode, String)>
+where
+    M: Metrics + Clone + Send + Sync + 'static,
+{
+    debug!(
+        peer.endpoint=%endpoint,
+        peer.link_cost_offset=payload.link_cost_offset,
+        "Attempting to update peer"
+    );
+    let endpoint = match Endpoint::from_str(&endpoint) {
+        Ok(endpoint) => endpoint,
+        Err(e) => return Err((StatusCode::BAD_REQUEST, e.to_string())),
+    };
+
+    match state
+        .node
+        .lock()
+        .await
+        .set_peer_link_cost_offset(endpoint, payload.link_cost_offset)
+    {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(PeerNotFound) => Err((
+            StatusCode::NOT_FOUND,
+            "A peer identified by that endpoint does not exist".to_string(),
+        )),
+    }
+}
+
+/// Get the access control list applied to inbound peer connections.
+async fn get_peer_acl<M>(State(state): State<HttpServerState<M>>) -> Json<PeerAcl>
+where
+    M: Metrics + Clone + Send + Sync + 'static,
+{
+    debug!("Loading peer access control list");
+    Json(state.node.lock().await.peer_acl())
+}
+
+/// Replace the access control list applied to inbound peer connections.
+async fn set_peer_acl<M>(
+    State(state): State<HttpServerState<M>>,
+    Json(payload): Json<PeerAcl>,
+) -> StatusCode
+where
+    M: Metrics + Clone + Send + Sync + 'static,
+{
+    debug!("Replacing peer access control list");
+    state.node.lock().await.set_peer_acl(payload);
+    StatusCode::NO_CONTENT
+}
+
 /// Alias to a [`Metric`](crate::metric::Metric) for serialization in the API.
 #[derive(Debug, PartialEq)]
 pub enum Metric {
@@ -174,6 +251,9 @@ pub struct Route {
     pub metric: Metric,
     /// Sequence number of the route.
     pub seqno: u16,
+    /// Whether the source of the route is suppressed because it is flapping.
+    #[serde(default)]
+    pub suppressed: bool,
 }
 
 /// List all currently selected routes.
@@ -182,10 +262,8 @@ where
     M: Metrics + Clone + Send + Sync + 'static,
 {
     debug!("Loading selected routes");
-    let routes = state
-        .node
-        .lock()
-        .await
+    let node = state.node.lock().await;
+    let routes = node
         .selected_routes()
         .into_iter()
         .map(|sr| Route {
@@ -197,6 +275,7 @@ where
                 Metric::Value(sr.metric().into())
             },
             seqno: sr.seqno().into(),
+            suppressed: node.is_route_suppressed(&sr),
         })
         .collect();
 
@@ -209,10 +288,8 @@ where
     M: Metrics + Clone + Send + Sync + 'static,
 {
     debug!("Loading fallback routes");
-    let routes = state
-        .node
-        .lock()
-        .await
+    let node = state.node.lock().await;
+    let routes = node
         .fallback_routes()
         .into_iter()
         .map(|sr| Route {
@@ -224,6 +301,7 @@ where
                 Metric::Value(sr.metric().into())
             },
             seqno: sr.seqno().into(),
+            suppressed: node.is_route_suppressed(&sr),
         })
         .collect();
 
@@ -236,6 +314,8 @@ where
 pub struct Info {
     /// The overlay subnet in use by the node.
     pub node_subnet: String,
+    /// Additional subnets announced by the node.
+    pub derived_subnets: Vec<String>,
 }
 
 /// Get general info about the node.
@@ -243,8 +323,14 @@ async fn get_info<M>(State(state): State<HttpServerState<M>>) -> Json<Info>
 where
     M: Metrics + Clone + Send + Sync + 'static,
 {
+    let info = state.node.lock().await.info();
     Json(Info {
-        node_subnet: state.node.lock().await.info().node_subnet.to_string(),
+        node_subnet: info.node_subnet.to_string(),
+        derived_subnets: info
+            .derived_subnets
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
     })
 }
 
@@ -391,21 +477,24 @@ mod tests {
             subnet: "406:1d77:2438:aa7c::/64".to_string(),
             next_hop: "TCP [2a02:1811:d584:7400:c503:ff39:de03:9e44]:45694 <-> [2a01:4f8:212:fa6::2]:9651".to_string(),
             metric: Metric::Value(20),
-            seqno: 0
+            seqno: 0,
+            suppressed: false,
         });
 
         assert_eq!(routes[1], Route {
             subnet: "407:8458:dbf5:4ed7::/64".to_string(),
             next_hop: "TCP [2a02:1811:d584:7400:c503:ff39:de03:9e44]:45694 <-> [2a01:4f8:212:fa6::2]:9651".to_string(),
             metric: Metric::Value(174),
-            seqno: 0
+            seqno: 0,
+            suppressed: false,
         });
 
         assert_eq!(routes[2], Route {
             subnet: "408:7ba3:3a4d:808a::/64".to_string(),
             next_hop: "TCP [2a02:1811:d584:7400:c503:ff39:de03:9e44]:45694 <-> [2a01:4f8:212:fa6::2]:9651".to_string(),
             metric: Metric::Infinite,
-            seqno: 0
+            seqno: 0,
+            suppressed: false,
         });
     }
 }
@@ -7,5 +7,5 @@ mod routes;
 pub use inspect::inspect;
 #[cfg(feature = "message")]
 pub use message::{recv_msg, send_msg};
-pub use peer::{add_peers, list_peers, remove_peers};
+pub use peer::{add_peers, list_peers, remove_peers, set_peer_link_cost_offset};
 pub use routes::{list_fallback_routes, list_selected_routes};
@@ -1,5 +1,5 @@
 use mycelium::peer_manager::PeerStats;
-use mycelium_api::AddPeer;
+use mycelium_api::{AddPeer, UpdatePeer};
 use prettytable::{row, Table};
 use std::net::SocketAddr;
 use tracing::{debug, error};
@@ -37,16 +37,20 @@ pub async fn list_peers(
                             "Type",
                             "Connection",
                             "Rx total",
-                            "Tx total"
+                            "Tx total",
+                            "Cost offset",
+                            "Public key"
                         ]);
                         for peer in peers.iter() {
                             table.add_row(row![
                                 peer.endpoint.proto(),
-                                peer.endpoint.address(),
+                                peer.endpoint.host(),
                                 peer.pt,
                                 peer.connection_state,
                                 format_bytes(peer.rx_bytes),
                                 format_bytes(peer.tx_bytes),
+                                peer.link_cost_offset,
+                                peer.public_key.map(|pk| pk.to_string()).unwrap_or_default(),
                             ]);
                         }
                         table.printstd();
@@ -97,13 +101,17 @@ pub async fn remove_peers(
 pub async fn add_peers(
     server_addr: SocketAddr,
     peers: Vec<String>,
+    link_cost_offset: u16,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let client = reqwest::Client::new();
     for peer in peers.into_iter() {
         let request_url = format!("http://{server_addr}/api/v1/admin/peers");
         if let Err(e) = client
             .post(&request_url)
-            .json(&AddPeer { endpoint: peer })
+            .json(&AddPeer {
+                endpoint: peer,
+                link_cost_offset,
+            })
             .send()
             .await
             .and_then(|res| res.error_for_status())
@@ -115,3 +123,27 @@ pub async fn add_peers(
 
     Ok(())
 }
+
+/// Change the cost added to the link cost of an existing peer
+pub async fn set_peer_link_cost_offset(
+    server_addr: SocketAddr,
+    peer: String,
+    link_cost_offset: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
+    // encode to pass in URL
+    let peer_encoded = urlencoding::encode(&peer);
+    let request_url = format!("http://{server_addr}/api/v1/admin/peers/{peer_encoded}");
+    if let Err(e) = client
+        .put(&request_url)
+        .json(&UpdatePeer { link_cost_offset })
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+    {
+        error!("Failed to update peer: {e}");
+        return Err(e.into());
+    }
+
+    Ok(())
+}
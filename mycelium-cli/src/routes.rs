@@ -25,7 +25,7 @@ pub async fn list_selected_routes(
                 // Print routes in table format
                 let routes: Vec<Route> = resp.json().await?;
                 let mut table = Table::new();
-                table.add_row(row!["Subnet", "Next Hop", "Metric", "Seq No"]);
+                table.add_row(row!["Subnet", "Next Hop", "Metric", "Seq No", "Suppressed"]);
 
                 for route in routes.iter() {
                     table.add_row(row![
@@ -33,6 +33,7 @@ pub async fn list_selected_routes(
                         &route.next_hop,
                         route.metric,
                         route.seqno,
+                        route.suppressed,
                     ]);
                 }
 
@@ -65,7 +66,7 @@ pub async fn list_fallback_routes(
                 // Print routes in table format
                 let routes: Vec<Route> = resp.json().await?;
                 let mut table = Table::new();
-                table.add_row(row!["Subnet", "Next Hop", "Metric", "Seq No"]);
+                table.add_row(row!["Subnet", "Next Hop", "Metric", "Seq No", "Suppressed"]);
 
                 for route in routes.iter() {
                     table.add_row(row![
@@ -73,6 +74,7 @@ pub async fn list_fallback_routes(
                         &route.next_hop,
                         route.metric,
                         route.seqno,
+                        route.suppressed,
                     ]);
                 }
 
@@ -29,10 +29,14 @@ pub struct PrometheusExporter {
     router_propage_selected_peers_time_spent: IntCounter,
     router_update_skipped_route_selection: IntCounter,
     router_update_denied_by_filter: IntCounter,
+    router_update_invalid_signature: IntCounter,
     router_update_not_interested: IntCounter,
+    data_plane_replayed_packet_dropped: IntCounter,
+    data_plane_unsecured_packet_dropped: IntCounter,
     peer_manager_peer_added: IntCounterVec,
     peer_manager_known_peers: IntGauge,
     peer_manager_connection_attemps: IntCounterVec,
+    peer_manager_inbound_connection_rejected: IntCounterVec,
 }
 
 impl PrometheusExporter {
@@ -138,11 +142,26 @@ impl PrometheusExporter {
                 "Updates which were received and immediately denied by a configured filter",
             )
             .expect("Can register an int counter in default registry"),
+            router_update_invalid_signature: register_int_counter!(
+                "mycelium_router_update_invalid_signature",
+                "Updates which were denied because they had an invalid signature, or were not signed while a signature is required",
+            )
+            .expect("Can register an int counter in default registry"),
             router_update_not_interested: register_int_counter!(
                 "mycelium_router_update_not_interested",
                 "Updates which were allowed by the configured filters, but not of interest as they were either not feasible, or retractions, for an unknown subnet",
             )
             .expect("Can register an int counter in default registry"),
+            data_plane_replayed_packet_dropped: register_int_counter!(
+                "mycelium_data_plane_replayed_packet_dropped",
+                "Data packets which were dropped because they were already received, or too old to verify that they were not",
+            )
+            .expect("Can register an int counter in default registry"),
+            data_plane_unsecured_packet_dropped: register_int_counter!(
+                "mycelium_data_plane_unsecured_packet_dropped",
+                "Data packets which were dropped because they were, or would be, encrypted with the static shared secret, while the remote uses session keys",
+            )
+            .expect("Can register an int counter in default registry"),
             peer_manager_peer_added: register_int_counter_vec!(
                 opts!(
                     "mycelium_peer_manager_peers_added",
@@ -164,6 +183,14 @@ impl PrometheusExporter {
                 &["connection_state"]
             )
             .expect("Can register int counter vec in the default registry"),
+            peer_manager_inbound_connection_rejected: register_int_counter_vec!(
+                opts!(
+                    "mycelium_peer_manager_inbound_connection_rejected",
+                    "Inbound connections rejected by the peer access control list, by reason"
+                ),
+                &["reason"]
+            )
+            .expect("Can register int counter vec in the default registry"),
         }
     }
 
@@ -391,17 +418,34 @@ impl Metrics for PrometheusExporter {
         self.router_update_denied_by_filter.inc()
     }
 
+    #[inline]
+    fn router_update_invalid_signature(&self) {
+        self.router_update_invalid_signature.inc()
+    }
+
     #[inline]
     fn router_update_not_interested(&self) {
         self.router_update_not_interested.inc()
     }
 
+    #[inline]
+    fn data_plane_replayed_packet_dropped(&self) {
+        self.data_plane_replayed_packet_dropped.inc()
+    }
+
+    #[inline]
+    fn data_plane_unsecured_packet_dropped(&self) {
+        self.data_plane_unsecured_packet_dropped.inc()
+    }
+
     #[inline]
     fn peer_manager_peer_added(&self, pt: mycelium::peer_manager::PeerType) {
         let label = match pt {
             mycelium::peer_manager::PeerType::Static => "static",
             mycelium::peer_manager::PeerType::Inbound => "inbound",
             mycelium::peer_manager::PeerType::LinkLocalDiscovery => "link_local",
+            mycelium::peer_manager::PeerType::HolePunched => "hole_punched",
+            mycelium::peer_manager::PeerType::PeerExchange => "peer_exchange",
         };
         self.peer_manager_peer_added
             .with_label_values(&[label])
@@ -426,6 +470,17 @@ impl Metrics for PrometheusExporter {
             .with_label_values(&["finished"])
             .inc()
     }
+
+    #[inline]
+    fn peer_manager_inbound_connection_rejected(&self, reason: mycelium::peer_acl::RejectReason) {
+        let label = match reason {
+            mycelium::peer_acl::RejectReason::SourceAddress => "source_address",
+            mycelium::peer_acl::RejectReason::PublicKey => "public_key",
+        };
+        self.peer_manager_inbound_connection_rejected
+            .with_label_values(&[label])
+            .inc()
+    }
 }
 
 impl Default for PrometheusExporter {
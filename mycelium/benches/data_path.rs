@@ -0,0 +1,191 @@
+//! Packets per second of the data path between the TUN interface and peers.
+//!
+//! Every packet is put in a [`PacketBuffer`] like it is read from the TUN interface, encrypted,
+//! and encoded like it is written to a peer connection. The receiving side decodes the packet and
+//! decrypts it again. Relaying nodes only decode and encode the packet.
+//!
+//! This only uses public APIs which also exist on older versions, so running it on two revisions
+//! compares them:
+//!
+//! ```text
+//! cargo bench -p mycelium --bench data_path
+//! ```
+
+use std::{
+    hint::black_box,
+    net::Ipv6Addr,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::BytesMut;
+use mycelium::{
+    crypto::{PacketBuffer, PublicKey, SecretKey, SharedSecret},
+    packet::{Codec, DataPacket, Packet},
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Amount of packets processed in every benchmark.
+const PACKETS: usize = 1 << 20;
+
+/// Sizes of the (unencrypted) packets which are benchmarked.
+const PACKET_SIZES: [usize; 3] = [64, 576, 1280];
+
+/// Amount of packets sent between threads at once in the threaded benchmark.
+const BATCH_SIZE: usize = 64;
+
+fn main() {
+    let sk1 = SecretKey::new();
+    let sk2 = SecretKey::new();
+    let sender = sk1.shared_secret(&PublicKey::from(&sk2));
+    let receiver = sk2.shared_secret(&PublicKey::from(&sk1));
+    let src_ip = PublicKey::from(&sk1).address();
+    let dst_ip = PublicKey::from(&sk2).address();
+
+    for size in PACKET_SIZES {
+        report("end to end", size, || {
+            end_to_end(&sender, &receiver, src_ip, dst_ip, size)
+        });
+        report("relay", size, || relay(&sender, src_ip, dst_ip, size));
+        report("end to end, 2 threads", size, || {
+            end_to_end_threaded(&sender, &receiver, src_ip, dst_ip, size)
+        });
+    }
+}
+
+/// Run a benchmark and print the amount of packets per second it processed.
+fn report(name: &str, size: usize, bench: impl Fn() -> Duration) {
+    // Warm up, so allocators and pools are in a steady state.
+    bench();
+    let elapsed = bench();
+    println!(
+        "{name:<24} {size:>5} bytes: {:>12.0} packets/s",
+        PACKETS as f64 / elapsed.as_secs_f64()
+    );
+}
+
+/// Create a packet like it is read from the TUN interface.
+fn tun_packet(size: usize) -> PacketBuffer {
+    let mut pb = PacketBuffer::new();
+    pb.buffer_mut()[..size].fill(0xAA);
+    pb.set_size(size);
+    pb
+}
+
+/// Encrypt a packet, and encode it like it is sent to a peer.
+fn send(
+    codec: &mut Codec,
+    wire: &mut BytesMut,
+    key: &SharedSecret,
+    src_ip: Ipv6Addr,
+    dst_ip: Ipv6Addr,
+    size: usize,
+) {
+    let packet = DataPacket {
+        raw_data: key.encrypt(tun_packet(size)),
+        hop_limit: 64,
+        src_ip,
+        dst_ip,
+    };
+    codec
+        .encode(Packet::DataPacket(packet), wire)
+        .expect("Can encode data packet");
+}
+
+/// Decode a packet received from a peer.
+fn receive(codec: &mut Codec, wire: &mut BytesMut) -> DataPacket {
+    match codec.decode(wire) {
+        Ok(Some(Packet::DataPacket(packet))) => packet,
+        _ => panic!("Wire contains a data packet"),
+    }
+}
+
+/// Send packets from one node to another over a single connection.
+fn end_to_end(
+    sender: &SharedSecret,
+    receiver: &SharedSecret,
+    src_ip: Ipv6Addr,
+    dst_ip: Ipv6Addr,
+    size: usize,
+) -> Duration {
+    let mut codec = Codec::new();
+    let mut wire = BytesMut::new();
+
+    let start = Instant::now();
+    for _ in 0..PACKETS {
+        send(&mut codec, &mut wire, sender, src_ip, dst_ip, size);
+        let packet = receive(&mut codec, &mut wire);
+        black_box(
+            receiver
+                .decrypt(packet.raw_data)
+                .expect("Packet decrypts with the shared secret"),
+        );
+    }
+    start.elapsed()
+}
+
+/// Forward packets received from one peer to another peer, without decrypting them.
+fn relay(sender: &SharedSecret, src_ip: Ipv6Addr, dst_ip: Ipv6Addr, size: usize) -> Duration {
+    let mut codec = Codec::new();
+    let mut wire = BytesMut::new();
+    let mut out = BytesMut::new();
+
+    // Encode a packet upfront, only decoding and encoding is measured.
+    send(&mut codec, &mut wire, sender, src_ip, dst_ip, size);
+    let received = wire.split().freeze();
+
+    let start = Instant::now();
+    for _ in 0..PACKETS {
+        // Copy the packet like it is read from the connection.
+        wire.extend_from_slice(&received);
+        let packet = receive(&mut codec, &mut wire);
+        codec
+            .encode(Packet::DataPacket(packet), &mut out)
+            .expect("Can encode data packet");
+        black_box(&out);
+        out.clear();
+    }
+    start.elapsed()
+}
+
+/// Send packets from one node to another, where sending and receiving happens on different
+/// threads. Buffers are allocated on the sending thread, and released on the receiving thread.
+fn end_to_end_threaded(
+    sender: &SharedSecret,
+    receiver: &SharedSecret,
+    src_ip: Ipv6Addr,
+    dst_ip: Ipv6Addr,
+    size: usize,
+) -> Duration {
+    let (tx, rx) = mpsc::sync_channel::<Vec<DataPacket>>(16);
+    let receiver = receiver.clone();
+
+    let start = Instant::now();
+    let handle = thread::spawn(move || {
+        for batch in rx {
+            for packet in batch {
+                black_box(
+                    receiver
+                        .decrypt(packet.raw_data)
+                        .expect("Packet decrypts with the shared secret"),
+                );
+            }
+        }
+    });
+
+    let mut codec = Codec::new();
+    let mut wire = BytesMut::new();
+    for _ in 0..PACKETS / BATCH_SIZE {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        for _ in 0..BATCH_SIZE {
+            send(&mut codec, &mut wire, sender, src_ip, dst_ip, size);
+            batch.push(receive(&mut codec, &mut wire));
+        }
+        tx.send(batch).expect("Receiving thread is running");
+    }
+    drop(tx);
+    handle.join().expect("Receiving thread does not panic");
+
+    start.elapsed()
+}
@@ -11,14 +11,21 @@ use tokio_util::codec::{Decoder, Encoder};
 use tracing::trace;
 
 pub use self::{
-    hello::Hello, ihu::Ihu, route_request::RouteRequest, seqno_request::SeqNoRequest,
+    hello::Hello,
+    hole_punch::{HolePunchIntroduction, HolePunchRequest},
+    ihu::Ihu,
+    peer_exchange::{PeerExchange, MAX_PEER_EXCHANGE_PEERS},
+    route_request::RouteRequest,
+    seqno_request::SeqNoRequest,
     update::Update,
 };
 
 pub use self::tlv::Tlv;
 
 mod hello;
+mod hole_punch;
 mod ihu;
+mod peer_exchange;
 mod route_request;
 mod seqno_request;
 mod tlv;
@@ -42,6 +49,12 @@ const TLV_TYPE_UPDATE: u8 = 8;
 const TLV_TYPE_ROUTE_REQUEST: u8 = 9;
 /// TLV type for the [`SeqNoRequest`] tlv
 const TLV_TYPE_SEQNO_REQUEST: u8 = 10;
+/// TLV type for the [`HolePunchRequest`] tlv, from the range reserved for experimental use.
+const TLV_TYPE_HOLE_PUNCH_REQUEST: u8 = 224;
+/// TLV type for the [`HolePunchIntroduction`] tlv, from the range reserved for experimental use.
+const TLV_TYPE_HOLE_PUNCH_INTRODUCTION: u8 = 225;
+/// TLV type for the [`PeerExchange`] tlv, from the range reserved for experimental use.
+const TLV_TYPE_PEER_EXCHANGE: u8 = 226;
 
 /// Wildcard address, the value is empty (0 bytes length).
 const AE_WILDCARD: u8 = 0;
@@ -142,6 +155,13 @@ impl Decoder for Codec {
             TLV_TYPE_UPDATE => Update::from_bytes(src, body_len).map(From::from),
             TLV_TYPE_ROUTE_REQUEST => RouteRequest::from_bytes(src, body_len).map(From::from),
             TLV_TYPE_SEQNO_REQUEST => SeqNoRequest::from_bytes(src, body_len).map(From::from),
+            TLV_TYPE_HOLE_PUNCH_REQUEST => {
+                HolePunchRequest::from_bytes(src, body_len).map(From::from)
+            }
+            TLV_TYPE_HOLE_PUNCH_INTRODUCTION => {
+                HolePunchIntroduction::from_bytes(src, body_len).map(From::from)
+            }
+            TLV_TYPE_PEER_EXCHANGE => PeerExchange::from_bytes(src, body_len).map(From::from),
             _ => {
                 // unrecoginized body type, silently drop
                 trace!("Dropping unrecognized tlv");
@@ -174,6 +194,9 @@ impl Encoder<Tlv> for Codec {
             Tlv::Update(_) => dst.put_u8(TLV_TYPE_UPDATE),
             Tlv::RouteRequest(_) => dst.put_u8(TLV_TYPE_ROUTE_REQUEST),
             Tlv::SeqNoRequest(_) => dst.put_u8(TLV_TYPE_SEQNO_REQUEST),
+            Tlv::HolePunchRequest(_) => dst.put_u8(TLV_TYPE_HOLE_PUNCH_REQUEST),
+            Tlv::HolePunchIntroduction(_) => dst.put_u8(TLV_TYPE_HOLE_PUNCH_INTRODUCTION),
+            Tlv::PeerExchange(_) => dst.put_u8(TLV_TYPE_PEER_EXCHANGE),
         }
         dst.put_u8(item.wire_size());
         item.write_bytes(dst);
@@ -189,7 +212,10 @@ mod tests {
     use futures::{SinkExt, StreamExt};
     use tokio_util::codec::Framed;
 
-    use crate::subnet::Subnet;
+    use crate::{
+        crypto::{PublicKey, SecretKey},
+        subnet::Subnet,
+    };
 
     #[tokio::test]
     async fn codec_hello() {
@@ -293,6 +319,26 @@ mod tests {
         assert_eq!(super::Tlv::from(snr), recv_update);
     }
 
+    #[tokio::test]
+    async fn codec_hole_punch_request() {
+        let (tx, rx) = tokio::io::duplex(1024);
+        let mut sender = Framed::new(tx, super::Codec::new());
+        let mut receiver = Framed::new(rx, super::Codec::new());
+
+        let hpr = super::HolePunchRequest::new(PublicKey::from(&SecretKey::new()));
+
+        sender
+            .send(hpr.clone().into())
+            .await
+            .expect("Send on a non-networked buffer can never fail; qed");
+        let recv_hpr = receiver
+            .next()
+            .await
+            .expect("Buffer isn't closed so this is always `Some`; qed")
+            .expect("Can decode the previously encoded value");
+        assert_eq!(super::Tlv::from(hpr), recv_hpr);
+    }
+
     #[tokio::test]
     async fn codec_route_request() {
         let (tx, rx) = tokio::io::duplex(1024);
@@ -1,5 +1,7 @@
 //! The babel [Hello TLV](https://datatracker.ietf.org/doc/html/rfc8966#section-4.6.5).
 
+use std::time::Duration;
+
 use bytes::{Buf, BufMut};
 use tracing::trace;
 
@@ -32,6 +34,17 @@ impl Hello {
         }
     }
 
+    /// Returns the [`SeqNo`] of this `Hello`.
+    pub fn seqno(&self) -> SeqNo {
+        self.seqno
+    }
+
+    /// Returns the interval after which the sender of this `Hello` intends to send the next one.
+    /// An interval of 0 means the sender does not send `Hello`s on a schedule.
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval as u64 * 10)
+    }
+
     /// Calculates the size on the wire of this `Hello`.
     pub fn wire_size(&self) -> u8 {
         HELLO_WIRE_SIZE
@@ -0,0 +1,176 @@
+//! TLV's used to coordinate UDP hole punching between two nodes behind NAT.
+//!
+//! A node which only reaches a remote through a neighbour sends a [`HolePunchRequest`] for the
+//! remote to that neighbour. If the neighbour has inbound QUIC connections of both nodes, it knows
+//! their public QUIC addresses as seen from the internet. It then sends a
+//! [`HolePunchIntroduction`] to both of them, containing the address of the other node. Both nodes
+//! then connect to each other at the same time, which opens the NAT on both sides.
+
+use std::net::{Ipv6Addr, SocketAddr};
+
+use bytes::{Buf, BufMut};
+use tracing::trace;
+
+use crate::crypto::PublicKey;
+
+/// Wire size of a [`HolePunchRequest`].
+const HOLE_PUNCH_REQUEST_WIRE_SIZE: u8 = 32;
+/// Wire size of a [`HolePunchIntroduction`]. The address is always encoded as an IPv6 address,
+/// IPv4 addresses are encoded as IPv4 mapped IPv6 addresses.
+const HOLE_PUNCH_INTRODUCTION_WIRE_SIZE: u8 = 32 + 16 + 2;
+
+/// Request to a neighbour to introduce us to a remote node, so we can connect to it directly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HolePunchRequest {
+    /// The public key of the node we want to connect to.
+    target: PublicKey,
+}
+
+impl HolePunchRequest {
+    /// Create a new `HolePunchRequest` for the node with the given [`PublicKey`].
+    pub fn new(target: PublicKey) -> Self {
+        Self { target }
+    }
+
+    /// The [`PublicKey`] of the node we want to be introduced to.
+    pub fn target(&self) -> PublicKey {
+        self.target
+    }
+
+    /// Calculates the size on the wire of this `HolePunchRequest`.
+    pub fn wire_size(&self) -> u8 {
+        HOLE_PUNCH_REQUEST_WIRE_SIZE
+    }
+
+    /// Construct a `HolePunchRequest` from wire bytes.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if there are insufficient bytes present in the provided buffer to
+    /// decode a complete `HolePunchRequest`.
+    pub fn from_bytes(src: &mut bytes::BytesMut, len: u8) -> Option<Self> {
+        if len != HOLE_PUNCH_REQUEST_WIRE_SIZE {
+            trace!("Invalid length for hole punch request, drop packet");
+            src.advance(len as usize);
+            return None;
+        }
+
+        let mut target = [0; 32];
+        src.copy_to_slice(&mut target);
+
+        trace!("Read hole punch request tlv body");
+
+        Some(Self {
+            target: PublicKey::from(target),
+        })
+    }
+
+    /// Encode this `HolePunchRequest` tlv as part of a packet.
+    pub fn write_bytes(&self, dst: &mut bytes::BytesMut) {
+        dst.put_slice(self.target.as_bytes());
+    }
+}
+
+/// Introduction of a remote node, which also received an introduction for us.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HolePunchIntroduction {
+    /// The public key of the introduced node.
+    peer: PublicKey,
+    /// The address of the QUIC socket of the introduced node, as observed by the sender.
+    address: SocketAddr,
+}
+
+impl HolePunchIntroduction {
+    /// Create a new `HolePunchIntroduction` for the node with the given [`PublicKey`], which has
+    /// its QUIC socket at the given address.
+    pub fn new(peer: PublicKey, address: SocketAddr) -> Self {
+        Self { peer, address }
+    }
+
+    /// The [`PublicKey`] of the introduced node.
+    pub fn peer(&self) -> PublicKey {
+        self.peer
+    }
+
+    /// The public address of the QUIC socket of the introduced node.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Calculates the size on the wire of this `HolePunchIntroduction`.
+    pub fn wire_size(&self) -> u8 {
+        HOLE_PUNCH_INTRODUCTION_WIRE_SIZE
+    }
+
+    /// Construct a `HolePunchIntroduction` from wire bytes.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if there are insufficient bytes present in the provided buffer to
+    /// decode a complete `HolePunchIntroduction`.
+    pub fn from_bytes(src: &mut bytes::BytesMut, len: u8) -> Option<Self> {
+        if len != HOLE_PUNCH_INTRODUCTION_WIRE_SIZE {
+            trace!("Invalid length for hole punch introduction, drop packet");
+            src.advance(len as usize);
+            return None;
+        }
+
+        let mut peer = [0; 32];
+        src.copy_to_slice(&mut peer);
+        let mut ip = [0; 16];
+        src.copy_to_slice(&mut ip);
+        let port = src.get_u16();
+
+        trace!("Read hole punch introduction tlv body");
+
+        Some(Self {
+            peer: PublicKey::from(peer),
+            address: SocketAddr::new(Ipv6Addr::from(ip).to_canonical(), port),
+        })
+    }
+
+    /// Encode this `HolePunchIntroduction` tlv as part of a packet.
+    pub fn write_bytes(&self, dst: &mut bytes::BytesMut) {
+        dst.put_slice(self.peer.as_bytes());
+        let ip = match self.address {
+            SocketAddr::V4(address) => address.ip().to_ipv6_mapped(),
+            SocketAddr::V6(address) => *address.ip(),
+        };
+        dst.put_slice(&ip.octets());
+        dst.put_u16(self.address.port());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use bytes::Buf;
+
+    use crate::crypto::{PublicKey, SecretKey};
+
+    use super::HolePunchIntroduction;
+
+    #[test]
+    fn introduction_ipv4_roundtrip() {
+        let mut buf = bytes::BytesMut::new();
+
+        let intro = HolePunchIntroduction::new(
+            PublicKey::from(&SecretKey::new()),
+            SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 9651),
+        );
+
+        intro.write_bytes(&mut buf);
+
+        assert_eq!(buf.len(), intro.wire_size() as usize);
+        assert_eq!(
+            buf[32..50],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 192, 0, 2, 1, 37, 179]
+        );
+
+        let len = buf.len() as u8;
+        let recv = HolePunchIntroduction::from_bytes(&mut buf, len);
+        assert_eq!(Some(intro), recv);
+        assert_eq!(buf.remaining(), 0);
+    }
+}
@@ -1,6 +1,9 @@
 //! The babel [IHU TLV](https://datatracker.ietf.org/doc/html/rfc8966#name-ihu).
 
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
 
 use bytes::{Buf, BufMut};
 use tracing::trace;
@@ -35,6 +38,11 @@ impl Ihu {
         }
     }
 
+    /// Returns the interval after which the sender of this `Ihu` intends to send the next one.
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval as u64 * 10)
+    }
+
     /// Calculates the size on the wire of this `Ihu`.
     pub fn wire_size(&self) -> u8 {
         IHU_BASE_WIRE_SIZE
@@ -0,0 +1,217 @@
+//! TLV used to share endpoints of peers with neighbours.
+//!
+//! A node which has peer exchange enabled periodically sends a [`PeerExchange`] to its neighbours,
+//! containing the public key and endpoint of some of the peers it connected to. Since the node
+//! connected to these endpoints itself, they are known to accept connections. Neighbours can then
+//! connect to these endpoints as well.
+
+use std::net::{Ipv6Addr, SocketAddr};
+
+use bytes::{Buf, BufMut};
+use tracing::trace;
+
+use crate::{
+    crypto::PublicKey,
+    endpoint::{Endpoint, Protocol},
+};
+
+/// The maximum amount of peers in a single [`PeerExchange`]. This keeps the TLV within the
+/// maximum TLV size.
+pub const MAX_PEER_EXCHANGE_PEERS: usize = 4;
+
+/// Wire size of a single peer in a [`PeerExchange`]: public key, protocol, IPv6 address and port.
+/// IPv4 addresses are encoded as IPv4 mapped IPv6 addresses.
+const PEER_WIRE_SIZE: u8 = 32 + 1 + 16 + 2;
+
+/// Wire value of [`Protocol::Tcp`].
+const PROTO_TCP: u8 = 0;
+/// Wire value of [`Protocol::Tls`].
+const PROTO_TLS: u8 = 1;
+/// Wire value of [`Protocol::Quic`].
+const PROTO_QUIC: u8 = 2;
+/// Wire value of [`Protocol::Ws`].
+const PROTO_WS: u8 = 3;
+
+/// Endpoints of peers of the sender, which can be connected to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerExchange {
+    /// The public key and endpoint of every peer.
+    peers: Vec<(PublicKey, Endpoint)>,
+}
+
+impl PeerExchange {
+    /// Create a new `PeerExchange` for the given peers. Only endpoints with a socket address using
+    /// [`Protocol::Tcp`], [`Protocol::Tls`], [`Protocol::Quic`] or [`Protocol::Ws`] can be shared,
+    /// other endpoints are ignored.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if more than [`MAX_PEER_EXCHANGE_PEERS`] are given.
+    pub fn new(peers: Vec<(PublicKey, Endpoint)>) -> Self {
+        assert!(
+            peers.len() <= MAX_PEER_EXCHANGE_PEERS,
+            "At most {MAX_PEER_EXCHANGE_PEERS} peers can be exchanged at once"
+        );
+
+        Self {
+            peers: peers
+                .into_iter()
+                .filter(|(_, endpoint)| {
+                    endpoint.address().is_some() && proto_to_wire(endpoint.proto()).is_some()
+                })
+                .collect(),
+        }
+    }
+
+    /// The public key and endpoint of the exchanged peers.
+    pub fn peers(&self) -> &[(PublicKey, Endpoint)] {
+        &self.peers
+    }
+
+    /// Calculates the size on the wire of this `PeerExchange`.
+    pub fn wire_size(&self) -> u8 {
+        self.peers.len() as u8 * PEER_WIRE_SIZE
+    }
+
+    /// Construct a `PeerExchange` from wire bytes. Peers with an unknown protocol are skipped.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if there are insufficient bytes present in the provided buffer to
+    /// decode a complete `PeerExchange`.
+    pub fn from_bytes(src: &mut bytes::BytesMut, len: u8) -> Option<Self> {
+        if !len.is_multiple_of(PEER_WIRE_SIZE) {
+            trace!("Invalid length for peer exchange, drop packet");
+            src.advance(len as usize);
+            return None;
+        }
+
+        let mut peers = Vec::with_capacity((len / PEER_WIRE_SIZE) as usize);
+        for _ in 0..len / PEER_WIRE_SIZE {
+            let mut key = [0; 32];
+            src.copy_to_slice(&mut key);
+            let proto = src.get_u8();
+            let mut ip = [0; 16];
+            src.copy_to_slice(&mut ip);
+            let port = src.get_u16();
+
+            let proto = match proto {
+                PROTO_TCP => Protocol::Tcp,
+                PROTO_TLS => Protocol::Tls,
+                PROTO_QUIC => Protocol::Quic,
+                PROTO_WS => Protocol::Ws,
+                _ => {
+                    trace!(proto, "Skipping exchanged peer with unknown protocol");
+                    continue;
+                }
+            };
+            peers.push((
+                PublicKey::from(key),
+                Endpoint::new(
+                    proto,
+                    SocketAddr::new(Ipv6Addr::from(ip).to_canonical(), port),
+                ),
+            ));
+        }
+
+        trace!("Read peer exchange tlv body");
+
+        Some(Self { peers })
+    }
+
+    /// Encode this `PeerExchange` tlv as part of a packet.
+    pub fn write_bytes(&self, dst: &mut bytes::BytesMut) {
+        for (key, endpoint) in &self.peers {
+            let (Some(proto), Some(address)) =
+                (proto_to_wire(endpoint.proto()), endpoint.address())
+            else {
+                unreachable!("Only endpoints which can be encoded are stored; qed");
+            };
+            dst.put_slice(key.as_bytes());
+            dst.put_u8(proto);
+            let ip = match address {
+                SocketAddr::V4(address) => address.ip().to_ipv6_mapped(),
+                SocketAddr::V6(address) => *address.ip(),
+            };
+            dst.put_slice(&ip.octets());
+            dst.put_u16(address.port());
+        }
+    }
+}
+
+/// The wire value of a [`Protocol`], if it can be exchanged.
+fn proto_to_wire(proto: Protocol) -> Option<u8> {
+    match proto {
+        Protocol::Tcp => Some(PROTO_TCP),
+        Protocol::Tls => Some(PROTO_TLS),
+        Protocol::Quic => Some(PROTO_QUIC),
+        Protocol::Ws => Some(PROTO_WS),
+        // Wss needs a hostname to verify the certificate of the remote.
+        Protocol::Wss => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use bytes::Buf;
+
+    use crate::{
+        crypto::{PublicKey, SecretKey},
+        endpoint::{Endpoint, Protocol},
+    };
+
+    use super::PeerExchange;
+
+    #[test]
+    fn roundtrip() {
+        let mut buf = bytes::BytesMut::new();
+
+        let tcp = (
+            PublicKey::from(&SecretKey::new()),
+            Endpoint::new(
+                Protocol::Tcp,
+                SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 9651),
+            ),
+        );
+        let quic = (
+            PublicKey::from(&SecretKey::new()),
+            Endpoint::new(
+                Protocol::Quic,
+                SocketAddr::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).into(), 9651),
+            ),
+        );
+        let pex = PeerExchange::new(vec![tcp, quic]);
+
+        pex.write_bytes(&mut buf);
+
+        assert_eq!(buf.len(), pex.wire_size() as usize);
+
+        let len = buf.len() as u8;
+        let recv = PeerExchange::from_bytes(&mut buf, len);
+        assert_eq!(Some(pex), recv);
+        assert_eq!(buf.remaining(), 0);
+    }
+
+    #[test]
+    fn unsupported_endpoints_are_not_exchanged() {
+        let wss = (
+            PublicKey::from(&SecretKey::new()),
+            "wss://[2001:db8::1]:443"
+                .parse::<Endpoint>()
+                .expect("Valid endpoint"),
+        );
+        let hostname = (
+            PublicKey::from(&SecretKey::new()),
+            "tcp://peer.example.org:9651"
+                .parse::<Endpoint>()
+                .expect("Valid endpoint"),
+        );
+
+        let pex = PeerExchange::new(vec![wss, hostname]);
+
+        assert!(pex.peers().is_empty());
+        assert_eq!(pex.wire_size(), 0);
+    }
+}
@@ -1,5 +1,8 @@
 pub use super::{hello::Hello, ihu::Ihu, update::Update};
-use super::{route_request::RouteRequest, SeqNoRequest};
+use super::{
+    route_request::RouteRequest, HolePunchIntroduction, HolePunchRequest, PeerExchange,
+    SeqNoRequest,
+};
 
 /// A single `Tlv` in a babel packet body.
 #[derive(Debug, Clone, PartialEq)]
@@ -14,6 +17,12 @@ pub enum Tlv {
     RouteRequest(RouteRequest),
     /// SeqNoRequest Tlv type
     SeqNoRequest(SeqNoRequest),
+    /// HolePunchRequest Tlv type
+    HolePunchRequest(HolePunchRequest),
+    /// HolePunchIntroduction Tlv type
+    HolePunchIntroduction(HolePunchIntroduction),
+    /// PeerExchange Tlv type
+    PeerExchange(PeerExchange),
 }
 
 impl Tlv {
@@ -26,6 +35,9 @@ impl Tlv {
             Self::Update(update) => update.wire_size(),
             Self::RouteRequest(route_request) => route_request.wire_size(),
             Self::SeqNoRequest(seqno_request) => seqno_request.wire_size(),
+            Self::HolePunchRequest(hole_punch_request) => hole_punch_request.wire_size(),
+            Self::HolePunchIntroduction(introduction) => introduction.wire_size(),
+            Self::PeerExchange(peer_exchange) => peer_exchange.wire_size(),
         }
     }
 
@@ -37,10 +49,31 @@ impl Tlv {
             Self::Update(update) => update.write_bytes(dst),
             Self::RouteRequest(route_request) => route_request.write_bytes(dst),
             Self::SeqNoRequest(seqno_request) => seqno_request.write_bytes(dst),
+            Self::HolePunchRequest(hole_punch_request) => hole_punch_request.write_bytes(dst),
+            Self::HolePunchIntroduction(introduction) => introduction.write_bytes(dst),
+            Self::PeerExchange(peer_exchange) => peer_exchange.write_bytes(dst),
         }
     }
 }
 
+impl From<PeerExchange> for Tlv {
+    fn from(v: PeerExchange) -> Self {
+        Self::PeerExchange(v)
+    }
+}
+
+impl From<HolePunchIntroduction> for Tlv {
+    fn from(v: HolePunchIntroduction) -> Self {
+        Self::HolePunchIntroduction(v)
+    }
+}
+
+impl From<HolePunchRequest> for Tlv {
+    fn from(v: HolePunchRequest) -> Self {
+        Self::HolePunchRequest(v)
+    }
+}
+
 impl From<SeqNoRequest> for Tlv {
     fn from(v: SeqNoRequest) -> Self {
         Self::SeqNoRequest(v)
@@ -8,7 +8,13 @@ use std::{
 use bytes::{Buf, BufMut};
 use tracing::trace;
 
-use crate::{metric::Metric, router_id::RouterId, sequence_number::SeqNo, subnet::Subnet};
+use crate::{
+    crypto::{SecretKey, Signature, SIGNATURE_SIZE},
+    metric::Metric,
+    router_id::RouterId,
+    sequence_number::SeqNo,
+    subnet::Subnet,
+};
 
 use super::{AE_IPV4, AE_IPV6, AE_IPV6_LL, AE_WILDCARD};
 
@@ -24,6 +30,20 @@ const FLAG_MASK: u8 = 0b1100_0000;
 /// Base wire size of an [`Update`] without variable length address encoding.
 const UPDATE_BASE_WIRE_SIZE: u8 = 10 + RouterId::BYTE_SIZE as u8;
 
+/// Sub-TLV type of a single byte of padding, this has no length field.
+const SUB_TLV_TYPE_PAD1: u8 = 0;
+/// Sub-TLV type carrying the [`Signature`] of the origin of an [`Update`]. This is taken from the
+/// range reserved for experimental use.
+const SUB_TLV_TYPE_SIGNATURE: u8 = 112;
+/// Bit set on sub-TLV types which are mandatory. If such a sub-TLV is not understood, the
+/// whole TLV must be ignored.
+const SUB_TLV_MANDATORY: u8 = 0x80;
+/// Wire size of the signature sub-TLV, including its type and length.
+const SIGNATURE_SUB_TLV_WIRE_SIZE: u8 = 2 + SIGNATURE_SIZE as u8;
+
+/// Context prepended to the data signed by the origin of an [`Update`].
+const SIGNATURE_CONTEXT: &[u8] = b"mycelium update";
+
 /// Update TLV body as defined in https://datatracker.ietf.org/doc/html/rfc8966#name-update.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Update {
@@ -41,6 +61,9 @@ pub struct Update {
     /// Router id of the sender. Importantly this is not part of the update itself, though we do
     /// transmit it for now as such.
     router_id: RouterId,
+    /// Signature of the router identified by the router id, over the router id, subnet and
+    /// seqno. This is carried in a sub-TLV.
+    signature: Option<Signature>,
 }
 
 impl Update {
@@ -61,6 +84,7 @@ impl Update {
             metric,
             subnet,
             router_id,
+            signature: None,
         }
     }
 
@@ -74,6 +98,11 @@ impl Update {
         self.metric
     }
 
+    /// Set the metric announced in this `Update`.
+    pub fn set_metric(&mut self, metric: Metric) {
+        self.metric = metric;
+    }
+
     /// Return the [`Subnet`] in this `Update.`
     pub fn subnet(&self) -> Subnet {
         self.subnet
@@ -84,10 +113,66 @@ impl Update {
         self.router_id
     }
 
+    /// Return the [`Signature`] of the origin of this `Update`, if it is signed.
+    pub fn signature(&self) -> Option<Signature> {
+        self.signature
+    }
+
+    /// Set the [`Signature`] carried in this `Update`. Since the signature does not cover the
+    /// metric, a signature created by the origin can be forwarded as is.
+    pub fn with_signature(mut self, signature: Option<Signature>) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// Sign this `Update`. The [`SecretKey`] must be the key of the router id in the update,
+    /// otherwise the signature won't be valid.
+    pub fn sign(&mut self, secret: &SecretKey) {
+        self.signature = Some(secret.sign(&self.signed_data()));
+    }
+
+    /// Checks if this `Update` carries a valid signature of the router identified by its router
+    /// id. Returns false if the update is not signed.
+    pub fn verify_signature(&self) -> bool {
+        match self.signature {
+            Some(ref signature) => self
+                .router_id
+                .to_pubkey()
+                .verify(&self.signed_data(), signature),
+            None => false,
+        }
+    }
+
+    /// The data covered by the signature of an `Update`.
+    fn signed_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(SIGNATURE_CONTEXT.len() + RouterId::BYTE_SIZE + 20);
+        data.extend_from_slice(SIGNATURE_CONTEXT);
+        data.extend_from_slice(&self.router_id.as_bytes());
+        match self.subnet.address() {
+            IpAddr::V4(ip) => {
+                data.push(AE_IPV4);
+                data.push(self.subnet.prefix_len());
+                data.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                data.push(AE_IPV6);
+                data.push(self.subnet.prefix_len());
+                data.extend_from_slice(&ip.octets());
+            }
+        }
+        data.extend_from_slice(&u16::from(self.seqno).to_be_bytes());
+        data
+    }
+
     /// Calculates the size on the wire of this `Update`.
     pub fn wire_size(&self) -> u8 {
         let address_bytes = (self.subnet.prefix_len() + 7) / 8;
-        UPDATE_BASE_WIRE_SIZE + address_bytes
+        let sub_tlv_bytes = if self.signature.is_some() {
+            SIGNATURE_SUB_TLV_WIRE_SIZE
+        } else {
+            0
+        };
+        UPDATE_BASE_WIRE_SIZE + address_bytes + sub_tlv_bytes
     }
 
     /// Get the time until a new `Update` for the [`Subnet`] is received at the latest.
@@ -103,6 +188,7 @@ impl Update {
     /// This function will panic if there are insufficient bytes present in the provided buffer to
     /// decode a complete `Update`.
     pub fn from_bytes(src: &mut bytes::BytesMut, len: u8) -> Option<Self> {
+        let start = src.remaining();
         let ae = src.get_u8();
         let flags = src.get_u8() & FLAG_MASK;
         let plen = src.get_u8();
@@ -167,6 +253,10 @@ impl Update {
 
         trace!("Read update tlv body");
 
+        // Any remaining bytes in the TLV are sub-TLV's.
+        let sub_tlv_len = (len as usize).saturating_sub(start - src.remaining());
+        let signature = read_sub_tlvs(src, sub_tlv_len)?;
+
         Some(Update {
             flags,
             interval,
@@ -174,6 +264,7 @@ impl Update {
             metric,
             subnet,
             router_id,
+            signature,
         })
     }
 
@@ -195,10 +286,52 @@ impl Update {
             IpAddr::V4(ip) => dst.put_slice(&ip.octets()[..prefix_len]),
             IpAddr::V6(ip) => dst.put_slice(&ip.octets()[..prefix_len]),
         }
-        dst.put_slice(&self.router_id.as_bytes()[..])
+        dst.put_slice(&self.router_id.as_bytes()[..]);
+        if let Some(ref signature) = self.signature {
+            dst.put_u8(SUB_TLV_TYPE_SIGNATURE);
+            dst.put_u8(SIGNATURE_SIZE as u8);
+            dst.put_slice(signature.as_bytes());
+        }
     }
 }
 
+/// Read the sub-TLV's of an [`Update`], which take up `len` bytes. Unknown sub-TLV's are skipped.
+/// Returns the signature if one is present, or [`Option::None`] if the update must be ignored.
+/// In the latter case, all `len` bytes are still consumed.
+fn read_sub_tlvs(src: &mut bytes::BytesMut, len: usize) -> Option<Option<Signature>> {
+    let mut sub_tlvs = src.split_to(len);
+    let mut signature = None;
+    while sub_tlvs.has_remaining() {
+        let sub_tlv_type = sub_tlvs.get_u8();
+        if sub_tlv_type == SUB_TLV_TYPE_PAD1 {
+            continue;
+        }
+        if !sub_tlvs.has_remaining() {
+            trace!("Truncated sub tlv in update");
+            return None;
+        }
+        let sub_tlv_len = sub_tlvs.get_u8() as usize;
+        if sub_tlvs.remaining() < sub_tlv_len {
+            trace!("Truncated sub tlv in update");
+            return None;
+        }
+        match sub_tlv_type {
+            SUB_TLV_TYPE_SIGNATURE if sub_tlv_len == SIGNATURE_SIZE => {
+                let mut raw_signature = [0; SIGNATURE_SIZE];
+                sub_tlvs.copy_to_slice(&mut raw_signature);
+                signature = Some(Signature::from(raw_signature));
+            }
+            t if t & SUB_TLV_MANDATORY != 0 => {
+                trace!(sub_tlv_type = t, "Unknown mandatory sub tlv in update");
+                return None;
+            }
+            _ => sub_tlvs.advance(sub_tlv_len),
+        }
+    }
+
+    Some(signature)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -206,7 +339,7 @@ mod tests {
         time::Duration,
     };
 
-    use crate::{router_id::RouterId, subnet::Subnet};
+    use crate::{crypto::SecretKey, router_id::RouterId, subnet::Subnet};
     use bytes::Buf;
 
     #[test]
@@ -221,6 +354,7 @@ mod tests {
             subnet: Subnet::new(Ipv6Addr::new(512, 25, 26, 27, 28, 0, 0, 29).into(), 64)
                 .expect("64 is a valid IPv6 prefix size; qed"),
             router_id: RouterId::from([1u8; RouterId::BYTE_SIZE]),
+            signature: None,
         };
 
         ihu.write_bytes(&mut buf);
@@ -245,6 +379,7 @@ mod tests {
             subnet: Subnet::new(Ipv4Addr::new(10, 101, 4, 1).into(), 23)
                 .expect("23 is a valid IPv4 prefix size; qed"),
             router_id: RouterId::from([2u8; RouterId::BYTE_SIZE]),
+            signature: None,
         };
 
         ihu.write_bytes(&mut buf);
@@ -276,6 +411,7 @@ mod tests {
             subnet: Subnet::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0).into(), 0)
                 .expect("0 is a valid IPv6 prefix size; qed"),
             router_id: RouterId::from([3u8; RouterId::BYTE_SIZE]),
+            signature: None,
         };
 
         let buf_len = buf.len();
@@ -301,6 +437,7 @@ mod tests {
             subnet: Subnet::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 10, 20, 30, 40).into(), 64)
                 .expect("92 is a valid IPv6 prefix size; qed"),
             router_id: RouterId::from([4u8; RouterId::BYTE_SIZE]),
+            signature: None,
         };
 
         let buf_len = buf.len();
@@ -350,6 +487,7 @@ mod tests {
             subnet: Subnet::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 10, 20, 30, 40).into(), 64)
                 .expect("92 is a valid IPv6 prefix size; qed"),
             router_id: RouterId::from([4u8; RouterId::BYTE_SIZE]),
+            signature: None,
         };
 
         let buf_len = buf.len();
@@ -382,4 +520,89 @@ mod tests {
         assert_eq!(Some(hello_src), decoded);
         assert_eq!(buf.remaining(), 0);
     }
+
+    #[test]
+    fn signed_roundtrip() {
+        let sk = SecretKey::new();
+        let mut buf = bytes::BytesMut::new();
+
+        let mut update = super::Update::new(
+            Duration::from_secs(64),
+            10.into(),
+            25.into(),
+            Subnet::new(Ipv6Addr::new(0x400, 1, 2, 3, 0, 0, 0, 0).into(), 64)
+                .expect("64 is a valid IPv6 prefix size; qed"),
+            RouterId::new((&sk).into()),
+        );
+        update.sign(&sk);
+        assert!(update.verify_signature());
+        assert_eq!(update.wire_size(), 58 + 66);
+
+        update.write_bytes(&mut buf);
+        assert_eq!(buf.len(), update.wire_size() as usize);
+        let buf_len = buf.len();
+        let decoded = super::Update::from_bytes(&mut buf, buf_len as u8);
+
+        assert_eq!(Some(update), decoded);
+        assert!(decoded.unwrap().verify_signature());
+        assert_eq!(buf.remaining(), 0);
+    }
+
+    #[test]
+    fn signature_covers_seqno() {
+        let sk = SecretKey::new();
+        let mut update = super::Update::new(
+            Duration::from_secs(64),
+            10.into(),
+            25.into(),
+            Subnet::new(Ipv6Addr::new(0x400, 1, 2, 3, 0, 0, 0, 0).into(), 64)
+                .expect("64 is a valid IPv6 prefix size; qed"),
+            RouterId::new((&sk).into()),
+        );
+        assert!(!update.verify_signature());
+        update.sign(&sk);
+
+        // The metric is not signed, so it can be changed by every hop.
+        update.set_metric(100.into());
+        assert!(update.verify_signature());
+
+        let mut inflated = update.clone();
+        inflated.seqno = 11.into();
+        assert!(!inflated.verify_signature());
+
+        // A signature from a different key is not valid either.
+        update.sign(&SecretKey::new());
+        assert!(!update.verify_signature());
+    }
+
+    #[test]
+    fn decode_skips_unknown_sub_tlvs() {
+        let mut buf = bytes::BytesMut::from(
+            &[
+                3, 0, 64, 0, 3, 232, 0, 42, 3, 1, 0, 10, 0, 20, 0, 30, 0, 40, 4, 4, 4, 4, 4, 4, 4,
+                4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+                4, 4, 4, 4, 4, // Pad1, followed by an unknown sub tlv with 3 bytes of data.
+                0, 20, 3, 1, 2, 3,
+            ][..],
+        );
+
+        let buf_len = buf.len();
+        let decoded = super::Update::from_bytes(&mut buf, buf_len as u8)
+            .expect("Unknown non mandatory sub tlvs are ignored");
+        assert_eq!(decoded.signature(), None);
+        assert_eq!(buf.remaining(), 0);
+
+        // Same, but the unknown sub tlv is mandatory.
+        let mut buf = bytes::BytesMut::from(
+            &[
+                3, 0, 64, 0, 3, 232, 0, 42, 3, 1, 0, 10, 0, 20, 0, 30, 0, 40, 4, 4, 4, 4, 4, 4, 4,
+                4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+                4, 4, 4, 4, 4, 200, 2, 1, 2,
+            ][..],
+        );
+
+        let buf_len = buf.len();
+        assert_eq!(super::Update::from_bytes(&mut buf, buf_len as u8), None);
+        assert_eq!(buf.remaining(), 0);
+    }
 }
@@ -0,0 +1,181 @@
+//! Pool of byte buffers, reused for packets in the data path.
+//!
+//! Every packet between the TUN interface and peers needs a buffer to be read, encrypted and
+//! decoded in. Allocating (and zeroing) a new buffer for every packet dominates the cost of the
+//! data path at high packet rates, so buffers are returned here once a packet is sent, and reused
+//! for the next packet.
+//!
+//! Every thread has its own pool, so getting and returning a buffer is uncontended when the tokio
+//! worker threads are pinned to cores. Packets are often read on one thread and released on
+//! another though, so buffers which don't fit in the local pool are moved to a shared pool, from
+//! which threads with an empty local pool take buffers.
+
+use std::{cell::RefCell, sync::Mutex};
+
+/// Maximum amount of buffers kept in the pool of a single thread.
+const LOCAL_POOL_SIZE: usize = 256;
+
+/// Maximum total capacity, in bytes, of the buffers kept in the pool of a single thread.
+const LOCAL_POOL_BYTES: usize = 1 << 20;
+
+/// Maximum amount of buffers kept in the shared pool.
+const SHARED_POOL_SIZE: usize = 4096;
+
+/// Maximum total capacity, in bytes, of the buffers kept in the shared pool.
+const SHARED_POOL_BYTES: usize = 8 << 20;
+
+/// Amount of buffers moved between a local pool and the shared pool at once, so the shared pool
+/// is only locked once for this many buffers.
+const TRANSFER_BATCH_SIZE: usize = 64;
+
+/// Buffers with a larger capacity than this are not pooled, so a single oversized buffer does not
+/// keep memory around which is not needed for regular packets.
+const MAX_POOLED_CAPACITY: usize = u16::MAX as usize;
+
+thread_local! {
+    static LOCAL_POOL: RefCell<Pool> = const { RefCell::new(Pool::new()) };
+}
+
+static SHARED_POOL: Mutex<Pool> = Mutex::new(Pool::new());
+
+/// A set of buffers, which tracks the total capacity of those buffers.
+struct Pool {
+    /// The pooled buffers.
+    buffers: Vec<Vec<u8>>,
+    /// Sum of the capacity of all buffers in the pool.
+    bytes: usize,
+}
+
+impl Pool {
+    /// Create a new empty pool.
+    const fn new() -> Self {
+        Self {
+            buffers: Vec::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Check if a buffer of `capacity` bytes can be added without exceeding `max_buffers` or
+    /// `max_bytes`.
+    fn fits(&self, capacity: usize, max_buffers: usize, max_bytes: usize) -> bool {
+        self.buffers.len() < max_buffers && self.bytes + capacity <= max_bytes
+    }
+
+    /// Add a buffer to the pool.
+    fn push(&mut self, buf: Vec<u8>) {
+        self.bytes += buf.capacity();
+        self.buffers.push(buf);
+    }
+
+    /// Take the most recently added buffer from the pool, if there is one.
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        let buf = self.buffers.pop()?;
+        self.bytes -= buf.capacity();
+        Some(buf)
+    }
+}
+
+/// Get an empty buffer which can hold at least `capacity` bytes without reallocating.
+pub fn get(capacity: usize) -> Vec<u8> {
+    let mut buf = LOCAL_POOL
+        .try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.buffers.is_empty() {
+                let mut shared = SHARED_POOL.lock().unwrap();
+                for _ in 0..TRANSFER_BATCH_SIZE {
+                    let Some(capacity) = shared.buffers.last().map(Vec::capacity) else {
+                        break;
+                    };
+                    // Always take at least one buffer, even if it exceeds the local limit.
+                    if !pool.buffers.is_empty() && pool.bytes + capacity > LOCAL_POOL_BYTES {
+                        break;
+                    }
+                    let buf = shared.pop().unwrap();
+                    pool.push(buf);
+                }
+            }
+            pool.pop()
+        })
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+
+    buf.clear();
+    if buf.capacity() < capacity {
+        // Reserving space in the pooled buffer could copy the old content, which is not needed.
+        buf = Vec::with_capacity(capacity);
+    }
+    buf
+}
+
+/// Return a buffer to the pool, so it can be reused by [`get`].
+pub fn put(buf: Vec<u8>) {
+    if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+        return;
+    }
+
+    // The local pool might already be destroyed if this is called while the thread exits, in
+    // which case the buffer is dropped.
+    let _ = LOCAL_POOL.try_with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if !pool.fits(buf.capacity(), LOCAL_POOL_SIZE, LOCAL_POOL_BYTES) {
+            let mut shared = SHARED_POOL.lock().unwrap();
+            for _ in 0..TRANSFER_BATCH_SIZE {
+                let Some(capacity) = pool.buffers.last().map(Vec::capacity) else {
+                    break;
+                };
+                if !shared.fits(capacity, SHARED_POOL_SIZE, SHARED_POOL_BYTES) {
+                    break;
+                }
+                let local = pool.pop().unwrap();
+                shared.push(local);
+            }
+            if !pool.fits(buf.capacity(), LOCAL_POOL_SIZE, LOCAL_POOL_BYTES) {
+                // Both pools are full, drop the buffer.
+                return;
+            }
+        }
+        pool.push(buf);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn buffers_are_reused() {
+        let mut buf = super::get(1500);
+        assert!(buf.capacity() >= 1500);
+        buf.resize(1500, 1);
+        let ptr = buf.as_ptr();
+        super::put(buf);
+
+        // The last returned buffer is reused first, without its content.
+        let buf = super::get(1000);
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+        super::put(buf);
+
+        // A buffer which is too small is replaced.
+        let buf = super::get(2000);
+        assert!(buf.capacity() >= 2000);
+    }
+
+    #[test]
+    fn oversized_buffers_are_not_pooled() {
+        super::put(vec![0; super::MAX_POOLED_CAPACITY + 1]);
+
+        assert!(super::get(100).capacity() <= super::MAX_POOLED_CAPACITY);
+    }
+
+    #[test]
+    fn pools_are_bounded_by_bytes() {
+        for _ in 0..super::SHARED_POOL_BYTES / super::MAX_POOLED_CAPACITY + 100 {
+            super::put(Vec::with_capacity(super::MAX_POOLED_CAPACITY));
+        }
+
+        super::LOCAL_POOL.with(|pool| {
+            assert!(pool.borrow().bytes <= super::LOCAL_POOL_BYTES);
+        });
+        assert!(super::SHARED_POOL.lock().unwrap().bytes <= super::SHARED_POOL_BYTES);
+    }
+}
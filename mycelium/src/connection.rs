@@ -1,12 +1,20 @@
 use std::{io, net::SocketAddr, pin::Pin};
 
+use bytes::Bytes;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     net::TcpStream,
 };
 
+use crate::endpoint::{Endpoint, Protocol};
+
+pub mod handshake;
+mod prefixed;
+pub use prefixed::Prefixed;
 mod tracked;
 pub use tracked::Tracked;
+mod ws;
+pub use ws::WebSocket;
 
 #[cfg(feature = "private-network")]
 mod tls;
@@ -31,12 +39,36 @@ const PACKET_PROCESSING_COST_IP6_QUIC: u16 = 7;
 // TODO
 const PACKET_PROCESSING_COST_IP4_QUIC: u16 = 12;
 
+/// Cost to add to the peer_link_cost for "local processing", when peers are connected over a
+/// WebSocket over IPv6.
+///
+/// WebSockets run over Tcp, but every packet is additionally framed as a WebSocket message, and
+/// the connection might pass through an HTTP proxy. This is slightly higher than
+/// [`PACKET_PROCESSING_COST_IP6_TCP`], so we skew towards plain Tcp connections if peers are
+/// connected over both.
+const PACKET_PROCESSING_COST_IP6_WS: u16 = 12;
+
+/// Cost to add to the peer_link_cost for "local processing", when peers are connected over a
+/// WebSocket over IPv4.
+///
+/// See [`PACKET_PROCESSING_COST_IP6_WS`].
+const PACKET_PROCESSING_COST_IP4_WS: u16 = 17;
+
 pub trait Connection: AsyncRead + AsyncWrite {
     /// Get an identifier for this connection, which shows details about the remote
     fn identifier(&self) -> Result<String, io::Error>;
 
     /// The static cost of using this connection
     fn static_link_cost(&self) -> Result<u16, io::Error>;
+
+    /// The [`Endpoint`] of the remote side of this connection.
+    fn remote_endpoint(&self) -> Result<Endpoint, io::Error>;
+
+    /// Unreliable [`Datagrams`] which can be exchanged alongside this connection, if both sides
+    /// support them.
+    fn datagrams(&self) -> Option<Datagrams> {
+        None
+    }
 }
 
 /// A wrapper around a quic send and quic receive stream, implementing the [`Connection`] trait.
@@ -44,12 +76,57 @@ pub struct Quic {
     tx: quinn::SendStream,
     rx: quinn::RecvStream,
     remote: SocketAddr,
+    datagrams: Option<Datagrams>,
 }
 
 impl Quic {
-    /// Create a new wrapper around Quic streams.
-    pub fn new(tx: quinn::SendStream, rx: quinn::RecvStream, remote: SocketAddr) -> Self {
-        Quic { tx, rx, remote }
+    /// Create a new wrapper around Quic streams. If datagrams were negotiated on the quic
+    /// connection, they are exposed through [`Connection::datagrams`].
+    pub fn new(
+        tx: quinn::SendStream,
+        rx: quinn::RecvStream,
+        remote: SocketAddr,
+        con: &quinn::Connection,
+    ) -> Self {
+        Quic {
+            tx,
+            rx,
+            remote,
+            datagrams: Datagrams::new(con),
+        }
+    }
+}
+
+/// Unreliable, unordered datagrams sent over a quic connection. Since lost datagrams are not
+/// retransmitted, they don't suffer from head-of-line blocking like packets on a stream.
+#[derive(Clone)]
+pub struct Datagrams {
+    con: quinn::Connection,
+}
+
+impl Datagrams {
+    /// Get the [`Datagrams`] of a quic connection. Returns [`None`] if the remote did not
+    /// indicate it accepts datagrams during the quic handshake.
+    pub fn new(con: &quinn::Connection) -> Option<Self> {
+        con.max_datagram_size()
+            .map(|_| Datagrams { con: con.clone() })
+    }
+
+    /// The maximum size of a datagram which can currently be sent. This changes with the path MTU
+    /// of the connection. Returns [`None`] if datagrams can't be sent (anymore).
+    pub fn max_size(&self) -> Option<usize> {
+        self.con.max_datagram_size()
+    }
+
+    /// Queue a datagram to be sent. If the send buffer is full, the oldest queued datagrams are
+    /// dropped.
+    pub fn send(&self, datagram: Bytes) -> Result<(), io::Error> {
+        self.con.send_datagram(datagram).map_err(io::Error::other)
+    }
+
+    /// Receive the next datagram from the remote.
+    pub async fn recv(&self) -> Result<Bytes, io::Error> {
+        self.con.read_datagram().await.map_err(io::Error::other)
     }
 }
 
@@ -71,6 +148,10 @@ impl Connection for TcpStream {
             SocketAddr::V6(_) => PACKET_PROCESSING_COST_IP6_TCP,
         })
     }
+
+    fn remote_endpoint(&self) -> Result<Endpoint, io::Error> {
+        Ok(Endpoint::new(Protocol::Tcp, self.peer_addr()?))
+    }
 }
 
 impl AsyncRead for Quic {
@@ -141,6 +222,14 @@ impl Connection for Quic {
             SocketAddr::V6(_) => PACKET_PROCESSING_COST_IP6_QUIC,
         })
     }
+
+    fn remote_endpoint(&self) -> Result<Endpoint, io::Error> {
+        Ok(Endpoint::new(Protocol::Quic, self.remote))
+    }
+
+    fn datagrams(&self) -> Option<Datagrams> {
+        self.datagrams.clone()
+    }
 }
 
 #[cfg(test)]
@@ -155,4 +244,11 @@ impl Connection for DuplexStream {
     fn static_link_cost(&self) -> Result<u16, io::Error> {
         Ok(1)
     }
+
+    fn remote_endpoint(&self) -> Result<Endpoint, io::Error> {
+        Ok(Endpoint::new(
+            Protocol::Tcp,
+            (std::net::Ipv6Addr::LOCALHOST, 0).into(),
+        ))
+    }
 }
@@ -0,0 +1,326 @@
+//! Link handshake, which authenticates the node key of the remote side of a connection.
+//!
+//! Right after a connection is set up, the side which set it up sends a hello with its public key
+//! and a random challenge. The side which accepted the connection waits for the first byte of the
+//! remote before it sends its own hello. Once the hello of the remote is received, each side
+//! replies with a signature over the challenge of the remote and both public keys.
+//!
+//! Older nodes don't do the handshake, they start sending packets as soon as the connection is
+//! set up. The first byte of a hello differs from the version byte of a packet header, so the
+//! accepting side can tell these nodes apart before it sent anything, and the connecting side
+//! finds out from the first byte it receives. Some older nodes fail to decode the hello, and close
+//! the connection without sending anything, so the connecting side also treats a connection which
+//! is closed before the first byte as a legacy remote. It is up to the caller to decide whether
+//! such legacy remotes are accepted.
+//!
+//! If the connection is secured by TLS, keying material exported from the TLS session is included
+//! in the signed data, which binds the handshake to that session. Plain TCP connections have no
+//! such binding, there the handshake only proves that the remote held its key when the connection
+//! was set up.
+
+use std::{fmt, io, time::Duration};
+
+use rand::RngCore;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::crypto::{PublicKey, SecretKey, Signature, SIGNATURE_SIZE};
+
+/// Magic bytes at the start of a handshake hello. The first byte differs from the version byte of
+/// a packet header, which is how legacy remotes are detected.
+const HANDSHAKE_MAGIC: &[u8; 6] = b"mlink\0";
+/// Version of the handshake.
+const HANDSHAKE_VERSION: u8 = 1;
+/// Size of the random challenge in a hello.
+const CHALLENGE_SIZE: usize = 32;
+/// Size of a hello on the wire.
+const HELLO_SIZE: usize = HANDSHAKE_MAGIC.len() + 1 + 32 + CHALLENGE_SIZE;
+/// Context prepended to the data signed in the handshake.
+const HANDSHAKE_CONTEXT: &[u8] = b"mycelium link handshake";
+/// Maximum time allowed to complete the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Label used to export keying material from a TLS session, to bind the handshake to it.
+pub const CHANNEL_BINDING_LABEL: &str = "EXPORTER-mycelium-link-handshake";
+/// Amount of keying material exported from a TLS session to bind the handshake to it.
+pub const CHANNEL_BINDING_SIZE: usize = 32;
+
+/// Reasons the link handshake failed.
+#[derive(Debug)]
+pub enum HandshakeError {
+    /// An IO error occurred on the connection.
+    Io(io::Error),
+    /// The remote did not complete the handshake in time.
+    Timeout,
+    /// The remote sent an invalid hello.
+    InvalidHello,
+    /// The remote does not support the handshake, it sent a packet instead of a hello, or closed
+    /// the connection after our hello.
+    Unsupported,
+    /// The remote could not prove it owns the public key it sent.
+    InvalidSignature,
+}
+
+/// The side of the connection performing the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// The side which set up the connection, it sends its hello first.
+    Initiator,
+    /// The side which accepted the connection, it waits for the first byte of the remote.
+    Responder,
+}
+
+/// The remote of a connection, as found by the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remote {
+    /// The remote proved it owns this key.
+    Verified(PublicKey),
+    /// The remote does not do the handshake. It sent this byte as the start of its first packet,
+    /// which must be read again before anything else on the connection. This is only returned to
+    /// a [`Role::Responder`].
+    Legacy(u8),
+}
+
+/// Perform the link handshake on a new connection. `channel_binding` must be the same on both
+/// sides, it is empty if the connection is not secured.
+pub async fn handshake<C>(
+    connection: &mut C,
+    secret: &SecretKey,
+    channel_binding: &[u8],
+    role: Role,
+) -> Result<Remote, HandshakeError>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    tokio::time::timeout(
+        HANDSHAKE_TIMEOUT,
+        exchange(connection, secret, channel_binding, role),
+    )
+    .await
+    .map_err(|_| HandshakeError::Timeout)?
+}
+
+/// Actual handshake message exchange.
+async fn exchange<C>(
+    connection: &mut C,
+    secret: &SecretKey,
+    channel_binding: &[u8],
+    role: Role,
+) -> Result<Remote, HandshakeError>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    let public_key = PublicKey::from(secret);
+    let mut challenge = [0; CHALLENGE_SIZE];
+    rand::thread_rng().fill_bytes(&mut challenge);
+
+    let mut hello = [0; HELLO_SIZE];
+    hello[..HANDSHAKE_MAGIC.len()].copy_from_slice(HANDSHAKE_MAGIC);
+    hello[HANDSHAKE_MAGIC.len()] = HANDSHAKE_VERSION;
+    hello[HANDSHAKE_MAGIC.len() + 1..HANDSHAKE_MAGIC.len() + 33]
+        .copy_from_slice(public_key.as_bytes());
+    hello[HANDSHAKE_MAGIC.len() + 33..].copy_from_slice(&challenge);
+    if role == Role::Initiator {
+        connection.write_all(&hello).await?;
+        connection.flush().await?;
+    }
+
+    // Legacy remotes are detected on the first byte, they might not send enough for a full hello.
+    let mut remote_hello = [0; HELLO_SIZE];
+    if let Err(e) = connection.read_exact(&mut remote_hello[..1]).await {
+        // Legacy remotes might close the connection because they can't decode our hello.
+        return match (role, e.kind()) {
+            (Role::Initiator, io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset) => {
+                Err(HandshakeError::Unsupported)
+            }
+            _ => Err(e.into()),
+        };
+    }
+    if remote_hello[0] != HANDSHAKE_MAGIC[0] {
+        return match role {
+            Role::Initiator => Err(HandshakeError::Unsupported),
+            Role::Responder => Ok(Remote::Legacy(remote_hello[0])),
+        };
+    }
+    connection.read_exact(&mut remote_hello[1..]).await?;
+    if &remote_hello[..HANDSHAKE_MAGIC.len()] != HANDSHAKE_MAGIC
+        || remote_hello[HANDSHAKE_MAGIC.len()] != HANDSHAKE_VERSION
+    {
+        return Err(HandshakeError::InvalidHello);
+    }
+    let mut remote_key = [0; 32];
+    remote_key
+        .copy_from_slice(&remote_hello[HANDSHAKE_MAGIC.len() + 1..HANDSHAKE_MAGIC.len() + 33]);
+    let remote_public_key = PublicKey::from(remote_key);
+    let remote_challenge = &remote_hello[HANDSHAKE_MAGIC.len() + 33..];
+
+    if role == Role::Responder {
+        connection.write_all(&hello).await?;
+        connection.flush().await?;
+    }
+
+    let signature = secret.sign(&signed_data(
+        channel_binding,
+        remote_challenge,
+        &public_key,
+        &remote_public_key,
+    ));
+    connection.write_all(signature.as_bytes()).await?;
+    connection.flush().await?;
+
+    let mut remote_signature = [0; SIGNATURE_SIZE];
+    connection.read_exact(&mut remote_signature).await?;
+    if !remote_public_key.verify(
+        &signed_data(channel_binding, &challenge, &remote_public_key, &public_key),
+        &Signature::from(remote_signature),
+    ) {
+        return Err(HandshakeError::InvalidSignature);
+    }
+
+    Ok(Remote::Verified(remote_public_key))
+}
+
+/// The data signed by `signer` to prove it owns its key to `verifier`, who sent `challenge`.
+fn signed_data(
+    channel_binding: &[u8],
+    challenge: &[u8],
+    signer: &PublicKey,
+    verifier: &PublicKey,
+) -> Vec<u8> {
+    let mut data =
+        Vec::with_capacity(HANDSHAKE_CONTEXT.len() + channel_binding.len() + CHALLENGE_SIZE + 64);
+    data.extend_from_slice(HANDSHAKE_CONTEXT);
+    data.extend_from_slice(channel_binding);
+    data.extend_from_slice(challenge);
+    data.extend_from_slice(signer.as_bytes());
+    data.extend_from_slice(verifier.as_bytes());
+    data
+}
+
+impl From<io::Error> for HandshakeError {
+    fn from(value: io::Error) -> Self {
+        HandshakeError::Io(value)
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Io(e) => write!(f, "IO error during link handshake: {e}"),
+            HandshakeError::Timeout => {
+                f.write_str("remote did not complete link handshake in time")
+            }
+            HandshakeError::InvalidHello => f.write_str("remote sent an invalid link handshake"),
+            HandshakeError::Unsupported => {
+                f.write_str("remote does not support the link handshake")
+            }
+            HandshakeError::InvalidSignature => {
+                f.write_str("remote could not prove ownership of its public key")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::crypto::{PublicKey, SecretKey};
+
+    use super::{handshake, HandshakeError, Remote, Role, HANDSHAKE_MAGIC};
+
+    #[tokio::test]
+    async fn handshake_exchanges_verified_keys() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        let (sk_a, sk_b) = (SecretKey::new(), SecretKey::new());
+
+        let (res_a, res_b) = tokio::join!(
+            handshake(&mut a, &sk_a, b"binding", Role::Initiator),
+            handshake(&mut b, &sk_b, b"binding", Role::Responder)
+        );
+
+        assert_eq!(res_a.unwrap(), Remote::Verified(PublicKey::from(&sk_b)));
+        assert_eq!(res_b.unwrap(), Remote::Verified(PublicKey::from(&sk_a)));
+    }
+
+    #[tokio::test]
+    async fn handshake_fails_on_channel_binding_mismatch() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        let (sk_a, sk_b) = (SecretKey::new(), SecretKey::new());
+
+        let (res_a, res_b) = tokio::join!(
+            handshake(&mut a, &sk_a, b"binding", Role::Initiator),
+            handshake(&mut b, &sk_b, b"other binding", Role::Responder)
+        );
+
+        assert!(matches!(res_a, Err(HandshakeError::InvalidSignature)));
+        assert!(matches!(res_b, Err(HandshakeError::InvalidSignature)));
+    }
+
+    #[tokio::test]
+    async fn handshake_rejects_invalid_hello() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+
+        // Starts with the magic, but the version is not supported.
+        let mut hello = [0; 128];
+        hello[..HANDSHAKE_MAGIC.len()].copy_from_slice(HANDSHAKE_MAGIC);
+        hello[HANDSHAKE_MAGIC.len()] = u8::MAX;
+        b.write_all(&hello).await.unwrap();
+
+        assert!(matches!(
+            handshake(&mut a, &SecretKey::new(), &[], Role::Responder).await,
+            Err(HandshakeError::InvalidHello)
+        ));
+    }
+
+    #[tokio::test]
+    async fn responder_detects_legacy_remote() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+
+        // Something which looks like a packet header followed by garbage.
+        b.write_all(&[1; 8]).await.unwrap();
+
+        assert_eq!(
+            handshake(&mut a, &SecretKey::new(), &[], Role::Responder)
+                .await
+                .unwrap(),
+            Remote::Legacy(1)
+        );
+        // Nothing was sent to the legacy remote, and only the first byte was read.
+        drop(a);
+        let mut received = Vec::new();
+        b.read_to_end(&mut received).await.unwrap();
+        assert!(received.is_empty());
+    }
+
+    #[tokio::test]
+    async fn initiator_detects_legacy_remote() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+
+        b.write_all(&[1; 8]).await.unwrap();
+
+        assert!(matches!(
+            handshake(&mut a, &SecretKey::new(), &[], Role::Initiator).await,
+            Err(HandshakeError::Unsupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn initiator_detects_legacy_remote_closing_connection() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+
+        // The remote can't decode the hello as a packet, and closes the connection.
+        let remote = tokio::spawn(async move {
+            let mut magic = [0; HANDSHAKE_MAGIC.len()];
+            b.read_exact(&mut magic).await.unwrap();
+            assert_eq!(&magic, HANDSHAKE_MAGIC);
+        });
+
+        assert!(matches!(
+            handshake(&mut a, &SecretKey::new(), &[], Role::Initiator).await,
+            Err(HandshakeError::Unsupported)
+        ));
+        remote.await.unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use std::{pin::Pin, task::Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::Connection;
+
+/// Wrapper which returns a byte which was already read from a connection, before reading from the
+/// connection itself. This is used when the first byte of a legacy remote was consumed to detect
+/// it does not do the link handshake.
+pub struct Prefixed<C> {
+    /// Byte to return on the next read, if it was not returned yet.
+    prefix: Option<u8>,
+    /// Underlying connection.
+    con: C,
+}
+
+impl<C> Prefixed<C>
+where
+    C: Connection + Unpin,
+{
+    /// Create a new wrapper which returns `prefix` before any data read from `con`.
+    pub fn new(prefix: u8, con: C) -> Self {
+        Self {
+            prefix: Some(prefix),
+            con,
+        }
+    }
+}
+
+impl<C> Connection for Prefixed<C>
+where
+    C: Connection + Unpin,
+{
+    #[inline]
+    fn identifier(&self) -> Result<String, std::io::Error> {
+        self.con.identifier()
+    }
+
+    #[inline]
+    fn static_link_cost(&self) -> Result<u16, std::io::Error> {
+        self.con.static_link_cost()
+    }
+
+    #[inline]
+    fn remote_endpoint(&self) -> Result<crate::endpoint::Endpoint, std::io::Error> {
+        self.con.remote_endpoint()
+    }
+
+    #[inline]
+    fn datagrams(&self) -> Option<super::Datagrams> {
+        self.con.datagrams()
+    }
+}
+
+impl<C> AsyncRead for Prefixed<C>
+where
+    C: AsyncRead + Unpin,
+{
+    #[inline]
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if buf.remaining() > 0 {
+            if let Some(prefix) = self.prefix.take() {
+                buf.put_slice(&[prefix]);
+                return Poll::Ready(Ok(()));
+            }
+        }
+        Pin::new(&mut self.con).poll_read(cx, buf)
+    }
+}
+
+impl<C> AsyncWrite for Prefixed<C>
+where
+    C: AsyncWrite + Unpin,
+{
+    #[inline]
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.con).poll_write(cx, buf)
+    }
+
+    #[inline]
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.con).poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.con).poll_shutdown(cx)
+    }
+
+    #[inline]
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.con).poll_write_vectored(cx, bufs)
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        self.con.is_write_vectored()
+    }
+}
@@ -2,6 +2,8 @@ use std::{io, net::SocketAddr};
 
 use tokio::net::TcpStream;
 
+use crate::endpoint::{Endpoint, Protocol};
+
 impl super::Connection for tokio_openssl::SslStream<TcpStream> {
     fn identifier(&self) -> Result<String, io::Error> {
         Ok(format!(
@@ -20,4 +22,8 @@ impl super::Connection for tokio_openssl::SslStream<TcpStream> {
             SocketAddr::V6(_) => super::PACKET_PROCESSING_COST_IP6_TCP,
         })
     }
+
+    fn remote_endpoint(&self) -> Result<Endpoint, io::Error> {
+        Ok(Endpoint::new(Protocol::Tls, self.get_ref().peer_addr()?))
+    }
 }
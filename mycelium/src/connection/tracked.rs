@@ -45,6 +45,16 @@ where
     fn static_link_cost(&self) -> Result<u16, std::io::Error> {
         self.con.static_link_cost()
     }
+
+    #[inline]
+    fn remote_endpoint(&self) -> Result<crate::endpoint::Endpoint, std::io::Error> {
+        self.con.remote_endpoint()
+    }
+
+    #[inline]
+    fn datagrams(&self) -> Option<super::Datagrams> {
+        self.con.datagrams()
+    }
 }
 
 impl<C> AsyncRead for Tracked<C>
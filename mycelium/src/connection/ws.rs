@@ -0,0 +1,120 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use bytes::{Buf, Bytes};
+use futures::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+use crate::endpoint::{Endpoint, Protocol};
+
+/// A wrapper around a [`WebSocketStream`], implementing the [`Connection`](super::Connection)
+/// trait. Every write is sent as a single binary message, received binary messages are read as a
+/// continuous stream of bytes.
+pub struct WebSocket<S> {
+    stream: WebSocketStream<S>,
+    /// Remaining data of the last received message which was not read yet.
+    read_buf: Bytes,
+    proto: Protocol,
+    remote: SocketAddr,
+}
+
+impl<S> WebSocket<S> {
+    /// Create a new wrapper around a [`WebSocketStream`]. The protocol must be either
+    /// [`Protocol::Ws`] or [`Protocol::Wss`].
+    pub fn new(stream: WebSocketStream<S>, proto: Protocol, remote: SocketAddr) -> Self {
+        Self {
+            stream,
+            read_buf: Bytes::new(),
+            proto,
+            remote,
+        }
+    }
+}
+
+impl<S> AsyncRead for WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while !self.read_buf.has_remaining() {
+            match ready!(Pin::new(&mut self.stream).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => self.read_buf = data.into(),
+                // Pings are answered by the stream itself, other messages are not used.
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Err(io::Error::other(e))),
+            }
+        }
+
+        let n = self.read_buf.len().min(buf.remaining());
+        buf.put_slice(&self.read_buf[..n]);
+        self.read_buf.advance(n);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        ready!(Pin::new(&mut self.stream).poll_ready(cx)).map_err(io::Error::other)?;
+        Pin::new(&mut self.stream)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(io::Error::other)?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.stream)
+            .poll_flush(cx)
+            .map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.stream)
+            .poll_close(cx)
+            .map_err(io::Error::other)
+    }
+}
+
+impl<S> super::Connection for WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn identifier(&self) -> Result<String, io::Error> {
+        Ok(format!("{} -> {}", self.proto, self.remote))
+    }
+
+    fn static_link_cost(&self) -> Result<u16, io::Error> {
+        Ok(match self.remote {
+            SocketAddr::V4(_) => super::PACKET_PROCESSING_COST_IP4_WS,
+            SocketAddr::V6(ip) if ip.ip().to_ipv4_mapped().is_some() => {
+                super::PACKET_PROCESSING_COST_IP4_WS
+            }
+            SocketAddr::V6(_) => super::PACKET_PROCESSING_COST_IP6_WS,
+        })
+    }
+
+    fn remote_endpoint(&self) -> Result<Endpoint, io::Error> {
+        Ok(Endpoint::new(self.proto, self.remote))
+    }
+}
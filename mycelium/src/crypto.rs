@@ -8,14 +8,30 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
-use aes_gcm::{aead::OsRng, AeadCore, AeadInPlace, Aes256Gcm, Key, KeyInit};
+use aes_gcm::{
+    aead::{rand_core::RngCore, Nonce, OsRng},
+    AeadCore, AeadInPlace, Aes256Gcm, Key, KeyInit,
+};
+use curve25519_dalek::{montgomery::MontgomeryPoint, scalar::clamp_integer, EdwardsPoint, Scalar};
+use ed25519_dalek::{
+    hazmat::{raw_sign, ExpandedSecretKey},
+    VerifyingKey,
+};
 use serde::{de::Visitor, Deserialize, Serialize};
 
+use crate::buffer_pool;
+
 /// Default MTU for a packet. Ideally this would not be needed and the [`PacketBuffer`] takes a
 /// const generic argument which is then expanded with the needed extra space for the buffer,
 /// however as it stands const generics can only be used standalone and not in a constant
 /// expression. This _is_ possible on nightly rust, with a feature gate (generic_const_exprs).
-const PACKET_SIZE: usize = 1400;
+pub const DEFAULT_PACKET_SIZE: usize = 1400;
+
+/// Maximum MTU for a packet. The length of the body of a
+/// [`DataPacket`](crate::packet::DataPacket) is encoded in 16 bits, so the encrypted packet,
+/// including the header, tag and nonce, must fit in that.
+pub const MAX_PACKET_SIZE: usize =
+    u16::MAX as usize - AES_TAG_SIZE - AES_NONCE_SIZE - DATA_HEADER_SIZE;
 
 /// Size of an AES_GCM tag in bytes.
 const AES_TAG_SIZE: usize = 16;
@@ -23,11 +39,26 @@ const AES_TAG_SIZE: usize = 16;
 /// Size of an AES_GCM nonce in bytes.
 const AES_NONCE_SIZE: usize = 12;
 
+/// Size of the counter in a nonce created by [`SharedSecret::encrypt_with_counter`].
+const NONCE_COUNTER_SIZE: usize = 8;
+
 /// Size of user defined data header. This header will be part of the encrypted data.
 const DATA_HEADER_SIZE: usize = 4;
 
-/// Size of a `PacketBuffer`.
-const PACKET_BUFFER_SIZE: usize = PACKET_SIZE + AES_TAG_SIZE + AES_NONCE_SIZE + DATA_HEADER_SIZE;
+/// Extra space in a `PacketBuffer` besides the packet itself.
+const PACKET_BUFFER_OVERHEAD: usize = AES_TAG_SIZE + AES_NONCE_SIZE + DATA_HEADER_SIZE;
+
+/// Domain separation for the hash used to derive additional addresses from a [`PublicKey`].
+const DERIVED_ADDRESS_CONTEXT: &[u8] = b"mycelium derived subnet";
+
+/// Context used to derive the nonce prefix of signatures made by a [`SecretKey`].
+const SIGNING_NONCE_CONTEXT: &str = "mycelium 2024-10 signature nonce prefix";
+
+/// Context used to derive a session key from an ephemeral key exchange.
+const SESSION_KEY_CONTEXT: &str = "mycelium 2024-11 session key";
+
+/// Size of a [`Signature`] in bytes.
+pub const SIGNATURE_SIZE: usize = 64;
 
 /// A public key used as part of Diffie Hellman key exchange. It is derived from a [`SecretKey`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -48,7 +79,8 @@ pub struct SecretKey(x25519_dalek::StaticSecret);
 pub struct SharedSecret([u8; 32]);
 
 /// A buffer for packets. This holds enough space to  encrypt a packet in place without
-/// reallocating.
+/// reallocating. The backing memory is taken from, and returned to, a pool shared by all packets,
+/// so packets in the data path don't need a new allocation.
 ///
 /// Internally, the buffer is created with an additional header. Because this header is part of the
 /// encrypted content, it is not included in the global version set by the main packet header. As
@@ -69,6 +101,11 @@ pub struct PacketBufferHeaderMut<'a> {
     data: &'a mut [u8; DATA_HEADER_SIZE],
 }
 
+/// A signature created by a [`SecretKey`], which can be verified with the associated
+/// [`PublicKey`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Signature([u8; SIGNATURE_SIZE]);
+
 /// Opaque type indicating decryption failed.
 #[derive(Debug, Clone, Copy)]
 pub struct DecryptionError;
@@ -97,6 +134,30 @@ impl SecretKey {
     pub fn shared_secret(&self, other: &PublicKey) -> SharedSecret {
         SharedSecret(self.0.diffie_hellman(&other.0).to_bytes())
     }
+
+    /// Sign a message with this `SecretKey`.
+    ///
+    /// The key is used as an Ed25519 key, following the XEdDSA scheme. This allows verifiers to
+    /// derive the verifying key from the [`PublicKey`], so no separate signing key needs to be
+    /// distributed.
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        let scalar = Scalar::from_bytes_mod_order(clamp_integer(self.0.to_bytes()));
+        let point = EdwardsPoint::mul_base(&scalar);
+        // The sign of the Edwards point is lost when converting it to a Montgomery point, and
+        // verifiers always recover the point with a positive sign. If our point has a negative
+        // sign, negate the key so it matches.
+        let (scalar, point) = if point.compress().as_bytes()[31] & 0x80 != 0 {
+            (-scalar, -point)
+        } else {
+            (scalar, point)
+        };
+        let esk = ExpandedSecretKey {
+            scalar,
+            hash_prefix: blake3::derive_key(SIGNING_NONCE_CONTEXT, self.as_bytes()),
+        };
+
+        Signature(raw_sign::<sha2::Sha512>(&esk, message, &VerifyingKey::from(point)).to_bytes())
+    }
 }
 
 impl Default for SecretKey {
@@ -112,15 +173,31 @@ impl PublicKey {
     pub fn address(&self) -> Ipv6Addr {
         let mut hasher = blake3::Hasher::new();
         hasher.update(self.as_bytes());
-        let mut buf = [0; 16];
-        hasher.finalize_xof().fill(&mut buf);
-        // Mangle the first byte to be of the expected form. Because of the network range
-        // requirement, we MUST set the third bit, and MAY set the last bit. Instead of discarding
-        // the first 7 bits of the hash, use the first byte to determine if the last bit is set.
-        // If there is an odd number of bits set in the first byte, set the last bit of the result.
-        let lsb = buf[0].count_ones() as u8 % 2;
-        buf[0] = 0x04 | lsb;
-        Ipv6Addr::from(buf)
+        hash_to_address(hasher)
+    }
+
+    /// Generates the [`Ipv6Addr`] of an additional subnet owned by this `PublicKey`, identified
+    /// by `index`. Since these are derived from the `PublicKey`, anyone can verify that the
+    /// subnet is owned by the `PublicKey`.
+    ///
+    /// The generated address is guaranteed to be part of the `400::/7` range.
+    pub fn derived_address(&self, index: u16) -> Ipv6Addr {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.as_bytes());
+        hasher.update(DERIVED_ADDRESS_CONTEXT);
+        hasher.update(&index.to_be_bytes());
+        hash_to_address(hasher)
+    }
+
+    /// Verify a [`Signature`] on a message, created by the [`SecretKey`] of this `PublicKey`.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        let Some(point) = MontgomeryPoint(self.to_bytes()).to_edwards(0) else {
+            return false;
+        };
+
+        VerifyingKey::from(point)
+            .verify_strict(message, &ed25519_dalek::Signature::from_bytes(&signature.0))
+            .is_ok()
     }
 
     /// Convert this `PublicKey` to a byte array.
@@ -134,14 +211,60 @@ impl PublicKey {
     }
 }
 
+/// Get the counter from the nonce of data encrypted with [`SharedSecret::encrypt_with_counter`].
+/// This does not verify the data, so the counter must only be used if the data decrypts
+/// successfully. If the data is too short to contain a nonce, [`None`] is returned.
+pub fn nonce_counter(data: &[u8]) -> Option<u64> {
+    if data.len() < AES_NONCE_SIZE + AES_TAG_SIZE + DATA_HEADER_SIZE {
+        return None;
+    }
+
+    Some(u64::from_be_bytes(
+        data[data.len() - NONCE_COUNTER_SIZE..]
+            .try_into()
+            .expect("Slice has the size of a counter; qed"),
+    ))
+}
+
+/// Create an [`Ipv6Addr`] in the `400::/7` range from the output of a hasher.
+fn hash_to_address(hasher: blake3::Hasher) -> Ipv6Addr {
+    let mut buf = [0; 16];
+    hasher.finalize_xof().fill(&mut buf);
+    // Mangle the first byte to be of the expected form. Because of the network range
+    // requirement, we MUST set the third bit, and MAY set the last bit. Instead of discarding
+    // the first 7 bits of the hash, use the first byte to determine if the last bit is set.
+    // If there is an odd number of bits set in the first byte, set the last bit of the result.
+    let lsb = buf[0].count_ones() as u8 % 2;
+    buf[0] = 0x04 | lsb;
+    Ipv6Addr::from(buf)
+}
+
 impl SharedSecret {
     /// Encrypt a [`PacketBuffer`] using the `SharedSecret` as key.
     ///
     /// Internally, a new random nonce will be generated using the OS's crypto rng generator. This
     /// nonce is appended to the encrypted data.
-    pub fn encrypt(&self, mut data: PacketBuffer) -> Vec<u8> {
+    pub fn encrypt(&self, data: PacketBuffer) -> Vec<u8> {
+        self.encrypt_with_nonce(data, Aes256Gcm::generate_nonce(OsRng))
+    }
+
+    /// Encrypt a [`PacketBuffer`] using the `SharedSecret` as key, like
+    /// [`SharedSecret::encrypt`]. The last 8 bytes of the nonce are set to the given counter,
+    /// which can be read again with [`nonce_counter`]. Since the nonce is authenticated, the
+    /// counter can't be modified without decryption failing.
+    ///
+    /// The counter must never be reused with the same `SharedSecret`. The first 4 bytes of the
+    /// nonce are random.
+    pub fn encrypt_with_counter(&self, data: PacketBuffer, counter: u64) -> Vec<u8> {
+        let mut nonce = Nonce::<Aes256Gcm>::default();
+        OsRng.fill_bytes(&mut nonce[..AES_NONCE_SIZE - NONCE_COUNTER_SIZE]);
+        nonce[AES_NONCE_SIZE - NONCE_COUNTER_SIZE..].copy_from_slice(&counter.to_be_bytes());
+        self.encrypt_with_nonce(data, nonce)
+    }
+
+    /// Encrypt a [`PacketBuffer`] in place using the given nonce, and append the tag and nonce.
+    fn encrypt_with_nonce(&self, mut data: PacketBuffer, nonce: Nonce<Aes256Gcm>) -> Vec<u8> {
         let key: Key<Aes256Gcm> = self.0.into();
-        let nonce = Aes256Gcm::generate_nonce(OsRng);
 
         let cipher = Aes256Gcm::new(&key);
         let tag = cipher
@@ -154,7 +277,7 @@ impl SharedSecret {
 
         data.buf.truncate(data.size + AES_NONCE_SIZE + AES_TAG_SIZE);
 
-        data.buf
+        std::mem::take(&mut data.buf)
     }
 
     /// Decrypt a message previously encrypted with an equivalent `SharedSecret`. In other words, a
@@ -164,23 +287,35 @@ impl SharedSecret {
     /// If the passed in data to decrypt does not contain a valid nonce, decryption fails and an
     /// opaque error is returned. As an extension to this, if the data is not of sufficient length
     /// to contain a valid nonce, an error is returned immediately.
-    pub fn decrypt(&self, mut data: Vec<u8>) -> Result<PacketBuffer, DecryptionError> {
+    pub fn decrypt(&self, data: Vec<u8>) -> Result<PacketBuffer, DecryptionError> {
+        self.try_decrypt(data).map_err(|_| DecryptionError)
+    }
+
+    /// Decrypt a message previously encrypted with an equivalent `SharedSecret`, like
+    /// [`SharedSecret::decrypt`]. If decryption fails, the data is returned unmodified, so it can
+    /// be decrypted with a different `SharedSecret`.
+    pub fn try_decrypt(&self, mut data: Vec<u8>) -> Result<PacketBuffer, Vec<u8>> {
         // Make sure we have sufficient data (i.e. a nonce).
         if data.len() < AES_NONCE_SIZE + AES_TAG_SIZE + DATA_HEADER_SIZE {
-            return Err(DecryptionError);
+            return Err(data);
         }
 
         let data_len = data.len();
 
         let key: Key<Aes256Gcm> = self.0.into();
-        {
+        let decrypted = {
             let (data, nonce) = data.split_at_mut(data_len - AES_NONCE_SIZE);
             let (data, tag) = data.split_at_mut(data.len() - AES_TAG_SIZE);
 
             let cipher = Aes256Gcm::new(&key);
+            // The buffer is only modified if the tag is valid, so on error the data is still
+            // intact.
             cipher
                 .decrypt_in_place_detached((&*nonce).into(), &[], data, (&*tag).into())
-                .map_err(|_| DecryptionError)?;
+                .is_ok()
+        };
+        if !decrypted {
+            return Err(data);
         }
 
         Ok(PacketBuffer {
@@ -189,15 +324,47 @@ impl SharedSecret {
             buf: data,
         })
     }
+
+    /// Derive a session key from this `SharedSecret` and the [`SharedSecret`] of an ephemeral key
+    /// exchange. The ephemeral [`PublicKey`]s of the initiator and responder of the key exchange
+    /// are included, so the session key is bound to this specific exchange.
+    ///
+    /// Since the ephemeral secret keys are discarded once the session key is derived, the session
+    /// key can't be recovered later, even if the static [`SecretKey`]s are compromised.
+    pub fn derive_session_key(
+        &self,
+        ephemeral: &SharedSecret,
+        initiator: &PublicKey,
+        responder: &PublicKey,
+    ) -> SharedSecret {
+        let mut hasher = blake3::Hasher::new_derive_key(SESSION_KEY_CONTEXT);
+        hasher.update(&ephemeral.0);
+        hasher.update(&self.0);
+        hasher.update(initiator.as_bytes());
+        hasher.update(responder.as_bytes());
+        SharedSecret(*hasher.finalize().as_bytes())
+    }
 }
 
 impl PacketBuffer {
-    /// Create a new blank `PacketBuffer`.
+    /// Create a new blank `PacketBuffer`, which can hold a packet of [`DEFAULT_PACKET_SIZE`].
     pub fn new() -> Self {
-        Self {
-            buf: vec![0; PACKET_BUFFER_SIZE],
-            size: 0,
-        }
+        Self::with_mtu(DEFAULT_PACKET_SIZE)
+    }
+
+    /// Create a new blank `PacketBuffer`, which can hold a packet of the given size.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `mtu` is larger than [`MAX_PACKET_SIZE`].
+    pub fn with_mtu(mtu: usize) -> Self {
+        assert!(
+            mtu <= MAX_PACKET_SIZE,
+            "MTU {mtu} exceeds the maximum packet size"
+        );
+        let mut buf = buffer_pool::get(mtu + PACKET_BUFFER_OVERHEAD);
+        buf.resize(mtu + PACKET_BUFFER_OVERHEAD, 0);
+        Self { buf, size: 0 }
     }
 
     /// Get a reference to the packet header.
@@ -243,6 +410,12 @@ impl Default for PacketBuffer {
     }
 }
 
+impl Drop for PacketBuffer {
+    fn drop(&mut self) {
+        buffer_pool::put(std::mem::take(&mut self.buf));
+    }
+}
+
 impl From<[u8; 32]> for SecretKey {
     /// Load a secret key from a byte array.
     fn from(bytes: [u8; 32]) -> SecretKey {
@@ -250,6 +423,20 @@ impl From<[u8; 32]> for SecretKey {
     }
 }
 
+impl Signature {
+    /// View this `Signature` as a byte array.
+    pub fn as_bytes(&self) -> &[u8; SIGNATURE_SIZE] {
+        &self.0
+    }
+}
+
+impl From<[u8; SIGNATURE_SIZE]> for Signature {
+    /// Load a signature from a byte array.
+    fn from(bytes: [u8; SIGNATURE_SIZE]) -> Signature {
+        Signature(bytes)
+    }
+}
+
 impl fmt::Display for PublicKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(&faster_hex::hex_string(self.as_bytes()))
@@ -369,7 +556,40 @@ impl fmt::Debug for PacketBuffer {
 
 #[cfg(test)]
 mod tests {
-    use super::{PacketBuffer, SecretKey, AES_NONCE_SIZE, AES_TAG_SIZE, DATA_HEADER_SIZE};
+    use super::{
+        PacketBuffer, PublicKey, SecretKey, AES_NONCE_SIZE, AES_TAG_SIZE, DATA_HEADER_SIZE,
+    };
+
+    #[test]
+    fn signatures_verify_with_public_key() {
+        // Run this multiple times, so keys with both signs of the Edwards point are covered.
+        for _ in 0..16 {
+            let sk = SecretKey::new();
+            let pk = PublicKey::from(&sk);
+
+            let signature = sk.sign(b"some message");
+            assert!(pk.verify(b"some message", &signature));
+            assert!(!pk.verify(b"some other message", &signature));
+            assert!(!PublicKey::from(&SecretKey::new()).verify(b"some message", &signature));
+        }
+    }
+
+    #[test]
+    fn derived_addresses_are_distinct_and_global() {
+        let pk = PublicKey::from(&SecretKey::new());
+
+        let mut prefixes = vec![pk.address().segments()[..4].to_vec()];
+        for index in 1..=16 {
+            let addr = pk.derived_address(index);
+            assert!((0x04..0x06).contains(&(addr.octets()[0])));
+            prefixes.push(addr.segments()[..4].to_vec());
+        }
+        prefixes.sort();
+        prefixes.dedup();
+        assert_eq!(prefixes.len(), 17);
+
+        assert_eq!(pk.derived_address(1), pk.derived_address(1));
+    }
 
     #[test]
     /// Test if encryption works in general. We just create some random value and encrypt it.
@@ -424,6 +644,58 @@ mod tests {
         assert_eq!(&*original, &data[..]);
     }
 
+    #[test]
+    /// The counter in the nonce can be read from the encrypted data, and is authenticated.
+    fn nonce_counter_is_authenticated() {
+        let k1 = SecretKey::new();
+        let k2 = SecretKey::new();
+
+        let ss1 = k1.shared_secret(&(&k2).into());
+        let ss2 = k2.shared_secret(&(&k1).into());
+
+        let data = b"fj3 90u 4t9j vd0s";
+        let mut pb = PacketBuffer::new();
+
+        pb.buffer_mut()[..data.len()].copy_from_slice(data);
+        pb.set_size(data.len());
+
+        let mut res = ss1.encrypt_with_counter(pb, 1337);
+        assert_eq!(super::nonce_counter(&res), Some(1337));
+
+        let len = res.len();
+        res[len - 1] ^= 1;
+        assert_eq!(super::nonce_counter(&res), Some(1336));
+        assert!(ss2.decrypt(res).is_err());
+    }
+
+    #[test]
+    /// A failed decryption must return the data unmodified, so it can be decrypted with another
+    /// key.
+    fn failed_decryption_returns_data() {
+        let k1 = SecretKey::new();
+        let k2 = SecretKey::new();
+        let k3 = SecretKey::new();
+
+        let ss1 = k1.shared_secret(&(&k2).into());
+        let ss2 = k2.shared_secret(&(&k1).into());
+        let ss3 = k3.shared_secret(&(&k1).into());
+
+        let data = b"4t8 uqj3 gp9j23 jfq98j";
+        let mut pb = PacketBuffer::new();
+
+        pb.buffer_mut()[..data.len()].copy_from_slice(data);
+        pb.set_size(data.len());
+
+        let res = ss1.encrypt(pb);
+
+        let res = ss3
+            .try_decrypt(res)
+            .expect_err("Decryption with wrong key fails");
+        let original = ss2.try_decrypt(res).expect("Decryption works");
+
+        assert_eq!(&*original, &data[..]);
+    }
+
     #[test]
     /// Test if PacketBufferHeaderMut actually modifies the PacketBuffer storage.
     fn modify_header() {
@@ -444,7 +716,12 @@ mod tests {
     fn buffer_mapping() {
         let mut pb = PacketBuffer::new();
 
-        assert_eq!(pb.buffer().len(), super::PACKET_SIZE);
-        assert_eq!(pb.buffer_mut().len(), super::PACKET_SIZE);
+        assert_eq!(pb.buffer().len(), super::DEFAULT_PACKET_SIZE);
+        assert_eq!(pb.buffer_mut().len(), super::DEFAULT_PACKET_SIZE);
+
+        let mut pb = PacketBuffer::with_mtu(9000);
+
+        assert_eq!(pb.buffer().len(), 9000);
+        assert_eq!(pb.buffer_mut().len(), 9000);
     }
 }
@@ -1,15 +1,39 @@
-use std::net::{IpAddr, Ipv6Addr};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use etherparse::{icmpv6::DestUnreachableCode, Icmpv6Type, PacketBuilder};
 use futures::{Sink, SinkExt, Stream, StreamExt};
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::{sync::mpsc::UnboundedReceiver, time::MissedTickBehavior};
 use tracing::{debug, error, trace, warn};
 
-use crate::{crypto::PacketBuffer, metrics::Metrics, packet::DataPacket, router::Router};
+use crate::{
+    crypto::{self, PacketBuffer, PublicKey, SharedSecret},
+    metrics::Metrics,
+    packet::DataPacket,
+    replay_window::ReplayWindows,
+    router::{Flow, Router},
+    session::{KeyExchange, SessionCache},
+};
 
 /// Current version of the user data header.
 const USER_DATA_VERSION: u8 = 1;
 
+/// Bit set in the version of the user data header if the packet is encrypted with a session key
+/// instead of the static shared secret. Since the header is encrypted, this can't be modified by
+/// intermediate nodes.
+const USER_DATA_SESSION_KEY_FLAG: u8 = 0b1000_0000;
+
+/// Bit set in the third byte of the user data header if the nonce of the packet contains a replay
+/// counter. Older nodes don't set this, and ignore it when receiving packets.
+const USER_DATA_REPLAY_COUNTER_FLAG: u8 = 0b0000_0001;
+
 /// Type value indicating L3 data in the user data header.
 const USER_DATA_L3_TYPE: u8 = 0;
 
@@ -20,12 +44,32 @@ const USER_DATA_MESSAGE_TYPE: u8 = 1;
 /// intermediate nodes send back icmp data, as the original data is encrypted.
 const USER_DATA_OOB_ICMP: u8 = 2;
 
+/// Type value indicating a session key exchange in the user data header.
+const USER_DATA_KEY_EXCHANGE_TYPE: u8 = 3;
+
 /// Minimum size in bytes of an IPv6 header.
 const IPV6_MIN_HEADER_SIZE: usize = 40;
 
+/// Next header value in an IPv6 header indicating an ICMPv6 packet.
+const IPV6_NEXT_HEADER_ICMP6: u8 = 58;
+
 /// Size of an ICMPv6 header.
 const ICMP6_HEADER_SIZE: usize = 8;
 
+/// Type value of an ICMPv6 Packet Too Big message.
+const ICMP6_PACKET_TOO_BIG_TYPE: u8 = 2;
+
+/// Time after which a path MTU learned from a remote is forgotten, so the path MTU can increase
+/// again. This is the minimum recommended in https://www.rfc-editor.org/rfc/rfc8201#section-4.
+const PATH_MTU_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Time after which the replay window of a remote which stopped sending packets encrypted with
+/// the static shared secret is removed. Only the highest counter seen from the remote is kept.
+const REPLAY_WINDOW_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Interval at which idle replay windows are removed.
+const REPLAY_WINDOW_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Minimum MTU for IPV6 according to https://www.rfc-editor.org/rfc/rfc8200#section-5.
 /// For ICMP, the packet must not be greater than this value. This is specified in
 /// https://datatracker.ietf.org/doc/html/rfc4443#section-2.4, section (c).
@@ -50,6 +94,17 @@ const MESSAGE_HOP_LIMIT: u8 = 64;
 /// DataPlane itself can be cloned, but this is not cheap on the router and should be avoided.
 pub struct DataPlane<M> {
     router: Router<M>,
+    sessions: SessionCache,
+    /// Counter included in every packet we encrypt, so receivers can detect replays.
+    replay_counter: Arc<AtomicU64>,
+    /// Replay windows for packets encrypted with the static shared secret, of remotes which sent us
+    /// packets. Packets encrypted with a session key are checked against the window of that key
+    /// in the [`SessionCache`].
+    replay_windows: Arc<Mutex<ReplayWindows>>,
+    /// MTU of the local TUN interface. Larger packets from remotes can't be delivered.
+    tun_mtu: usize,
+    /// Path MTU of remotes which sent us a Packet Too Big, and the time it was learned.
+    path_mtus: Arc<Mutex<HashMap<PublicKey, (usize, Instant)>>>,
 }
 
 impl<M> DataPlane<M>
@@ -60,12 +115,14 @@ where
     ///
     /// `l3_packet_stream` is a stream of l3 packets from the host, usually read from a TUN interface.
     /// `l3_packet_sink` is a sink for l3 packets received from a romte, usually send to a TUN interface,
+    /// `tun_mtu` is the largest l3 packet which can be sent to `l3_packet_sink`.
     pub fn new<S, T, U>(
         router: Router<M>,
         l3_packet_stream: S,
         l3_packet_sink: T,
         message_packet_sink: U,
         host_packet_source: UnboundedReceiver<DataPacket>,
+        tun_mtu: u16,
     ) -> Self
     where
         S: Stream<Item = Result<PacketBuffer, std::io::Error>> + Send + Unpin + 'static,
@@ -74,12 +131,27 @@ where
         U: Sink<(PacketBuffer, IpAddr, IpAddr)> + Send + Unpin + 'static,
         U::Error: std::fmt::Display,
     {
-        let dp = Self { router };
+        // Start the replay counter at the current time in nanoseconds, so it keeps increasing
+        // when the node restarts. This only matters for packets encrypted with the static shared
+        // secret, the replay windows of session keys start over with every key.
+        let replay_counter = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_nanos() as u64)
+            .unwrap_or_default();
+        let dp = Self {
+            sessions: SessionCache::new(router.node_public_key()),
+            replay_counter: Arc::new(AtomicU64::new(replay_counter)),
+            replay_windows: Arc::new(Mutex::new(ReplayWindows::new())),
+            tun_mtu: tun_mtu as usize,
+            path_mtus: Arc::new(Mutex::new(HashMap::new())),
+            router,
+        };
 
         tokio::spawn(
             dp.clone()
                 .inject_l3_packet_loop(l3_packet_stream, l3_packet_sink.clone()),
         );
+        tokio::spawn(Self::sweep_replay_windows(dp.replay_windows.clone()));
         tokio::spawn(dp.clone().extract_packet_loop(
             l3_packet_sink,
             message_packet_sink,
@@ -169,15 +241,38 @@ where
                 continue;
             }
 
+            // Packets which are larger than the path MTU learned from the destination can't be
+            // delivered, let the host know so it can lower the MTU without a round trip. The
+            // path MTU is never lower than the IPv6 minimum MTU, so smaller packets are skipped.
+            if packet.len() > MIN_IPV6_MTU {
+                if let Some(path_mtu) = self.path_mtu(dst_ip) {
+                    if packet.len() > path_mtu {
+                        trace!("Packet to {dst_ip} exceeds path MTU {path_mtu}");
+                        if let Some(icmp) = self.packet_too_big(src_ip, path_mtu, &packet) {
+                            if let Err(e) = l3_packet_sink.send(icmp).await {
+                                error!("Failed to send ICMP packet to host: {e}");
+                            }
+                        }
+                        continue;
+                    }
+                }
+            }
+
             // No need to verify destination address, if it is not part of the global subnet there
             // should not be a route for it, and therefore the route step will generate the
             // appropriate ICMP.
 
+            // Identify the flow before the packet is encrypted, so multipath forwarding can keep
+            // packets of the same connection on the same path.
+            let flow = Flow::from_ipv6_packet(&packet).unwrap_or(Flow::new(src_ip, dst_ip));
+
             let mut header = packet.header_mut();
             header[0] = USER_DATA_VERSION;
             header[1] = USER_DATA_L3_TYPE;
 
-            if let Some(icmp) = self.encrypt_and_route_packet(src_ip, dst_ip, hop_limit, packet) {
+            if let Some(icmp) =
+                self.encrypt_and_route_packet(src_ip, dst_ip, hop_limit, flow, packet)
+            {
                 if let Err(e) = l3_packet_sink.send(icmp).await {
                     error!("Could not forward icmp packet back to TUN interface {e}");
                 }
@@ -198,11 +293,19 @@ where
         header[0] = USER_DATA_VERSION;
         header[1] = USER_DATA_MESSAGE_TYPE;
 
-        self.encrypt_and_route_packet(src_ip, dst_ip, MESSAGE_HOP_LIMIT, packet);
+        self.encrypt_and_route_packet(
+            src_ip,
+            dst_ip,
+            MESSAGE_HOP_LIMIT,
+            Flow::new(src_ip, dst_ip),
+            packet,
+        );
     }
 
     /// Encrypt the content of a packet based on the destination key, and then inject the packet
-    /// into the [`Router`] for processing.
+    /// into the [`Router`] for processing. If a session key is established with the destination,
+    /// it is used instead of the static shared secret. Packets for a destination which is known to
+    /// use session keys are dropped while a new session key is negotiated.
     ///
     /// If no key exists for the destination, the content can'be encrypted, the packet is not injected
     /// into the router, and a packet is returned containing an ICMP packet. Note that a return
@@ -212,7 +315,8 @@ where
         src_ip: Ipv6Addr,
         dst_ip: Ipv6Addr,
         hop_limit: u8,
-        packet: PacketBuffer,
+        flow: Flow,
+        mut packet: PacketBuffer,
     ) -> Option<PacketBuffer> {
         // Get shared secret from node and dest address
         let shared_secret = match self.router.get_shared_secret_from_dest(dst_ip.into()) {
@@ -244,16 +348,153 @@ where
             }
         };
 
-        self.router.route_packet(DataPacket {
-            dst_ip,
-            src_ip,
-            hop_limit,
-            raw_data: shared_secret.encrypt(packet),
+        let remote = self.router.get_pubkey(dst_ip.into());
+        let session_key = remote.and_then(|remote| {
+            if let Some(key_exchange) = self.sessions.start_key_exchange(&remote) {
+                self.send_key_exchange(remote, &shared_secret, key_exchange);
+            }
+            self.sessions.encryption_key(&remote)
         });
+        let key = match session_key {
+            Some(key) => {
+                packet.header_mut()[0] |= USER_DATA_SESSION_KEY_FLAG;
+                key
+            }
+            // Don't downgrade to the static shared secret for remotes which use session keys.
+            None if remote.is_some_and(|remote| self.sessions.is_secured(&remote)) => {
+                trace!("Dropping packet for {dst_ip} until a new session key is established");
+                self.router.metrics().data_plane_unsecured_packet_dropped();
+                return None;
+            }
+            // Fall back to the static shared secret while no session key is negotiated.
+            None => shared_secret,
+        };
+
+        self.router.route_flow_packet(
+            DataPacket {
+                dst_ip,
+                src_ip,
+                hop_limit,
+                raw_data: self.encrypt(&key, packet),
+            },
+            flow,
+        );
 
         None
     }
 
+    /// Send a [`KeyExchange`] to a remote. This is always encrypted with the static shared
+    /// secret, which authenticates both nodes.
+    fn send_key_exchange(
+        &self,
+        remote: PublicKey,
+        shared_secret: &SharedSecret,
+        key_exchange: KeyExchange,
+    ) {
+        let mut packet = PacketBuffer::new();
+        let size = key_exchange.write_bytes(packet.buffer_mut());
+        packet.set_size(size);
+
+        let mut header = packet.header_mut();
+        header[0] = USER_DATA_VERSION;
+        header[1] = USER_DATA_KEY_EXCHANGE_TYPE;
+
+        self.router.route_packet(DataPacket {
+            dst_ip: remote.address(),
+            src_ip: self.router.node_public_key().address(),
+            hop_limit: MESSAGE_HOP_LIMIT,
+            raw_data: self.encrypt(shared_secret, packet),
+        });
+    }
+
+    /// Encrypt a packet with the given key, including the next replay counter in the nonce.
+    fn encrypt(&self, key: &SharedSecret, mut packet: PacketBuffer) -> Vec<u8> {
+        packet.header_mut()[2] |= USER_DATA_REPLAY_COUNTER_FLAG;
+        key.encrypt_with_counter(packet, self.replay_counter.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Create an ICMPv6 Packet Too Big message for a packet which exceeds the given MTU. The
+    /// message is sent from our node address to `dst_ip`, and contains as much of the original
+    /// packet as allowed by the IPv6 minimum MTU.
+    fn packet_too_big(&self, dst_ip: Ipv6Addr, mtu: usize, packet: &[u8]) -> Option<PacketBuffer> {
+        let mut pb = PacketBuffer::new();
+        let host = self.router.node_public_key().address().octets();
+        let icmp = PacketBuilder::ipv6(host, dst_ip.octets(), 64)
+            .icmpv6(Icmpv6Type::PacketTooBig { mtu: mtu as u32 });
+        let orig_buf_end = packet
+            .len()
+            .min(MIN_IPV6_MTU - IPV6_MIN_HEADER_SIZE - ICMP6_HEADER_SIZE);
+        pb.set_size(icmp.size(orig_buf_end));
+        let mut b = pb.buffer_mut();
+        if let Err(e) = icmp.write(&mut b, &packet[..orig_buf_end]) {
+            error!("Failed to construct packet too big ICMP packet {e}");
+            return None;
+        }
+
+        Some(pb)
+    }
+
+    /// Get the path MTU learned from the remote owning the destination, if it is not expired.
+    fn path_mtu(&self, dst_ip: Ipv6Addr) -> Option<usize> {
+        let remote = self.router.get_pubkey(dst_ip.into())?;
+        let mut path_mtus = self.path_mtus.lock().unwrap();
+        match path_mtus.get(&remote) {
+            Some((mtu, learned)) if learned.elapsed() < PATH_MTU_TIMEOUT => Some(*mtu),
+            Some(_) => {
+                path_mtus.remove(&remote);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Record the MTU of a Packet Too Big sent by a remote. The path MTU only decreases, until the
+    /// learned value expires.
+    fn learn_path_mtu(&self, remote: PublicKey, mtu: usize) {
+        let mtu = mtu.max(MIN_IPV6_MTU);
+        let mut path_mtus = self.path_mtus.lock().unwrap();
+        let entry = path_mtus.entry(remote).or_insert((mtu, Instant::now()));
+        if mtu <= entry.0 || entry.1.elapsed() >= PATH_MTU_TIMEOUT {
+            *entry = (mtu, Instant::now());
+        }
+    }
+
+    /// Check if a packet from the remote with the given replay counter was not received before
+    /// with the same key. `session_key` is the session key which decrypted the packet, or [`None`]
+    /// if it was decrypted with the static shared secret. This must only be called once the
+    /// packet is decrypted, so the counter is authenticated.
+    fn accept_replay_counter(
+        &self,
+        remote: PublicKey,
+        session_key: Option<&SharedSecret>,
+        counter: u64,
+    ) -> bool {
+        if let Some(key) = session_key {
+            return self.sessions.accept_replay_counter(&remote, key, counter);
+        }
+
+        self.replay_windows.lock().unwrap().accept(remote, counter)
+    }
+
+    /// Periodic task to remove the replay windows of remotes which did not send packets encrypted
+    /// with the static shared secret for some time. The highest counter of a removed window is
+    /// kept, so older packets are still rejected.
+    async fn sweep_replay_windows(replay_windows: Arc<Mutex<ReplayWindows>>) {
+        let mut interval = tokio::time::interval(REPLAY_WINDOW_SWEEP_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            trace!("Removing idle replay windows");
+
+            replay_windows
+                .lock()
+                .unwrap()
+                .remove_idle(REPLAY_WINDOW_TIMEOUT);
+        }
+    }
+
     async fn extract_packet_loop<T, U>(
         self,
         mut l3_packet_sink: T,
@@ -267,29 +508,70 @@ where
     {
         while let Some(data_packet) = host_packet_source.recv().await {
             // decrypt & send to TUN interface
-            let shared_secret = if let Some(ss) = self
-                .router
-                .get_shared_secret_from_dest(data_packet.src_ip.into())
-            {
-                ss
-            } else {
-                trace!("Received packet from unknown sender");
-                continue;
-            };
-            let mut decrypted_packet = match shared_secret.decrypt(data_packet.raw_data) {
-                Ok(data) => data,
-                Err(_) => {
-                    debug!("Dropping data packet with invalid encrypted content");
+            let (shared_secret, remote) = match (
+                self.router
+                    .get_shared_secret_from_dest(data_packet.src_ip.into()),
+                self.router.get_pubkey(data_packet.src_ip.into()),
+            ) {
+                (Some(ss), Some(remote)) => (ss, remote),
+                _ => {
+                    trace!("Received packet from unknown sender");
                     continue;
                 }
             };
+            // The counter can only be trusted once the packet is decrypted.
+            let replay_counter = crypto::nonce_counter(&data_packet.raw_data);
+            // Try the session keys first, and fall back to the static shared secret.
+            let (mut decrypted_packet, session_key) =
+                match self.sessions.decrypt(&remote, data_packet.raw_data) {
+                    Ok((data, key)) => (data, Some(key)),
+                    Err(raw_data) => match shared_secret.decrypt(raw_data) {
+                        Ok(data) => (data, None),
+                        Err(_) => {
+                            debug!("Dropping data packet with invalid encrypted content");
+                            continue;
+                        }
+                    },
+                };
 
             // Check header
             let header = decrypted_packet.header();
-            if header[0] != USER_DATA_VERSION {
+            if header[0] & !USER_DATA_SESSION_KEY_FLAG != USER_DATA_VERSION {
                 trace!("Dropping decrypted packet with unknown header version");
                 continue;
             }
+            if (header[0] & USER_DATA_SESSION_KEY_FLAG != 0) != session_key.is_some() {
+                trace!("Dropping decrypted packet which was encrypted with an unexpected key");
+                continue;
+            }
+            // Once a remote used a session key, only key exchanges can use the static secret.
+            if session_key.is_none()
+                && header[1] != USER_DATA_KEY_EXCHANGE_TYPE
+                && self.sessions.is_secured(&remote)
+            {
+                debug!(
+                    "Dropping packet from {} encrypted with the static secret instead of a session key",
+                    data_packet.src_ip
+                );
+                self.router.metrics().data_plane_unsecured_packet_dropped();
+                continue;
+            }
+            // Packets from older nodes don't have a replay counter.
+            let replay_counter =
+                replay_counter.filter(|_| header[2] & USER_DATA_REPLAY_COUNTER_FLAG != 0);
+            // Key exchanges are not checked, so a remote which restarted with a lower counter can
+            // always negotiate a new session key. Replayed key exchanges are detected by the
+            // session cache instead.
+            if header[2] & USER_DATA_REPLAY_COUNTER_FLAG != 0
+                && header[1] != USER_DATA_KEY_EXCHANGE_TYPE
+                && !replay_counter.is_some_and(|counter| {
+                    self.accept_replay_counter(remote, session_key.as_ref(), counter)
+                })
+            {
+                trace!("Dropping replayed packet from {}", data_packet.src_ip);
+                self.router.metrics().data_plane_replayed_packet_dropped();
+                continue;
+            }
 
             // Route based on packet type.
             match header[1] {
@@ -301,6 +583,39 @@ where
                         );
                         continue;
                     }
+                    if real_packet.len() > self.tun_mtu {
+                        // Let the sender know the largest packet we can deliver, so the host
+                        // retransmits with a lower MTU.
+                        trace!(
+                            "Packet from {} exceeds TUN MTU {}",
+                            data_packet.src_ip,
+                            self.tun_mtu
+                        );
+                        let inner_src = Ipv6Addr::from(
+                            <&[u8] as TryInto<[u8; 16]>>::try_into(&real_packet[8..24])
+                                .expect("Static range bounds on slice are correct length"),
+                        );
+                        if let Some(mut icmp) =
+                            self.packet_too_big(inner_src, self.tun_mtu, real_packet)
+                        {
+                            let mut header = icmp.header_mut();
+                            header[0] = USER_DATA_VERSION;
+                            header[1] = USER_DATA_L3_TYPE;
+                            let node_ip = self.router.node_public_key().address();
+                            self.encrypt_and_route_packet(
+                                node_ip,
+                                inner_src,
+                                64,
+                                Flow::new(node_ip, inner_src),
+                                icmp,
+                            );
+                        }
+                        continue;
+                    }
+                    if let Some(mtu) = packet_too_big_mtu(real_packet, remote.address()) {
+                        trace!("Learned path MTU {mtu} from {}", data_packet.src_ip);
+                        self.learn_path_mtu(remote, mtu);
+                    }
                     // Adjust the hop limit in the decrypted packet to the new value.
                     real_packet[7] = data_packet.hop_limit;
                     if let Err(e) = l3_packet_sink.send(decrypted_packet).await {
@@ -339,13 +654,16 @@ where
                     );
                     trace!("ICMP for original target {dec_ip}");
 
-                    let key =
-                        if let Some(key) = self.router.get_shared_secret_from_dest(dec_ip.into()) {
-                            key
-                        } else {
+                    let (key, target) = match (
+                        self.router.get_shared_secret_from_dest(dec_ip.into()),
+                        self.router.get_pubkey(dec_ip.into()),
+                    ) {
+                        (Some(key), Some(target)) => (key, target),
+                        _ => {
                             debug!("Can't decrypt OOB ICMP packet from unknown host");
                             continue;
-                        };
+                        }
+                    };
 
                     let (_, body) = match etherparse::IpHeaders::from_slice(&real_packet[16..]) {
                         Ok(r) => r,
@@ -373,7 +691,12 @@ where
                     };
 
                     // Where are the leftover bytes coming from
-                    let orig_pb = match key.decrypt(body[..body.len()].to_vec()) {
+                    let orig_pb = match self
+                        .sessions
+                        .decrypt(&target, body[..body.len()].to_vec())
+                        .map(|(pb, _)| pb)
+                        .or_else(|data| key.decrypt(data))
+                    {
                         Ok(pb) => pb,
                         Err(e) => {
                             warn!("Failed to decrypt ICMP data body {e}");
@@ -402,6 +725,28 @@ where
                         continue;
                     }
                 }
+                USER_DATA_KEY_EXCHANGE_TYPE => {
+                    // Key exchanges must be authenticated with the static shared secret.
+                    if session_key.is_some() {
+                        debug!("Dropping key exchange encrypted with a session key");
+                        continue;
+                    }
+                    let Some(key_exchange) = KeyExchange::from_bytes(&decrypted_packet) else {
+                        debug!(
+                            "Dropping malformed key exchange from {}",
+                            data_packet.src_ip
+                        );
+                        continue;
+                    };
+                    if let Some(response) = self.sessions.handle_key_exchange(
+                        &remote,
+                        &shared_secret,
+                        key_exchange,
+                        replay_counter,
+                    ) {
+                        self.send_key_exchange(remote, &shared_secret, response);
+                    }
+                }
                 _ => {
                     trace!("Dropping decrypted packet with unknown protocol type");
                     continue;
@@ -420,6 +765,80 @@ where
     fn clone(&self) -> Self {
         Self {
             router: self.router.clone(),
+            sessions: self.sessions.clone(),
+            replay_counter: self.replay_counter.clone(),
+            replay_windows: self.replay_windows.clone(),
+            tun_mtu: self.tun_mtu,
+            path_mtus: self.path_mtus.clone(),
         }
     }
 }
+
+/// Get the MTU of an IPv6 packet containing an ICMPv6 Packet Too Big message sent by the node
+/// with the given overlay address. If the packet is not a Packet Too Big message, it has extension
+/// headers, or it is sent by another host in the subnet of the node, [`None`] is returned. Only the
+/// node itself is trusted to report its MTU, otherwise any host in its subnet could lower the path
+/// MTU towards it.
+fn packet_too_big_mtu(packet: &[u8], remote_address: Ipv6Addr) -> Option<usize> {
+    if packet.len() < IPV6_MIN_HEADER_SIZE + ICMP6_HEADER_SIZE
+        || packet[6] != IPV6_NEXT_HEADER_ICMP6
+        || packet[IPV6_MIN_HEADER_SIZE] != ICMP6_PACKET_TOO_BIG_TYPE
+        || packet[8..24] != remote_address.octets()
+    {
+        return None;
+    }
+
+    // The MTU follows the type, code and checksum fields of the ICMPv6 header.
+    let mtu_start = IPV6_MIN_HEADER_SIZE + 4;
+    Some(u32::from_be_bytes(
+        packet[mtu_start..mtu_start + 4]
+            .try_into()
+            .expect("Static range bounds on slice are correct length"),
+    ) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use crate::crypto::{PublicKey, SecretKey};
+
+    use super::{
+        packet_too_big_mtu, ICMP6_HEADER_SIZE, ICMP6_PACKET_TOO_BIG_TYPE, IPV6_MIN_HEADER_SIZE,
+        IPV6_NEXT_HEADER_ICMP6,
+    };
+
+    /// Create an IPv6 packet from `src` containing an ICMPv6 Packet Too Big with the given MTU.
+    fn packet_too_big(src: Ipv6Addr, mtu: u32) -> Vec<u8> {
+        let mut packet = vec![0; IPV6_MIN_HEADER_SIZE + ICMP6_HEADER_SIZE];
+        packet[0] = 6 << 4;
+        packet[6] = IPV6_NEXT_HEADER_ICMP6;
+        packet[8..24].copy_from_slice(&src.octets());
+        packet[IPV6_MIN_HEADER_SIZE] = ICMP6_PACKET_TOO_BIG_TYPE;
+        packet[IPV6_MIN_HEADER_SIZE + 4..IPV6_MIN_HEADER_SIZE + 8]
+            .copy_from_slice(&mtu.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn packet_too_big_from_remote_is_accepted() {
+        let remote = PublicKey::from(&SecretKey::new()).address();
+
+        assert_eq!(
+            packet_too_big_mtu(&packet_too_big(remote, 1400), remote),
+            Some(1400)
+        );
+    }
+
+    #[test]
+    fn packet_too_big_from_other_host_in_subnet_is_ignored() {
+        let remote = PublicKey::from(&SecretKey::new()).address();
+        let mut other = remote.octets();
+        other[15] ^= 1;
+
+        assert_eq!(
+            packet_too_big_mtu(&packet_too_big(Ipv6Addr::from(other), 1280), remote),
+            None
+        );
+    }
+}
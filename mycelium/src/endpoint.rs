@@ -1,5 +1,5 @@
 use std::{
-    fmt,
+    fmt, io,
     net::{AddrParseError, SocketAddr},
     str::FromStr,
 };
@@ -15,6 +15,8 @@ pub enum EndpointParseError {
     UnknownProtocol,
     /// Error while parsing the specific address.
     Address(AddrParseError),
+    /// The port of a hostname endpoint is not valid.
+    InvalidPort,
 }
 
 /// Protocol used by an endpoint.
@@ -27,20 +29,48 @@ pub enum Protocol {
     Tls,
     /// Quic protocol (over UDP).
     Quic,
+    /// Plain text WebSocket, over Tcp.
+    Ws,
+    /// WebSocket over Tls. The certificate of the remote is verified against the bundled Mozilla
+    /// root certificates.
+    Wss,
 }
 
-/// An endpoint defines a address and a protocol to use when communicating with it.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+/// An endpoint defines a address and a protocol to use when communicating with it. The address is
+/// either a [`SocketAddr`], or a hostname and port which is resolved every time the endpoint is
+/// connected to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "EndpointRepr", into = "EndpointRepr")]
 pub struct Endpoint {
     proto: Protocol,
-    socket_addr: SocketAddr,
+    host: Host,
+}
+
+/// The address part of an [`Endpoint`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Host {
+    /// A fixed socket address.
+    Addr(SocketAddr),
+    /// A hostname which needs to be resolved, and a port.
+    Name(String, u16),
+}
+
+/// Serialized form of an [`Endpoint`]. For hostname endpoints, the socket address is formatted as
+/// `hostname:port`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EndpointRepr {
+    proto: Protocol,
+    socket_addr: String,
 }
 
 impl Endpoint {
     /// Create a new `Endpoint` with given [`Protocol`] and address.
     pub fn new(proto: Protocol, socket_addr: SocketAddr) -> Self {
-        Self { proto, socket_addr }
+        Self {
+            proto,
+            host: Host::Addr(socket_addr),
+        }
     }
 
     /// Get the [`Protocol`] used by this `Endpoint`.
@@ -48,10 +78,63 @@ impl Endpoint {
         self.proto
     }
 
-    /// Get the [`SocketAddr`] used by this `Endpoint`.
-    pub fn address(&self) -> SocketAddr {
-        self.socket_addr
+    /// Get the [`Host`] used by this `Endpoint`.
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// Get the [`SocketAddr`] used by this `Endpoint`. Returns [`Option::None`] if the endpoint
+    /// uses a hostname.
+    pub fn address(&self) -> Option<SocketAddr> {
+        match self.host {
+            Host::Addr(socket_addr) => Some(socket_addr),
+            Host::Name(..) => None,
+        }
+    }
+
+    /// Get the hostname used by this `Endpoint`, if it uses one.
+    pub fn hostname(&self) -> Option<&str> {
+        match &self.host {
+            Host::Addr(_) => None,
+            Host::Name(hostname, _) => Some(hostname),
+        }
+    }
+
+    /// Resolve the addresses of this `Endpoint`. For hostname endpoints, this returns all IPv4 and
+    /// IPv6 addresses the hostname currently resolves to.
+    pub async fn resolve(&self) -> Result<Vec<SocketAddr>, io::Error> {
+        match &self.host {
+            Host::Addr(socket_addr) => Ok(vec![*socket_addr]),
+            Host::Name(hostname, port) => Ok(tokio::net::lookup_host((hostname.as_str(), *port))
+                .await?
+                .collect()),
+        }
+    }
+}
+
+/// Checks if the given string is a valid hostname. To avoid treating malformed IP addresses as
+/// hostnames, the last label can't be numeric.
+fn is_valid_hostname(hostname: &str) -> bool {
+    let hostname = hostname.strip_suffix('.').unwrap_or(hostname);
+    if hostname.is_empty() || hostname.len() > 253 {
+        return false;
     }
+
+    let labels_valid = hostname.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    });
+
+    labels_valid
+        && !hostname
+            .rsplit('.')
+            .next()
+            .is_some_and(|tld| tld.bytes().all(|b| b.is_ascii_digit()))
 }
 
 impl FromStr for Endpoint {
@@ -65,10 +148,21 @@ impl FromStr for Endpoint {
                     "tcp" => Protocol::Tcp,
                     "quic" => Protocol::Quic,
                     "tls" => Protocol::Tls,
+                    "ws" => Protocol::Ws,
+                    "wss" => Protocol::Wss,
                     _ => return Err(EndpointParseError::UnknownProtocol),
                 };
-                let socket_addr = SocketAddr::from_str(socket)?;
-                Ok(Endpoint { proto, socket_addr })
+                let host = match SocketAddr::from_str(socket) {
+                    Ok(socket_addr) => Host::Addr(socket_addr),
+                    Err(e) => match socket.rsplit_once(':') {
+                        Some((hostname, port)) if is_valid_hostname(hostname) => Host::Name(
+                            hostname.to_lowercase(),
+                            port.parse().map_err(|_| EndpointParseError::InvalidPort)?,
+                        ),
+                        _ => return Err(e.into()),
+                    },
+                };
+                Ok(Endpoint { proto, host })
             }
         }
     }
@@ -76,7 +170,38 @@ impl FromStr for Endpoint {
 
 impl fmt::Display for Endpoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_fmt(format_args!("{} {}", self.proto, self.socket_addr))
+        f.write_fmt(format_args!("{} {}", self.proto, self.host))
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Addr(socket_addr) => socket_addr.fmt(f),
+            Self::Name(hostname, port) => f.write_fmt(format_args!("{hostname}:{port}")),
+        }
+    }
+}
+
+impl From<Endpoint> for EndpointRepr {
+    fn from(value: Endpoint) -> Self {
+        Self {
+            proto: value.proto,
+            socket_addr: value.host.to_string(),
+        }
+    }
+}
+
+impl TryFrom<EndpointRepr> for Endpoint {
+    type Error = EndpointParseError;
+
+    fn try_from(value: EndpointRepr) -> Result<Self, Self::Error> {
+        // Reuse the parsing logic, the protocol is already known.
+        let endpoint = Endpoint::from_str(&format!("tcp://{}", value.socket_addr))?;
+        Ok(Endpoint {
+            proto: value.proto,
+            host: endpoint.host,
+        })
     }
 }
 
@@ -86,6 +211,8 @@ impl fmt::Display for Protocol {
             Self::Tcp => "Tcp",
             Self::Tls => "Tls",
             Self::Quic => "Quic",
+            Self::Ws => "Ws",
+            Self::Wss => "Wss",
         })
     }
 }
@@ -96,6 +223,7 @@ impl fmt::Display for EndpointParseError {
             Self::MissingProtocol => f.write_str("missing leading protocol identifier"),
             Self::UnknownProtocol => f.write_str("protocol for endpoint is not supported"),
             Self::Address(e) => f.write_fmt(format_args!("failed to parse address: {}", e)),
+            Self::InvalidPort => f.write_str("invalid port for hostname"),
         }
     }
 }
@@ -114,3 +242,75 @@ impl From<AddrParseError> for EndpointParseError {
         Self::Address(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, str::FromStr};
+
+    use super::{Endpoint, EndpointParseError, EndpointRepr, Protocol};
+
+    #[test]
+    fn parse_socket_address() {
+        let endpoint = Endpoint::from_str("tcp://[2001:db8::1]:9651").unwrap();
+
+        assert_eq!(endpoint.proto(), Protocol::Tcp);
+        assert_eq!(
+            endpoint.address(),
+            Some(SocketAddr::from_str("[2001:db8::1]:9651").unwrap())
+        );
+        assert_eq!(endpoint.hostname(), None);
+    }
+
+    #[test]
+    fn parse_hostname() {
+        let endpoint = Endpoint::from_str("quic://Peer.Example.org:9651").unwrap();
+
+        assert_eq!(endpoint.proto(), Protocol::Quic);
+        assert_eq!(endpoint.address(), None);
+        assert_eq!(endpoint.hostname(), Some("peer.example.org"));
+        assert_eq!(endpoint.to_string(), "Quic peer.example.org:9651");
+    }
+
+    #[test]
+    fn reject_invalid_hosts() {
+        assert!(matches!(
+            Endpoint::from_str("tcp://peer.example.org:96510"),
+            Err(EndpointParseError::InvalidPort)
+        ));
+        assert!(matches!(
+            Endpoint::from_str("tcp://peer.example.org"),
+            Err(EndpointParseError::Address(_))
+        ));
+        assert!(matches!(
+            Endpoint::from_str("tcp://192.0.2.256:9651"),
+            Err(EndpointParseError::Address(_))
+        ));
+        assert!(matches!(
+            Endpoint::from_str("tcp://peer_1.example.org:9651"),
+            Err(EndpointParseError::Address(_))
+        ));
+    }
+
+    #[test]
+    fn repr_roundtrip() {
+        for s in [
+            "tcp://192.0.2.1:9651",
+            "tls://peer.example.org:9651",
+            "wss://peer.example.org:443",
+        ] {
+            let endpoint = Endpoint::from_str(s).unwrap();
+            let repr = EndpointRepr::from(endpoint.clone());
+            assert_eq!(Endpoint::try_from(repr).unwrap(), endpoint);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_socket_address() {
+        let endpoint = Endpoint::from_str("tcp://192.0.2.1:9651").unwrap();
+
+        assert_eq!(
+            endpoint.resolve().await.unwrap(),
+            vec![SocketAddr::from_str("192.0.2.1:9651").unwrap()]
+        );
+    }
+}
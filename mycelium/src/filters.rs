@@ -1,4 +1,4 @@
-use crate::{babel, subnet::Subnet};
+use crate::{babel, peer::Peer, subnet::Subnet, MAX_DERIVED_SUBNETS};
 
 /// This trait is used to filter incoming updates from peers. Only updates which pass all
 /// configured filters on the local [`Router`](crate::router::Router) will actually be forwarded
@@ -6,6 +6,24 @@ use crate::{babel, subnet::Subnet};
 pub trait RouteUpdateFilter {
     /// Judge an incoming update.
     fn allow(&self, update: &babel::Update) -> bool;
+
+    /// Judge an incoming update received from the given neighbour. Filters can modify the update
+    /// before it is processed, e.g. to change the metric.
+    ///
+    /// The default implementation defers to [`RouteUpdateFilter::allow`].
+    fn apply(&self, update: &mut babel::Update, neighbour: &Peer) -> bool {
+        let _ = neighbour;
+        self.allow(update)
+    }
+}
+
+/// This trait is used to filter outgoing updates to peers. Only updates which pass all configured
+/// export filters on the local [`Router`](crate::router::Router) are actually sent to the peer.
+pub trait RouteExportFilter {
+    /// Judge an outgoing update to the given peer. Filters can modify the update before it is
+    /// sent. Since a filter can only increase the metric of an update, route selection on the
+    /// receiving side stays loop free.
+    fn apply(&self, update: &mut babel::Update, peer: &Peer) -> bool;
 }
 
 /// Limit the subnet size of subnets announced in updates to be at most `N` bits. Note that "at
@@ -37,7 +55,9 @@ impl RouteUpdateFilter for AllowedSubnet {
     }
 }
 
-/// Limit the announced subnets to those which contain the derived IP from the `RouterId`.
+/// Limit the announced subnets to those which contain the derived IP from the `RouterId`, or
+/// are one of the [additional subnets](crate::crypto::PublicKey::derived_address) of the
+/// `RouterId`.
 ///
 /// Since retractions can be sent by any node to indicate they don't have a route for the subnet,
 /// these are also allowed.
@@ -45,9 +65,17 @@ pub struct RouterIdOwnsSubnet;
 
 impl RouteUpdateFilter for RouterIdOwnsSubnet {
     fn allow(&self, update: &babel::Update) -> bool {
-        update.metric().is_infinite()
-            || update
-                .subnet()
-                .contains_ip(update.router_id().to_pubkey().address().into())
+        if update.metric().is_infinite() {
+            return true;
+        }
+
+        let subnet = update.subnet();
+        let pubkey = update.router_id().to_pubkey();
+        subnet.contains_ip(pubkey.address().into())
+            || (1..=MAX_DERIVED_SUBNETS).any(|index| {
+                Subnet::new(pubkey.derived_address(index).into(), 64)
+                    .expect("64 is a valid IPv6 prefix size; qed")
+                    .contains_subnet(&subnet)
+            })
     }
 }
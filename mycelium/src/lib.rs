@@ -1,4 +1,7 @@
-use std::net::{IpAddr, Ipv6Addr};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+};
 #[cfg(feature = "message")]
 use std::{future::Future, time::Duration};
 
@@ -17,6 +20,7 @@ use subnet::Subnet;
 use tracing::{error, info, warn};
 
 mod babel;
+mod buffer_pool;
 mod connection;
 pub mod crypto;
 pub mod data;
@@ -29,12 +33,20 @@ mod metric;
 pub mod metrics;
 pub mod packet;
 mod peer;
+pub mod peer_acl;
 pub mod peer_manager;
+pub mod policy;
+pub mod proxy;
+mod replay_window;
 pub mod router;
 mod router_id;
 mod routing_table;
 mod seqno_cache;
 mod sequence_number;
+mod session;
+mod signature_cache;
+#[cfg(any(test, feature = "test-support"))]
+pub mod sim;
 mod source_table;
 pub mod subnet;
 pub mod task;
@@ -44,6 +56,15 @@ mod tun;
 pub const GLOBAL_SUBNET_ADDRESS: IpAddr = IpAddr::V6(Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 0));
 /// The prefix length of the global subnet used.
 pub const GLOBAL_SUBNET_PREFIX_LEN: u8 = 7;
+/// The maximum amount of additional subnets a node can announce, besides its own subnet.
+pub const MAX_DERIVED_SUBNETS: u16 = 16;
+/// The default MTU of the TUN interface.
+pub const DEFAULT_TUN_MTU: u16 = crypto::DEFAULT_PACKET_SIZE as u16;
+/// The minimum MTU of the TUN interface. This is the minimum MTU required by IPv6.
+pub const MIN_TUN_MTU: u16 = 1280;
+/// The maximum MTU of the TUN interface. Some platforms (macOS) prepend a 4 byte header to
+/// packets read from the TUN interface, so room for that is reserved in a packet buffer.
+pub const MAX_TUN_MTU: u16 = (crypto::MAX_PACKET_SIZE - 4) as u16;
 
 /// Config for a mycelium [`Node`].
 pub struct Config<M> {
@@ -51,17 +72,43 @@ pub struct Config<M> {
     pub node_key: crypto::SecretKey,
     /// Statically configured peers.
     pub peers: Vec<Endpoint>,
+    /// Cost added to the link cost of statically configured peers, to make them more or less
+    /// preferable. Peers which are not in here don't have an additional cost.
+    pub peer_link_cost_offsets: HashMap<Endpoint, u16>,
     /// Tun interface should be disabled.
     pub no_tun: bool,
     /// Listen port for TCP connections.
     pub tcp_listen_port: u16,
     /// Listen port for Quic connections.
     pub quic_listen_port: Option<u16>,
+    /// Listen port for WebSocket connections. No WebSocket listener is started if this is not
+    /// set.
+    pub ws_listen_port: Option<u16>,
+    /// HTTP proxy which outbound WebSocket connections are tunneled through. If this is not set,
+    /// the proxies in the `HTTP_PROXY` and `HTTPS_PROXY` environment variables are used, see
+    /// [`ProxyConfig::from_env`](proxy::ProxyConfig::from_env).
+    pub ws_proxy: Option<proxy::HttpProxy>,
     /// Udp port for peer discovery.
     pub peer_discovery_port: Option<u16>,
+    /// Use UDP hole punching to connect directly to remotes behind NAT, which are reachable
+    /// through a neighbour. This requires quic to be enabled.
+    pub hole_punching: bool,
+    /// Enable peer exchange. The endpoints of our outbound peers are shared with neighbours, and
+    /// endpoints shared by neighbours are connected to until there are at least this many outbound
+    /// peers.
+    pub peer_exchange_target: Option<usize>,
     /// Name for the TUN device.
     #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
     pub tun_name: String,
+    /// MTU of the TUN interface, between [`MIN_TUN_MTU`] and [`MAX_TUN_MTU`]. Packets from
+    /// remotes which are larger than this are answered with an ICMPv6 Packet Too Big. Only the
+    /// MTU of the receiving node is learned from those, not the limits of the peer links along
+    /// the path.
+    pub tun_mtu: u16,
+    /// Use TCP segmentation offload on the TUN interface. The kernel hands over large TCP segments,
+    /// which are split in packets which fit the MTU, and consecutive TCP segments from remotes are
+    /// coalesced before they are handed to the kernel. This is only supported on Linux.
+    pub tun_offload: bool,
 
     /// Configuration for a private network, if run in that mode. To enable private networking,
     /// this must be a name + a PSK.
@@ -84,6 +131,25 @@ pub struct Config<M> {
     /// set this to a value which is higher than the amount of logical CPU cores available to the
     /// system.
     pub update_workers: usize,
+
+    /// Amount of additional subnets, derived from the node key, which are announced by the node.
+    /// Traffic for these subnets is delivered to the TUN interface, so they can be routed to
+    /// e.g. containers or VMs on the host. At most [`MAX_DERIVED_SUBNETS`] can be announced.
+    pub derived_subnets: u16,
+
+    /// Configuration of the timers used by the router.
+    pub router_config: router::RouterConfig,
+
+    /// Operator defined policy applied to imported and exported routes.
+    pub route_policy: policy::RoutePolicy,
+
+    /// Access control list applied to inbound peer connections.
+    pub peer_acl: peer_acl::PeerAcl,
+
+    /// Accept peers running an older version, which does not support the link handshake, and
+    /// connect to them without it. Such peers don't have a verified public key. This is meant for
+    /// the transition to authenticated links, and will be disabled by default in a future release.
+    pub legacy_peers: bool,
 }
 
 /// The Node is the main structure in mycelium. It governs the entire data flow.
@@ -98,6 +164,8 @@ pub struct Node<M> {
 pub struct NodeInfo {
     /// The overlay subnet in use by the node.
     pub node_subnet: Subnet,
+    /// Additional subnets announced by the node.
+    pub derived_subnets: Vec<Subnet>,
 }
 
 impl<M> Node<M>
@@ -116,9 +184,30 @@ where
                 .into());
             }
         }
+        if config.derived_subnets > MAX_DERIVED_SUBNETS {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("at most {MAX_DERIVED_SUBNETS} derived subnets can be announced"),
+            )
+            .into());
+        }
+        if !(MIN_TUN_MTU..=MAX_TUN_MTU).contains(&config.tun_mtu) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("TUN MTU must be between {MIN_TUN_MTU} and {MAX_TUN_MTU}"),
+            )
+            .into());
+        }
+        config.router_config.validate()?;
+        #[cfg(not(target_os = "linux"))]
+        if config.tun_offload {
+            warn!("TUN offload is only supported on Linux, ignoring it");
+        }
+
         let node_pub_key = crypto::PublicKey::from(&config.node_key);
         let node_addr = node_pub_key.address();
         let (tun_tx, tun_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (peer_manager_tlv_tx, peer_manager_tlv_rx) = tokio::sync::mpsc::unbounded_channel();
 
         let node_subnet = Subnet::new(
             // Truncate last 64 bits of address.
@@ -130,21 +219,46 @@ where
         )
         .expect("64 is a valid IPv6 prefix size; qed");
 
+        let mut static_routes = vec![node_subnet];
+        for index in 1..=config.derived_subnets {
+            let subnet = Subnet::new(
+                Subnet::new(node_pub_key.derived_address(index).into(), 64)
+                    .expect("64 is a valid IPv6 prefix size; qed")
+                    .network(),
+                64,
+            )
+            .expect("64 is a valid IPv6 prefix size; qed");
+            info!("Announcing derived subnet {subnet}");
+            static_routes.push(subnet);
+        }
+
+        let mut update_filters: Vec<Box<dyn filters::RouteUpdateFilter + Send + Sync>> = vec![
+            Box::new(filters::AllowedSubnet::new(
+                Subnet::new(GLOBAL_SUBNET_ADDRESS, GLOBAL_SUBNET_PREFIX_LEN)
+                    .expect("Global subnet is properly defined; qed"),
+            )),
+            Box::new(filters::MaxSubnetSize::<64>),
+            Box::new(filters::RouterIdOwnsSubnet),
+        ];
+        let mut export_filters: Vec<Box<dyn filters::RouteExportFilter + Send + Sync>> = vec![];
+        if let Some(import_policy) = config.route_policy.import_filter() {
+            update_filters.push(Box::new(import_policy));
+        }
+        if let Some(export_policy) = config.route_policy.export_filter() {
+            export_filters.push(Box::new(export_policy));
+        }
+
         // Creating a new Router instance
         let router = match router::Router::new(
             config.update_workers,
+            config.router_config,
             tun_tx,
             node_subnet,
-            vec![node_subnet],
+            static_routes,
             (config.node_key, node_pub_key),
-            vec![
-                Box::new(filters::AllowedSubnet::new(
-                    Subnet::new(GLOBAL_SUBNET_ADDRESS, GLOBAL_SUBNET_PREFIX_LEN)
-                        .expect("Global subnet is properly defined; qed"),
-                )),
-                Box::new(filters::MaxSubnetSize::<64>),
-                Box::new(filters::RouterIdOwnsSubnet),
-            ],
+            update_filters,
+            export_filters,
+            peer_manager_tlv_tx,
             config.metrics.clone(),
         ) {
             Ok(router) => {
@@ -164,10 +278,21 @@ where
         let pm = peer_manager::PeerManager::new(
             router.clone(),
             config.peers,
+            config.peer_link_cost_offsets,
+            config.peer_acl,
+            config.legacy_peers,
             config.tcp_listen_port,
             config.quic_listen_port,
+            config.ws_listen_port,
+            config
+                .ws_proxy
+                .map(proxy::ProxyConfig::new)
+                .unwrap_or_else(proxy::ProxyConfig::from_env),
             config.peer_discovery_port.unwrap_or_default(),
             config.peer_discovery_port.is_none(),
+            peer_manager_tlv_rx,
+            config.hole_punching,
+            config.peer_exchange_target,
             config.private_network_config,
             config.metrics,
             config.firewall_mark,
@@ -193,6 +318,7 @@ where
                 futures::sink::drain(),
                 msg_sender,
                 tun_rx,
+                config.tun_mtu,
             )
         } else {
             #[cfg(not(any(
@@ -220,6 +346,8 @@ where
                         .expect("64 is a valid subnet size for IPv6; qed"),
                     route_subnet: Subnet::new(GLOBAL_SUBNET_ADDRESS, GLOBAL_SUBNET_PREFIX_LEN)
                         .expect("Static configured TUN route is valid; qed"),
+                    mtu: config.tun_mtu,
+                    offload: config.tun_offload,
                 };
                 #[cfg(any(target_os = "android", target_os = "ios"))]
                 let tun_config = TunConfig {
@@ -229,7 +357,14 @@ where
                 let (rxhalf, txhalf) = tun::new(tun_config).await?;
 
                 info!("Node overlay IP: {node_addr}");
-                DataPlane::new(router.clone(), rxhalf, txhalf, msg_sender, tun_rx)
+                DataPlane::new(
+                    router.clone(),
+                    rxhalf,
+                    txhalf,
+                    msg_sender,
+                    tun_rx,
+                    config.tun_mtu,
+                )
             }
         };
 
@@ -246,8 +381,16 @@ where
 
     /// Get information about the running `Node`
     pub fn info(&self) -> NodeInfo {
+        let node_subnet = self.router.node_tun_subnet();
         NodeInfo {
-            node_subnet: self.router.node_tun_subnet(),
+            node_subnet,
+            derived_subnets: self
+                .router
+                .static_routes()
+                .iter()
+                .filter(|subnet| **subnet != node_subnet)
+                .copied()
+                .collect(),
         }
     }
 
@@ -256,9 +399,32 @@ where
         self.peer_manager.peers()
     }
 
-    /// Add a new peer to the system identified by an [`Endpoint`].
-    pub fn add_peer(&self, endpoint: Endpoint) -> Result<(), PeerExists> {
-        self.peer_manager.add_peer(endpoint)
+    /// Add a new peer to the system identified by an [`Endpoint`]. The `link_cost_offset` is
+    /// added to the link cost of the peer.
+    pub fn add_peer(&self, endpoint: Endpoint, link_cost_offset: u16) -> Result<(), PeerExists> {
+        self.peer_manager.add_peer(endpoint, link_cost_offset)
+    }
+
+    /// Change the cost added to the link cost of an existing peer identified by an
+    /// [`Endpoint`].
+    pub fn set_peer_link_cost_offset(
+        &self,
+        endpoint: Endpoint,
+        link_cost_offset: u16,
+    ) -> Result<(), PeerNotFound> {
+        self.peer_manager
+            .set_link_cost_offset(&endpoint, link_cost_offset)
+    }
+
+    /// Get the access control list applied to inbound peer connections.
+    pub fn peer_acl(&self) -> peer_acl::PeerAcl {
+        self.peer_manager.peer_acl()
+    }
+
+    /// Replace the access control list applied to inbound peer connections. Connected inbound
+    /// peers which are not allowed by the new list are disconnected.
+    pub fn set_peer_acl(&self, peer_acl: peer_acl::PeerAcl) {
+        self.peer_manager.set_peer_acl(peer_acl)
     }
 
     /// Remove an existing peer identified by an [`Endpoint`] from the system.
@@ -276,6 +442,12 @@ where
         self.router.load_fallback_routes()
     }
 
+    /// Checks if the source of a [`RouteEntry`] is currently suppressed by route flap dampening.
+    /// Routes from a suppressed source are not selected.
+    pub fn is_route_suppressed(&self, route: &RouteEntry) -> bool {
+        self.router.is_source_suppressed(&route.source())
+    }
+
     /// Get public key from the IP of `Node`
     pub fn get_pubkey_from_ip(&self, ip: IpAddr) -> Option<crypto::PublicKey> {
         self.router.get_pubkey(ip)
@@ -2,7 +2,7 @@
 //! interest is the [`Metrics`] trait. Users can provide their own implementation of this, or use
 //! the default provided implementation to disable gathering metrics.
 
-use crate::peer_manager::PeerType;
+use crate::{peer_acl::RejectReason, peer_manager::PeerType};
 
 /// The collection of all metrics exported by a [`mycelium node`](crate::Node). It is up to the
 /// user to provide an implementation which implements the methods for metrics they are interested
@@ -163,11 +163,26 @@ pub trait Metrics {
     #[inline]
     fn router_update_denied_by_filter(&self) {}
 
+    /// An update was denied because its signature is invalid, or because it was not signed while
+    /// a signature is required.
+    #[inline]
+    fn router_update_invalid_signature(&self) {}
+
     /// An update was accepted by the router filters, but was otherwise unfeasible or a retraction,
     /// for an unknown subnet.
     #[inline]
     fn router_update_not_interested(&self) {}
 
+    /// The [`DataPlane`](crate::data::DataPlane) dropped a data packet because it was already
+    /// received before, or because it is too old to verify that it was not.
+    #[inline]
+    fn data_plane_replayed_packet_dropped(&self) {}
+
+    /// The [`DataPlane`](crate::data::DataPlane) dropped a data packet because it was, or would
+    /// be, encrypted with the static shared secret, while the remote is known to use session keys.
+    #[inline]
+    fn data_plane_unsecured_packet_dropped(&self) {}
+
     /// A new [`Peer`](crate::peer::Peer) was added to the
     /// [`PeerManager`](crate::peer_manager::PeerManager) while it is running.
     #[inline]
@@ -187,4 +202,9 @@ pub trait Metrics {
     /// remote endpoint. The connection could have failed.
     #[inline]
     fn peer_manager_connection_finished(&self) {}
+
+    /// The [`PeerManager`](crate::peer_manager::PeerManager) rejected an inbound connection
+    /// because of the configured [`PeerAcl`](crate::peer_acl::PeerAcl).
+    #[inline]
+    fn peer_manager_inbound_connection_rejected(&self, _reason: RejectReason) {}
 }
@@ -1,4 +1,4 @@
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 pub use control::ControlPacket;
 pub use data::DataPacket;
 use tokio_util::codec::{Decoder, Encoder};
@@ -127,8 +127,76 @@ impl Encoder<Packet> for Codec {
     }
 }
 
+/// The size of a [`DataPacket`] when it is sent as a datagram, see [`encode_datagram`].
+pub fn datagram_len(packet: &DataPacket) -> usize {
+    PACKET_HEADER_SIZE + packet.encoded_len()
+}
+
+/// Encode a [`DataPacket`] as a single datagram. The datagram has the same format as the packet
+/// would have on a stream.
+pub fn encode_datagram(packet: DataPacket) -> Result<Bytes, std::io::Error> {
+    let mut dst = BytesMut::with_capacity(datagram_len(&packet));
+    Codec::new().encode(Packet::DataPacket(packet), &mut dst)?;
+    Ok(dst.freeze())
+}
+
+/// Decode a datagram created by [`encode_datagram`]. The datagram must contain exactly one
+/// [`DataPacket`].
+pub fn decode_datagram(datagram: Bytes) -> Result<DataPacket, std::io::Error> {
+    let mut src = BytesMut::from(&datagram[..]);
+    match Codec::new().decode(&mut src)? {
+        Some(Packet::DataPacket(packet)) if src.is_empty() => Ok(packet),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Datagram does not contain a single data packet",
+        )),
+    }
+}
+
 impl Default for Codec {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::{datagram_len, decode_datagram, encode_datagram, DataPacket};
+
+    fn data_packet() -> DataPacket {
+        DataPacket {
+            raw_data: vec![1, 2, 3, 4, 5],
+            hop_limit: 64,
+            src_ip: Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 1),
+            dst_ip: Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 2),
+        }
+    }
+
+    #[test]
+    fn datagram_roundtrip() {
+        let packet = data_packet();
+        let len = datagram_len(&packet);
+
+        let datagram = encode_datagram(packet).unwrap();
+        assert_eq!(datagram.len(), len);
+
+        let decoded = decode_datagram(datagram).unwrap();
+        assert_eq!(decoded.raw_data, vec![1, 2, 3, 4, 5]);
+        assert_eq!(decoded.hop_limit, 64);
+        assert_eq!(decoded.src_ip, Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 1));
+        assert_eq!(decoded.dst_ip, Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 2));
+    }
+
+    #[test]
+    fn reject_truncated_and_trailing_datagrams() {
+        let datagram = encode_datagram(data_packet()).unwrap();
+
+        assert!(decode_datagram(datagram.slice(..datagram.len() - 1)).is_err());
+
+        let mut long = datagram.to_vec();
+        long.push(0);
+        assert!(decode_datagram(long.into()).is_err());
+    }
+}
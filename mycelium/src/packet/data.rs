@@ -3,6 +3,8 @@ use std::net::Ipv6Addr;
 use bytes::{Buf, BufMut, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
+use crate::buffer_pool;
+
 /// Size of the header start for a data packet (before the IP addresses).
 const DATA_PACKET_HEADER_SIZE: usize = 4;
 
@@ -18,6 +20,13 @@ pub struct DataPacket {
     pub dst_ip: Ipv6Addr,
 }
 
+impl DataPacket {
+    /// The size of this `DataPacket` when it is encoded.
+    pub fn encoded_len(&self) -> usize {
+        DATA_PACKET_HEADER_SIZE + 16 + 16 + self.raw_data.len()
+    }
+}
+
 pub struct Codec {
     header_vals: Option<HeaderValues>,
     src_ip: Option<Ipv6Addr>,
@@ -112,9 +121,10 @@ impl Decoder for Codec {
             return Ok(None);
         }
 
-        // Decode octets
-        let mut data = vec![0u8; data_len];
-        data.copy_from_slice(&src[..data_len]);
+        // Decode octets. The buffer is returned to the pool once the packet is encoded again, or
+        // dropped after it is decrypted.
+        let mut data = buffer_pool::get(data_len);
+        data.extend_from_slice(&src[..data_len]);
         src.advance(data_len);
 
         // Reset state
@@ -148,6 +158,7 @@ impl Encoder<DataPacket> for Codec {
         dst.put_slice(&item.dst_ip.octets());
         // Write the data
         dst.extend_from_slice(&item.raw_data);
+        buffer_pool::put(item.raw_data);
 
         Ok(())
     }
@@ -1,11 +1,13 @@
+use bytes::Bytes;
 use futures::{SinkExt, StreamExt};
 use std::{
     error::Error,
     io,
     sync::{
-        atomic::{AtomicBool, AtomicU64, Ordering},
-        Arc, RwLock, Weak,
+        atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering},
+        Arc, Mutex, RwLock, Weak,
     },
+    time::Duration,
 };
 use tokio::{
     select,
@@ -15,13 +17,21 @@ use tokio_util::codec::Framed;
 use tracing::{debug, error, info, trace};
 
 use crate::{
-    connection::{self, Connection},
+    connection::{self, Connection, Datagrams},
+    crypto::PublicKey,
+    endpoint::Endpoint,
     packet::{self, Packet},
 };
 use crate::{
     packet::{ControlPacket, DataPacket},
     sequence_number::SeqNo,
 };
+use link_quality::{HelloHistory, ETX_PERFECT};
+
+mod link_quality;
+
+/// Error returned when sending a packet to a [`Peer`] which has no live connection.
+const NO_LIVE_CONNECTION: &str = "peer has no live connection";
 
 /// The maximum amount of packets to immediately send if they are ready when the first one is
 /// received.
@@ -47,16 +57,65 @@ const EXISTING_METRIC_FACTOR: u32 = 9;
 /// Divisor for smoothed metric calcuation of the combined metric
 const TOTAL_METRIC_DIVISOR: u32 = 10;
 
+/// Receive the next datagram, if datagrams are available. Returns [`None`] immediately if they
+/// are not, which disables the datagram branch in the peer loop.
+async fn recv_datagram(datagrams: Option<&Datagrams>) -> Option<Result<Bytes, io::Error>> {
+    Some(datagrams?.recv().await)
+}
+
+/// Send a [`DataPacket`] as datagram. If datagrams are not available, or the packet does not fit
+/// in a datagram, the packet is returned so it can be sent over the connection instead.
+fn send_datagram(
+    datagrams: Option<&Datagrams>,
+    packet: DataPacket,
+    bytes_written: &AtomicU64,
+) -> Option<DataPacket> {
+    let Some(datagrams) = datagrams else {
+        return Some(packet);
+    };
+    if datagrams
+        .max_size()
+        .is_none_or(|max_size| packet::datagram_len(&packet) > max_size)
+    {
+        return Some(packet);
+    }
+
+    // Datagrams are unreliable anyway, so packets which fail to send are dropped.
+    match packet::encode_datagram(packet).and_then(|datagram| {
+        let len = datagram.len();
+        datagrams.send(datagram).map(|_| len)
+    }) {
+        Ok(len) => {
+            bytes_written.fetch_add(len as u64, Ordering::Relaxed);
+        }
+        Err(e) => trace!("Failed to send data packet as datagram: {e}"),
+    }
+
+    None
+}
+
 #[derive(Debug, Clone)]
 /// A peer represents a directly connected participant in the network.
+///
+/// A peer can be connected over multiple [`Connection`]s at the same time, e.g. over both IPv4 and
+/// IPv6, or both TCP and QUIC. Packets are sent over the live connection with the lowest static
+/// link cost, and received from all of them. If a connection dies, the next best one is used
+/// immediately. The peer itself only dies once all its connections died.
+///
+/// HELLO's are sent over all connections, so every connection receives traffic from the remote.
+/// A connection which did not receive anything for too long is considered stale, and closed by
+/// [`Peer::close_stale_links`] while a fresh one remains.
 pub struct Peer {
     inner: Arc<PeerInner>,
 }
 
-/// A weak reference to a peer, which does not prevent it from being cleaned up. This can be used
-/// to check liveliness of the [`Peer`] instance it originated from.
+/// A weak reference to a single connection of a peer, which does not prevent it from being cleaned
+/// up. This can be used to check liveliness of the connection, and get the [`Peer`] instance it
+/// belongs to.
+#[derive(Clone)]
 pub struct PeerRef {
     inner: Weak<PeerInner>,
+    link: Weak<Link>,
 }
 
 impl Peer {
@@ -64,38 +123,96 @@ impl Peer {
         router_data_tx: mpsc::Sender<DataPacket>,
         router_control_tx: mpsc::UnboundedSender<(ControlPacket, Peer)>,
         connection: C,
+        remote_public_key: Option<PublicKey>,
         dead_peer_sink: mpsc::Sender<Peer>,
         bytes_written: Arc<AtomicU64>,
         bytes_read: Arc<AtomicU64>,
     ) -> Result<Self, io::Error> {
-        // Wrap connection so we can get access to the counters.
-        let connection = connection::Tracked::new(bytes_read, bytes_written, connection);
-
-        // Data channel for peer
-        let (to_peer_data, mut from_routing_data) = mpsc::unbounded_channel::<DataPacket>();
-        // Control channel for peer
-        let (to_peer_control, mut from_routing_control) =
-            mpsc::unbounded_channel::<ControlPacket>();
-        let death_notifier = Arc::new(Notify::new());
-        let death_watcher = death_notifier.clone();
         let peer = Peer {
             inner: Arc::new(PeerInner {
                 state: RwLock::new(PeerState::new()),
-                to_peer_data,
-                to_peer_control,
+                links: RwLock::new(Vec::new()),
+                active_link: Mutex::new(Weak::new()),
+                router_data_tx,
+                router_control_tx,
+                dead_peer_sink,
                 connection_identifier: connection.identifier()?,
-                static_link_cost: connection.static_link_cost()?,
-                death_notifier,
+                remote_endpoint: connection.remote_endpoint()?,
+                remote_public_key,
+                link_cost_offset: AtomicU16::new(0),
                 alive: AtomicBool::new(true),
             }),
         };
 
-        // Framed for peer
+        peer.add_connection(connection, bytes_written, bytes_read)?;
+        // Set the initial active link, so it is not reported as a change.
+        peer.active_link_changed();
+
+        Ok(peer)
+    }
+
+    /// Add a [`Connection`] to the remote of this `Peer`. The connection must be authenticated as
+    /// the same remote. The returned [`PeerRef`] refers to the new connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `Peer` already died.
+    pub fn add_connection<C: Connection + Unpin + Send + 'static>(
+        &self,
+        connection: C,
+        bytes_written: Arc<AtomicU64>,
+        bytes_read: Arc<AtomicU64>,
+    ) -> Result<PeerRef, io::Error> {
+        // Data packets are sent as datagrams if the connection supports them, so they don't
+        // suffer from head-of-line blocking. Control packets always use the connection itself.
+        let datagrams = connection.datagrams();
+        let (datagram_bytes_read, datagram_bytes_written) =
+            (bytes_read.clone(), bytes_written.clone());
+
+        // Wrap connection so we can get access to the counters.
+        let connection = connection::Tracked::new(bytes_read, bytes_written, connection);
+
+        // Data channel for the connection
+        let (to_link_data, mut from_routing_data) = mpsc::unbounded_channel::<DataPacket>();
+        // Control channel for the connection
+        let (to_link_control, mut from_routing_control) =
+            mpsc::unbounded_channel::<ControlPacket>();
+        let link = Arc::new(Link {
+            to_link_data,
+            to_link_control,
+            connection_identifier: connection.identifier()?,
+            static_link_cost: connection.static_link_cost()?,
+            death_notifier: Notify::new(),
+            alive: AtomicBool::new(true),
+            last_received: Mutex::new(tokio::time::Instant::now()),
+        });
+
+        {
+            // Check under the lock, so we don't add a connection while the last one dies.
+            let mut links = self.inner.links.write().unwrap();
+            if !self.alive() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "peer already died",
+                ));
+            }
+            links.push(link.clone());
+        }
+
+        let peer_ref = PeerRef {
+            inner: Arc::downgrade(&self.inner),
+            link: Arc::downgrade(&link),
+        };
+
+        // Framed for the connection
         // Used to send and receive packets from a TCP stream
         let mut framed = Framed::new(connection, packet::Codec::new());
 
         {
-            let peer = peer.clone();
+            let peer = self.clone();
+            let router_data_tx = self.inner.router_data_tx.clone();
+            let router_control_tx = self.inner.router_control_tx.clone();
+            let dead_peer_sink = self.inner.dead_peer_sink.clone();
 
             tokio::spawn(async move {
                 loop {
@@ -104,6 +221,7 @@ impl Peer {
                         frame = framed.next() => {
                             match frame {
                                 Some(Ok(packet)) => {
+                                    link.received();
                                     match packet {
                                         Packet::DataPacket(packet) => {
                                             // An error here means the receiver is dropped/closed,
@@ -125,26 +243,57 @@ impl Peer {
                                     }
                                 }
                                 Some(Err(e)) => {
-                                    error!("Frame error from {}: {e}", peer.connection_identifier());
+                                    error!("Frame error from {}: {e}", link.connection_identifier);
                                     break;
                                 },
                                 None => {
-                                    info!("Stream to {} is closed", peer.connection_identifier());
+                                    info!("Stream to {} is closed", link.connection_identifier);
                                     break;
                                 }
                             }
                         }
 
+                        // Received as datagram
+                        Some(datagram) = recv_datagram(datagrams.as_ref()) => {
+                            let datagram = match datagram {
+                                Ok(datagram) => datagram,
+                                Err(e) => {
+                                    error!("Datagram error from {}: {e}", link.connection_identifier);
+                                    break;
+                                }
+                            };
+                            datagram_bytes_read.fetch_add(datagram.len() as u64, Ordering::Relaxed);
+                            link.received();
+                            match packet::decode_datagram(datagram) {
+                                Ok(packet) => {
+                                    // An error here means the receiver is dropped/closed,
+                                    // this is not recoverable.
+                                    if let Err(error) = router_data_tx.send(packet).await {
+                                        error!("Error sending to to_routing_data: {}", error);
+                                        break
+                                    }
+                                }
+                                Err(e) => {
+                                    debug!("Dropping invalid datagram from {}: {e}", link.connection_identifier);
+                                }
+                            }
+                        }
+
                         Some(packet) = from_routing_data.recv() => {
-                            if let Err(e) = framed.feed(Packet::DataPacket(packet)).await {
-                                error!("Failed to feed data packet to connection: {e}");
-                                break
+                            if let Some(packet) = send_datagram(datagrams.as_ref(), packet, &datagram_bytes_written) {
+                                if let Err(e) = framed.feed(Packet::DataPacket(packet)).await {
+                                    error!("Failed to feed data packet to connection: {e}");
+                                    break
+                                }
                             }
 
                             for _ in 1..PACKET_COALESCE_WINDOW {
                                 // There can be 2 cases of errors here, empty channel and no more
                                 // senders. In both cases we don't really care at this point.
                                 if let Ok(packet) = from_routing_data.try_recv() {
+                                    let Some(packet) = send_datagram(datagrams.as_ref(), packet, &datagram_bytes_written) else {
+                                        continue
+                                    };
                                     if let Err(e) = framed.feed(Packet::DataPacket(packet)).await {
                                         error!("Failed to feed data packet to connection: {e}");
                                         break
@@ -188,15 +337,33 @@ impl Peer {
                             }
                         }
 
-                        _ = death_watcher.notified() => {
+                        _ = link.death_notifier.notified() => {
                             break;
                         }
                     }
                 }
 
-                // Notify router we are dead, also modify our internal state to declare that.
                 // Relaxed ordering is fine, we just care that the variable is set.
-                peer.inner.alive.store(false, Ordering::Relaxed);
+                link.alive.store(false, Ordering::Relaxed);
+                let last_link = {
+                    let mut links = peer.inner.links.write().unwrap();
+                    links.retain(|l| !Arc::ptr_eq(l, &link));
+                    if links.is_empty() {
+                        peer.inner.alive.store(false, Ordering::Relaxed);
+                    }
+                    links.is_empty()
+                };
+                if !last_link {
+                    info!(
+                        "Connection {} died, peer {} is still connected",
+                        link.connection_identifier,
+                        peer.connection_identifier()
+                    );
+                    return;
+                }
+
+                // Notify router we are dead, our internal state is already modified to declare
+                // that.
                 let remote_id = peer.connection_identifier().clone();
                 debug!("Notifying router peer {remote_id} is dead");
                 if let Err(e) = dead_peer_sink.send(peer).await {
@@ -205,7 +372,74 @@ impl Peer {
             });
         }
 
-        Ok(peer)
+        Ok(peer_ref)
+    }
+
+    /// The live connection with the lowest static link cost, which is used to send packets.
+    fn active_link(&self) -> Option<Arc<Link>> {
+        self.inner
+            .links
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|link| link.alive.load(Ordering::Relaxed))
+            .min_by_key(|link| link.static_link_cost)
+            .cloned()
+    }
+
+    /// Checks if the active link changed since the last call, i.e. packets are now sent over a
+    /// different connection. As the static link cost of the connection is part of the
+    /// [`rx_cost`](Peer::rx_cost), the router needs to rerun route selection if this is the case.
+    pub fn active_link_changed(&self) -> bool {
+        let active = self
+            .active_link()
+            .map(|link| Arc::downgrade(&link))
+            .unwrap_or_default();
+        let mut last_active = self.inner.active_link.lock().unwrap();
+        if Weak::ptr_eq(&last_active, &active) {
+            return false;
+        }
+        *last_active = active;
+        true
+    }
+
+    /// Close all connections which did not receive anything from the remote for longer than
+    /// `threshold`, as long as at least 1 connection which did remains. If all connections are
+    /// stale, they are kept, and it is up to the regular dead peer detection to decide if the
+    /// `Peer` died.
+    ///
+    /// Returns the amount of closed connections.
+    pub fn close_stale_links(&self, threshold: Duration) -> usize {
+        let links = self.inner.links.read().unwrap();
+        let (stale, fresh): (Vec<_>, Vec<_>) = links
+            .iter()
+            .filter(|link| link.alive.load(Ordering::Relaxed))
+            .partition(|link| link.last_received().elapsed() > threshold);
+        if fresh.is_empty() {
+            return 0;
+        }
+
+        for link in &stale {
+            info!(
+                "Connection {} of peer {} is stale, closing it",
+                link.connection_identifier,
+                self.connection_identifier()
+            );
+            link.died();
+        }
+
+        stale.len()
+    }
+
+    /// The amount of live connections to this `Peer`.
+    pub fn connection_count(&self) -> usize {
+        self.inner
+            .links
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|link| link.alive.load(Ordering::Relaxed))
+            .count()
     }
 
     /// Get current sequence number for this peer.
@@ -230,22 +464,86 @@ impl Peer {
     /// It's send over the to_peer_data channel and read from the corresponding receiver.
     /// The receiver sends the packet over the TCP stream towards the destined peer instance on another node
     pub fn send_data_packet(&self, data_packet: DataPacket) -> Result<(), Box<dyn Error>> {
-        Ok(self.inner.to_peer_data.send(data_packet)?)
+        let link = self.active_link().ok_or(NO_LIVE_CONNECTION)?;
+        Ok(link.to_link_data.send(data_packet)?)
+    }
+
+    /// Send a control packet over all live connections of this `Peer`, rather than only the
+    /// active one. This is used for HELLO's, so the liveness of every connection can be tracked
+    /// on both sides.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet could not be sent over any connection.
+    pub fn send_control_packet_on_all_links(
+        &self,
+        control_packet: ControlPacket,
+    ) -> Result<(), Box<dyn Error>> {
+        let links = self.inner.links.read().unwrap();
+        let mut sent = false;
+        for link in links
+            .iter()
+            .filter(|link| link.alive.load(Ordering::Relaxed))
+        {
+            sent |= link.to_link_control.send(control_packet.clone()).is_ok();
+        }
+
+        if sent {
+            Ok(())
+        } else {
+            Err(NO_LIVE_CONNECTION.into())
+        }
     }
 
     /// For sending control packets towards a peer instance on this node.
     /// It's send over the to_peer_control channel and read from the corresponding receiver.
     /// The receiver sends the packet over the TCP stream towards the destined peer instance on another node
     pub fn send_control_packet(&self, control_packet: ControlPacket) -> Result<(), Box<dyn Error>> {
-        Ok(self.inner.to_peer_control.send(control_packet)?)
+        let link = self.active_link().ok_or(NO_LIVE_CONNECTION)?;
+        Ok(link.to_link_control.send(control_packet)?)
     }
 
     /// Get the cost to use the peer, i.e. the additional impact on the [`crate::metric::Metric`]
     /// for using this `Peer`.
     ///
-    /// This is a smoothed value, which is calculated over the recent history of link cost.
+    /// This is the [measured cost](Peer::rx_cost) of the link, with the configured
+    /// [link cost offset](Peer::set_link_cost_offset) added on top. The offset is a local
+    /// preference, so this value must only be used to compute the metric of routes on this node.
     pub fn link_cost(&self) -> u16 {
-        self.inner.state.read().unwrap().link_cost + self.inner.static_link_cost
+        let cost = self.rx_cost() as u32 + self.link_cost_offset() as u32;
+        // Keep the cost finite, the peer is still reachable, albeit very expensive to use.
+        cost.min(u16::MAX as u32 - 1) as u16
+    }
+
+    /// Get the measured cost of the link to this `Peer`, as advertised to the `Peer` in IHU's.
+    ///
+    /// This is a smoothed value, which is calculated over the recent history of link cost.
+    ///
+    /// The latency based cost is scaled by the expected transmission count (ETX) of the link,
+    /// which is estimated from the amount of HELLO's we missed from this `Peer`. As such, a lossy
+    /// link is more expensive than a link with the same latency and no loss.
+    ///
+    /// The static link cost is the one of the connection which is currently used to send packets.
+    pub fn rx_cost(&self) -> u16 {
+        let static_link_cost = self
+            .active_link()
+            .map_or(0, |link| link.static_link_cost as u32);
+        let state = self.inner.state.read().unwrap();
+        let etx = state.hello_history.etx(tokio::time::Instant::now());
+        let cost = (state.link_cost as u32 + static_link_cost) * etx / ETX_PERFECT;
+        // Keep the cost finite, the peer is still reachable, albeit very expensive to use.
+        cost.min(u16::MAX as u32 - 1) as u16
+    }
+
+    /// The operator configured cost which is added to the link cost of this `Peer`.
+    pub fn link_cost_offset(&self) -> u16 {
+        self.inner.link_cost_offset.load(Ordering::Relaxed)
+    }
+
+    /// Set the operator configured cost which is added to the link cost of this `Peer`. This
+    /// allows making routes through this `Peer` more or less preferable.
+    pub fn set_link_cost_offset(&self, offset: u16) {
+        self.inner.link_cost_offset.store(offset, Ordering::Relaxed);
     }
 
     /// Sets the link cost based on the provided value.
@@ -262,11 +560,36 @@ impl Peer {
             / TOTAL_METRIC_DIVISOR) as u16;
     }
 
-    /// Identifier for the connection to the `Peer`.
+    /// Record that a HELLO with the given [`SeqNo`] and interval was received from this `Peer`.
+    /// This is used to estimate the packet loss on the link.
+    ///
+    /// Returns `false` if this HELLO is a copy of the previous one, received over a different
+    /// connection, in which case it must not be processed further.
+    pub fn record_received_hello(&self, seqno: SeqNo, interval: Duration) -> bool {
+        self.inner.state.write().unwrap().hello_history.record(
+            seqno,
+            interval,
+            tokio::time::Instant::now(),
+        )
+    }
+
+    /// Identifier for the connection the `Peer` was created with. This does not change if the
+    /// `Peer` uses a different connection later on.
     pub fn connection_identifier(&self) -> &String {
         &self.inner.connection_identifier
     }
 
+    /// The [`Endpoint`] of the remote side of the connection the `Peer` was created with.
+    pub fn remote_endpoint(&self) -> Endpoint {
+        self.inner.remote_endpoint.clone()
+    }
+
+    /// The [`PublicKey`] of the remote side of the connection to this `Peer`, as verified during
+    /// the link handshake. This is [`None`] for legacy remotes which don't do the handshake.
+    pub fn remote_public_key(&self) -> Option<PublicKey> {
+        self.inner.remote_public_key
+    }
+
     pub fn time_last_received_ihu(&self) -> tokio::time::Instant {
         self.inner.state.read().unwrap().time_last_received_ihu
     }
@@ -275,17 +598,43 @@ impl Peer {
         self.inner.state.write().unwrap().time_last_received_ihu = time
     }
 
-    /// Notify this `Peer` that it died.
+    /// The HELLO interval last advertised by this `Peer`, if any.
+    pub fn advertised_hello_interval(&self) -> Option<Duration> {
+        self.inner.state.read().unwrap().advertised_hello_interval
+    }
+
+    /// Record the HELLO interval advertised by this `Peer`. An interval of 0 means the peer does
+    /// not send HELLO's on a schedule, in which case no interval is recorded.
+    pub fn set_advertised_hello_interval(&self, interval: Duration) {
+        self.inner.state.write().unwrap().advertised_hello_interval =
+            Some(interval).filter(|interval| !interval.is_zero());
+    }
+
+    /// The IHU interval last advertised by this `Peer`, if any.
+    pub fn advertised_ihu_interval(&self) -> Option<Duration> {
+        self.inner.state.read().unwrap().advertised_ihu_interval
+    }
+
+    /// Record the IHU interval advertised by this `Peer`.
+    pub fn set_advertised_ihu_interval(&self, interval: Duration) {
+        self.inner.state.write().unwrap().advertised_ihu_interval =
+            Some(interval).filter(|interval| !interval.is_zero());
+    }
+
+    /// Notify this `Peer` that it died. All its connections are closed.
     ///
     /// While some [`Connection`] types can immediately detect that the connection itself is
     /// broken, not all of them can. In this scenario, we need to rely on an outside signal to tell
     /// us that we have, in fact, died.
     pub fn died(&self) {
+        let links = self.inner.links.read().unwrap();
         self.inner.alive.store(false, Ordering::Relaxed);
-        self.inner.death_notifier.notify_one();
+        for link in links.iter() {
+            link.died();
+        }
     }
 
-    /// Checks if the connection of this `Peer` is still alive.
+    /// Checks if any connection of this `Peer` is still alive.
     ///
     /// For connection types which don't have (real time) state information, this might return a
     /// false positive if the connection has actually died, but the Peer did not notice this (yet)
@@ -294,10 +643,19 @@ impl Peer {
         self.inner.alive.load(Ordering::Relaxed)
     }
 
-    /// Create a new [`PeerRef`] that refers to this `Peer` instance.
+    /// Create a new [`PeerRef`] that refers to the oldest live connection of this `Peer`. For a
+    /// new `Peer`, this is the connection it was created with.
     pub fn refer(&self) -> PeerRef {
         PeerRef {
             inner: Arc::downgrade(&self.inner),
+            link: self
+                .inner
+                .links
+                .read()
+                .unwrap()
+                .first()
+                .map(Arc::downgrade)
+                .unwrap_or_default(),
         }
     }
 }
@@ -306,18 +664,29 @@ impl PeerRef {
     /// Contructs a new `PeerRef` which is not associated with any actually [`Peer`].
     /// [`PeerRef::alive`] will always return false when called on this `PeerRef`.
     pub fn new() -> Self {
-        PeerRef { inner: Weak::new() }
+        PeerRef {
+            inner: Weak::new(),
+            link: Weak::new(),
+        }
     }
 
-    /// Check if the connection of the [`Peer`] this `PeerRef` points to is still alive.
+    /// Check if the connection this `PeerRef` points to is still alive.
     pub fn alive(&self) -> bool {
-        if let Some(peer) = self.inner.upgrade() {
-            peer.alive.load(Ordering::Relaxed)
+        if let Some(link) = self.link.upgrade() {
+            link.alive.load(Ordering::Relaxed)
         } else {
             false
         }
     }
 
+    /// Close the connection this `PeerRef` points to. If this is the last connection of the
+    /// [`Peer`], the `Peer` dies as well.
+    pub fn died(&self) {
+        if let Some(link) = self.link.upgrade() {
+            link.died();
+        }
+    }
+
     /// Attempts to convert this `PeerRef` into a full [`Peer`].
     pub fn upgrade(&self) -> Option<Peer> {
         self.inner.upgrade().map(|inner| Peer { inner })
@@ -339,17 +708,60 @@ impl PartialEq for Peer {
 #[derive(Debug)]
 struct PeerInner {
     state: RwLock<PeerState>,
-    to_peer_data: mpsc::UnboundedSender<DataPacket>,
-    to_peer_control: mpsc::UnboundedSender<ControlPacket>,
-    /// Used to identify peer based on its connection params.
+    /// Connections to the remote, in the order they were added.
+    links: RwLock<Vec<Arc<Link>>>,
+    /// The connection which was active when last checked, used to detect changes.
+    active_link: Mutex<Weak<Link>>,
+    router_data_tx: mpsc::Sender<DataPacket>,
+    router_control_tx: mpsc::UnboundedSender<(ControlPacket, Peer)>,
+    dead_peer_sink: mpsc::Sender<Peer>,
+    /// Used to identify peer based on the params of the connection it was created with.
     connection_identifier: String,
-    /// Static cost of using this link, to be added to the announced metric for routes through this
-    /// Peer.
+    /// Endpoint of the remote side of the connection the peer was created with.
+    remote_endpoint: Endpoint,
+    /// Public key of the remote, verified during the link handshake. Legacy remotes don't have
+    /// one.
+    remote_public_key: Option<PublicKey>,
+    /// Operator configured cost added to the link cost of this Peer.
+    link_cost_offset: AtomicU16,
+    /// Keep track if any connection is alive.
+    alive: AtomicBool,
+}
+
+/// A single connection of a [`Peer`].
+#[derive(Debug)]
+struct Link {
+    to_link_data: mpsc::UnboundedSender<DataPacket>,
+    to_link_control: mpsc::UnboundedSender<ControlPacket>,
+    /// Used to identify the connection in logs.
+    connection_identifier: String,
+    /// Static cost of using this connection, to be added to the announced metric for routes
+    /// through the Peer while this connection is used.
     static_link_cost: u16,
     /// Channel to notify the connection of its decease.
-    death_notifier: Arc<Notify>,
+    death_notifier: Notify,
     /// Keep track if the connection is alive.
     alive: AtomicBool,
+    /// Time a packet was last received over this connection.
+    last_received: Mutex<tokio::time::Instant>,
+}
+
+impl Link {
+    /// Notify this `Link` that it died.
+    fn died(&self) {
+        self.alive.store(false, Ordering::Relaxed);
+        self.death_notifier.notify_one();
+    }
+
+    /// Record that a packet was received over this `Link`.
+    fn received(&self) {
+        *self.last_received.lock().unwrap() = tokio::time::Instant::now();
+    }
+
+    /// Time a packet was last received over this `Link`.
+    fn last_received(&self) -> tokio::time::Instant {
+        *self.last_received.lock().unwrap()
+    }
 }
 
 #[derive(Debug)]
@@ -358,6 +770,11 @@ struct PeerState {
     time_last_received_hello: tokio::time::Instant,
     link_cost: u16,
     time_last_received_ihu: tokio::time::Instant,
+    hello_history: HelloHistory,
+    /// Last HELLO interval advertised by the peer, if it advertised a non-zero one.
+    advertised_hello_interval: Option<Duration>,
+    /// Last IHU interval advertised by the peer.
+    advertised_ihu_interval: Option<Duration>,
 }
 
 impl PeerState {
@@ -376,6 +793,160 @@ impl PeerState {
             link_cost,
             time_last_received_ihu,
             time_last_received_hello,
+            hello_history: HelloHistory::new(),
+            advertised_hello_interval: None,
+            advertised_ihu_interval: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicU64, Arc},
+        time::Duration,
+    };
+
+    use futures::SinkExt;
+    use tokio::{io::DuplexStream, sync::mpsc};
+    use tokio_util::codec::Framed;
+
+    use crate::{
+        babel,
+        crypto::{PublicKey, SecretKey},
+        packet::{self, ControlPacket, DataPacket, Packet},
+        sequence_number::SeqNo,
+    };
+
+    use super::Peer;
+
+    /// Router side of the channels of a [`Peer`] created with [`test_peer`].
+    struct RouterChannels {
+        _data_rx: mpsc::Receiver<DataPacket>,
+        control_rx: mpsc::UnboundedReceiver<(ControlPacket, Peer)>,
+        dead_peer_stream: mpsc::Receiver<Peer>,
+    }
+
+    /// Create a [`Peer`] with a random remote key on the given connection.
+    fn test_peer(connection: DuplexStream) -> (Peer, RouterChannels) {
+        let (router_data_tx, data_rx) = mpsc::channel(1);
+        let (router_control_tx, control_rx) = mpsc::unbounded_channel();
+        let (dead_peer_sink, dead_peer_stream) = mpsc::channel(1);
+
+        let peer = Peer::new(
+            router_data_tx,
+            router_control_tx,
+            connection,
+            Some(PublicKey::from(&SecretKey::new())),
+            dead_peer_sink,
+            Arc::new(AtomicU64::new(0)),
+            Arc::new(AtomicU64::new(0)),
+        )
+        .expect("Can create a peer");
+
+        (
+            peer,
+            RouterChannels {
+                _data_rx: data_rx,
+                control_rx,
+                dead_peer_stream,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn peer_survives_while_a_connection_is_alive() {
+        let (con1, remote1) = tokio::io::duplex(1500);
+        let (con2, remote2) = tokio::io::duplex(1500);
+
+        let (peer, mut channels) = test_peer(con1);
+        let first = peer.refer();
+        let second = peer
+            .add_connection(
+                con2,
+                Arc::new(AtomicU64::new(0)),
+                Arc::new(AtomicU64::new(0)),
+            )
+            .expect("Can add a connection to a live peer");
+        assert_eq!(peer.connection_count(), 2);
+
+        drop(remote1);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while first.alive() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Connection notices the remote closed it");
+        assert!(peer.alive());
+        assert!(second.alive());
+        assert_eq!(peer.connection_count(), 1);
+        assert!(channels.dead_peer_stream.try_recv().is_err());
+
+        drop(remote2);
+        let dead_peer =
+            tokio::time::timeout(Duration::from_secs(1), channels.dead_peer_stream.recv())
+                .await
+                .expect("Peer dies once all connections are closed")
+                .expect("Dead peer sink is not closed");
+        assert_eq!(dead_peer, peer);
+        assert!(!peer.alive());
+    }
+
+    #[tokio::test]
+    async fn stale_connection_is_replaced() {
+        let (con1, _remote1) = tokio::io::duplex(1500);
+        let (con2, remote2) = tokio::io::duplex(1500);
+
+        let (peer, mut channels) = test_peer(con1);
+        let first = peer.refer();
+        let second = peer
+            .add_connection(
+                con2,
+                Arc::new(AtomicU64::new(0)),
+                Arc::new(AtomicU64::new(0)),
+            )
+            .expect("Can add a connection to a live peer");
+        // Both connections have the same static link cost, so the first one stays active.
+        assert!(!peer.active_link_changed());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut remote2 = Framed::new(remote2, packet::Codec::new());
+        remote2
+            .send(Packet::ControlPacket(
+                babel::Hello::new_unicast(SeqNo::default(), 100).into(),
+            ))
+            .await
+            .expect("Can send a HELLO over the second connection");
+        channels
+            .control_rx
+            .recv()
+            .await
+            .expect("HELLO is forwarded to the router");
+
+        assert_eq!(peer.close_stale_links(Duration::from_millis(50)), 1);
+        assert!(!first.alive());
+        assert!(second.alive());
+        assert!(peer.active_link_changed());
+        assert!(!peer.active_link_changed());
+
+        // The last connection is kept, even if it is stale as well.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(peer.close_stale_links(Duration::from_millis(50)), 0);
+        assert!(second.alive());
+        assert!(peer.alive());
+    }
+
+    #[tokio::test]
+    async fn link_cost_offset_is_not_advertised() {
+        let (con, _remote) = tokio::io::duplex(1500);
+
+        let (peer, _channels) = test_peer(con);
+        let rx_cost = peer.rx_cost();
+
+        peer.set_link_cost_offset(500);
+
+        assert_eq!(peer.rx_cost(), rx_cost);
+        assert_eq!(peer.link_cost(), rx_cost + 500);
+    }
+}
@@ -0,0 +1,260 @@
+//! Link quality estimation based on received HELLO TLVs, as described in
+//! [appendix A of the babel RFC](https://datatracker.ietf.org/doc/html/rfc8966#appendix-A).
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::sequence_number::SeqNo;
+
+/// Amount of HELLO's which are tracked to estimate the packet loss on a link.
+const HELLO_HISTORY_SIZE: u8 = 16;
+
+/// Fixed point representation of an ETX of 1, i.e. a link without any loss.
+pub const ETX_PERFECT: u32 = 256;
+
+/// Highest ETX we report. This is used when none of the tracked HELLO's have been received.
+const ETX_MAX: u32 = ETX_PERFECT * HELLO_HISTORY_SIZE as u32 * HELLO_HISTORY_SIZE as u32;
+
+/// History of HELLO's received from a peer, used to estimate the packet loss on the link.
+#[derive(Debug, Default)]
+pub struct HelloHistory {
+    /// Bitmap of received HELLO's, the least significant bit is the most recent one.
+    history: u16,
+    /// Amount of valid entries in `history`.
+    len: u8,
+    /// Sequence number we expect on the next HELLO.
+    expected: Option<SeqNo>,
+    /// Time the last HELLO was received, and the interval it advertised.
+    last_received: Option<(Instant, Duration)>,
+}
+
+impl HelloHistory {
+    /// Create a new, empty `HelloHistory`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a received HELLO with the given [`SeqNo`].
+    ///
+    /// Gaps between the expected and received sequence number are recorded as lost HELLO's. If
+    /// the gap is larger than the tracked window, or the sequence number went backwards (e.g.
+    /// because the remote restarted), the history is reset.
+    ///
+    /// The `interval` advertised in the HELLO is used to age the history if no further HELLO's
+    /// arrive, see [`HelloHistory::etx`].
+    ///
+    /// A peer connected over multiple connections sends the same HELLO on all of them. Copies of
+    /// the last recorded HELLO are ignored, in which case this returns `false`.
+    pub fn record(&mut self, seqno: SeqNo, interval: Duration, now: Instant) -> bool {
+        if self.expected == Some(seqno + 1) {
+            return false;
+        }
+
+        let missed = self
+            .expected
+            .map(|expected| u16::from(seqno).wrapping_sub(expected.into()));
+
+        match missed {
+            Some(missed) if missed < HELLO_HISTORY_SIZE as u16 => {
+                for _ in 0..missed {
+                    self.push(false);
+                }
+                self.push(true);
+            }
+            _ => {
+                self.history = 0;
+                self.len = 0;
+                self.push(true);
+            }
+        }
+
+        self.expected = Some(seqno + 1);
+        self.last_received = Some((now, interval)).filter(|(_, interval)| !interval.is_zero());
+
+        true
+    }
+
+    /// Estimated expected transmission count of the link, as a fixed point number where
+    /// [`ETX_PERFECT`] is an ETX of 1.
+    ///
+    /// We can only observe the delivery ratio of packets sent by the remote. The IHU rx cost
+    /// field could be used to learn the delivery ratio in the other direction, but older nodes
+    /// fill it with their own link cost, so it can't be relied upon. Therefore the link is
+    /// assumed to be symmetric, i.e. ETX = 1 / (delivery ratio ^ 2).
+    ///
+    /// HELLO's which are overdue at `now` are counted as lost, so the estimate degrades while the
+    /// remote is silent instead of keeping the quality of the last received HELLO.
+    pub fn etx(&self, now: Instant) -> u32 {
+        let missed = self.overdue(now);
+        let (history, len) = if missed >= HELLO_HISTORY_SIZE as u32 {
+            (0, HELLO_HISTORY_SIZE)
+        } else {
+            (
+                self.history << missed,
+                (self.len as u32 + missed).min(HELLO_HISTORY_SIZE as u32) as u8,
+            )
+        };
+
+        if len == 0 {
+            return ETX_PERFECT;
+        }
+
+        let received = (history & Self::mask(len)).count_ones();
+        if received == 0 {
+            return ETX_MAX;
+        }
+
+        let len = len as u32;
+        ETX_PERFECT * len * len / (received * received)
+    }
+
+    /// Amount of HELLO's which should have been received by `now`, but weren't.
+    ///
+    /// As described in the RFC, the first HELLO is considered lost if none is received within 1.5
+    /// times the advertised interval, and every interval afterwards another one is lost.
+    fn overdue(&self, now: Instant) -> u32 {
+        let Some((last_received, interval)) = self.last_received else {
+            return 0;
+        };
+        let elapsed = now.saturating_duration_since(last_received);
+        let timeout = interval * 3 / 2;
+        if elapsed < timeout {
+            return 0;
+        }
+
+        ((elapsed - timeout).as_nanos() / interval.as_nanos() + 1).min(u32::MAX as u128) as u32
+    }
+
+    /// Push a new entry in the history.
+    fn push(&mut self, received: bool) {
+        self.history = (self.history << 1) | received as u16;
+        self.len = (self.len + 1).min(HELLO_HISTORY_SIZE);
+    }
+
+    /// Mask for the `len` most recent entries.
+    fn mask(len: u8) -> u16 {
+        if len >= 16 {
+            u16::MAX
+        } else {
+            (1 << len) - 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{HelloHistory, ETX_MAX, ETX_PERFECT};
+
+    const INTERVAL: Duration = Duration::from_secs(4);
+
+    #[test]
+    fn no_loss() {
+        let now = Instant::now();
+        let mut hh = HelloHistory::new();
+        assert_eq!(hh.etx(now), ETX_PERFECT);
+
+        for seqno in 0..40 {
+            hh.record(seqno.into(), INTERVAL, now);
+        }
+
+        assert_eq!(hh.etx(now), ETX_PERFECT);
+    }
+
+    #[test]
+    fn half_loss() {
+        let now = Instant::now();
+        let mut hh = HelloHistory::new();
+
+        for seqno in (0..32).step_by(2) {
+            hh.record(seqno.into(), INTERVAL, now);
+        }
+
+        // 8 out of 16 received.
+        assert_eq!(hh.etx(now), ETX_PERFECT * 4);
+    }
+
+    #[test]
+    fn loss_recovers() {
+        let now = Instant::now();
+        let mut hh = HelloHistory::new();
+
+        hh.record(0.into(), INTERVAL, now);
+        hh.record(4.into(), INTERVAL, now);
+        assert_eq!(hh.etx(now), ETX_PERFECT * 25 / 4);
+
+        for seqno in 5..21 {
+            hh.record(seqno.into(), INTERVAL, now);
+        }
+        assert_eq!(hh.etx(now), ETX_PERFECT);
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        let now = Instant::now();
+        let mut hh = HelloHistory::new();
+
+        assert!(hh.record(0.into(), INTERVAL, now));
+        assert!(!hh.record(0.into(), INTERVAL, now));
+        assert!(hh.record(1.into(), INTERVAL, now));
+        assert!(!hh.record(1.into(), INTERVAL, now));
+
+        assert_eq!(hh.etx(now), ETX_PERFECT);
+    }
+
+    #[test]
+    fn large_gap_resets_history() {
+        let now = Instant::now();
+        let mut hh = HelloHistory::new();
+
+        hh.record(0.into(), INTERVAL, now);
+        hh.record(2.into(), INTERVAL, now);
+        hh.record(100.into(), INTERVAL, now);
+
+        assert_eq!(hh.etx(now), ETX_PERFECT);
+    }
+
+    #[test]
+    fn seqno_wraps() {
+        let now = Instant::now();
+        let mut hh = HelloHistory::new();
+
+        hh.record(u16::MAX.into(), INTERVAL, now);
+        hh.record(0.into(), INTERVAL, now);
+
+        assert_eq!(hh.etx(now), ETX_PERFECT);
+        assert_ne!(hh.etx(now), ETX_MAX);
+    }
+
+    #[test]
+    fn silence_ages_history() {
+        let now = Instant::now();
+        let mut hh = HelloHistory::new();
+
+        for seqno in 0..16 {
+            hh.record(seqno.into(), INTERVAL, now);
+        }
+        assert_eq!(hh.etx(now + INTERVAL), ETX_PERFECT);
+
+        // 1.5 intervals without a HELLO, so 1 is lost.
+        assert_eq!(hh.etx(now + INTERVAL * 3 / 2), ETX_PERFECT * 256 / 225);
+        // Another interval later, the second one is lost as well.
+        assert_eq!(
+            hh.etx(now + INTERVAL * 3 / 2 + INTERVAL),
+            ETX_PERFECT * 256 / 196
+        );
+        // Eventually, all HELLO's in the history are lost.
+        assert_eq!(hh.etx(now + INTERVAL * 20), ETX_MAX);
+
+        // HELLO's arriving again, after the ones we missed, recover the link.
+        let later = now + INTERVAL * 20;
+        for seqno in 35..51 {
+            hh.record(seqno.into(), INTERVAL, later);
+        }
+        assert_eq!(hh.etx(later), ETX_PERFECT);
+    }
+}
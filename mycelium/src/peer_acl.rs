@@ -0,0 +1,148 @@
+//! Access control for inbound peer connections.
+//!
+//! A [`PeerAcl`] has an allow and a deny list, both of which can contain public keys and subnets.
+//! The source address of an inbound connection is checked against the subnets as soon as the
+//! connection is accepted, the public key of the remote is checked once the link handshake
+//! completed. Entries in the deny list always take precedence. If the allow list contains any
+//! subnets, the source address must be in one of them. Likewise, if the allow list contains any
+//! public keys, the remote must use one of them. Outbound connections to configured peers are not
+//! affected, but the public key of remotes learned through peer exchange or hole punching is
+//! checked once the link handshake completed, like for inbound connections.
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{crypto::PublicKey, subnet::Subnet};
+
+/// Access control list for inbound peer connections.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeerAcl {
+    /// Entries which are allowed to connect. Empty lists allow everything.
+    pub allow: AclEntries,
+    /// Entries which are never allowed to connect.
+    pub deny: AclEntries,
+}
+
+/// A list of public keys and subnets in a [`PeerAcl`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclEntries {
+    /// Public keys of remote nodes.
+    pub keys: Vec<PublicKey>,
+    /// Subnets containing the source address of remote nodes.
+    pub subnets: Vec<Subnet>,
+}
+
+/// The reason an inbound connection was rejected by a [`PeerAcl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The source address of the connection is denied, or not in an allowed subnet.
+    SourceAddress,
+    /// The public key of the remote is denied, or not in the allowed keys.
+    PublicKey,
+}
+
+impl PeerAcl {
+    /// Checks if a connection from the given source address is allowed.
+    pub fn allows_address(&self, ip: IpAddr) -> bool {
+        // Inbound IPv4 connections on a dual stack socket have an IPv4 mapped IPv6 address.
+        let ip = ip.to_canonical();
+
+        let contains_ip = |subnets: &[Subnet]| subnets.iter().any(|s| s.contains_ip(ip));
+
+        if contains_ip(&self.deny.subnets) {
+            return false;
+        }
+
+        self.allow.subnets.is_empty() || contains_ip(&self.allow.subnets)
+    }
+
+    /// Checks if a remote with the given public key is allowed.
+    pub fn allows_key(&self, key: &PublicKey) -> bool {
+        if self.deny.keys.contains(key) {
+            return false;
+        }
+
+        self.allow.keys.is_empty() || self.allow.keys.contains(key)
+    }
+
+    /// Checks if a legacy remote, which does not prove its public key, is allowed. This is only
+    /// the case if no key rules are configured at all. A denied remote could otherwise skip the
+    /// handshake to avoid the deny list.
+    pub fn allows_unverified(&self) -> bool {
+        self.allow.keys.is_empty() && self.deny.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use crate::crypto::{PublicKey, SecretKey};
+
+    use super::{AclEntries, PeerAcl};
+
+    #[test]
+    fn empty_acl_allows_everything() {
+        let acl = PeerAcl::default();
+
+        assert!(acl.allows_address(Ipv6Addr::LOCALHOST.into()));
+        assert!(acl.allows_key(&PublicKey::from(&SecretKey::new())));
+        assert!(acl.allows_unverified());
+    }
+
+    #[test]
+    fn deny_takes_precedence() {
+        let key = PublicKey::from(&SecretKey::new());
+        let acl = PeerAcl {
+            allow: AclEntries {
+                keys: vec![key],
+                subnets: vec!["10.0.0.0/8".parse().unwrap()],
+            },
+            deny: AclEntries {
+                keys: vec![key],
+                subnets: vec!["10.1.0.0/16".parse().unwrap()],
+            },
+        };
+
+        assert!(!acl.allows_key(&key));
+        assert!(!acl.allows_key(&PublicKey::from(&SecretKey::new())));
+        assert!(!acl.allows_unverified());
+        assert!(acl.allows_address(Ipv4Addr::new(10, 2, 0, 1).into()));
+        assert!(!acl.allows_address(Ipv4Addr::new(10, 1, 0, 1).into()));
+        assert!(!acl.allows_address(Ipv4Addr::new(192, 0, 2, 1).into()));
+    }
+
+    #[test]
+    fn denied_keys_reject_unverified_remotes() {
+        let denied = PublicKey::from(&SecretKey::new());
+        let acl = PeerAcl {
+            deny: AclEntries {
+                keys: vec![denied],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(!acl.allows_key(&denied));
+        assert!(acl.allows_key(&PublicKey::from(&SecretKey::new())));
+        assert!(!acl.allows_unverified());
+    }
+
+    #[test]
+    fn mapped_ipv4_addresses_match_ipv4_subnets() {
+        let acl = PeerAcl {
+            deny: AclEntries {
+                subnets: vec!["192.0.2.0/24".parse().unwrap()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mapped = IpAddr::V6(Ipv4Addr::new(192, 0, 2, 1).to_ipv6_mapped());
+        assert!(!acl.allows_address(mapped));
+        assert!(acl.allows_address(Ipv4Addr::new(198, 51, 100, 1).into()));
+    }
+}
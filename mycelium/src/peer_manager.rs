@@ -1,16 +1,29 @@
-use crate::connection::Quic;
+use crate::babel::{
+    HolePunchIntroduction, HolePunchRequest, PeerExchange, MAX_PEER_EXCHANGE_PEERS,
+};
+use crate::connection::handshake::{
+    self, HandshakeError, Remote, Role, CHANNEL_BINDING_LABEL, CHANNEL_BINDING_SIZE,
+};
+use crate::connection::{Connection, Prefixed, Quic, WebSocket};
+use crate::crypto::{PublicKey, SecretKey};
 use crate::endpoint::{Endpoint, Protocol};
 use crate::metrics::Metrics;
+use crate::packet::ControlPacket;
 use crate::peer::{Peer, PeerRef};
+use crate::peer_acl::{PeerAcl, RejectReason};
+use crate::proxy::{HttpProxy, ProxyConfig, ProxyStream};
 use crate::router::Router;
 use crate::router_id::RouterId;
 use futures::stream::FuturesUnordered;
 use futures::{FutureExt, StreamExt};
 #[cfg(feature = "private-network")]
 use openssl::ssl::{Ssl, SslAcceptor, SslConnector, SslMethod};
-use quinn::crypto::rustls::QuicClientConfig;
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
 use quinn::{MtuDiscoveryConfig, ServerConfig, TransportConfig};
+use rand::seq::SliceRandom;
+use rand::Rng;
 use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::server::WebPkiClientVerifier;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
@@ -21,13 +34,16 @@ use std::os::fd::AsFd;
 #[cfg(feature = "private-network")]
 use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{collections::hash_map::Entry, future::IntoFuture};
 use tokio::net::TcpStream;
 use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::Notify;
 use tokio::task::AbortHandle;
 use tokio::time::MissedTickBehavior;
+use tracing::field::display;
 use tracing::{debug, error, info, instrument, trace, warn};
 
 /// Magic bytes to identify a multicast UDP packet used in link local peer discovery.
@@ -40,9 +56,37 @@ const LL_PEER_DISCOVERY_GROUP: &str = "ff02::cafe";
 const LL_PEER_DISCOVERY_BEACON_INTERVAL: Duration = Duration::from_secs(60);
 /// The time between checking known peer liveness and trying to reconnect.
 const PEER_CONNECT_INTERVAL: Duration = Duration::from_secs(5);
+/// The maximum time between connection attempts to a peer we can't connect to. The time between
+/// attempts doubles after every failed attempt, starting at [`PEER_CONNECT_INTERVAL`].
+const MAX_PEER_CONNECT_BACKOFF: Duration = Duration::from_secs(300);
 /// The maximum amount of successive failures allowed when connecting to a local discovered peer,
 /// before it is forgotten.
 const MAX_FAILED_LOCAL_PEER_CONNECTION_ATTEMPTS: usize = 3;
+/// The maximum amount of successive failures allowed when connecting to a hole punched peer,
+/// before it is forgotten. Hole punching only works if both sides connect at the same time, so
+/// there is no point in trying again without a new introduction.
+const MAX_FAILED_HOLE_PUNCH_CONNECTION_ATTEMPTS: usize = 1;
+/// The time between looking for remotes we could connect to directly with hole punching.
+const HOLE_PUNCH_REQUEST_INTERVAL: Duration = Duration::from_secs(60);
+/// The minimum time between hole punch requests for the same remote.
+const HOLE_PUNCH_RETRY_INTERVAL: Duration = Duration::from_secs(600);
+/// The maximum amount of hole punch requests sent every [`HOLE_PUNCH_REQUEST_INTERVAL`].
+const MAX_HOLE_PUNCH_REQUESTS: usize = 8;
+/// The maximum amount of hole punched peers. Introductions are ignored once this many are known.
+const MAX_HOLE_PUNCHED_PEERS: usize = 16;
+/// The maximum amount of successive failures allowed when connecting to a peer learned through
+/// peer exchange, before it is forgotten.
+const MAX_FAILED_PEER_EXCHANGE_CONNECTION_ATTEMPTS: usize = 3;
+/// The time between sending the endpoints of our peers to neighbours.
+const PEER_EXCHANGE_INTERVAL: Duration = Duration::from_secs(300);
+/// The time between checking if we need to connect to more peers learned through peer exchange.
+const PEER_CANDIDATE_CONNECT_INTERVAL: Duration = Duration::from_secs(30);
+/// The time after which a peer candidate is forgotten if no neighbour shared it again.
+const PEER_CANDIDATE_TIMEOUT: Duration = Duration::from_secs(3600);
+/// The maximum amount of peers shared with a neighbour every [`PEER_EXCHANGE_INTERVAL`].
+const MAX_SHARED_PEERS: usize = 8;
+/// The maximum amount of peer candidates we keep.
+const MAX_PEER_CANDIDATES: usize = 64;
 /// The amount of time allowed for a peer to finish the quic handshake when it connects to us. This
 /// prevents a (mallicious) peer from hogging server resources. 10 seconds should be a reasonable
 /// default for this, though it can certainly be made more strict if required.
@@ -53,6 +97,23 @@ const INBOUND_QUIC_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 /// these in parralel. For now, 10 in parallel should be sufficient, though this can be
 /// increased/decreased based on observations.
 const MAX_INBOUND_CONCURRENT_QUICK_HANDSHAKES: usize = 10;
+/// The amount of time allowed for a peer to finish the TLS (if enabled) or WebSocket, and link
+/// handshake when it connects to us over TCP.
+const INBOUND_TCP_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The maximum amount of concurrent handshakes to process from peers connecting to us over TCP,
+/// per listener. See [`MAX_INBOUND_CONCURRENT_QUICK_HANDSHAKES`].
+const MAX_INBOUND_CONCURRENT_TCP_HANDSHAKES: usize = 10;
+/// Server name used in quic connections. In a private network, this name is also in the
+/// certificates of the nodes.
+const QUIC_SERVER_NAME: &str = "dummy.mycelium";
+/// Context used to derive the private network quic CA key from the network name and PSK.
+const PRIVATE_NETWORK_QUIC_CA_KEY_CONTEXT: &str = "mycelium private network quic CA key v1";
+/// Common name of the private network quic CA certificate.
+const PRIVATE_NETWORK_QUIC_CA_NAME: &str = "mycelium private network";
+/// PKCS#8 v1 encoding of an Ed25519 private key, without the actual 32 byte key.
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
 
 /// The PeerManager creates new peers by connecting to configured addresses, and setting up the
 /// connection. Once a connection is established, the created [`Peer`] is handed over to the
@@ -73,6 +134,10 @@ pub enum PeerType {
     LinkLocalDiscovery,
     /// A remote which initiated a connection to us.
     Inbound,
+    /// Peer introduced by a neighbour, connected to with UDP hole punching.
+    HolePunched,
+    /// Peer learned from a neighbour through peer exchange.
+    PeerExchange,
 }
 
 /// Local info about a peer.
@@ -86,8 +151,19 @@ struct PeerInfo {
     /// Amount of failed times we tried to connect to this peer. This is reset after a successful
     /// connection.
     connection_attempts: usize,
+    /// Earliest time at which we try to connect to this peer again.
+    next_connection_attempt: Instant,
+    /// Error of the last failed connection attempt, if the last attempt failed.
+    last_connection_error: Option<String>,
     /// Keep track of the amount of bytes we've sent to and received from this peer.
     con_traffic: ConnectionTraffic,
+    /// Cost added to the link cost of this peer.
+    link_cost_offset: u16,
+    /// The remote does not support the link handshake, so we connect to it without one.
+    legacy: bool,
+    /// Public key the remote must prove in the link handshake. This is set for remotes learned
+    /// from neighbours, as a neighbour could point us to any endpoint.
+    expected_public_key: Option<PublicKey>,
 }
 
 /// Counters for the amount of traffic written to and received from a [`Peer`].
@@ -126,6 +202,20 @@ pub struct PeerStats {
     pub tx_bytes: u64,
     /// Amount of bytes received from this [`Peer`].
     pub rx_bytes: u64,
+    /// Cost added to the link cost of this [`Peer`].
+    #[serde(default)]
+    pub link_cost_offset: u16,
+    /// Public key of the [`Peer`], as verified during the link handshake. This is only set if
+    /// there is a working connection.
+    #[serde(default)]
+    pub public_key: Option<PublicKey>,
+    /// Unix timestamp, in seconds, of the next connection attempt to this [`Peer`]. This is only
+    /// set if there is no connection, and we will try to connect to the [`Peer`].
+    #[serde(default)]
+    pub next_connection_attempt: Option<u64>,
+    /// Error of the last failed connection attempt to this [`Peer`], if the last attempt failed.
+    #[serde(default)]
+    pub last_connection_error: Option<String>,
 }
 
 impl PeerInfo {
@@ -140,6 +230,25 @@ impl PeerInfo {
     fn written(&self) -> u64 {
         self.con_traffic.rx_bytes.load(Ordering::Relaxed)
     }
+
+    /// Record a failed connection attempt, and schedule the next attempt.
+    fn connection_failed(&mut self, error: String) {
+        self.connection_attempts += 1;
+        self.next_connection_attempt =
+            Instant::now() + connection_backoff(self.connection_attempts);
+        self.last_connection_error = Some(error);
+    }
+}
+
+/// The time to wait before connecting to a peer after the given amount of successive failed
+/// connection attempts. This doubles with every failed attempt up to
+/// [`MAX_PEER_CONNECT_BACKOFF`]. A random jitter of up to half of the delay is subtracted, so
+/// nodes which lost connection to the same peer at the same time don't reconnect in lockstep.
+fn connection_backoff(failed_attempts: usize) -> Duration {
+    let delay = PEER_CONNECT_INTERVAL
+        .saturating_mul(1 << failed_attempts.saturating_sub(1).min(16))
+        .min(MAX_PEER_CONNECT_BACKOFF);
+    rand::thread_rng().gen_range(delay / 2..=delay)
 }
 
 /// Marker error to indicate a [`peer`](Endpoint) is already known.
@@ -150,16 +259,83 @@ pub struct PeerExists;
 #[derive(Debug)]
 pub struct PeerNotFound;
 
+/// Reasons a [`Peer`] could not be set up on a new connection.
+#[derive(Debug)]
+enum NewPeerError {
+    /// The link handshake failed.
+    Handshake(HandshakeError),
+    /// The remote completed the handshake, but its public key is not allowed by the [`PeerAcl`].
+    Rejected(PublicKey),
+    /// The remote completed the handshake with a different public key than expected.
+    UnexpectedKey {
+        expected: PublicKey,
+        actual: PublicKey,
+    },
+    /// The remote does not support the link handshake, and legacy remotes are not allowed.
+    Legacy,
+    /// The [`Peer`] could not be created.
+    Io(io::Error),
+}
+
+/// Reasons an outbound connection to a peer could not be set up.
+#[derive(Debug)]
+enum ConnectError {
+    /// The remote does not support the link handshake.
+    Legacy,
+    /// The connection failed for any other reason.
+    Failed(String),
+}
+
+/// A connection which completed the link handshake, or a connection to a legacy remote.
+struct PeerConnection {
+    /// The neighbour on the other side of the connection. If we were already connected to the
+    /// remote, this is the existing [`Peer`], and the connection was added to it.
+    peer: Peer,
+    /// Refers to this specific connection of `peer`.
+    pr: PeerRef,
+    /// Whether `peer` was created for this connection, in which case it still needs to be added to
+    /// the router.
+    new: bool,
+}
+
+impl PeerConnection {
+    /// The public key of the remote, as verified during the link handshake. Legacy remotes don't
+    /// have one.
+    fn remote_public_key(&self) -> Option<PublicKey> {
+        self.peer.remote_public_key()
+    }
+}
+
 /// PSK used to set up a shared network. Currently 32 bytes though this might change in the future.
 pub type PrivateNetworkKey = [u8; 32];
 
 struct Inner<M> {
     /// Router is unfortunately wrapped in a Mutex, because router is not Sync.
     router: Mutex<Router<M>>,
+    /// Key used to authenticate the node on new connections.
+    node_secret_key: SecretKey,
+    /// Access control list for inbound connections.
+    peer_acl: RwLock<PeerAcl>,
+    /// Accept legacy remotes which don't support the link handshake, and connect to them without
+    /// one.
+    legacy_peers: bool,
     peers: Mutex<HashMap<Endpoint, PeerInfo>>,
+    /// Connected neighbours by their public key. New connections to a remote which is already
+    /// connected are added to the existing [`Peer`].
+    neighbours: Mutex<HashMap<PublicKey, PeerRef>>,
     /// Listen port for new peer connections
     tcp_listen_port: u16,
     quic_socket: Option<quinn::Endpoint>,
+    /// TLS config used when connecting to quic peers in a private network. In a public network,
+    /// the certificate of the remote is not verified, so this is [`None`].
+    private_quic_client_config: Option<Arc<QuicClientConfig>>,
+    /// Listen port for new peer connections over WebSockets, if enabled.
+    ws_listen_port: Option<u16>,
+    /// Proxies used for outbound WebSocket connections.
+    ws_proxy: ProxyConfig,
+    /// Notified to check for peers to connect to immediately, instead of waiting for the next
+    /// [`PEER_CONNECT_INTERVAL`].
+    connect_now: Notify,
     /// Identity and name of a private network, if one exists
     private_network_config: Option<(String, [u8; 32])>,
     metrics: M,
@@ -174,37 +350,63 @@ where
     pub fn new(
         router: Router<M>,
         static_peers_sockets: Vec<Endpoint>,
+        peer_link_cost_offsets: HashMap<Endpoint, u16>,
+        peer_acl: PeerAcl,
+        legacy_peers: bool,
         tcp_listen_port: u16,
         quic_listen_port: Option<u16>,
+        ws_listen_port: Option<u16>,
+        ws_proxy: ProxyConfig,
         peer_discovery_port: u16,
         disable_peer_discovery: bool,
+        tlv_stream: UnboundedReceiver<(ControlPacket, Peer)>,
+        hole_punching: bool,
+        peer_exchange_target: Option<usize>,
         private_network_config: Option<(String, PrivateNetworkKey)>,
         metrics: M,
         firewall_mark: Option<u32>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let is_private_net = private_network_config.is_some();
 
-        // Currently we don't support Quic when a private network is used.
-        let quic_socket = if !is_private_net {
+        let (quic_socket, private_quic_client_config) =
             if let Some(quic_listen_port) = quic_listen_port {
-                Some(make_quic_endpoint(
-                    router.router_id(),
-                    quic_listen_port,
-                    firewall_mark,
-                )?)
+                // In a private network, quic connections are authenticated with certificates
+                // issued by a CA derived from the network name and PSK.
+                let (server_crypto, client_crypto) = match &private_network_config {
+                    Some((net_name, net_key)) => {
+                        let (server_crypto, client_crypto) =
+                            private_network_quic_crypto(net_name, net_key)?;
+                        (
+                            Some(server_crypto),
+                            Some(Arc::new(QuicClientConfig::try_from(client_crypto)?)),
+                        )
+                    }
+                    None => (None, None),
+                };
+                (
+                    Some(make_quic_endpoint(
+                        router.router_id(),
+                        quic_listen_port,
+                        firewall_mark,
+                        server_crypto,
+                    )?),
+                    client_crypto,
+                )
             } else {
-                None
-            }
-        } else {
-            None
-        };
+                (None, None)
+            };
 
         // Set the initially configured peer count in metrics.
         metrics.peer_manager_known_peers(static_peers_sockets.len());
 
+        let node_secret_key = router.node_secret_key();
+
         let mut peer_manager = PeerManager {
             inner: Arc::new(Inner {
                 router: Mutex::new(router),
+                node_secret_key,
+                peer_acl: RwLock::new(peer_acl),
+                legacy_peers,
                 peers: Mutex::new(
                     static_peers_sockets
                         .into_iter()
@@ -212,6 +414,8 @@ where
                         // loop will perform the actual check and figure out they are dead, then
                         // (re)connect.
                         .map(|s| {
+                            let link_cost_offset =
+                                peer_link_cost_offsets.get(&s).copied().unwrap_or_default();
                             (
                                 s,
                                 PeerInfo {
@@ -219,17 +423,27 @@ where
                                     connecting: false,
                                     pr: PeerRef::new(),
                                     connection_attempts: 0,
+                                    next_connection_attempt: Instant::now(),
+                                    last_connection_error: None,
                                     con_traffic: ConnectionTraffic {
                                         tx_bytes: Arc::new(AtomicU64::new(0)),
                                         rx_bytes: Arc::new(AtomicU64::new(0)),
                                     },
+                                    link_cost_offset,
+                                    legacy: false,
+                                    expected_public_key: None,
                                 },
                             )
                         })
                         .collect(),
                 ),
+                neighbours: Mutex::new(HashMap::new()),
                 tcp_listen_port,
                 quic_socket,
+                private_quic_client_config,
+                ws_listen_port,
+                ws_proxy,
+                connect_now: Notify::new(),
                 private_network_config,
                 metrics,
                 firewall_mark,
@@ -244,11 +458,16 @@ where
         peer_manager.abort_handles.push(handle.abort_handle());
         if is_private_net {
             info!("Enabled private network mode");
-        } else if peer_manager.inner.quic_socket.is_some() {
-            // Currently quic is not supported in private network mode.
+        }
+        if peer_manager.inner.quic_socket.is_some() {
             let handle = tokio::spawn(peer_manager.inner.clone().quic_listener());
             peer_manager.abort_handles.push(handle.abort_handle());
         };
+        // WebSockets are not supported in private network mode.
+        if !is_private_net && peer_manager.inner.ws_listen_port.is_some() {
+            let handle = tokio::spawn(peer_manager.inner.clone().ws_listener());
+            peer_manager.abort_handles.push(handle.abort_handle());
+        }
 
         // Start (re)connecting to outbound/local peers
         let handle = tokio::spawn(peer_manager.inner.clone().connect_to_peers());
@@ -266,17 +485,27 @@ where
             peer_manager.abort_handles.push(handle.abort_handle());
         }
 
+        // Hole punching needs a quic socket, as that is what the NAT is punched for.
+        let hole_punching = hole_punching && peer_manager.inner.quic_socket.is_some();
+        let handle = tokio::spawn(peer_manager.inner.clone().coordinate_with_neighbours(
+            tlv_stream,
+            hole_punching,
+            peer_exchange_target,
+        ));
+        peer_manager.abort_handles.push(handle.abort_handle());
+
         Ok(peer_manager)
     }
 
-    /// Add a new peer to the system.
+    /// Add a new peer to the system. The `link_cost_offset` is added to the link cost of the
+    /// peer, which makes routes through it less preferable.
     ///
     /// The peer starts of as a dead peer, and connecting is handled in the reconnect loop.
     ///
     /// # Errors
     ///
     /// This function returns an error if the [`Endpoint`] is already known.
-    pub fn add_peer(&self, peer: Endpoint) -> Result<(), PeerExists> {
+    pub fn add_peer(&self, peer: Endpoint, link_cost_offset: u16) -> Result<(), PeerExists> {
         let mut peer_map = self.inner.peers.lock().unwrap();
         if peer_map.contains_key(&peer) {
             return Err(PeerExists);
@@ -288,16 +517,51 @@ where
                 connecting: false,
                 pr: PeerRef::new(),
                 connection_attempts: 0,
+                next_connection_attempt: Instant::now(),
+                last_connection_error: None,
                 con_traffic: ConnectionTraffic {
                     tx_bytes: Arc::new(AtomicU64::new(0)),
                     rx_bytes: Arc::new(AtomicU64::new(0)),
                 },
+                link_cost_offset,
+                legacy: false,
+                expected_public_key: None,
             },
         );
 
         Ok(())
     }
 
+    /// Change the cost added to the link cost of a peer. If the peer is currently connected, the
+    /// new cost is used immediately.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no peer identified by the given [`Endpoint`].
+    pub fn set_link_cost_offset(
+        &self,
+        endpoint: &Endpoint,
+        link_cost_offset: u16,
+    ) -> Result<(), PeerNotFound> {
+        let peer = {
+            let mut peer_map = self.inner.peers.lock().unwrap();
+            let pi = peer_map.get_mut(endpoint).ok_or(PeerNotFound)?;
+            pi.link_cost_offset = link_cost_offset;
+            pi.pr.upgrade()
+        };
+
+        if let Some(peer) = peer {
+            peer.set_link_cost_offset(link_cost_offset);
+            self.inner
+                .router
+                .lock()
+                .unwrap()
+                .peer_link_cost_changed(&peer);
+        }
+
+        Ok(())
+    }
+
     /// Delete a peer from the system.
     ///
     /// The peer will be disconnected if it is currently connected.
@@ -308,11 +572,49 @@ where
     pub fn delete_peer(&self, endpoint: &Endpoint) -> Result<(), PeerNotFound> {
         let mut peer_map = self.inner.peers.lock().unwrap();
         peer_map.remove(endpoint).ok_or(PeerNotFound).map(|pi| {
-            // Make sure we kill the peer connection if one exists
-            if let Some(peer) = pi.pr.upgrade() {
+            // Make sure we kill the peer connection if one exists. Other connections to the same
+            // remote are not affected.
+            pi.pr.died();
+        })
+    }
+
+    /// Get the [`PeerAcl`] applied to inbound connections.
+    pub fn peer_acl(&self) -> PeerAcl {
+        self.inner.peer_acl.read().unwrap().clone()
+    }
+
+    /// Replace the [`PeerAcl`] applied to inbound connections. Inbound peers, and peers learned
+    /// from neighbours, which are connected but not allowed by the new [`PeerAcl`] are
+    /// disconnected.
+    pub fn set_peer_acl(&self, peer_acl: PeerAcl) {
+        for (endpoint, pi) in self.inner.peers.lock().unwrap().iter() {
+            let inbound = pi.pt == PeerType::Inbound;
+            if !inbound && pi.expected_public_key.is_none() {
+                continue;
+            }
+            let Some(peer) = pi.pr.upgrade() else {
+                continue;
+            };
+            // Inbound peers are always identified by their socket address. For outbound peers,
+            // this is the address of the remote, not our source address.
+            let address_allowed = !inbound
+                || endpoint
+                    .address()
+                    .is_none_or(|address| peer_acl.allows_address(address.ip()));
+            let key_allowed = match peer.remote_public_key() {
+                Some(remote_public_key) => peer_acl.allows_key(&remote_public_key),
+                None => peer_acl.allows_unverified(),
+            };
+            if !key_allowed {
+                info!(%endpoint, "Disconnecting peer which is no longer allowed");
                 peer.died();
+            } else if !address_allowed {
+                info!(%endpoint, "Disconnecting inbound connection which is no longer allowed");
+                pi.pr.died();
             }
-        })
+        }
+
+        *self.inner.peer_acl.write().unwrap() = peer_acl;
     }
 
     /// Get a view of all known peers and their stats.
@@ -327,12 +629,31 @@ where
             } else {
                 ConnectionState::Dead
             };
+            let next_connection_attempt =
+                if connection_state == ConnectionState::Dead && peer_info.pt != PeerType::Inbound {
+                    let delay = peer_info
+                        .next_connection_attempt
+                        .saturating_duration_since(Instant::now());
+                    (SystemTime::now() + delay)
+                        .duration_since(UNIX_EPOCH)
+                        .ok()
+                        .map(|timestamp| timestamp.as_secs())
+                } else {
+                    None
+                };
             pi.push(PeerStats {
-                endpoint: *endpoint,
+                endpoint: endpoint.clone(),
                 pt: peer_info.pt.clone(),
                 connection_state,
                 tx_bytes: peer_info.written(),
                 rx_bytes: peer_info.read(),
+                link_cost_offset: peer_info.link_cost_offset,
+                public_key: peer_info
+                    .pr
+                    .upgrade()
+                    .and_then(|peer| peer.remote_public_key()),
+                next_connection_attempt,
+                last_connection_error: peer_info.last_connection_error.clone(),
             });
         }
         pi
@@ -374,48 +695,84 @@ where
                         // Regardless of what happened, we are no longer connecting.
                         self.metrics.peer_manager_connection_finished();
                         pi.connecting = false;
-                        if let Some(peer) = maybe_new_peer {
-                            // We did find a new Peer, insert into router and keep track of it
-                            // Use fully qualified call to aid compiler in type inference.
-                            pi.pr = Peer::refer(&peer);
-                            self.router.lock().unwrap().add_peer_interface(peer);
-
-                            // We successfully connected, reset the connection_attempts counter to 0
-                            pi.connection_attempts = 0;
-                        } else {
-                            // Only log with error level on the first connection failure, to avoid spamming the logs
-                            if pi.connection_attempts == 0 {
-                                error!(endpoint.address=%endpoint.address(), endpoint.proto=%endpoint.proto(), "Couldn't connect to endpoint, turn on debug logging for more details");
-                            } else {
-                                debug!(endpoint.address=%endpoint.address(), endpoint.proto=%endpoint.proto(), attempt=%pi.connection_attempts+1, "Couldn't connect to endpoint")
+                        match maybe_new_peer {
+                            // Destructure the connection to aid the compiler in type inference.
+                            Ok(PeerConnection { peer, pr, new }) => {
+                                // We did find a new connection, keep track of it. If it is a new
+                                // Peer, also insert it into the router.
+                                pi.pr = pr;
+                                if new {
+                                    peer.set_link_cost_offset(pi.link_cost_offset);
+                                    self.router.lock().unwrap().add_peer_interface(peer);
+                                }
+
+                                // We successfully connected, reset the backoff. If the connection dies
+                                // we reconnect immediately.
+                                pi.connection_attempts = 0;
+                                pi.next_connection_attempt = Instant::now();
+                                pi.last_connection_error = None;
+                            }
+                            // Remotes with an expected public key must prove it, which legacy
+                            // remotes can't.
+                            Err(ConnectError::Legacy) if self.legacy_peers && !pi.legacy && pi.expected_public_key.is_none() => {
+                                // Not a failure, try again right away without the handshake.
+                                info!(endpoint.address=%endpoint.host(), endpoint.proto=%endpoint.proto(), "Remote does not support the link handshake, reconnecting as legacy peer");
+                                pi.legacy = true;
+                                pi.next_connection_attempt = Instant::now();
+                                self.connect_now.notify_one();
                             }
+                            Err(err) => {
+                                // Only log with error level on the first connection failure, to avoid spamming the logs
+                                if pi.connection_attempts == 0 {
+                                    error!(endpoint.address=%endpoint.host(), endpoint.proto=%endpoint.proto(), %err, "Couldn't connect to endpoint");
+                                } else {
+                                    debug!(endpoint.address=%endpoint.host(), endpoint.proto=%endpoint.proto(), attempt=%pi.connection_attempts+1, %err, "Couldn't connect to endpoint")
+                                }
 
-                            // Connection failed, add a failed attempt and forget about the peer if
-                            // needed.
-                            pi.connection_attempts += 1;
-                            if pi.pt == PeerType::LinkLocalDiscovery
-                                && pi.connection_attempts >= MAX_FAILED_LOCAL_PEER_CONNECTION_ATTEMPTS {
-                                info!(endpoint.address=%endpoint.address(), endpoint.proto=%endpoint.proto(), "Forgetting about locally discovered peer after failing to connect to it");
-                                peers.remove(&endpoint);
+                                // Connection failed, add a failed attempt and forget about the peer if
+                                // needed. If this was a legacy connection, check if the remote
+                                // supports the handshake by now on the next attempt.
+                                pi.legacy = false;
+                                pi.connection_failed(err.to_string());
+                                if pi.pt == PeerType::LinkLocalDiscovery
+                                    && pi.connection_attempts >= MAX_FAILED_LOCAL_PEER_CONNECTION_ATTEMPTS {
+                                    info!(endpoint.address=%endpoint.host(), endpoint.proto=%endpoint.proto(), "Forgetting about locally discovered peer after failing to connect to it");
+                                    peers.remove(&endpoint);
+                                } else if pi.pt == PeerType::HolePunched
+                                    && pi.connection_attempts >= MAX_FAILED_HOLE_PUNCH_CONNECTION_ATTEMPTS {
+                                    info!(endpoint.address=%endpoint.host(), endpoint.proto=%endpoint.proto(), "Forgetting about hole punched peer after failing to connect to it");
+                                    peers.remove(&endpoint);
+                                } else if pi.pt == PeerType::PeerExchange
+                                    && pi.connection_attempts >= MAX_FAILED_PEER_EXCHANGE_CONNECTION_ATTEMPTS {
+                                    info!(endpoint.address=%endpoint.host(), endpoint.proto=%endpoint.proto(), "Forgetting about peer learned through peer exchange after failing to connect to it");
+                                    peers.remove(&endpoint);
+                                }
                             }
                         }
+                    } else if let Ok(PeerConnection { pr, .. }) = maybe_new_peer {
+                        // The peer was removed while we were connecting, close the new connection.
+                        pr.died();
                     }
                 }
+                _ = self.connect_now.notified() => {
+                    peer_check_interval.reset_immediately();
+                }
                 _ = peer_check_interval.tick() => {
                     // Remove dead inbound peers
                     self.peers.lock().unwrap().retain(|_, v| v.pt != PeerType::Inbound || v.pr.alive());
                     debug!("Looking for dead peers");
                     // check if there is an entry for the peer in the router's peer list
+                    let now = Instant::now();
                     for (endpoint, pi) in self.peers.lock().unwrap().iter_mut() {
-                        if !pi.connecting && !pi.pr.alive() {
-                            debug!(endpoint.address=%endpoint.address(), endpoint.proto=%endpoint.proto(), "Found dead peer");
+                        if !pi.connecting && !pi.pr.alive() && pi.next_connection_attempt <= now {
+                            debug!(endpoint.address=%endpoint.host(), endpoint.proto=%endpoint.proto(), "Found dead peer");
                             if pi.pt == PeerType::Inbound {
-                                debug!(endpoint.address=%endpoint.address(), endpoint.proto=%endpoint.proto(), "Refusing to reconnect to inbound peer");
+                                debug!(endpoint.address=%endpoint.host(), endpoint.proto=%endpoint.proto(), "Refusing to reconnect to inbound peer");
                                 continue
                             }
                             // Mark that we are connecting to the peer.
                             pi.connecting = true;
-                            connection_futures.push(self.clone().connect_peer(*endpoint, pi.con_traffic.clone()));
+                            connection_futures.push(self.clone().connect_peer(endpoint.clone(), pi.con_traffic.clone(), pi.legacy, pi.expected_public_key));
                             self.metrics.peer_manager_connection_attempted();
                         }
                     }
@@ -424,32 +781,97 @@ where
         }
     }
 
-    /// Create a new connection to a remote peer
-    #[instrument(skip_all, fields(endpoint.proto=%endpoint.proto(), endpoint.address=%endpoint.address()))]
+    /// Create a new connection to a remote peer. If the remote is known to be `legacy`, the
+    /// connection is set up without the link handshake. If an `expected_public_key` is given, the
+    /// remote must prove it has this key, otherwise the connection is closed.
+    #[instrument(skip_all, fields(endpoint.proto=%endpoint.proto(), endpoint.address=%endpoint.host()))]
     async fn connect_peer(
         self: Arc<Self>,
         endpoint: Endpoint,
         ct: ConnectionTraffic,
-    ) -> (Endpoint, Option<Peer>) {
+        legacy: bool,
+        expected_public_key: Option<PublicKey>,
+    ) -> (Endpoint, Result<PeerConnection, ConnectError>) {
         debug!("Connecting");
-        match endpoint.proto() {
-            Protocol::Tcp | Protocol::Tls => self.connect_tcp_peer(endpoint, ct).await,
-            Protocol::Quic => self.connect_quic_peer(endpoint, ct).await,
+        // WebSocket connections through a proxy go to the proxy, which resolves the endpoint.
+        let proxy = match endpoint.proto() {
+            Protocol::Ws | Protocol::Wss => self
+                .ws_proxy
+                .proxy_for(endpoint.host(), endpoint.proto() == Protocol::Wss),
+            _ => None,
+        };
+        // Hostnames are resolved on every attempt, so we follow changes in their addresses.
+        let resolved = match proxy {
+            Some(proxy) => proxy
+                .resolve()
+                .await
+                .map_err(|e| format!("Couldn't resolve proxy {proxy}: {e}")),
+            None => endpoint
+                .resolve()
+                .await
+                .map_err(|e| format!("Couldn't resolve endpoint: {e}")),
+        };
+        let addresses = match resolved {
+            Ok(addresses) => addresses,
+            Err(e) => return (endpoint, Err(e.into())),
+        };
+
+        let mut last_error =
+            ConnectError::from(String::from("Endpoint did not resolve to any address"));
+        for address in addresses {
+            let res = match endpoint.proto() {
+                Protocol::Tcp | Protocol::Tls => {
+                    self.connect_tcp_peer(&endpoint, address, &ct, legacy, expected_public_key)
+                        .await
+                }
+                Protocol::Quic => {
+                    self.connect_quic_peer(address, &ct, legacy, expected_public_key)
+                        .await
+                }
+                Protocol::Ws | Protocol::Wss => {
+                    self.connect_ws_peer(
+                        &endpoint,
+                        address,
+                        proxy,
+                        &ct,
+                        legacy,
+                        expected_public_key,
+                    )
+                    .await
+                }
+            };
+            match res {
+                Ok(peer) => return (endpoint, Ok(peer)),
+                // All addresses of the endpoint belong to the same remote.
+                Err(ConnectError::Legacy) => return (endpoint, Err(ConnectError::Legacy)),
+                Err(e) => {
+                    debug!(%address, err=%e, "Couldn't connect to address");
+                    last_error = e;
+                }
+            }
         }
+
+        (endpoint, Err(last_error))
     }
 
+    #[instrument(skip_all, fields(address=%address))]
     async fn connect_tcp_peer(
-        self: Arc<Self>,
-        endpoint: Endpoint,
-        ct: ConnectionTraffic,
-    ) -> (Endpoint, Option<Peer>) {
+        &self,
+        endpoint: &Endpoint,
+        address: SocketAddr,
+        ct: &ConnectionTraffic,
+        legacy: bool,
+        expected_public_key: Option<PublicKey>,
+    ) -> Result<PeerConnection, ConnectError> {
         match (endpoint.proto(), &self.private_network_config) {
             (Protocol::Tcp, Some(_)) => {
                 warn!("Attempting to connect over Tcp while a private network is configured, connection will be upgraded to Tls")
             }
             (Protocol::Tls, None) => {
                 warn!("Attempting to connect over Tls while a private network is not enabled, refusing to connect. Use \"Tcp\" instead");
-                return (endpoint, None);
+                return Err("Tls is only supported in a private network"
+                    .to_string()
+                    .into());
             }
             _ => {}
         }
@@ -479,7 +901,7 @@ where
             None
         };
 
-        match TcpStream::connect(endpoint.address())
+        match TcpStream::connect(address)
             .map(|result| result.and_then(|socket| set_fw_mark(socket, self.firewall_mark)))
             .await
         {
@@ -487,35 +909,22 @@ where
                 debug!("Opened connection");
                 // Make sure Nagle's algorithm is disabled as it can cause latency spikes.
                 if let Err(e) = peer_stream.set_nodelay(true) {
-                    debug!(err=%e, "Couldn't disable Nagle's algorithm on stream");
-                    return (endpoint, None);
+                    return Err(format!("Couldn't disable Nagle's algorithm on stream: {e}").into());
                 }
 
-                // Scope the MutexGuard, if we don't do this the future won't be Send
-                let (router_data_tx, router_control_tx, dead_peer_sink) = {
-                    let router = self.router.lock().unwrap();
-                    (
-                        router.router_data_tx(),
-                        router.router_control_tx(),
-                        router.dead_peer_sink().clone(),
-                    )
-                };
-
                 #[cfg(feature = "private-network")]
                 let res = {
                     if let Some(connector) = connector {
                         let ssl = match Ssl::new(connector.context()) {
                             Ok(ssl) => ssl,
                             Err(e) => {
-                                debug!(err=%e, "Failed to create SSL object from acceptor after connecting to remote");
-                                return (endpoint, None);
+                                return Err(format!("Failed to create SSL object from acceptor after connecting to remote: {e}").into());
                             }
                         };
                         let mut ssl_stream = match tokio_openssl::SslStream::new(ssl, peer_stream) {
                             Ok(ssl_stream) => ssl_stream,
                             Err(e) => {
-                                debug!(err=%e, "Failed to create TLS stream from tcp connection to endpoint");
-                                return (endpoint, None);
+                                return Err(format!("Failed to create TLS stream from tcp connection to endpoint: {e}").into());
                             }
                         };
 
@@ -523,85 +932,88 @@ where
                         let pinned_stream = Pin::new(&mut ssl_stream);
                         if let Err(e) = pinned_stream.connect().await {
                             // Error here is likely a misconfigured server.
-                            debug!(err=%e, "Could not initiate TLS stream");
-                            return (endpoint, None);
+                            return Err(format!("Could not initiate TLS stream: {e}").into());
                         }
                         debug!("Completed TLS handshake");
 
-                        Peer::new(
-                            router_data_tx,
-                            router_control_tx,
+                        let mut channel_binding = [0; CHANNEL_BINDING_SIZE];
+                        if let Err(e) = ssl_stream.ssl().export_keying_material(
+                            &mut channel_binding,
+                            CHANNEL_BINDING_LABEL,
+                            None,
+                        ) {
+                            return Err(format!(
+                                "Could not export keying material from TLS stream: {e}"
+                            )
+                            .into());
+                        }
+
+                        self.new_peer(
                             ssl_stream,
-                            dead_peer_sink,
-                            ct.tx_bytes,
-                            ct.rx_bytes,
+                            &channel_binding,
+                            ct,
+                            false,
+                            legacy,
+                            expected_public_key,
                         )
+                        .await
                     } else {
-                        Peer::new(
-                            router_data_tx,
-                            router_control_tx,
-                            peer_stream,
-                            dead_peer_sink,
-                            ct.tx_bytes,
-                            ct.rx_bytes,
-                        )
+                        self.new_peer(peer_stream, &[], ct, false, legacy, expected_public_key)
+                            .await
                     }
                 };
 
                 #[cfg(not(feature = "private-network"))]
-                let res = Peer::new(
-                    router_data_tx,
-                    router_control_tx,
-                    peer_stream,
-                    dead_peer_sink,
-                    ct.tx_bytes,
-                    ct.rx_bytes,
-                );
+                let res = self
+                    .new_peer(peer_stream, &[], ct, false, legacy, expected_public_key)
+                    .await;
 
                 match res {
                     Ok(new_peer) => {
-                        info!("Connected to new peer");
-                        (endpoint, Some(new_peer))
-                    }
-                    Err(e) => {
-                        debug!(err=%e, "Failed to spawn peer");
-                        (endpoint, None)
+                        info!(
+                            remote_public_key = new_peer.remote_public_key().map(display),
+                            "Connected to new peer"
+                        );
+                        Ok(new_peer)
                     }
+                    Err(e) => Err(e.into()),
                 }
             }
-            Err(e) => {
-                debug!(err=%e, "Couldn't connect");
-                (endpoint, None)
-            }
+            Err(e) => Err(format!("Couldn't connect: {e}").into()),
         }
     }
 
+    #[instrument(skip_all, fields(address=%address))]
     async fn connect_quic_peer(
-        self: Arc<Self>,
-        endpoint: Endpoint,
-        ct: ConnectionTraffic,
-    ) -> (Endpoint, Option<Peer>) {
+        &self,
+        address: SocketAddr,
+        ct: &ConnectionTraffic,
+        legacy: bool,
+        expected_public_key: Option<PublicKey>,
+    ) -> Result<PeerConnection, ConnectError> {
         let quic_socket = if let Some(quic_socket) = &self.quic_socket {
             quic_socket
         } else {
-            debug!("Attempting to connect to quic peer while quic is disabled");
-            return (endpoint, None);
+            return Err("Quic is disabled".to_string().into());
         };
-        let provider = rustls::crypto::CryptoProvider::get_default()
-            .expect("We have a quic socket so there is a crypto provider installed");
-        let qcc = match QuicClientConfig::try_from(
-            rustls::ClientConfig::builder()
-                .dangerous()
-                .with_custom_certificate_verifier(SkipServerVerification::new(provider.clone()))
-                .with_no_client_auth(),
-        ) {
-            Ok(qcc) => qcc,
-            Err(err) => {
-                debug!(%err, "Failed to build quic client config");
-                return (endpoint, None);
+        let qcc = if let Some(qcc) = &self.private_quic_client_config {
+            qcc.clone()
+        } else {
+            let provider = rustls::crypto::CryptoProvider::get_default()
+                .expect("We have a quic socket so there is a crypto provider installed");
+            match QuicClientConfig::try_from(
+                rustls::ClientConfig::builder()
+                    .dangerous()
+                    .with_custom_certificate_verifier(SkipServerVerification::new(provider.clone()))
+                    .with_no_client_auth(),
+            ) {
+                Ok(qcc) => Arc::new(qcc),
+                Err(err) => {
+                    return Err(format!("Failed to build quic client config: {err}").into());
+                }
             }
         };
-        let mut config = quinn::ClientConfig::new(Arc::new(qcc));
+        let mut config = quinn::ClientConfig::new(qcc);
         // Todo: tweak transport config
         let mut transport_config = TransportConfig::default();
         transport_config.max_concurrent_uni_streams(0_u8.into());
@@ -612,56 +1024,130 @@ where
         transport_config.max_idle_timeout(Some(Duration::from_secs(60).try_into().unwrap()));
         transport_config.mtu_discovery_config(Some(MtuDiscoveryConfig::default()));
         transport_config.keep_alive_interval(Some(Duration::from_secs(20)));
-        // we don't use datagrams.
-        transport_config.datagram_receive_buffer_size(None);
-        transport_config.datagram_send_buffer_size(0);
+        // Data packets are sent as datagrams if the remote also supports them, keep the default
+        // datagram buffer sizes.
         config.transport_config(Arc::new(transport_config));
 
-        match quic_socket.connect_with(config, endpoint.address(), "dummy.mycelium") {
+        match quic_socket.connect_with(config, address, QUIC_SERVER_NAME) {
             Ok(connecting) => match connecting.await {
                 Ok(con) => match con.open_bi().await {
                     Ok((tx, rx)) => {
-                        let q_con = Quic::new(tx, rx, endpoint.address());
-                        let res = {
-                            let router = self.router.lock().unwrap();
-                            let router_data_tx = router.router_data_tx();
-                            let router_control_tx = router.router_control_tx();
-                            let dead_peer_sink = router.dead_peer_sink().clone();
-
-                            Peer::new(
-                                router_data_tx,
-                                router_control_tx,
+                        let q_con = Quic::new(tx, rx, address, &con);
+                        let mut channel_binding = [0; CHANNEL_BINDING_SIZE];
+                        if let Err(e) = con.export_keying_material(
+                            &mut channel_binding,
+                            CHANNEL_BINDING_LABEL.as_bytes(),
+                            &[],
+                        ) {
+                            return Err(format!(
+                                "Could not export keying material from quic connection: {e:?}"
+                            )
+                            .into());
+                        }
+                        match self
+                            .new_peer(
                                 q_con,
-                                dead_peer_sink,
-                                ct.tx_bytes,
-                                ct.rx_bytes,
+                                &channel_binding,
+                                ct,
+                                false,
+                                legacy,
+                                expected_public_key,
                             )
-                        };
-                        match res {
+                            .await
+                        {
                             Ok(new_peer) => {
-                                info!("Connected to new peer");
-                                (endpoint, Some(new_peer))
-                            }
-                            Err(e) => {
-                                debug!(err=%e, "Failed to spawn peer");
-                                (endpoint, None)
+                                info!(
+                                    remote_public_key = new_peer.remote_public_key().map(display),
+                                    "Connected to new peer"
+                                );
+                                Ok(new_peer)
                             }
+                            Err(e) => Err(e.into()),
                         }
                     }
-                    Err(e) => {
-                        debug!(err=%e, "Couldn't open bidirectional quic stream");
-                        (endpoint, None)
-                    }
+                    Err(e) => Err(format!("Couldn't open bidirectional quic stream: {e}").into()),
                 },
+                Err(e) => Err(format!("Couldn't complete quic connection: {e}").into()),
+            },
+            Err(e) => Err(format!("Couldn't initiate connection: {e}").into()),
+        }
+    }
+
+    #[instrument(skip_all, fields(address=%address))]
+    async fn connect_ws_peer(
+        &self,
+        endpoint: &Endpoint,
+        address: SocketAddr,
+        proxy: Option<&HttpProxy>,
+        ct: &ConnectionTraffic,
+        legacy: bool,
+        expected_public_key: Option<PublicKey>,
+    ) -> Result<PeerConnection, ConnectError> {
+        if self.private_network_config.is_some() {
+            warn!("Attempting to connect over a WebSocket while a private network is configured, refusing to connect");
+            return Err("WebSockets are not supported in a private network"
+                .to_string()
+                .into());
+        }
+
+        let peer_stream = match TcpStream::connect(address)
+            .map(|result| result.and_then(|socket| set_fw_mark(socket, self.firewall_mark)))
+            .await
+        {
+            Ok(peer_stream) => peer_stream,
+            Err(e) => return Err(format!("Couldn't connect: {e}").into()),
+        };
+        debug!("Opened connection");
+        // Make sure Nagle's algorithm is disabled as it can cause latency spikes.
+        if let Err(e) = peer_stream.set_nodelay(true) {
+            return Err(format!("Couldn't disable Nagle's algorithm on stream: {e}").into());
+        }
+
+        // Through a proxy, the connection goes to the proxy, which opens a tunnel to the endpoint.
+        let stream = match proxy {
+            Some(proxy) => match proxy.tunnel(peer_stream, endpoint.host()).await {
+                Ok(stream) => {
+                    debug!(%proxy, "Opened tunnel through proxy");
+                    stream
+                }
                 Err(e) => {
-                    debug!(err=%e, "Couldn't complete quic connection");
-                    (endpoint, None)
+                    return Err(format!("Couldn't open tunnel through proxy {proxy}: {e}").into())
                 }
             },
-            Err(e) => {
-                debug!(err=%e, "Couldn't initiate connection");
-                (endpoint, None)
+            None => ProxyStream::Tcp(peer_stream),
+        };
+
+        // The host in the url is used for the Host header and, in case of Wss, to verify the
+        // certificate of the remote, so keep the hostname if there is one.
+        let scheme = if endpoint.proto() == Protocol::Wss {
+            "wss"
+        } else {
+            "ws"
+        };
+        let url = format!("{scheme}://{}/", endpoint.host());
+        let ws_stream = match tokio_tungstenite::client_async_tls(url, stream).await {
+            Ok((ws_stream, _)) => ws_stream,
+            Err(e) => return Err(format!("Could not complete WebSocket handshake: {e}").into()),
+        };
+        debug!("Completed WebSocket handshake");
+
+        // There is no channel binding, the Tls session (if any) might be terminated by a proxy.
+        // Through a proxy, the address of a hostname endpoint is not known, the address of the
+        // proxy is used instead.
+        let remote = endpoint.address().unwrap_or(address);
+        let con = WebSocket::new(ws_stream, endpoint.proto(), remote);
+        match self
+            .new_peer(con, &[], ct, false, legacy, expected_public_key)
+            .await
+        {
+            Ok(new_peer) => {
+                info!(
+                    remote_public_key = new_peer.remote_public_key().map(display),
+                    "Connected to new peer"
+                );
+                Ok(new_peer)
             }
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -696,35 +1182,68 @@ where
             None
         };
 
-        // Take a copy of every channel here first so we avoid lock contention in the loop later.
-        let router_data_tx = self.router.lock().unwrap().router_data_tx();
-        let router_control_tx = self.router.lock().unwrap().router_control_tx();
-        let dead_peer_sink = self.router.lock().unwrap().dead_peer_sink().clone();
-
         let listener = TcpListener::bind(("::", self.tcp_listen_port))
             .map(|result| result.and_then(|listener| set_fw_mark(listener, self.firewall_mark)));
 
-        match listener.await {
-            Ok(listener) => loop {
-                match listener.accept().await {
-                    Ok((stream, remote)) => {
-                        let tx_bytes = Arc::new(AtomicU64::new(0));
-                        let rx_bytes = Arc::new(AtomicU64::new(0));
+        let listener = match listener.await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!(err=%e, "Error starting listener");
+                return;
+            }
+        };
+
+        let mut tcp_con_futures = FuturesUnordered::new();
+
+        let mut pending_tcp_handshakes = 0;
+
+        loop {
+            tokio::select! {
+                // FuturesUnordered always returns Some(...).
+                Some(handshake_result) = tcp_con_futures.next() => {
+                    pending_tcp_handshakes -= 1;
+                    // Since type inference failed here, use a fully qualified function call
+                    if Result::<(),tokio::time::error::Elapsed>::is_err(&handshake_result) {
+                        debug!("Dropping connection to peer who's handshake timed out");
+                    }
+                }
+                maybe_con = listener.accept(), if pending_tcp_handshakes < MAX_INBOUND_CONCURRENT_TCP_HANDSHAKES => {
+                    let (stream, remote) = match maybe_con {
+                        Ok(con) => con,
+                        Err(e) => {
+                            error!(err=%e, "Error accepting connection");
+                            continue;
+                        }
+                    };
+
+                    if !self.inbound_address_allowed(remote) {
+                        continue;
+                    }
+
+                    let con_traffic = ConnectionTraffic {
+                        tx_bytes: Arc::new(AtomicU64::new(0)),
+                        rx_bytes: Arc::new(AtomicU64::new(0)),
+                    };
 
+                    // Borrow these here so the moved future only takes a reference to them.
+                    let this = &self;
+                    #[cfg(feature = "private-network")]
+                    let acceptor = &acceptor;
+                    let con_future = async move {
                         #[cfg(feature = "private-network")]
-                        let new_peer = if let Some(acceptor) = &acceptor {
+                        let new_peer = if let Some(acceptor) = acceptor {
                             let ssl = match Ssl::new(acceptor.context()) {
                                 Ok(ssl) => ssl,
                                 Err(e) => {
                                     error!(%remote, err=%e, "Failed to create SSL object from acceptor after remote connected");
-                                    continue;
+                                    return;
                                 }
                             };
                             let mut ssl_stream = match tokio_openssl::SslStream::new(ssl, stream) {
                                 Ok(ssl_stream) => ssl_stream,
                                 Err(e) => {
                                     error!(%remote, err=%e, "Failed to create TLS stream from tcp connection");
-                                    continue;
+                                    return;
                                 }
                             };
 
@@ -734,50 +1253,39 @@ where
                                 // An error at this point generally means the handshake failed,
                                 // client error.
                                 debug!(%remote, err=%e, "Could not accept TLS stream");
-                                continue;
+                                return;
                             }
                             debug!(%remote, "Accepted TLS handshake");
 
-                            Peer::new(
-                                router_data_tx.clone(),
-                                router_control_tx.clone(),
-                                ssl_stream,
-                                dead_peer_sink.clone(),
-                                tx_bytes.clone(),
-                                rx_bytes.clone(),
-                            )
+                            let mut channel_binding = [0; CHANNEL_BINDING_SIZE];
+                            if let Err(e) = ssl_stream.ssl().export_keying_material(
+                                &mut channel_binding,
+                                CHANNEL_BINDING_LABEL,
+                                None,
+                            ) {
+                                debug!(%remote, err=%e, "Could not export keying material from TLS stream");
+                                return;
+                            }
+
+                            this.new_peer(ssl_stream, &channel_binding, &con_traffic, true, false, None).await
                         } else {
-                            Peer::new(
-                                router_data_tx.clone(),
-                                router_control_tx.clone(),
-                                stream,
-                                dead_peer_sink.clone(),
-                                tx_bytes.clone(),
-                                rx_bytes.clone(),
-                            )
+                            this.new_peer(stream, &[], &con_traffic, true, false, None).await
                         };
 
                         #[cfg(not(feature = "private-network"))]
-                        let new_peer = Peer::new(
-                            router_data_tx.clone(),
-                            router_control_tx.clone(),
-                            stream,
-                            dead_peer_sink.clone(),
-                            tx_bytes.clone(),
-                            rx_bytes.clone(),
-                        );
+                        let new_peer = this.new_peer(stream, &[], &con_traffic, true, false, None).await;
 
                         let new_peer = match new_peer {
                             Ok(peer) => peer,
                             Err(e) => {
-                                error!(err=%e, "Failed to spawn peer");
-                                continue;
+                                debug!(%remote, err=%e, "Failed to set up inbound peer");
+                                return;
                             }
                         };
-                        info!("Accepted new inbound peer");
-                        self.add_peer(
+                        info!(%remote, remote_public_key=new_peer.remote_public_key().map(display), "Accepted new inbound peer");
+                        this.add_peer(
                             Endpoint::new(
-                                if self.private_network_config.is_some() {
+                                if this.private_network_config.is_some() {
                                     Protocol::Tls
                                 } else {
                                     Protocol::Tcp
@@ -785,17 +1293,110 @@ where
                                 remote,
                             ),
                             PeerType::Inbound,
-                            ConnectionTraffic { tx_bytes, rx_bytes },
+                            con_traffic,
                             Some(new_peer),
+                            None,
                         );
+                    };
+
+                    pending_tcp_handshakes += 1;
+                    tcp_con_futures.push(tokio::time::timeout(INBOUND_TCP_HANDSHAKE_TIMEOUT, con_future));
+                }
+            }
+        }
+    }
+
+    /// Start listening for new peers connecting over plain text WebSockets on the configured
+    /// port. Peers connecting over Wss must do so through a reverse proxy which terminates the Tls
+    /// session.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `self.ws_listen_port` is [`None`].
+    async fn ws_listener(self: Arc<Self>) {
+        // SAFETY: This is safe because this method only get's called if we have a listen port.
+        let ws_listen_port = self.ws_listen_port.unwrap();
+        let listener = TcpListener::bind(("::", ws_listen_port))
+            .map(|result| result.and_then(|listener| set_fw_mark(listener, self.firewall_mark)));
+
+        let listener = match listener.await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!(err=%e, "Error starting WebSocket listener");
+                return;
+            }
+        };
+
+        let mut ws_con_futures = FuturesUnordered::new();
+
+        let mut pending_ws_handshakes = 0;
+
+        loop {
+            tokio::select! {
+                // FuturesUnordered always returns Some(...).
+                Some(handshake_result) = ws_con_futures.next() => {
+                    pending_ws_handshakes -= 1;
+                    // Since type inference failed here, use a fully qualified function call
+                    if Result::<(),tokio::time::error::Elapsed>::is_err(&handshake_result) {
+                        debug!("Dropping connection to peer who's handshake timed out");
                     }
-                    Err(e) => {
-                        error!(err=%e, "Error accepting connection");
+                }
+                maybe_con = listener.accept(), if pending_ws_handshakes < MAX_INBOUND_CONCURRENT_TCP_HANDSHAKES => {
+                    let (stream, remote) = match maybe_con {
+                        Ok(con) => con,
+                        Err(e) => {
+                            error!(err=%e, "Error accepting connection");
+                            continue;
+                        }
+                    };
+
+                    if !self.inbound_address_allowed(remote) {
+                        continue;
+                    }
+
+                    if let Err(e) = stream.set_nodelay(true) {
+                        debug!(%remote, err=%e, "Couldn't disable Nagle's algorithm on stream");
+                        continue;
                     }
+
+                    let con_traffic = ConnectionTraffic {
+                        tx_bytes: Arc::new(AtomicU64::new(0)),
+                        rx_bytes: Arc::new(AtomicU64::new(0)),
+                    };
+
+                    // Borrow this here so the moved future only takes a reference to it.
+                    let this = &self;
+                    let con_future = async move {
+                        let ws_stream = match tokio_tungstenite::accept_async(stream).await {
+                            Ok(ws_stream) => ws_stream,
+                            Err(e) => {
+                                debug!(%remote, err=%e, "Could not accept WebSocket handshake");
+                                return;
+                            }
+                        };
+                        debug!(%remote, "Accepted WebSocket handshake");
+
+                        let con = WebSocket::new(ws_stream, Protocol::Ws, remote);
+                        let new_peer = match this.new_peer(con, &[], &con_traffic, true, false, None).await {
+                            Ok(peer) => peer,
+                            Err(e) => {
+                                debug!(%remote, err=%e, "Failed to set up inbound peer");
+                                return;
+                            }
+                        };
+                        info!(%remote, remote_public_key=new_peer.remote_public_key().map(display), "Accepted new inbound peer");
+                        this.add_peer(
+                            Endpoint::new(Protocol::Ws, remote),
+                            PeerType::Inbound,
+                            con_traffic,
+                            Some(new_peer),
+                            None,
+                        );
+                    };
+
+                    pending_ws_handshakes += 1;
+                    ws_con_futures.push(tokio::time::timeout(INBOUND_TCP_HANDSHAKE_TIMEOUT, con_future));
                 }
-            },
-            Err(e) => {
-                error!(err=%e, "Error starting listener");
             }
         }
     }
@@ -808,10 +1409,6 @@ where
     async fn quic_listener(self: Arc<Self>) {
         // SAFETY: This is safe because this method only get's called if we have a quic socket.
         let quic_socket = self.quic_socket.as_ref().unwrap();
-        // Take a copy of every channel here first so we avoid lock contention in the loop later.
-        let router_data_tx = self.router.lock().unwrap().router_data_tx();
-        let router_control_tx = self.router.lock().unwrap().router_control_tx();
-        let dead_peer_sink = self.router.lock().unwrap().dead_peer_sink().clone();
 
         let mut quic_con_futures = FuturesUnordered::new();
 
@@ -832,6 +1429,11 @@ where
                         break
                     };
 
+                    if !self.inbound_address_allowed(con.remote_address()) {
+                        con.refuse();
+                        continue;
+                    }
+
 
                     let con_future = async  {
                         let con = match con.into_future().await {
@@ -843,35 +1445,41 @@ where
                         };
 
                         let quic_peer = match con.accept_bi().await {
-                            Ok((tx, rx)) => Quic::new(tx, rx, con.remote_address()),
+                            Ok((tx, rx)) => Quic::new(tx, rx, con.remote_address(), &con),
                             Err(e) => {
                                 debug!(err=%e, "Failed to accept bidirectional quic stream");
                                 return;
                             }
                         };
 
-                        let tx_bytes = Arc::new(AtomicU64::new(0));
-                        let rx_bytes = Arc::new(AtomicU64::new(0));
-                        let new_peer = match Peer::new(
-                            router_data_tx.clone(),
-                            router_control_tx.clone(),
-                            quic_peer,
-                            dead_peer_sink.clone(),
-                            tx_bytes.clone(),
-                            rx_bytes.clone(),
+                        let mut channel_binding = [0; CHANNEL_BINDING_SIZE];
+                        if let Err(e) = con.export_keying_material(
+                            &mut channel_binding,
+                            CHANNEL_BINDING_LABEL.as_bytes(),
+                            &[],
                         ) {
+                            debug!(err=?e, "Could not export keying material from quic connection");
+                            return;
+                        }
+
+                        let con_traffic = ConnectionTraffic {
+                            tx_bytes: Arc::new(AtomicU64::new(0)),
+                            rx_bytes: Arc::new(AtomicU64::new(0)),
+                        };
+                        let new_peer = match self.new_peer(quic_peer, &channel_binding, &con_traffic, true, false, None).await {
                             Ok(peer) => peer,
                             Err(e) => {
-                                error!(err=%e, "Failed to spawn peer");
+                                debug!(remote=%con.remote_address(), err=%e, "Failed to set up inbound quic peer");
                                 return;
                             }
                         };
-                        info!(remote=%con.remote_address(), "Accepted new inbound quic peer");
+                        info!(remote=%con.remote_address(), remote_public_key=new_peer.remote_public_key().map(display), "Accepted new inbound quic peer");
                         self.add_peer(
                             Endpoint::new(Protocol::Quic, con.remote_address()),
                             PeerType::Inbound,
-                            ConnectionTraffic { tx_bytes, rx_bytes },
+                            con_traffic,
                             Some(new_peer),
+                            None,
                         );
                     };
 
@@ -884,23 +1492,181 @@ where
         info!("Shutting down closed quic listener");
     }
 
-    /// Add a new peer identifier we discovered.
+    /// Authenticate the remote of a new connection with the link handshake, and create a
+    /// [`Peer`] for the connection if this succeeds. If there already is a live [`Peer`] for the
+    /// remote, the connection is added to it instead. The `channel_binding` is used to bind the
+    /// handshake to the security layer of the connection, if there is one. If an
+    /// `expected_public_key` is given, the remote must prove it has this key. For `inbound`
+    /// connections, and connections to remotes with an expected public key, which we learned
+    /// from neighbours, the verified public key of the remote must be allowed by the [`PeerAcl`].
+    ///
+    /// Inbound connections from legacy remotes, which don't support the handshake, are accepted
+    /// without a public key if legacy peers are allowed. Outbound connections to a remote known to
+    /// be `legacy` are set up without the handshake.
+    async fn new_peer<C>(
+        &self,
+        mut connection: C,
+        channel_binding: &[u8],
+        con_traffic: &ConnectionTraffic,
+        inbound: bool,
+        legacy: bool,
+        expected_public_key: Option<PublicKey>,
+    ) -> Result<PeerConnection, NewPeerError>
+    where
+        C: Connection + Unpin + Send + 'static,
+    {
+        if legacy {
+            debug!("Connecting to legacy remote without link handshake");
+            return self.attach_peer(connection, None, con_traffic);
+        }
+
+        let role = if inbound {
+            Role::Responder
+        } else {
+            Role::Initiator
+        };
+        let remote = handshake::handshake(
+            &mut connection,
+            &self.node_secret_key,
+            channel_binding,
+            role,
+        )
+        .await
+        .map_err(NewPeerError::Handshake)?;
+        let remote_public_key = match remote {
+            Remote::Verified(remote_public_key) => remote_public_key,
+            Remote::Legacy(prefix) => {
+                if !self.legacy_peers {
+                    return Err(NewPeerError::Legacy);
+                }
+                if !self.peer_acl.read().unwrap().allows_unverified() {
+                    self.metrics
+                        .peer_manager_inbound_connection_rejected(RejectReason::PublicKey);
+                    return Err(NewPeerError::Legacy);
+                }
+                debug!("Remote does not support the link handshake, accepting legacy peer");
+                return self.attach_peer(Prefixed::new(prefix, connection), None, con_traffic);
+            }
+        };
+        debug!(%remote_public_key, "Completed link handshake");
+
+        if let Some(expected) = expected_public_key.filter(|key| *key != remote_public_key) {
+            return Err(NewPeerError::UnexpectedKey {
+                expected,
+                actual: remote_public_key,
+            });
+        }
+
+        if (inbound || expected_public_key.is_some())
+            && !self.peer_acl.read().unwrap().allows_key(&remote_public_key)
+        {
+            if inbound {
+                self.metrics
+                    .peer_manager_inbound_connection_rejected(RejectReason::PublicKey);
+            }
+            return Err(NewPeerError::Rejected(remote_public_key));
+        }
+
+        self.attach_peer(connection, Some(remote_public_key), con_traffic)
+    }
+
+    /// Create a [`Peer`] for a connection, or add the connection to the existing [`Peer`] if the
+    /// remote with this public key is already connected. Connections to legacy remotes, which
+    /// don't have a verified public key, always get their own [`Peer`].
+    fn attach_peer<C>(
+        &self,
+        connection: C,
+        remote_public_key: Option<PublicKey>,
+        con_traffic: &ConnectionTraffic,
+    ) -> Result<PeerConnection, NewPeerError>
+    where
+        C: Connection + Unpin + Send + 'static,
+    {
+        let (router_data_tx, router_control_tx, dead_peer_sink) = {
+            let router = self.router.lock().unwrap();
+            (
+                router.router_data_tx(),
+                router.router_control_tx(),
+                router.dead_peer_sink().clone(),
+            )
+        };
+
+        let mut neighbours = self.neighbours.lock().unwrap();
+        neighbours.retain(|_, pr| pr.upgrade().is_some_and(|peer| peer.alive()));
+        if let Some(remote_public_key) = remote_public_key {
+            if let Some(peer) = neighbours
+                .get(&remote_public_key)
+                .and_then(PeerRef::upgrade)
+            {
+                debug!(%remote_public_key, "Adding connection to existing peer");
+                let pr = peer
+                    .add_connection(
+                        connection,
+                        con_traffic.tx_bytes.clone(),
+                        con_traffic.rx_bytes.clone(),
+                    )
+                    .map_err(NewPeerError::Io)?;
+                return Ok(PeerConnection {
+                    peer,
+                    pr,
+                    new: false,
+                });
+            }
+        }
+
+        let peer = Peer::new(
+            router_data_tx,
+            router_control_tx,
+            connection,
+            remote_public_key,
+            dead_peer_sink,
+            con_traffic.tx_bytes.clone(),
+            con_traffic.rx_bytes.clone(),
+        )
+        .map_err(NewPeerError::Io)?;
+        let pr = peer.refer();
+        if let Some(remote_public_key) = remote_public_key {
+            neighbours.insert(remote_public_key, pr.clone());
+        }
+
+        Ok(PeerConnection {
+            peer,
+            pr,
+            new: true,
+        })
+    }
+
+    /// Checks if an inbound connection from the given address is allowed by the [`PeerAcl`].
+    fn inbound_address_allowed(&self, remote: SocketAddr) -> bool {
+        if self.peer_acl.read().unwrap().allows_address(remote.ip()) {
+            return true;
+        }
+
+        debug!(%remote, "Rejecting inbound connection from address which is not allowed");
+        self.metrics
+            .peer_manager_inbound_connection_rejected(RejectReason::SourceAddress);
+        false
+    }
+
+    /// Add a new peer identifier we discovered. If an `expected_public_key` is given, connections
+    /// to the peer must prove this key.
     #[instrument(skip_all,fields(peer.endpoint=%endpoint))]
     fn add_peer(
         &self,
         endpoint: Endpoint,
         discovery_type: PeerType,
         con_traffic: ConnectionTraffic,
-        peer: Option<Peer>,
+        con: Option<PeerConnection>,
+        expected_public_key: Option<PublicKey>,
     ) {
         self.metrics.peer_manager_peer_added(discovery_type.clone());
         let mut peers = self.peers.lock().unwrap();
         // Filter out link local IP's we already know (because of reverse detection)
         if discovery_type == PeerType::LinkLocalDiscovery {
-            if let IpAddr::V6(ip) = endpoint.address().ip() {
+            if let Some(IpAddr::V6(ip)) = endpoint.address().map(|address| address.ip()) {
                 if ip.octets()[..8] == [0xfe, 0x80, 0, 0, 0, 0, 0, 0] {
                     for known_endpoint in peers.keys() {
-                        if known_endpoint.address().ip() == endpoint.address().ip()
+                        if known_endpoint.address().map(|address| address.ip()) == Some(ip.into())
                             && known_endpoint.proto() == endpoint.proto()
                         {
                             trace!(peer.known_endpoint=%known_endpoint, "Refusing to add link local discovered address as there already is a reverse connection");
@@ -911,23 +1677,24 @@ where
             }
         }
         // Only if we don't know it yet.
-        if let Entry::Vacant(e) = peers.entry(endpoint) {
+        if let Entry::Vacant(e) = peers.entry(endpoint.clone()) {
             e.insert(PeerInfo {
                 pt: discovery_type,
                 connecting: false,
-                pr: if let Some(p) = &peer {
-                    p.refer()
-                } else {
-                    PeerRef::new()
-                },
+                pr: con.as_ref().map(|con| con.pr.clone()).unwrap_or_default(),
                 connection_attempts: 0,
+                next_connection_attempt: Instant::now(),
+                last_connection_error: None,
                 con_traffic,
+                link_cost_offset: 0,
+                legacy: false,
+                expected_public_key,
             });
-            if let Some(p) = peer {
-                self.router.lock().unwrap().add_peer_interface(p);
+            if let Some(con) = con.filter(|con| con.new) {
+                self.router.lock().unwrap().add_peer_interface(con.peer);
             }
             info!("Added new peer");
-        } else if discovery_type == PeerType::Inbound {
+        } else if discovery_type == PeerType::Inbound && peers[&endpoint].pt == PeerType::Inbound {
             // We got an inbound peer with a duplicate entry. This is possible if the sending port
             // is the same as the previous one, which generally happens with our Quic setup. In
             // this case, the old connection needs to be replaced.
@@ -936,29 +1703,48 @@ where
                 PeerInfo {
                     pt: discovery_type,
                     connecting: false,
-                    pr: if let Some(p) = &peer {
-                        p.refer()
-                    } else {
-                        PeerRef::new()
-                    },
+                    pr: con.as_ref().map(|con| con.pr.clone()).unwrap_or_default(),
                     connection_attempts: 0,
+                    next_connection_attempt: Instant::now(),
+                    last_connection_error: None,
                     con_traffic,
+                    link_cost_offset: 0,
+                    legacy: false,
+                    expected_public_key,
                 },
             );
-            // If we have a new peer notify insert the new one in the router, then notify it that
-            // the old one is dead.
-            if let Some(p) = peer {
-                let router = self.router.lock().unwrap();
-                router.add_peer_interface(p);
-                if let Some(old_peer) = old_peer_info
+            // If we have a new connection, insert it in the router if it is a new peer, then close
+            // the old connection.
+            if let Some(con) = con {
+                if con.new {
+                    self.router.lock().unwrap().add_peer_interface(con.peer);
+                }
+                old_peer_info
                     .expect("We already checked the entry was occupied so this is always Some; qed")
                     .pr
-                    .upgrade()
-                {
-                    router.handle_dead_peer(old_peer);
-                }
+                    .died();
             }
             info!("Replaced existing inbound peer");
+        } else if let Some(con) = con.filter(|con| {
+            let pi = &peers[&endpoint];
+            !pi.pr.alive()
+                && pi
+                    .expected_public_key
+                    .is_none_or(|key| con.remote_public_key() == Some(key))
+        }) {
+            // An inbound connection from a remote we are trying to connect to, e.g. because both
+            // sides connect at the same time after a hole punch introduction. Use it until our own
+            // connection attempt completes. If the remote must have a specific key, the inbound
+            // connection must have proven it.
+            let pi = peers
+                .get_mut(&endpoint)
+                .expect("We already checked the entry was occupied so this is always Some; qed");
+            pi.pr = con.pr;
+            if con.new {
+                con.peer.set_link_cost_offset(pi.link_cost_offset);
+                self.router.lock().unwrap().add_peer_interface(con.peer);
+            }
+            info!("Using inbound connection for known peer");
         } else {
             debug!("Ignoring request to add as it already exists");
         }
@@ -1140,28 +1926,558 @@ where
                 rx_bytes: Arc::new(AtomicU64::new(0)),
             },
             None,
+            None,
         );
     }
+
+    /// Handle TLV's from neighbours which are processed by the peer manager, and periodically
+    /// send our own.
+    ///
+    /// If `hole_punching` is enabled, neighbours are asked to introduce us to remotes we can only
+    /// reach through them. Requests from neighbours are answered once the requested remote asked
+    /// for the neighbour as well, and both are connected to us over QUIC. Introductions we asked
+    /// for are turned into [`PeerType::HolePunched`] peers, which are connected to immediately.
+    ///
+    /// If a `peer_exchange_target` is set, the endpoints of our outbound peers are shared with
+    /// neighbours. Endpoints shared by neighbours are kept as candidates, which are connected to
+    /// as [`PeerType::PeerExchange`] peers while we have less than `peer_exchange_target` outbound
+    /// peers.
+    async fn coordinate_with_neighbours(
+        self: Arc<Self>,
+        mut tlv_stream: UnboundedReceiver<(ControlPacket, Peer)>,
+        hole_punching: bool,
+        peer_exchange_target: Option<usize>,
+    ) {
+        let mut hole_punch_interval = tokio::time::interval(HOLE_PUNCH_REQUEST_INTERVAL);
+        hole_punch_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut peer_exchange_interval = tokio::time::interval(PEER_EXCHANGE_INTERVAL);
+        peer_exchange_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut candidate_connect_interval = tokio::time::interval(PEER_CANDIDATE_CONNECT_INTERVAL);
+        candidate_connect_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // Neighbour we last sent a hole punch request for a remote to, and when.
+        let mut requested = HashMap::new();
+        // Hole punch requests from neighbours which are waiting for the matching request of the
+        // target, by requester and target, and the time they were received.
+        let mut received = HashMap::new();
+        // Endpoints learned through peer exchange, which we might connect to.
+        let mut candidates = HashMap::new();
+
+        loop {
+            tokio::select! {
+                _ = hole_punch_interval.tick(), if hole_punching => {
+                    self.request_hole_punches(&mut requested);
+                }
+                _ = peer_exchange_interval.tick(), if peer_exchange_target.is_some() => {
+                    self.share_peers();
+                }
+                _ = candidate_connect_interval.tick(), if peer_exchange_target.is_some() => {
+                    if let Some(target) = peer_exchange_target {
+                        self.connect_peer_candidates(target, &mut candidates);
+                    }
+                }
+                Some((tlv, source_peer)) = tlv_stream.recv() => {
+                    if !source_peer.alive() {
+                        trace!("Dropping TLV since sender is dead");
+                        continue;
+                    }
+                    match tlv {
+                        ControlPacket::HolePunchRequest(request) if hole_punching => {
+                            self.handle_hole_punch_request(request, &source_peer, &mut received)
+                        }
+                        ControlPacket::HolePunchIntroduction(introduction) if hole_punching => {
+                            self.handle_hole_punch_introduction(introduction, &source_peer, &requested)
+                        }
+                        ControlPacket::PeerExchange(peer_exchange) if peer_exchange_target.is_some() => {
+                            self.handle_peer_exchange(peer_exchange, &mut candidates)
+                        }
+                        _ => trace!("Ignoring TLV for disabled feature"),
+                    }
+                }
+                else => break,
+            }
+        }
+    }
+
+    /// Ask neighbours to introduce us to remotes which are reachable through them, but which we
+    /// are not connected to ourselves.
+    fn request_hole_punches(&self, requested: &mut HashMap<PublicKey, (PublicKey, Instant)>) {
+        let now = Instant::now();
+        requested.retain(|_, (_, last_request)| {
+            now.duration_since(*last_request) < HOLE_PUNCH_RETRY_INTERVAL
+        });
+
+        let hole_punched_peers = self
+            .peers
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|pi| pi.pt == PeerType::HolePunched)
+            .count();
+        if hole_punched_peers >= MAX_HOLE_PUNCHED_PEERS {
+            trace!("Not requesting hole punches, maximum amount of hole punched peers reached");
+            return;
+        }
+
+        let routes = self.router.lock().unwrap().load_selected_routes();
+        let neighbours = self.neighbours.lock().unwrap();
+        let mut requests = 0;
+        for route in routes {
+            if requests >= MAX_HOLE_PUNCH_REQUESTS {
+                break;
+            }
+            if route.metric().is_infinite() || !route.neighbour().alive() {
+                continue;
+            }
+            let target = route.source().router_id().to_pubkey();
+            if neighbours.contains_key(&target) || requested.contains_key(&target) {
+                continue;
+            }
+            // Legacy neighbours don't support hole punching.
+            let Some(neighbour) = route.neighbour().remote_public_key() else {
+                continue;
+            };
+
+            debug!(%target, neighbour=%route.neighbour().connection_identifier(), "Requesting hole punch");
+            if route
+                .neighbour()
+                .send_control_packet(HolePunchRequest::new(target).into())
+                .is_ok()
+            {
+                requested.insert(target, (neighbour, now));
+                requests += 1;
+            }
+        }
+    }
+
+    /// Introduce a neighbour to the remote it requested, and the other way around. This is only
+    /// done once both of them requested an introduction to the other, so both expect it, and if
+    /// both of them are connected to us over QUIC. The address of their inbound connection is the
+    /// address of their QUIC socket as seen from the internet.
+    ///
+    /// Requests waiting for the matching request of the target are kept in `received`. Every
+    /// neighbour can have at most [`MAX_HOLE_PUNCH_REQUESTS`] waiting requests received in the
+    /// last [`HOLE_PUNCH_REQUEST_INTERVAL`], further requests are ignored.
+    fn handle_hole_punch_request(
+        &self,
+        request: HolePunchRequest,
+        requester: &Peer,
+        received: &mut HashMap<(PublicKey, PublicKey), Instant>,
+    ) {
+        let target = request.target();
+        let Some(requester_public_key) = requester.remote_public_key() else {
+            trace!(%target, "Can't introduce legacy remote");
+            return;
+        };
+
+        let now = Instant::now();
+        received
+            .retain(|_, received_at| now.duration_since(*received_at) < HOLE_PUNCH_RETRY_INTERVAL);
+        if received.contains_key(&(requester_public_key, target)) {
+            trace!(requester=%requester_public_key, %target, "Ignoring repeated hole punch request");
+            return;
+        }
+        let recent_requests = received
+            .iter()
+            .filter(|((from, _), received_at)| {
+                *from == requester_public_key
+                    && now.duration_since(**received_at) < HOLE_PUNCH_REQUEST_INTERVAL
+            })
+            .count();
+        if recent_requests >= MAX_HOLE_PUNCH_REQUESTS {
+            debug!(requester=%requester_public_key, %target, "Ignoring hole punch request, too many recent requests from requester");
+            return;
+        }
+        if received.remove(&(target, requester_public_key)).is_none() {
+            trace!(requester=%requester_public_key, %target, "Waiting for hole punch request of target");
+            received.insert((requester_public_key, target), now);
+            return;
+        }
+        let mut requester_address = None;
+        let mut target_address = None;
+        for (endpoint, pi) in self.peers.lock().unwrap().iter() {
+            if pi.pt != PeerType::Inbound || endpoint.proto() != Protocol::Quic || !pi.pr.alive() {
+                continue;
+            }
+            let (Some(address), Some(peer)) = (endpoint.address(), pi.pr.upgrade()) else {
+                continue;
+            };
+            if peer == *requester {
+                requester_address = Some(address);
+            } else if peer.remote_public_key() == Some(target) {
+                target_address = Some((address, peer));
+            }
+        }
+
+        let (Some(requester_address), Some((target_address, target_peer))) =
+            (requester_address, target_address)
+        else {
+            trace!(%target, "Can't introduce remote which is not connected over quic");
+            return;
+        };
+
+        debug!(requester=%requester_address, target=%target_address, "Introducing peers for hole punching");
+        if let Err(e) =
+            requester.send_control_packet(HolePunchIntroduction::new(target, target_address).into())
+        {
+            debug!(err=%e, "Failed to send hole punch introduction");
+            return;
+        }
+        if let Err(e) = target_peer.send_control_packet(
+            HolePunchIntroduction::new(requester_public_key, requester_address).into(),
+        ) {
+            debug!(err=%e, "Failed to send hole punch introduction");
+        }
+    }
+
+    /// Add the remote from a hole punch introduction as new peer, and connect to it immediately.
+    /// The remote does the same, which opens the NAT on both sides. Only introductions we
+    /// `requested`, from the neighbour we sent the request to, are accepted. The remote must prove
+    /// it has the introduced public key.
+    fn handle_hole_punch_introduction(
+        &self,
+        introduction: HolePunchIntroduction,
+        source: &Peer,
+        requested: &HashMap<PublicKey, (PublicKey, Instant)>,
+    ) {
+        let remote_public_key = introduction.peer();
+        if remote_public_key == PublicKey::from(&self.node_secret_key) {
+            trace!("Ignoring hole punch introduction to ourselves");
+            return;
+        }
+        if !requested
+            .get(&remote_public_key)
+            .is_some_and(|(neighbour, _)| source.remote_public_key() == Some(*neighbour))
+        {
+            debug!(%remote_public_key, introducer=%source.connection_identifier(), "Ignoring hole punch introduction which was not requested");
+            return;
+        }
+        if self
+            .neighbours
+            .lock()
+            .unwrap()
+            .get(&remote_public_key)
+            .is_some_and(|pr| pr.alive())
+        {
+            trace!(%remote_public_key, "Ignoring hole punch introduction for connected remote");
+            return;
+        }
+        // The remote connects to us as well, so respect the ACL for inbound peers. The key is
+        // checked again once the remote proved it.
+        if !self.peer_acl.read().unwrap().allows_key(&remote_public_key) {
+            debug!(%remote_public_key, "Ignoring hole punch introduction for remote which is not allowed");
+            return;
+        }
+        let hole_punched_peers = self
+            .peers
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|pi| pi.pt == PeerType::HolePunched)
+            .count();
+        if hole_punched_peers >= MAX_HOLE_PUNCHED_PEERS {
+            debug!(%remote_public_key, "Ignoring hole punch introduction, maximum amount of hole punched peers reached");
+            return;
+        }
+
+        info!(%remote_public_key, address=%introduction.address(), introducer=%source.connection_identifier(), "Received hole punch introduction");
+        self.add_peer(
+            Endpoint::new(Protocol::Quic, introduction.address()),
+            PeerType::HolePunched,
+            ConnectionTraffic {
+                tx_bytes: Arc::new(AtomicU64::new(0)),
+                rx_bytes: Arc::new(AtomicU64::new(0)),
+            },
+            None,
+            Some(remote_public_key),
+        );
+        self.connect_now.notify_one();
+    }
+
+    /// Send the endpoints of some of our live outbound peers to every neighbour. Only endpoints
+    /// with a public address are shared, and a neighbour never receives its own endpoint.
+    fn share_peers(&self) {
+        let shareable = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(endpoint, pi)| {
+                pi.pt != PeerType::Inbound
+                    && endpoint
+                        .address()
+                        .is_some_and(|address| is_public_address(address.ip()))
+            })
+            .filter_map(|(endpoint, pi)| {
+                pi.pr
+                    .upgrade()
+                    .filter(|_| pi.pr.alive())
+                    .map(|peer| (peer, endpoint.clone()))
+            })
+            .collect::<Vec<_>>();
+        if shareable.is_empty() {
+            return;
+        }
+
+        let neighbours = self.router.lock().unwrap().peer_interfaces();
+        let mut rng = rand::thread_rng();
+        for neighbour in neighbours {
+            let exchanged = shareable
+                .iter()
+                .filter(|(peer, _)| *peer != neighbour)
+                .filter_map(|(peer, endpoint)| {
+                    peer.remote_public_key()
+                        .map(|remote_public_key| (remote_public_key, endpoint.clone()))
+                })
+                .collect::<Vec<_>>();
+            let exchanged = exchanged
+                .choose_multiple(&mut rng, MAX_SHARED_PEERS)
+                .cloned()
+                .collect::<Vec<_>>();
+            for chunk in exchanged.chunks(MAX_PEER_EXCHANGE_PEERS) {
+                if let Err(e) =
+                    neighbour.send_control_packet(PeerExchange::new(chunk.to_vec()).into())
+                {
+                    debug!(err=%e, "Failed to send peer exchange");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Add the endpoints shared by a neighbour to the peer candidates, if we can connect to them
+    /// and are not connected to them yet. If there are too many candidates, the oldest ones are
+    /// removed.
+    fn handle_peer_exchange(
+        &self,
+        peer_exchange: PeerExchange,
+        candidates: &mut HashMap<Endpoint, PeerCandidate>,
+    ) {
+        let node_public_key = PublicKey::from(&self.node_secret_key);
+        let connected = self.connected_neighbours();
+        let known_endpoints = self
+            .peers
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        for (remote_public_key, endpoint) in peer_exchange.peers() {
+            if *remote_public_key == node_public_key
+                || connected.contains(remote_public_key)
+                || known_endpoints.contains(endpoint)
+                || !self.can_connect(endpoint.proto())
+                || !endpoint
+                    .address()
+                    .is_some_and(|address| is_public_address(address.ip()))
+            {
+                continue;
+            }
+
+            if candidates.len() >= MAX_PEER_CANDIDATES && !candidates.contains_key(endpoint) {
+                let oldest = candidates
+                    .iter()
+                    .min_by_key(|(_, candidate)| candidate.learned)
+                    .map(|(endpoint, _)| endpoint.clone());
+                if let Some(oldest) = oldest {
+                    candidates.remove(&oldest);
+                }
+            }
+            trace!(%endpoint, "Learned peer candidate");
+            candidates.insert(
+                endpoint.clone(),
+                PeerCandidate {
+                    remote_public_key: *remote_public_key,
+                    learned: Instant::now(),
+                },
+            );
+        }
+    }
+
+    /// Connect to peer candidates while we have less than `target` outbound peers. Newer
+    /// candidates are preferred. For diversity, we don't connect to candidates in the same
+    /// network as another outbound peer, see [`address_group`].
+    fn connect_peer_candidates(
+        &self,
+        target: usize,
+        candidates: &mut HashMap<Endpoint, PeerCandidate>,
+    ) {
+        candidates.retain(|_, candidate| candidate.learned.elapsed() < PEER_CANDIDATE_TIMEOUT);
+
+        let connected = self.connected_neighbours();
+        let mut outbound = 0;
+        let mut groups = HashSet::new();
+        {
+            let peers = self.peers.lock().unwrap();
+            for (endpoint, pi) in peers.iter() {
+                if pi.pt == PeerType::Inbound {
+                    continue;
+                }
+                if pi.connecting || pi.pr.alive() {
+                    outbound += 1;
+                }
+                if let Some(address) = endpoint.address() {
+                    groups.insert(address_group(address.ip()));
+                }
+            }
+            candidates.retain(|endpoint, _| !peers.contains_key(endpoint));
+        }
+        if outbound >= target {
+            return;
+        }
+
+        let mut ordered = candidates.iter().collect::<Vec<_>>();
+        ordered.sort_by_key(|(_, candidate)| std::cmp::Reverse(candidate.learned));
+        let mut chosen_keys = HashSet::new();
+        let mut chosen = Vec::new();
+        for (endpoint, candidate) in ordered {
+            if outbound + chosen.len() >= target {
+                break;
+            }
+            let Some(address) = endpoint.address() else {
+                continue;
+            };
+            if connected.contains(&candidate.remote_public_key)
+                || chosen_keys.contains(&candidate.remote_public_key)
+                || !groups.insert(address_group(address.ip()))
+            {
+                continue;
+            }
+            chosen_keys.insert(candidate.remote_public_key);
+            chosen.push((endpoint.clone(), candidate.remote_public_key));
+        }
+
+        if chosen.is_empty() {
+            return;
+        }
+        for (endpoint, remote_public_key) in chosen {
+            candidates.remove(&endpoint);
+            info!(%endpoint, %remote_public_key, "Connecting to peer learned through peer exchange");
+            // The neighbour might have lied about the endpoint, so the remote must prove it has
+            // the public key the neighbour claimed.
+            self.add_peer(
+                endpoint,
+                PeerType::PeerExchange,
+                ConnectionTraffic {
+                    tx_bytes: Arc::new(AtomicU64::new(0)),
+                    rx_bytes: Arc::new(AtomicU64::new(0)),
+                },
+                None,
+                Some(remote_public_key),
+            );
+        }
+        self.connect_now.notify_one();
+    }
+
+    /// Public keys of the neighbours we are currently connected to.
+    fn connected_neighbours(&self) -> HashSet<PublicKey> {
+        self.neighbours
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, pr)| pr.alive())
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// Checks if we can connect to endpoints using the given [`Protocol`].
+    fn can_connect(&self, proto: Protocol) -> bool {
+        match proto {
+            Protocol::Quic => self.quic_socket.is_some(),
+            Protocol::Tls => self.private_network_config.is_some(),
+            Protocol::Tcp | Protocol::Ws | Protocol::Wss => self.private_network_config.is_none(),
+        }
+    }
+}
+
+/// A remote learned through peer exchange, which we might connect to.
+struct PeerCandidate {
+    /// The public key of the remote, as claimed by the neighbour which shared it.
+    remote_public_key: PublicKey,
+    /// The last time a neighbour shared this candidate.
+    learned: Instant,
+}
+
+/// Checks if an address is publicly reachable, i.e. it is not a private, loopback, link local or
+/// otherwise special purpose address. Addresses in the overlay network are not public either.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Shared address space for carrier grade NAT, 100.64.0.0/10.
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0b1100_0000 == 64))
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, fc00::/7.
+                || segments[0] & 0xfe00 == 0xfc00
+                // Link local, fe80::/10.
+                || segments[0] & 0xffc0 == 0xfe80
+                // Documentation, 2001:db8::/32.
+                || (segments[0] == 0x2001 && segments[1] == 0xdb8)
+                // The overlay network itself, 400::/7.
+                || segments[0] & 0xfe00 == 0x400)
+        }
+    }
+}
+
+/// The network an address belongs to, used to avoid connecting to many peers in the same network.
+/// For IPv4 this is the /16, for IPv6 the /32 containing the address.
+fn address_group(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let [a, b, _, _] = ip.octets();
+            IpAddr::V4([a, b, 0, 0].into())
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            IpAddr::V6(std::net::Ipv6Addr::new(
+                segments[0],
+                segments[1],
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ))
+        }
+    }
 }
 
 /// Spawn a quic socket which can be used to both receive quic connections and initiate new quic
-/// connections to remotes.
+/// connections to remotes. If `server_crypto` is set, it is used to accept connections, otherwise
+/// a self signed certificate is generated.
 fn make_quic_endpoint(
     router_id: RouterId,
     quic_listen_port: u16,
     firewall_mark: Option<u32>,
+    server_crypto: Option<rustls::ServerConfig>,
 ) -> Result<quinn::Endpoint, Box<dyn std::error::Error>> {
     // Install ring crypto provider for rustls
     rustls::crypto::CryptoProvider::install_default(rustls::crypto::ring::default_provider())
         .expect("Crypto provider has not been installed yet");
-    // Generate self signed certificate certificate.
-    // TODO: sign with router keys
-    let cert = rcgen::generate_simple_self_signed(vec![format!("{router_id}")])?;
-    let certificate_der = CertificateDer::from(cert.cert);
-    let private_key = PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
-    let certificate_chain = vec![certificate_der];
-
-    let mut server_config = ServerConfig::with_single_cert(certificate_chain, private_key.into())?;
+    let mut server_config = if let Some(server_crypto) = server_crypto {
+        ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(server_crypto)?))
+    } else {
+        // Generate self signed certificate certificate.
+        // TODO: sign with router keys
+        let cert = rcgen::generate_simple_self_signed(vec![format!("{router_id}")])?;
+        let certificate_der = CertificateDer::from(cert.cert);
+        let private_key = PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+        let certificate_chain = vec![certificate_der];
+
+        ServerConfig::with_single_cert(certificate_chain, private_key.into())?
+    };
     // We can unwrap this since it's the only current instance.
     let transport_config = Arc::get_mut(&mut server_config.transport).unwrap();
     // We don't use unidirectional streams.
@@ -1173,9 +2489,8 @@ fn make_quic_endpoint(
     transport_config.max_idle_timeout(Some(Duration::from_secs(60).try_into()?));
     transport_config.mtu_discovery_config(Some(MtuDiscoveryConfig::default()));
     transport_config.keep_alive_interval(Some(Duration::from_secs(20)));
-    // we don't use datagrams.
-    transport_config.datagram_receive_buffer_size(None);
-    transport_config.datagram_send_buffer_size(0);
+    // Data packets are sent as datagrams if the remote also supports them, keep the default
+    // datagram buffer sizes.
     // TODO: further tweak this.
 
     let socket = std::net::UdpSocket::bind(("::", quic_listen_port))
@@ -1194,6 +2509,60 @@ fn make_quic_endpoint(
     Ok(endpoint)
 }
 
+/// Create the TLS configs for quic connections in a private network.
+///
+/// A CA key is derived from the network name and PSK, so every member of the network has the same
+/// CA, without the need to distribute it. Every node then issues its own certificate with this CA.
+/// Both sides of a connection must present a certificate issued by the CA, so only nodes which
+/// know the network name and PSK can complete the quic handshake.
+fn private_network_quic_crypto(
+    net_name: &str,
+    net_key: &PrivateNetworkKey,
+) -> Result<(rustls::ServerConfig, rustls::ClientConfig), Box<dyn std::error::Error>> {
+    let mut hasher = blake3::Hasher::new_derive_key(PRIVATE_NETWORK_QUIC_CA_KEY_CONTEXT);
+    hasher.update(&(net_name.len() as u64).to_be_bytes());
+    hasher.update(net_name.as_bytes());
+    hasher.update(net_key);
+    let mut ca_key_der = ED25519_PKCS8_PREFIX.to_vec();
+    ca_key_der.extend_from_slice(hasher.finalize().as_bytes());
+    let ca_key = rcgen::KeyPair::from_pkcs8_der_and_sign_algo(
+        &PrivatePkcs8KeyDer::from(ca_key_der),
+        &rcgen::PKCS_ED25519,
+    )?;
+
+    let mut ca_params = rcgen::CertificateParams::default();
+    ca_params.distinguished_name = rcgen::DistinguishedName::new();
+    ca_params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, PRIVATE_NETWORK_QUIC_CA_NAME);
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca_cert = ca_params.self_signed(&ca_key)?;
+
+    let key = rcgen::KeyPair::generate()?;
+    let cert = rcgen::CertificateParams::new(vec![QUIC_SERVER_NAME.to_string()])?
+        .signed_by(&key, &ca_cert, &ca_key)?;
+    let certificate_chain = vec![cert.der().clone()];
+    let private_key = PrivatePkcs8KeyDer::from(key.serialize_der());
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(ca_cert.der().clone())?;
+    let roots = Arc::new(roots);
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let client_verifier =
+        WebPkiClientVerifier::builder_with_provider(roots.clone(), provider.clone()).build()?;
+    let server_crypto = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_client_cert_verifier(client_verifier)
+        .with_single_cert(certificate_chain.clone(), private_key.clone_key().into())?;
+    let client_crypto = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_root_certificates(roots)
+        .with_client_auth_cert(certificate_chain, private_key.into())?;
+
+    Ok((server_crypto, client_crypto))
+}
+
 // Firewall marks are only supported on Linux
 #[cfg(target_os = "linux")]
 fn set_fw_mark<S: AsFd>(socket: S, mark: Option<u32>) -> io::Result<S> {
@@ -1309,6 +2678,8 @@ impl fmt::Display for PeerType {
             Self::Static => "Static",
             Self::Inbound => "Inbound",
             Self::LinkLocalDiscovery => "LinkLocalDiscovery",
+            Self::HolePunched => "HolePunched",
+            Self::PeerExchange => "PeerExchange",
         })
     }
 }
@@ -1328,3 +2699,153 @@ impl fmt::Display for PeerNotFound {
 }
 
 impl std::error::Error for PeerNotFound {}
+
+impl fmt::Display for NewPeerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NewPeerError::Handshake(e) => e.fmt(f),
+            NewPeerError::Rejected(public_key) => {
+                write!(f, "public key {public_key} is not allowed to connect")
+            }
+            NewPeerError::UnexpectedKey { expected, actual } => {
+                write!(f, "remote has public key {actual} instead of {expected}")
+            }
+            NewPeerError::Legacy => f.write_str(
+                "remote does not support the link handshake, and legacy peers are not allowed",
+            ),
+            NewPeerError::Io(e) => write!(f, "could not create peer: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for NewPeerError {}
+
+impl From<String> for ConnectError {
+    fn from(value: String) -> Self {
+        ConnectError::Failed(value)
+    }
+}
+
+impl From<NewPeerError> for ConnectError {
+    fn from(value: NewPeerError) -> Self {
+        match value {
+            NewPeerError::Handshake(HandshakeError::Unsupported) => ConnectError::Legacy,
+            e => ConnectError::Failed(format!("Failed to set up peer: {e}")),
+        }
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::Legacy => {
+                f.write_str("Failed to set up peer: remote does not support the link handshake")
+            }
+            ConnectError::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::{
+        address_group, connection_backoff, is_public_address, private_network_quic_crypto,
+        MAX_PEER_CONNECT_BACKOFF, PEER_CONNECT_INTERVAL, QUIC_SERVER_NAME,
+    };
+
+    /// Run a TLS handshake between the given configs in memory.
+    fn tls_handshake(
+        client: rustls::ClientConfig,
+        server: rustls::ServerConfig,
+    ) -> Result<(), rustls::Error> {
+        let mut client =
+            rustls::ClientConnection::new(Arc::new(client), QUIC_SERVER_NAME.try_into().unwrap())?;
+        let mut server = rustls::ServerConnection::new(Arc::new(server))?;
+
+        while client.is_handshaking() || server.is_handshaking() {
+            let mut buf = Vec::new();
+            client.write_tls(&mut buf).unwrap();
+            server.read_tls(&mut buf.as_slice()).unwrap();
+            server.process_new_packets()?;
+
+            let mut buf = Vec::new();
+            server.write_tls(&mut buf).unwrap();
+            client.read_tls(&mut buf.as_slice()).unwrap();
+            client.process_new_packets()?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn private_network_quic_certificates() {
+        let (server, _) = private_network_quic_crypto("network", &[1; 32]).unwrap();
+        let (_, client) = private_network_quic_crypto("network", &[1; 32]).unwrap();
+        assert!(tls_handshake(client, server).is_ok());
+
+        let (server, _) = private_network_quic_crypto("network", &[1; 32]).unwrap();
+        let (_, client) = private_network_quic_crypto("network", &[2; 32]).unwrap();
+        assert!(tls_handshake(client, server).is_err());
+
+        let (server, _) = private_network_quic_crypto("network", &[1; 32]).unwrap();
+        let (_, client) = private_network_quic_crypto("other network", &[1; 32]).unwrap();
+        assert!(tls_handshake(client, server).is_err());
+    }
+
+    #[test]
+    fn connection_backoff_doubles_up_to_cap() {
+        for attempts in 1..7 {
+            let delay = PEER_CONNECT_INTERVAL * (1 << (attempts - 1));
+            let backoff = connection_backoff(attempts as usize);
+            assert!(backoff >= delay / 2 && backoff <= delay);
+        }
+
+        for attempts in [10, 64, usize::MAX] {
+            let backoff = connection_backoff(attempts);
+            assert!(backoff >= MAX_PEER_CONNECT_BACKOFF / 2 && backoff <= MAX_PEER_CONNECT_BACKOFF);
+        }
+    }
+
+    #[test]
+    fn only_public_addresses_are_exchanged() {
+        for ip in [
+            IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)),
+            IpAddr::V4(Ipv4Addr::new(100, 64, 0, 1)),
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0x400, 1, 2, 3, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv4Addr::new(192, 168, 1, 1).to_ipv6_mapped()),
+        ] {
+            assert!(!is_public_address(ip), "{ip} is not public");
+        }
+
+        for ip in [
+            IpAddr::V4(Ipv4Addr::new(188, 40, 132, 242)),
+            IpAddr::V4(Ipv4Addr::new(100, 128, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0x2a01, 0x4f8, 0x212, 0xfa6, 0, 0, 0, 2)),
+        ] {
+            assert!(is_public_address(ip), "{ip} is public");
+        }
+    }
+
+    #[test]
+    fn address_groups() {
+        assert_eq!(
+            address_group(Ipv4Addr::new(188, 40, 132, 242).into()),
+            address_group(Ipv4Addr::new(188, 40, 1, 1).to_ipv6_mapped().into()),
+        );
+        assert_ne!(
+            address_group(Ipv4Addr::new(188, 40, 132, 242).into()),
+            address_group(Ipv4Addr::new(188, 41, 132, 242).into()),
+        );
+        assert_eq!(
+            address_group(Ipv6Addr::new(0x2a01, 0x4f8, 0x212, 0xfa6, 0, 0, 0, 2).into()),
+            address_group(Ipv6Addr::new(0x2a01, 0x4f8, 1, 0, 0, 0, 0, 1).into()),
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Operator defined route policies.
+//!
+//! A policy is an ordered list of [`PolicyRule`]s. For every update, the first rule which matches
+//! the update decides what happens with it. Updates which don't match any rule are allowed
+//! unchanged. Import rules are applied to updates received from peers, export rules are applied
+//! to updates before they are sent to peers.
+
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{
+    babel,
+    crypto::PublicKey,
+    endpoint::Endpoint,
+    filters::{RouteExportFilter, RouteUpdateFilter},
+    metric::Metric,
+    peer::Peer,
+    subnet::Subnet,
+};
+
+/// Route policy of a node.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RoutePolicy {
+    /// Rules applied to updates received from peers.
+    pub import: Vec<PolicyRule>,
+    /// Rules applied to updates before they are sent to peers.
+    pub export: Vec<PolicyRule>,
+}
+
+impl RoutePolicy {
+    /// Compile the import rules into a [`RouteUpdateFilter`]. Returns [`Option::None`] if there
+    /// are no import rules.
+    pub fn import_filter(&self) -> Option<ImportPolicy> {
+        if self.import.is_empty() {
+            None
+        } else {
+            Some(ImportPolicy {
+                rules: self.import.clone(),
+            })
+        }
+    }
+
+    /// Compile the export rules into a [`RouteExportFilter`]. Returns [`Option::None`] if there
+    /// are no export rules.
+    pub fn export_filter(&self) -> Option<ExportPolicy> {
+        if self.export.is_empty() {
+            None
+        } else {
+            Some(ExportPolicy {
+                rules: self.export.clone(),
+            })
+        }
+    }
+}
+
+/// Action taken for an update matched by a [`PolicyRule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    /// Allow the update, after applying the metric offset and cap of the rule.
+    Allow,
+    /// Reject the update.
+    Deny,
+}
+
+/// A single rule in a [`RoutePolicy`]. A rule matches an update if all of its set conditions
+/// match.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRule {
+    /// Action taken if this rule matches.
+    pub action: PolicyAction,
+    /// Only match routes for subnets contained in this subnet.
+    #[serde(default)]
+    pub subnet: Option<Subnet>,
+    /// Only match routes originated by the router with this public key.
+    #[serde(default)]
+    pub router_id: Option<PublicKey>,
+    /// Only match updates received from (import) or sent to (export) a peer at this endpoint.
+    /// The port is ignored if it is set to 0, so all connections from a remote host can be
+    /// matched.
+    #[serde(default, deserialize_with = "deserialize_endpoint")]
+    pub neighbour: Option<Endpoint>,
+    /// Value added to the metric of allowed updates.
+    #[serde(default)]
+    pub metric_offset: Option<u16>,
+    /// Allowed updates with a metric higher than this, after applying the offset, are rejected.
+    /// Retractions are never rejected by this.
+    #[serde(default)]
+    pub max_metric: Option<u16>,
+}
+
+impl PolicyRule {
+    /// Checks if this rule matches the given update. If `neighbour` is [`Option::None`], rules
+    /// which require a specific neighbour never match.
+    fn matches(&self, update: &babel::Update, neighbour: Option<&Endpoint>) -> bool {
+        if let Some(subnet) = self.subnet {
+            if !subnet.contains_subnet(&update.subnet()) {
+                return false;
+            }
+        }
+
+        if let Some(router_id) = self.router_id {
+            if update.router_id().to_pubkey() != router_id {
+                return false;
+            }
+        }
+
+        if let Some(expected) = &self.neighbour {
+            let Some(neighbour) = neighbour else {
+                return false;
+            };
+            // Hostname endpoints are rejected when the rule is parsed, and a connected peer always
+            // has an address.
+            let (Some(expected_address), Some(address)) = (expected.address(), neighbour.address())
+            else {
+                return false;
+            };
+            if expected.proto() != neighbour.proto()
+                || expected_address.ip() != address.ip()
+                || (expected_address.port() != 0 && expected_address.port() != address.port())
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Apply the action of this rule to the update. Returns true if the update is allowed.
+    fn apply(&self, update: &mut babel::Update) -> bool {
+        if self.action == PolicyAction::Deny {
+            return false;
+        }
+
+        // Retractions are passed as is, there is nothing to change on them.
+        if update.metric().is_infinite() {
+            return true;
+        }
+
+        if let Some(offset) = self.metric_offset {
+            update.set_metric(update.metric() + Metric::from(offset));
+        }
+
+        if let Some(max_metric) = self.max_metric {
+            if update.metric() > Metric::from(max_metric) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Evaluate a list of rules against an update. The first matching rule decides, if no rule
+/// matches the update is allowed.
+fn evaluate(
+    rules: &[PolicyRule],
+    update: &mut babel::Update,
+    neighbour: Option<&Endpoint>,
+) -> bool {
+    match rules.iter().find(|rule| rule.matches(update, neighbour)) {
+        Some(rule) => rule.apply(update),
+        None => true,
+    }
+}
+
+/// Import rules of a [`RoutePolicy`], used as a [`RouteUpdateFilter`].
+pub struct ImportPolicy {
+    rules: Vec<PolicyRule>,
+}
+
+impl RouteUpdateFilter for ImportPolicy {
+    fn allow(&self, update: &babel::Update) -> bool {
+        evaluate(&self.rules, &mut update.clone(), None)
+    }
+
+    fn apply(&self, update: &mut babel::Update, neighbour: &Peer) -> bool {
+        evaluate(&self.rules, update, Some(&neighbour.remote_endpoint()))
+    }
+}
+
+/// Export rules of a [`RoutePolicy`], used as a [`RouteExportFilter`].
+pub struct ExportPolicy {
+    rules: Vec<PolicyRule>,
+}
+
+impl RouteExportFilter for ExportPolicy {
+    fn apply(&self, update: &mut babel::Update, peer: &Peer) -> bool {
+        evaluate(&self.rules, update, Some(&peer.remote_endpoint()))
+    }
+}
+
+/// Deserialize an optional [`Endpoint`] from its string representation.
+fn deserialize_endpoint<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Endpoint>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| {
+            let endpoint = s.parse::<Endpoint>().map_err(de::Error::custom)?;
+            if endpoint.hostname().is_some() {
+                return Err(de::Error::custom(
+                    "neighbour must be a socket address, not a hostname",
+                ));
+            }
+            Ok(endpoint)
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv6Addr, time::Duration};
+
+    use crate::{
+        babel, crypto::SecretKey, endpoint::Endpoint, metric::Metric, router_id::RouterId,
+        sequence_number::SeqNo, subnet::Subnet,
+    };
+
+    use super::{evaluate, PolicyAction, PolicyRule};
+
+    fn rule(action: PolicyAction) -> PolicyRule {
+        PolicyRule {
+            action,
+            subnet: None,
+            router_id: None,
+            neighbour: None,
+            metric_offset: None,
+            max_metric: None,
+        }
+    }
+
+    fn update(sk: &SecretKey, metric: Metric) -> babel::Update {
+        babel::Update::new(
+            Duration::from_secs(60),
+            SeqNo::new(),
+            metric,
+            Subnet::new(Ipv6Addr::new(0x400, 1, 0, 0, 0, 0, 0, 0).into(), 64).unwrap(),
+            RouterId::new(sk.into()),
+        )
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let sk = SecretKey::new();
+        let rules = vec![
+            PolicyRule {
+                subnet: Some("600::/7".parse().unwrap()),
+                ..rule(PolicyAction::Allow)
+            },
+            PolicyRule {
+                router_id: Some((&sk).into()),
+                ..rule(PolicyAction::Deny)
+            },
+            rule(PolicyAction::Allow),
+        ];
+
+        assert!(!evaluate(&rules, &mut update(&sk, 10.into()), None));
+        assert!(evaluate(
+            &rules,
+            &mut update(&SecretKey::new(), 10.into()),
+            None
+        ));
+        // No rules allows everything.
+        assert!(evaluate(&[], &mut update(&sk, 10.into()), None));
+    }
+
+    #[test]
+    fn neighbour_match() {
+        let sk = SecretKey::new();
+        let rules = vec![PolicyRule {
+            neighbour: Some("tcp://[2001:db8::1]:0".parse().unwrap()),
+            ..rule(PolicyAction::Deny)
+        }];
+
+        let ep = |s: &str| s.parse::<Endpoint>().unwrap();
+        assert!(!evaluate(
+            &rules,
+            &mut update(&sk, 10.into()),
+            Some(&ep("tcp://[2001:db8::1]:43210"))
+        ));
+        assert!(evaluate(
+            &rules,
+            &mut update(&sk, 10.into()),
+            Some(&ep("quic://[2001:db8::1]:43210"))
+        ));
+        assert!(evaluate(
+            &rules,
+            &mut update(&sk, 10.into()),
+            Some(&ep("tcp://[2001:db8::2]:43210"))
+        ));
+        // Without a known neighbour the rule can't match.
+        assert!(evaluate(&rules, &mut update(&sk, 10.into()), None));
+    }
+
+    #[test]
+    fn metric_offset_and_cap() {
+        let sk = SecretKey::new();
+        let rules = vec![PolicyRule {
+            metric_offset: Some(100),
+            max_metric: Some(500),
+            ..rule(PolicyAction::Allow)
+        }];
+
+        let mut u = update(&sk, 300.into());
+        assert!(evaluate(&rules, &mut u, None));
+        assert_eq!(u.metric(), Metric::from(400));
+
+        assert!(!evaluate(&rules, &mut update(&sk, 450.into()), None));
+
+        let mut retraction = update(&sk, Metric::infinite());
+        assert!(evaluate(&rules, &mut retraction, None));
+        assert!(retraction.metric().is_infinite());
+    }
+}
@@ -0,0 +1,234 @@
+//! Sliding window used to detect replayed data packets.
+//!
+//! Senders include a monotonic counter in every encrypted data packet. The receiver keeps a
+//! [`ReplayWindow`] per remote, which tracks which counters have been seen recently. Since packets
+//! can be reordered when they are forwarded over different paths, counters lower than the highest
+//! seen counter are still accepted, as long as they are within the window and were not seen
+//! before.
+//!
+//! The windows of remotes are kept in [`ReplayWindows`]. Windows which are not used for some time
+//! can be removed to save memory. The highest counter seen in a removed window is remembered, so a
+//! packet which was accepted before is never accepted again.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::crypto::PublicKey;
+
+/// Amount of counters before the highest seen counter which are still accepted.
+const REPLAY_WINDOW_SIZE: u64 = 1024;
+
+/// Amount of counters tracked by a single word of the bitmap.
+const WORD_BITS: u64 = u64::BITS as u64;
+
+/// Tracks the counters of packets received from a single remote.
+pub struct ReplayWindow {
+    /// The highest counter seen so far, if any.
+    highest: Option<u64>,
+    /// Bitmap of the counters in the window which have been seen. Counter `c` is tracked by bit
+    /// `c % REPLAY_WINDOW_SIZE`.
+    seen: [u64; (REPLAY_WINDOW_SIZE / WORD_BITS) as usize],
+}
+
+impl ReplayWindow {
+    /// Create a new empty `ReplayWindow`.
+    pub fn new() -> Self {
+        Self {
+            highest: None,
+            seen: [0; (REPLAY_WINDOW_SIZE / WORD_BITS) as usize],
+        }
+    }
+
+    /// Create a `ReplayWindow` which only accepts counters higher than `highest`, as if all
+    /// counters up to and including `highest` have been seen.
+    pub fn starting_after(highest: u64) -> Self {
+        Self {
+            highest: Some(highest),
+            seen: [u64::MAX; (REPLAY_WINDOW_SIZE / WORD_BITS) as usize],
+        }
+    }
+
+    /// The highest counter seen so far, if any.
+    pub fn highest(&self) -> Option<u64> {
+        self.highest
+    }
+
+    /// Check if a packet with the given counter should be accepted. If it is accepted, the
+    /// counter is marked as seen, so a replay of the same packet is rejected.
+    ///
+    /// This must only be called for packets which are authenticated, otherwise a forged counter
+    /// can move the window.
+    pub fn accept(&mut self, counter: u64) -> bool {
+        let Some(highest) = self.highest else {
+            self.highest = Some(counter);
+            self.set(counter);
+            return true;
+        };
+
+        if counter > highest {
+            // Counters which are now part of the window have not been seen yet. If the window
+            // moves entirely, everything is cleared.
+            if counter - highest >= REPLAY_WINDOW_SIZE {
+                self.seen = [0; (REPLAY_WINDOW_SIZE / WORD_BITS) as usize];
+            } else {
+                for skipped in highest + 1..counter {
+                    self.clear(skipped);
+                }
+            }
+            self.highest = Some(counter);
+            self.set(counter);
+            return true;
+        }
+
+        if highest - counter >= REPLAY_WINDOW_SIZE || self.is_set(counter) {
+            return false;
+        }
+
+        self.set(counter);
+        true
+    }
+
+    /// Get the word and mask of the bit tracking a counter.
+    fn position(counter: u64) -> (usize, u64) {
+        let bit = counter % REPLAY_WINDOW_SIZE;
+        ((bit / WORD_BITS) as usize, 1 << (bit % WORD_BITS))
+    }
+
+    /// Mark a counter as seen.
+    fn set(&mut self, counter: u64) {
+        let (word, mask) = Self::position(counter);
+        self.seen[word] |= mask;
+    }
+
+    /// Mark a counter as not seen.
+    fn clear(&mut self, counter: u64) {
+        let (word, mask) = Self::position(counter);
+        self.seen[word] &= !mask;
+    }
+
+    /// Check if a counter is marked as seen.
+    fn is_set(&self, counter: u64) -> bool {
+        let (word, mask) = Self::position(counter);
+        self.seen[word] & mask != 0
+    }
+}
+
+impl Default for ReplayWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The [`ReplayWindow`]s of multiple remotes.
+#[derive(Default)]
+pub struct ReplayWindows {
+    /// Windows of remotes which sent packets, and the time the window was last used.
+    windows: HashMap<PublicKey, (ReplayWindow, Instant)>,
+    /// Highest counter seen by removed windows. A new window for the remote starts after it.
+    floors: HashMap<PublicKey, u64>,
+}
+
+impl ReplayWindows {
+    /// Create a new empty `ReplayWindows`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if a packet from the given remote with the given counter should be accepted, see
+    /// [`ReplayWindow::accept`].
+    pub fn accept(&mut self, remote: PublicKey, counter: u64) -> bool {
+        let floors = &self.floors;
+        let (window, last_used) = self.windows.entry(remote).or_insert_with(|| {
+            let window = floors.get(&remote).map_or_else(ReplayWindow::new, |floor| {
+                ReplayWindow::starting_after(*floor)
+            });
+            (window, Instant::now())
+        });
+        *last_used = Instant::now();
+        window.accept(counter)
+    }
+
+    /// Remove the windows which were not used for at least `timeout`. Only the highest counter
+    /// seen by a removed window is kept.
+    pub fn remove_idle(&mut self, timeout: Duration) {
+        let floors = &mut self.floors;
+        self.windows.retain(|remote, (window, last_used)| {
+            if last_used.elapsed() < timeout {
+                return true;
+            }
+            if let Some(highest) = window.highest() {
+                floors.insert(*remote, highest);
+            }
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::crypto::{PublicKey, SecretKey};
+
+    use super::{ReplayWindow, ReplayWindows, REPLAY_WINDOW_SIZE};
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let mut window = ReplayWindow::new();
+
+        for counter in 1_000..1_100 {
+            assert!(window.accept(counter));
+        }
+        for counter in 1_000..1_100 {
+            assert!(!window.accept(counter));
+        }
+    }
+
+    #[test]
+    fn reordered_packets_are_accepted() {
+        let mut window = ReplayWindow::new();
+
+        assert!(window.accept(10));
+        assert!(window.accept(15));
+        assert!(window.accept(12));
+        assert!(window.accept(11));
+        assert!(!window.accept(12));
+        // Counters skipped when the window moved must not be marked as seen.
+        assert!(window.accept(10 + REPLAY_WINDOW_SIZE + 5));
+        assert!(window.accept(20 + REPLAY_WINDOW_SIZE));
+        assert!(!window.accept(10 + REPLAY_WINDOW_SIZE + 5));
+    }
+
+    #[test]
+    fn old_packets_are_rejected() {
+        let mut window = ReplayWindow::new();
+
+        assert!(window.accept(5_000));
+        assert!(window.accept(5_000 - REPLAY_WINDOW_SIZE + 1));
+        assert!(!window.accept(5_000 - REPLAY_WINDOW_SIZE));
+        // Jump beyond the window, everything before the new window is rejected.
+        assert!(window.accept(5_000 + 10 * REPLAY_WINDOW_SIZE));
+        assert!(!window.accept(5_000));
+        assert!(window.accept(5_000 + 9 * REPLAY_WINDOW_SIZE + 1));
+    }
+
+    #[test]
+    fn removed_windows_reject_old_counters() {
+        let remote = PublicKey::from(&SecretKey::new());
+        let mut windows = ReplayWindows::new();
+
+        assert!(windows.accept(remote, 1_000));
+        assert!(windows.accept(remote, 990));
+        windows.remove_idle(Duration::ZERO);
+
+        // Counters accepted by the removed window, or older ones, are not accepted again.
+        assert!(!windows.accept(remote, 1_000));
+        assert!(!windows.accept(remote, 990));
+        assert!(!windows.accept(remote, 995));
+        assert!(!windows.accept(remote, 10));
+        assert!(windows.accept(remote, 1_001));
+        assert!(!windows.accept(remote, 1_001));
+    }
+}
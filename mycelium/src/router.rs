@@ -1,7 +1,7 @@
 use crate::{
     babel::{self, Hello, Ihu, RouteRequest, SeqNoRequest, Update},
     crypto::{PacketBuffer, PublicKey, SecretKey, SharedSecret},
-    filters::RouteUpdateFilter,
+    filters::{RouteExportFilter, RouteUpdateFilter},
     metric::Metric,
     metrics::Metrics,
     packet::{ControlPacket, DataPacket},
@@ -10,6 +10,7 @@ use crate::{
     routing_table::{RouteEntry, RouteKey, RouteList, RoutingTable},
     seqno_cache::{SeqnoCache, SeqnoRequestCacheKey},
     sequence_number::SeqNo,
+    signature_cache::SignatureCache,
     source_table::{FeasibilityDistance, SourceKey, SourceTable},
     subnet::Subnet,
 };
@@ -18,36 +19,33 @@ use etherparse::{
     Icmpv6Type,
 };
 use std::{
+    collections::hash_map::RandomState,
     error::Error,
-    hash::{Hash, Hasher},
+    hash::{BuildHasher, Hash, Hasher},
     net::IpAddr,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
     time::{Duration, Instant},
 };
 use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender};
 use tracing::{debug, error, info, trace, warn};
 
-/// Time between HELLO messags, in seconds
-const HELLO_INTERVAL: u64 = 20;
-/// Time filled in in IHU packet
-const IHU_INTERVAL: Duration = Duration::from_secs(HELLO_INTERVAL * 3);
-/// Max time used in UPDATE packets. For local (static) routes this is the timeout they are
-/// advertised with.
-const UPDATE_INTERVAL: Duration = Duration::from_secs(HELLO_INTERVAL * 3 * 5);
-/// Time between route table dumps to peers.
-const ROUTE_PROPAGATION_INTERVAL: Duration = UPDATE_INTERVAL;
-/// Amount of seconds that can elapse before we consider a [`Peer`] as dead from the routers POV.
-/// Since IHU's are sent in response to HELLO packets, this MUST be greater than the
-/// [`HELLO_INTERVAL`].
-///
-/// We allow missing 1 hello, + some latency, so 2 HELLO's + 3 seconds for latency.
-const DEAD_PEER_THRESHOLD: Duration = Duration::from_secs(HELLO_INTERVAL * 2 + 3);
+pub use config::{RouterConfig, RouterConfigError};
+pub use dampening::{DampeningConfig, DampeningConfigError};
+
+use dampening::{DampeningTable, REANNOUNCEMENT_PENALTY, RETRACTION_PENALTY};
+pub use flow::Flow;
+
+mod config;
+mod dampening;
+mod flow;
+
 /// The duration between checks for dead peers in the router. This check only looks for peers where
-/// time since the last IHU exceeds DEAD_PEER_THRESHOLD.
+/// time since the last IHU exceeds the dead peer threshold of that peer.
 const DEAD_PEER_CHECK_INTERVAL: Duration = Duration::from_secs(10);
 
-/// Amount of time to wait between consecutive seqno bumps of the local router seqno.
-const SEQNO_BUMP_TIMEOUT: Duration = Duration::from_secs(4);
+/// The duration between decay runs of the flap dampening penalties. Suppressed sources are used
+/// again at most this much time after their penalty decayed below the reuse threshold.
+const DAMPENING_DECAY_INTERVAL: Duration = Duration::from_secs(5);
 
 /// Metric change of more than 10 is considered a large change.
 const BIG_METRIC_CHANGE_TRESHOLD: Metric = Metric::new(10);
@@ -55,9 +53,6 @@ const BIG_METRIC_CHANGE_TRESHOLD: Metric = Metric::new(10);
 /// The amount a metric of a route needs to improve before we will consider switching to it.
 const SIGNIFICANT_METRIC_IMPROVEMENT: Metric = Metric::new(10);
 
-/// Hold retracted routes for 1 minute before purging them from the [`RoutingTable`].
-const RETRACTED_ROUTE_HOLD_TIME: Duration = Duration::from_secs(60);
-
 /// The interval specified in updates if the update won't be repeated.
 const INTERVAL_NOT_REPEATING: Duration = Duration::from_millis(0);
 
@@ -66,7 +61,7 @@ pub struct Router<M> {
     peer_interfaces: Arc<RwLock<Vec<Peer>>>,
     source_table: Arc<RwLock<SourceTable>>,
     // Router SeqNo and last time it was bumped
-    router_seqno: Arc<RwLock<(SeqNo, Instant)>>,
+    router_seqno: Arc<RwLock<(SeqNo, tokio::time::Instant)>>,
     static_routes: Vec<Subnet>,
     router_id: RouterId,
     node_keypair: (SecretKey, PublicKey),
@@ -75,12 +70,24 @@ pub struct Router<M> {
     node_tun: UnboundedSender<DataPacket>,
     node_tun_subnet: Subnet,
     update_filters: Arc<Vec<Box<dyn RouteUpdateFilter + Send + Sync>>>,
+    /// Filters applied to every update before it is sent to a peer.
+    export_filters: Arc<Vec<Box<dyn RouteExportFilter + Send + Sync>>>,
     /// Channel injected into peers, so they can notify the router if they exit.
     dead_peer_sink: mpsc::Sender<Peer>,
+    /// Channel to forward received TLV's which are handled by the peer manager.
+    peer_manager_tlv_sink: UnboundedSender<(ControlPacket, Peer)>,
     /// Channel to notify the router of expired SourceKey's.
     expired_source_key_sink: mpsc::Sender<SourceKey>,
     seqno_cache: SeqnoCache,
+    /// Cache of verified signatures on received updates.
+    signature_cache: SignatureCache,
     update_workers: usize,
+    config: RouterConfig,
+    /// Hasher used to assign [`Flow`]s to paths. This is randomized per router, so different
+    /// routers don't make the same choice for the same flow.
+    flow_hasher: RandomState,
+    /// Flap dampening state of sources, if flap dampening is enabled.
+    dampening: Option<Arc<Mutex<DampeningTable>>>,
     metrics: M,
 }
 
@@ -90,16 +97,23 @@ where
 {
     /// Create a new `Router`.
     ///
+    /// Received hole punch and peer exchange TLV's are not processed by the router, but forwarded
+    /// to `peer_manager_tlv_sink`, together with the [`Peer`] which sent them.
+    ///
     /// # Panics
     ///
     /// If update_workers is not in the range of [1..255], this will panic.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         update_workers: usize,
+        config: RouterConfig,
         node_tun: UnboundedSender<DataPacket>,
         node_tun_subnet: Subnet,
         static_routes: Vec<Subnet>,
         node_keypair: (SecretKey, PublicKey),
         update_filters: Vec<Box<dyn RouteUpdateFilter + Send + Sync>>,
+        export_filters: Vec<Box<dyn RouteExportFilter + Send + Sync>>,
+        peer_manager_tlv_sink: UnboundedSender<(ControlPacket, Peer)>,
         metrics: M,
     ) -> Result<Self, Box<dyn Error>> {
         // We could use a NonZeroU8 here, but for now just handle this manually as this might get
@@ -108,6 +122,8 @@ where
             panic!("update workers must be at least 1 and at most 255");
         }
 
+        config.validate()?;
+
         // Tx is passed onto each new peer instance. This enables peers to send control packets to the router.
         let (router_control_tx, router_control_rx) = mpsc::unbounded_channel();
         // Tx is passed onto each new peer instance. This enables peers to send data packets to the router.
@@ -126,7 +142,7 @@ where
             routing_table,
             peer_interfaces: Arc::new(RwLock::new(Vec::new())),
             source_table: Arc::new(RwLock::new(SourceTable::new())),
-            router_seqno: Arc::new(RwLock::new((SeqNo::new(), Instant::now()))),
+            router_seqno: Arc::new(RwLock::new((SeqNo::new(), tokio::time::Instant::now()))),
             static_routes,
             router_id,
             node_keypair,
@@ -135,10 +151,18 @@ where
             node_tun,
             node_tun_subnet,
             dead_peer_sink,
+            peer_manager_tlv_sink,
             expired_source_key_sink,
             seqno_cache,
+            signature_cache: SignatureCache::new(),
             update_filters: Arc::new(update_filters),
+            export_filters: Arc::new(export_filters),
             update_workers,
+            config,
+            flow_hasher: RandomState::new(),
+            dampening: config
+                .flap_dampening
+                .map(|dc| Arc::new(Mutex::new(DampeningTable::new(dc)))),
             metrics,
         };
 
@@ -156,6 +180,10 @@ where
 
         tokio::spawn(Router::check_for_dead_peers(router.clone()));
 
+        if router.dampening.is_some() {
+            tokio::spawn(Router::decay_flap_penalties(router.clone()));
+        }
+
         tokio::spawn(Router::process_expired_source_keys(
             router.clone(),
             expired_source_key_stream,
@@ -183,6 +211,11 @@ where
         self.node_tun_subnet
     }
 
+    /// Get the subnets announced by this router.
+    pub fn static_routes(&self) -> &[Subnet] {
+        &self.static_routes
+    }
+
     pub fn node_tun(&self) -> UnboundedSender<DataPacket> {
         self.node_tun.clone()
     }
@@ -216,11 +249,22 @@ where
         self.node_keypair.1
     }
 
+    /// Get the [`Metrics`] implementation used by the `Router`.
+    pub fn metrics(&self) -> &M {
+        &self.metrics
+    }
+
     /// Get the [`RouterId`] of the `Router`.
     pub fn router_id(&self) -> RouterId {
         self.router_id
     }
 
+    /// Get the [`SecretKey`] of the node. This is used to authenticate the node on new
+    /// connections.
+    pub fn node_secret_key(&self) -> SecretKey {
+        self.node_keypair.0.clone()
+    }
+
     /// Get the [`PublicKey`] for an [`IpAddr`] if a route exists to the IP.
     pub fn get_pubkey(&self, ip: IpAddr) -> Option<PublicKey> {
         self.routing_table
@@ -260,6 +304,40 @@ where
         self.metrics.router_peer_removed();
     }
 
+    /// Notify the router that the link cost of a [`Peer`] changed outside of the regular link
+    /// cost measurements, e.g. because its link cost offset was changed.
+    ///
+    /// This reruns route selection for all subnets with a route through the peer, and sends a
+    /// triggered update if the selected route is through this peer, so neighbours learn about the
+    /// new metric.
+    pub fn peer_link_cost_changed(&self, peer: &Peer) {
+        let affected = self
+            .routing_table
+            .read()
+            .iter()
+            .filter_map(|(subnet, rl)| {
+                rl.iter()
+                    .find(|re| re.neighbour() == peer)
+                    .map(|re| (subnet, re.selected()))
+            })
+            .collect::<Vec<_>>();
+
+        for (subnet, was_selected) in affected {
+            self.route_selection(subnet);
+            // Route selection only triggers an update if the selected route changed.
+            if was_selected
+                && self
+                    .routing_table
+                    .routes(subnet)
+                    .as_ref()
+                    .and_then(|rl| rl.selected())
+                    .is_some_and(|sre| sre.neighbour() == peer)
+            {
+                self.trigger_update(subnet, None);
+            }
+        }
+    }
+
     /// Get a list of all selected route entries.
     pub fn load_selected_routes(&self) -> Vec<RouteEntry> {
         self.routing_table
@@ -279,7 +357,8 @@ where
             .collect()
     }
 
-    /// Task which periodically checks for dead peers in the Router.
+    /// Task which periodically checks for dead peers, and stale connections of live peers, in the
+    /// Router.
     async fn check_for_dead_peers(self) {
         loop {
             // check for dead peers every second
@@ -287,28 +366,109 @@ where
 
             trace!("Checking for dead peers");
 
-            let dead_peers = {
+            let (dead_peers, changed_peers) = {
                 // a peer is assumed dead when the peer's last sent ihu exceeds a threshold
                 let mut dead_peers = Vec::new();
+                let mut changed_peers = Vec::new();
                 for peer in self.peer_interfaces.read().unwrap().iter() {
                     // check if the peer's last_received_ihu is greater than the threshold
-                    if peer.time_last_received_ihu().elapsed() > DEAD_PEER_THRESHOLD {
+                    let threshold = self.config.peer_dead_threshold(
+                        peer.advertised_hello_interval(),
+                        peer.advertised_ihu_interval(),
+                    );
+                    if peer.time_last_received_ihu().elapsed() > threshold {
                         // peer is dead
                         info!("Peer {} is dead", peer.connection_identifier());
                         // Notify peer it's dead in case it's not aware of that yet.
                         peer.died();
                         dead_peers.push(peer.clone());
+                        continue;
+                    }
+
+                    // HELLO's are sent over every connection, so the same threshold applies to
+                    // the individual connections of the peer.
+                    peer.close_stale_links(threshold);
+                    if peer.active_link_changed() {
+                        changed_peers.push(peer.clone());
                     }
                 }
-                dead_peers
+                (dead_peers, changed_peers)
             };
 
             for dead_peer in dead_peers {
                 self.handle_dead_peer(dead_peer);
             }
+
+            // The static link cost of the new active connection might be different, so routes
+            // through the peer need to be reselected.
+            for peer in changed_peers {
+                debug!(
+                    "Active connection of peer {} changed",
+                    peer.connection_identifier()
+                );
+                self.peer_link_cost_changed(&peer);
+            }
         }
     }
 
+    /// Task which periodically decays the flap dampening penalties, and reruns route selection for
+    /// sources which are no longer suppressed.
+    async fn decay_flap_penalties(self) {
+        let Some(dampening) = self.dampening.clone() else {
+            return;
+        };
+
+        loop {
+            tokio::time::sleep(DAMPENING_DECAY_INTERVAL).await;
+
+            let reusable = dampening.lock().unwrap().decay(tokio::time::Instant::now());
+
+            for source in reusable {
+                info!(%source, "Route flap dampening no longer suppresses source");
+                // Route selection expects at least 1 route to be present.
+                if self
+                    .routing_table
+                    .routes(source.subnet())
+                    .is_some_and(|rl| !rl.is_empty())
+                {
+                    self.route_selection(source.subnet());
+                }
+            }
+        }
+    }
+
+    /// Checks if routes from the given [`SourceKey`] are currently suppressed by route flap
+    /// dampening.
+    pub fn is_source_suppressed(&self, source: &SourceKey) -> bool {
+        self.dampening
+            .as_ref()
+            .is_some_and(|d| d.lock().unwrap().is_suppressed(source))
+    }
+
+    /// Record a change in reachability of a source for route flap dampening. Returns true if the
+    /// source became suppressed because of this.
+    fn record_flap(&self, source: SourceKey, reachable: bool) -> bool {
+        let Some(dampening) = &self.dampening else {
+            return false;
+        };
+
+        let penalty = if reachable {
+            REANNOUNCEMENT_PENALTY
+        } else {
+            RETRACTION_PENALTY
+        };
+        let suppressed =
+            dampening
+                .lock()
+                .unwrap()
+                .record_flap(source, penalty, tokio::time::Instant::now());
+        if suppressed {
+            info!(%source, "Source is flapping, suppressing its routes");
+        }
+
+        suppressed
+    }
+
     /// Remove a dead peer from the router.
     pub fn handle_dead_peer(&self, dead_peer: Peer) {
         self.metrics.router_peer_died();
@@ -337,7 +497,7 @@ where
                         // Don't clear selected flag yet, running route selection does that for us.
                         re.set_metric(Metric::infinite());
                         re.set_expires(
-                            tokio::time::Instant::now() + RETRACTED_ROUTE_HOLD_TIME,
+                            tokio::time::Instant::now() + self.config.retracted_route_hold_time,
                             eres.clone(),
                             ct.clone(),
                         );
@@ -448,7 +608,7 @@ where
                     if entry.selected() {
                         debug!(%subnet, peer = rk.neighbour().connection_identifier(), "Selected route expired, increasing metric to infinity");
                         entry.set_metric(Metric::infinite());
-                        entry.set_expires(tokio::time::Instant::now() + RETRACTED_ROUTE_HOLD_TIME, eres.clone(), ct.clone());
+                        entry.set_expires(tokio::time::Instant::now() + self.config.retracted_route_hold_time, eres.clone(), ct.clone());
                     } else {
                         debug!(%subnet, peer = rk.neighbour().connection_identifier(), "Unselected route expired, removing fallback route");
                         routes.remove(rk.neighbour());
@@ -549,6 +709,14 @@ where
                         break;
                     };
                 }
+                tlv @ (babel::Tlv::HolePunchRequest(_)
+                | babel::Tlv::HolePunchIntroduction(_)
+                | babel::Tlv::PeerExchange(_)) => {
+                    // Nobody might be listening for these, which is fine.
+                    if self.peer_manager_tlv_sink.send((tlv, source_peer)).is_err() {
+                        trace!("Dropping TLV as it is not handled by a peer manager");
+                    }
+                }
             }
         }
     }
@@ -667,11 +835,19 @@ where
     }
 
     /// Handle a received hello TLV
-    fn handle_incoming_hello(&self, _: babel::Hello, source_peer: Peer) {
+    fn handle_incoming_hello(&self, hello: babel::Hello, source_peer: Peer) {
         self.metrics.router_process_hello();
+        // Track received hello's so we can estimate the loss on the link. Peers send their
+        // HELLO's over all connections, only the first copy is answered.
+        if !source_peer.record_received_hello(hello.seqno(), hello.interval()) {
+            return;
+        }
+        source_peer.set_advertised_hello_interval(hello.interval());
         // Upon receiving and Hello message from a peer, this node has to send a IHU back
-        // TODO: properly calculate RX cost, for now just set the link cost.
-        let ihu = ControlPacket::new_ihu(source_peer.link_cost().into(), IHU_INTERVAL, None);
+        // TODO: properly calculate RX cost, for now just set the measured link cost. The local
+        // link cost offset is deliberately left out, it only applies to routes on this node.
+        let ihu =
+            ControlPacket::new_ihu(source_peer.rx_cost().into(), self.config.ihu_interval, None);
         if source_peer.send_control_packet(ihu).is_err() {
             trace!(
                 "Failed to send IHU reply to peer: {}",
@@ -681,8 +857,9 @@ where
     }
 
     /// Handle a received IHU TLV
-    fn handle_incoming_ihu(&self, _: babel::Ihu, source_peer: Peer) {
+    fn handle_incoming_ihu(&self, ihu: babel::Ihu, source_peer: Peer) {
         self.metrics.router_process_ihu();
+        source_peer.set_advertised_ihu_interval(ihu.interval());
         // reset the IHU timer associated with the peer
         // measure time between Hello and and IHU and set the link cost
         let time_diff = tokio::time::Instant::now()
@@ -718,12 +895,13 @@ where
                     return;
                 }
                 babel::Update::new(
-                    advertised_update_interval(sre),
+                    advertised_update_interval(sre, self.config.update_interval),
                     sre.seqno(),
                     sre.metric() + Metric::from(sre.neighbour().link_cost()),
                     subnet,
                     sre.source().router_id(),
                 )
+                .with_signature(sre.signature())
             }
             // Could be a request for a static route/subnet.
             else if let Some(static_route) = self
@@ -734,12 +912,10 @@ where
                 trace!(
                     "Advertising static route {static_route} in response to route request for {subnet}"
                 );
-                babel::Update::new(
-                    UPDATE_INTERVAL, // Static route is advertised with the default interval
-                    self.router_seqno.read().unwrap().0, // Updates receive the seqno of the router
-                    Metric::from(0), // Static route has no further hop costs
+                self.local_update(
+                    self.config.update_interval, // Static route is advertised with the update interval
+                    Metric::from(0),             // Static route has no further hop costs
                     *static_route,
-                    self.router_id,
                 )
             }
             // If the requested route is not present, send a retraction
@@ -747,12 +923,12 @@ where
                 trace!(
                     "Sending retraction for unknown subnet {subnet} in response to route request"
                 );
-                babel::Update::new(
+                // Retractions receive the seqno and router ID of the router, since we advertise
+                // this.
+                self.local_update(
                     INTERVAL_NOT_REPEATING,
-                    self.router_seqno.read().unwrap().0, // Retractions receive the seqno of the router
-                    Metric::infinite(),                  // Static route has no further hop costs
-                    subnet,                              // Advertise the exact subnet requested
-                    self.router_id, // Our own router ID, since we advertise this
+                    Metric::infinite(), // Static route has no further hop costs
+                    subnet,             // Advertise the exact subnet requested
                 )
             };
 
@@ -794,7 +970,7 @@ where
                     seqno_request.prefix()
                 );
                 let update = babel::Update::new(
-                    advertised_update_interval(route_entry),
+                    advertised_update_interval(route_entry, self.config.update_interval),
                     route_entry.seqno(), // updates receive the seqno of the router
                     route_entry.metric() + Metric::from(source_peer.link_cost()),
                     // the cost of the route is the cost of the route + the cost of the link to the peer
@@ -802,7 +978,8 @@ where
                     // we looked for the router_id, which is a public key, in the dest_pubkey_map
                     // if the router_id is not in the map, then the route came from the node itself
                     route_entry.source().router_id(),
-                );
+                )
+                .with_signature(route_entry.signature());
 
                 self.update_source_table(&update);
 
@@ -830,7 +1007,7 @@ where
             && seqno_request.seqno().gt(&router_seqno)
             && self.static_routes.contains(&seqno_request.prefix())
         {
-            if last_seqno_bump.elapsed() >= SEQNO_BUMP_TIMEOUT {
+            if last_seqno_bump.elapsed() >= self.config.seqno_bump_timeout {
                 trace!("Ignoring seqno bump request which happened too fast");
                 return;
             }
@@ -842,14 +1019,14 @@ where
             {
                 let mut router_seqno = self.router_seqno.write().unwrap();
                 // First check again if we should bump
-                if router_seqno.1.elapsed() >= SEQNO_BUMP_TIMEOUT {
+                if router_seqno.1.elapsed() >= self.config.seqno_bump_timeout {
                     trace!("Ignoring seqno bump request which happened too fast");
                     return;
                 }
                 // Bump seqno
                 router_seqno.0 += 1;
                 // Set last modified time
-                router_seqno.1 = Instant::now();
+                router_seqno.1 = tokio::time::Instant::now();
             }
 
             self.propagate_static_routes_to_peers();
@@ -881,7 +1058,7 @@ where
 
             let mut visited_peers = vec![];
             if let Some((last_sent, visited)) = self.seqno_cache.info(&srck) {
-                if last_sent.elapsed() < SEQNO_BUMP_TIMEOUT {
+                if last_sent.elapsed() < self.config.seqno_bump_timeout {
                     visited_peers = visited;
                 }
             }
@@ -970,12 +1147,21 @@ where
     /// the current route.
     fn find_best_route<'a>(&self, routes: &'a RouteList) -> Option<&'a RouteEntry> {
         let source_table = self.source_table.read().unwrap();
-        let current = routes.selected();
+        let dampening = self.dampening.as_ref().map(|d| d.lock().unwrap());
+        let suppressed = |re: &RouteEntry| {
+            dampening
+                .as_ref()
+                .is_some_and(|d| d.is_suppressed(&re.source()))
+        };
+        let current = routes.selected().filter(|re| !suppressed(re));
         let best = routes
             .iter()
             // Infinite metrics are technically feasible, but for route selection we explicitly
-            // don't want infinite metrics as those routes are unreachable.
-            .filter(|re| !re.metric().is_infinite() && source_table.route_feasible(re))
+            // don't want infinite metrics as those routes are unreachable. Routes from sources
+            // which are suppressed due to flapping are not considered either.
+            .filter(|re| {
+                !re.metric().is_infinite() && source_table.route_feasible(re) && !suppressed(re)
+            })
             .min_by_key(|re| re.metric() + Metric::from(re.neighbour().link_cost()));
 
         if let (Some(best), Some(current)) = (best, current) {
@@ -996,17 +1182,27 @@ where
     }
 
     /// Handle a received update TLV
-    fn handle_incoming_update(&self, update: babel::Update, source_peer: Peer) {
+    fn handle_incoming_update(&self, mut update: babel::Update, source_peer: Peer) {
         self.metrics.router_process_update();
         // Check if we actually allow this update based on filters.
         for filter in &*self.update_filters {
-            if !filter.allow(&update) {
+            if !filter.apply(&mut update, &source_peer) {
                 debug!("Update denied by filter");
                 self.metrics.router_update_denied_by_filter();
                 return;
             }
         }
 
+        if !self.update_signature_acceptable(&update) {
+            debug!(
+                subnet = %update.subnet(),
+                router_id = %update.router_id(),
+                "Update denied because of invalid or missing signature"
+            );
+            self.metrics.router_update_invalid_signature();
+            return;
+        }
+
         let metric = update.metric();
         let router_id = update.router_id();
         let seqno = update.seqno();
@@ -1053,11 +1249,14 @@ where
             }
         };
 
+        let source_key = SourceKey::new(subnet, router_id);
         let mut old_selected_route = None;
-        let route_selection = routing_table_entries.update_routes(|routes, eres, ct| {
+        let mut was_reachable = false;
+        let mut route_selection = routing_table_entries.update_routes(|routes, eres, ct| {
             // Take a deep copy of the old selected route if there is one, deep copy since we will
             // potentially mutate the route list so we can't keep a reference to it.
             old_selected_route = routes.selected().cloned();
+            was_reachable = source_reachable(routes, source_key);
 
             let maybe_existing_entry = routes.iter_mut().find(|re| re.neighbour() == &source_peer);
 
@@ -1097,12 +1296,14 @@ where
                 existing_entry.set_seqno(seqno);
                 existing_entry.set_metric(metric);
                 existing_entry.set_router_id(router_id);
+                existing_entry.set_signature(update.signature());
 
                 // Only reset the timer if the update is feasible, this will allow unfeasible updates
                 // to be flushed out naturally. Note that a retraction is always feasbile.
                 if update_feasible {
                     existing_entry.set_expires(
-                        tokio::time::Instant::now() + route_hold_time(&update),
+                        tokio::time::Instant::now()
+                            + route_hold_time(&update, self.config.retracted_route_hold_time),
                         eres.clone(),
                         ct.clone(),
                     );
@@ -1137,23 +1338,29 @@ where
                 }
 
                 // Create new entry in the route table
-                routes.insert(
-                    RouteEntry::new(
-                        SourceKey::new(subnet, router_id),
-                        source_peer.clone(),
-                        metric,
-                        seqno,
-                        false,
-                        tokio::time::Instant::now() + route_hold_time(&update),
-                    ),
-                    eres.clone(),
-                    ct.clone(),
+                let mut route_entry = RouteEntry::new(
+                    SourceKey::new(subnet, router_id),
+                    source_peer.clone(),
+                    metric,
+                    seqno,
+                    false,
+                    tokio::time::Instant::now()
+                        + route_hold_time(&update, self.config.retracted_route_hold_time),
                 );
+                route_entry.set_signature(update.signature());
+                routes.insert(route_entry, eres.clone(), ct.clone());
             }
 
             true
         });
 
+        // Track changes in reachability of the source for route flap dampening. If the source
+        // becomes suppressed, route selection must run to unselect it.
+        let is_reachable = source_reachable(&routing_table_entries.routes(), source_key);
+        if was_reachable != is_reachable && self.record_flap(source_key, is_reachable) {
+            route_selection = true;
+        }
+
         if !route_selection {
             self.metrics.router_update_skipped_route_selection();
             return;
@@ -1241,6 +1448,41 @@ where
         }
     }
 
+    /// Checks if the signature of an update allows it to be accepted. Signed updates must carry a
+    /// valid signature. Unsigned updates are accepted, unless signatures are required or the
+    /// source has recently sent signed updates. Unsigned retractions are always accepted, since a
+    /// neighbour can always stop advertising a route anyway.
+    fn update_signature_acceptable(&self, update: &babel::Update) -> bool {
+        if update.signature().is_some() {
+            return self.signature_cache.verify(update);
+        }
+
+        if update.metric().is_infinite() {
+            return true;
+        }
+
+        !self.config.require_signed_updates
+            && !self
+                .signature_cache
+                .is_signed_source(&SourceKey::new(update.subnet(), update.router_id()))
+    }
+
+    /// Create an [`Update`] originated by this router, with the current router seqno. The update
+    /// is signed if this is enabled in the config.
+    fn local_update(&self, interval: Duration, metric: Metric, subnet: Subnet) -> Update {
+        let mut update = babel::Update::new(
+            interval,
+            self.router_seqno.read().unwrap().0,
+            metric,
+            subnet,
+            self.router_id,
+        );
+        if self.config.sign_updates {
+            update.sign(&self.node_keypair.0);
+        }
+        update
+    }
+
     /// Trigger an update for the given [`Subnet`]. If `peers` is [`None`], send the update to all
     /// peers the `Router` knows.
     fn trigger_update(&self, subnet: Subnet, peers: Option<Vec<Peer>>) {
@@ -1292,7 +1534,7 @@ where
                 // If it's more than some time since we last sent an update to any peer for this
                 // seqno request, send it again. We use the seqno bump timeout here, since that is
                 // the quickest time between bumps from a peer.
-                if last_sent.elapsed() < SEQNO_BUMP_TIMEOUT {
+                if last_sent.elapsed() < self.config.seqno_bump_timeout {
                     peers_sent = visited;
                 }
             };
@@ -1350,9 +1592,14 @@ where
         self.static_routes.contains(&subnet)
     }
 
-    pub fn route_packet(&self, mut data_packet: DataPacket) {
-        let node_tun_subnet = self.node_tun_subnet();
+    pub fn route_packet(&self, data_packet: DataPacket) {
+        let flow = Flow::new(data_packet.src_ip, data_packet.dst_ip);
+        self.route_flow_packet(data_packet, flow)
+    }
 
+    /// Route a [`DataPacket`] which is part of the given [`Flow`]. If multipath forwarding is
+    /// enabled, the flow decides which of the equal cost paths is used.
+    pub fn route_flow_packet(&self, mut data_packet: DataPacket, flow: Flow) {
         trace!(
             "Incoming data packet {} -> {}",
             data_packet.src_ip,
@@ -1366,19 +1613,25 @@ where
         }
         data_packet.hop_limit -= 1;
 
-        if node_tun_subnet.contains_ip(data_packet.dst_ip.into()) {
+        // Packets for any of the subnets we announce are delivered locally. For additional
+        // subnets, the host is responsible for further routing from the TUN interface.
+        if self
+            .static_routes
+            .iter()
+            .any(|sr| sr.contains_ip(data_packet.dst_ip.into()))
+        {
             self.metrics.router_route_packet_local();
             if let Err(e) = self.node_tun().send(data_packet) {
                 error!("Error sending data packet to TUN interface: {:?}", e);
             }
         } else {
-            match self.routing_table.selected_route(data_packet.dst_ip.into()) {
-                Some(route_entry) => {
+            match self.next_hop(data_packet.dst_ip.into(), &flow) {
+                Some(neighbour) => {
                     self.metrics.router_route_packet_forward();
-                    if let Err(e) = route_entry.neighbour().send_data_packet(data_packet) {
+                    if let Err(e) = neighbour.send_data_packet(data_packet) {
                         error!(
                             "Error sending data packet to peer {}: {:?}",
-                            route_entry.neighbour().connection_identifier(),
+                            neighbour.connection_identifier(),
                             e
                         );
                     }
@@ -1391,6 +1644,47 @@ where
         }
     }
 
+    /// Find the neighbour to forward a packet of the given [`Flow`] to.
+    ///
+    /// Without multipath forwarding, this is the neighbour of the selected route. Otherwise, all
+    /// feasible routes from the same source as the selected route, with a metric at most the
+    /// configured delta worse than the selected route, are candidates. A candidate is chosen with
+    /// rendezvous hashing on the flow, so a flow sticks to its path as long as that path remains
+    /// a candidate, even if other paths come and go.
+    fn next_hop(&self, dst: IpAddr, flow: &Flow) -> Option<Peer> {
+        let Some(delta) = self.config.ecmp_metric_delta else {
+            return self
+                .routing_table
+                .selected_route(dst)
+                .map(|re| re.neighbour().clone());
+        };
+
+        let routes = self.routing_table.best_routes(dst)?;
+        let selected = routes.selected()?;
+        let max_metric =
+            u16::from(selected.metric() + Metric::from(selected.neighbour().link_cost()))
+                .saturating_add(delta);
+
+        let source_table = self.source_table.read().unwrap();
+        routes
+            .iter()
+            .filter(|re| {
+                // The selected route is always a candidate, even if it is not strictly feasible
+                // anymore when compared to the feasibility distance we advertised for it.
+                re.neighbour() == selected.neighbour()
+                    || (re.source() == selected.source()
+                        && !re.metric().is_infinite()
+                        && source_table.route_feasible(re)
+                        && u16::from(re.metric() + Metric::from(re.neighbour().link_cost()))
+                            <= max_metric)
+            })
+            .max_by_key(|re| {
+                self.flow_hasher
+                    .hash_one((flow, re.neighbour().connection_identifier()))
+            })
+            .map(|re| re.neighbour().clone())
+    }
+
     /// Handle a received data packet.
     async fn handle_incoming_data_packet(self, mut router_data_rx: Receiver<DataPacket>) {
         while let Some(data_packet) = router_data_rx.recv().await {
@@ -1481,7 +1775,7 @@ where
     /// Task to propagate the static routes periodically
     async fn propagate_static_routes(self) {
         loop {
-            tokio::time::sleep(ROUTE_PROPAGATION_INTERVAL).await;
+            tokio::time::sleep(self.config.update_interval).await;
 
             trace!("Propagating static routes");
 
@@ -1501,7 +1795,7 @@ where
     /// Task to propagate selected routes periodically
     async fn propagate_selected_routes(self) {
         loop {
-            tokio::time::sleep(ROUTE_PROPAGATION_INTERVAL).await;
+            tokio::time::sleep(self.config.update_interval).await;
 
             trace!("Propagating selected routes");
 
@@ -1523,12 +1817,13 @@ where
                 let router_id = sre.source().router_id();
 
                 let update = babel::Update::new(
-                    advertised_update_interval(&sre),
+                    advertised_update_interval(&sre, self.config.update_interval),
                     seqno,
                     metric,
                     subnet,
                     router_id,
-                );
+                )
+                .with_signature(sre.signature());
 
                 // Before sending an update, the source table might need to be updated
                 self.update_source_table(&update);
@@ -1604,7 +1899,7 @@ where
 
     /// Task which periodically sends a Hello TLV to all known peers
     async fn start_periodic_hello_sender(self) {
-        let hello_interval = Duration::from_secs(HELLO_INTERVAL);
+        let hello_interval = self.config.hello_interval;
         loop {
             tokio::time::sleep(hello_interval).await;
 
@@ -1612,7 +1907,8 @@ where
                 let hello = ControlPacket::new_hello(peer, hello_interval);
                 peer.set_time_last_received_hello(tokio::time::Instant::now());
 
-                if peer.send_control_packet(hello).is_err() {
+                // Send over all connections, so the remote can detect stale ones.
+                if peer.send_control_packet_on_all_links(hello).is_err() {
                     trace!(
                         "Failed to send Hello TLV to dead peer {}",
                         peer.connection_identifier()
@@ -1625,7 +1921,7 @@ where
     /// Send a control packet to a peer.
     ///
     /// Errors are not propagated to the caller.
-    fn send_update(&self, peer: &Peer, update: Update) {
+    fn send_update(&self, peer: &Peer, mut update: Update) {
         trace!("Sending update to peer");
 
         // Sanity check, verify what we are doing is actually usefull
@@ -1635,6 +1931,17 @@ where
             return;
         }
 
+        for filter in &*self.export_filters {
+            if !filter.apply(&mut update, peer) {
+                trace!(
+                    "Update for {} to peer {} denied by export filter",
+                    update.subnet(),
+                    peer.connection_identifier()
+                );
+                return;
+            }
+        }
+
         if peer
             .send_control_packet(ControlPacket::Update(update))
             .is_err()
@@ -1650,12 +1957,10 @@ where
     /// Propagate the static routes to a single peer
     fn propagate_static_route_to_peer(&self, peer: &Peer) {
         for sr in self.static_routes.iter() {
-            let update = babel::Update::new(
-                UPDATE_INTERVAL,
-                self.router_seqno.read().unwrap().0, // updates receive the seqno of the router
-                Metric::from(0),                     // Static route has no further hop costs
+            let update = self.local_update(
+                self.config.update_interval,
+                Metric::from(0), // Static route has no further hop costs
                 *sr,
-                self.router_id,
             );
             self.update_source_table(&update);
 
@@ -1667,29 +1972,25 @@ where
     /// Propagate a selected route. Unless peers are specified, all knwon peers in the router are
     /// used.
     fn propagate_selected_route(&self, subnet: Subnet, peers: Option<Vec<Peer>>) {
-        let (update, maybe_neigh) =
-            if let Some(sre) = self.routing_table.selected_route(subnet.address()) {
-                let update = babel::Update::new(
-                    advertised_update_interval(&sre),
-                    sre.seqno(),
-                    sre.metric() + Metric::from(sre.neighbour().link_cost()),
-                    sre.source().subnet(),
-                    sre.source().router_id(),
-                );
-                (update, Some(sre.neighbour().clone()))
-            } else {
-                // This can happen if the only feasible route gets an infinite metric, as those are
-                // never selected.
-                debug!(subnet = %subnet, "Retracting route");
-                let update = babel::Update::new(
-                    UPDATE_INTERVAL,
-                    self.router_seqno.read().unwrap().0,
-                    Metric::infinite(),
-                    subnet,
-                    self.router_id,
-                );
-                (update, None)
-            };
+        let (update, maybe_neigh) = if let Some(sre) =
+            self.routing_table.selected_route(subnet.address())
+        {
+            let update = babel::Update::new(
+                advertised_update_interval(&sre, self.config.update_interval),
+                sre.seqno(),
+                sre.metric() + Metric::from(sre.neighbour().link_cost()),
+                sre.source().subnet(),
+                sre.source().router_id(),
+            )
+            .with_signature(sre.signature());
+            (update, Some(sre.neighbour().clone()))
+        } else {
+            // This can happen if the only feasible route gets an infinite metric, as those are
+            // never selected.
+            debug!(subnet = %subnet, "Retracting route");
+            let update = self.local_update(self.config.update_interval, Metric::infinite(), subnet);
+            (update, None)
+        };
 
         self.update_source_table(&update);
 
@@ -1737,13 +2038,14 @@ where
                 continue;
             }
             let update = babel::Update::new(
-                advertised_update_interval(&sre),
+                advertised_update_interval(&sre, self.config.update_interval),
                 sre.seqno(),
                 // the cost of the route is the cost of the route + the cost of the link to the next-hop
                 sre.metric() + neigh_link_cost,
                 subnet,
                 sre.source().router_id(),
-            );
+            )
+            .with_signature(sre.signature());
             debug!(
                 subnet = %subnet,
                 metric = %sre.metric() + neigh_link_cost,
@@ -1780,24 +2082,37 @@ where
             node_tun: self.node_tun.clone(),
             node_tun_subnet: self.node_tun_subnet,
             update_filters: self.update_filters.clone(),
+            export_filters: self.export_filters.clone(),
             dead_peer_sink: self.dead_peer_sink.clone(),
+            peer_manager_tlv_sink: self.peer_manager_tlv_sink.clone(),
             expired_source_key_sink: self.expired_source_key_sink.clone(),
             seqno_cache: self.seqno_cache.clone(),
+            signature_cache: self.signature_cache.clone(),
             update_workers: self.update_workers,
+            config: self.config,
+            flow_hasher: self.flow_hasher.clone(),
+            dampening: self.dampening.clone(),
             metrics: self.metrics.clone(),
         }
     }
 }
 
+/// Checks if a [`RouteList`] contains a route with a finite metric from the given source.
+fn source_reachable(routes: &RouteList, source: SourceKey) -> bool {
+    routes
+        .iter()
+        .any(|re| re.source() == source && !re.metric().is_infinite())
+}
+
 /// Calculate the hold time for a [`RouteEntry`] from an [`Update`](babel::Update) .
-fn route_hold_time(update: &babel::Update) -> Duration {
+fn route_hold_time(update: &babel::Update, retracted_route_hold_time: Duration) -> Duration {
     // According to https://datatracker.ietf.org/doc/html/rfc8966#section-appendix.b a good value
     // would be 3.5 times the update inteval.
     // In case of a retracted route: in general this should not be added to the routing table, so
     // the only reason this is called is because a route was retracted through an update. Even if
     // the peer won't send this again, hold the route for some time so it can get flushed properly.
     if update.metric().is_infinite() {
-        RETRACTED_ROUTE_HOLD_TIME
+        retracted_route_hold_time
     } else {
         // Route expiry time -> 3.5 times advertised Update interval.
         Duration::from_millis((update.interval().as_millis() * 7 / 2) as u64)
@@ -1805,7 +2120,7 @@ fn route_hold_time(update: &babel::Update) -> Duration {
 }
 
 /// Calculates the interval to use when announcing updates on (selected) routes.
-fn advertised_update_interval(sre: &RouteEntry) -> Duration {
+fn advertised_update_interval(sre: &RouteEntry, update_interval: Duration) -> Duration {
     // We actually just need to set the value of the update interval, since that is the upper bound
     // on when we will advertise the route again.
     // One caveat is an expired route. If an entry is expired, it means that it will change state
@@ -1814,7 +2129,7 @@ fn advertised_update_interval(sre: &RouteEntry) -> Duration {
     if sre.metric().is_infinite() && sre.expires().elapsed() != Duration::from_millis(0) {
         INTERVAL_NOT_REPEATING
     } else {
-        UPDATE_INTERVAL
+        update_interval
     }
 }
 
@@ -1833,8 +2148,11 @@ mod tests {
         sequence_number::SeqNo, source_table::SourceKey, subnet::Subnet,
     };
 
+    use super::RouterConfig;
+
     #[test]
     fn calculate_route_hold_time() {
+        let config = RouterConfig::default();
         let router_id = RouterId::new(PublicKey::from([0; 32]));
         let seqno = SeqNo::new();
         let metric = Metric::new(0);
@@ -1848,19 +2166,19 @@ mod tests {
         let update = Update::new(Duration::from_secs(60), seqno, metric, subnet, router_id);
         assert_eq!(
             Duration::from_millis(210_000),
-            super::route_hold_time(&update)
+            super::route_hold_time(&update, config.retracted_route_hold_time)
         );
         let update = Update::new(Duration::from_secs(1), seqno, metric, subnet, router_id);
         assert_eq!(
             Duration::from_millis(3_500),
-            super::route_hold_time(&update)
+            super::route_hold_time(&update, config.retracted_route_hold_time)
         );
         // Since update is expressed in centiseconds, we lose precision and
         // Duration::from_milis(478) is equal to Duration::from_millis(470);
         let update = Update::new(Duration::from_millis(478), seqno, metric, subnet, router_id);
         assert_eq!(
             Duration::from_millis(1_645),
-            super::route_hold_time(&update)
+            super::route_hold_time(&update, config.retracted_route_hold_time)
         );
 
         // Retractions are also held for some time
@@ -1872,13 +2190,14 @@ mod tests {
             router_id,
         );
         assert_eq!(
-            super::RETRACTED_ROUTE_HOLD_TIME,
-            super::route_hold_time(&update)
+            config.retracted_route_hold_time,
+            super::route_hold_time(&update, config.retracted_route_hold_time)
         );
     }
 
     #[tokio::test]
     async fn calculate_advertised_update_interval() {
+        let config = RouterConfig::default();
         // Set up a dummy peer since that is needed to create a `RouteEntry`
         let (router_data_tx, _router_data_rx) = mpsc::channel(1);
         let (router_control_tx, _router_control_rx) = mpsc::unbounded_channel();
@@ -1888,6 +2207,7 @@ mod tests {
             router_data_tx,
             router_control_tx,
             con1,
+            Some(PublicKey::from([0; 32])),
             dead_peer_sink,
             Arc::new(AtomicU64::new(0)),
             Arc::new(AtomicU64::new(0)),
@@ -1912,8 +2232,8 @@ mod tests {
         );
         // We can't match exactly here since everything takes a non instant amount of time to do,
         // but basically verify that the calculated interval is within expected parameters.
-        let advertised_interval = super::advertised_update_interval(&re);
-        assert_eq!(advertised_interval, super::UPDATE_INTERVAL);
+        let advertised_interval = super::advertised_update_interval(&re, config.update_interval);
+        assert_eq!(advertised_interval, config.update_interval);
 
         // Expired route with finite metric
         let expiration = tokio::time::Instant::now() + Duration::from_secs(0);
@@ -1925,8 +2245,8 @@ mod tests {
             selected,
             expiration,
         );
-        let advertised_interval = super::advertised_update_interval(&re);
-        assert_eq!(advertised_interval, super::UPDATE_INTERVAL);
+        let advertised_interval = super::advertised_update_interval(&re, config.update_interval);
+        assert_eq!(advertised_interval, config.update_interval);
 
         // Expired route with infinite metric
         let re = super::RouteEntry::new(
@@ -1937,7 +2257,7 @@ mod tests {
             selected,
             expiration,
         );
-        let advertised_interval = super::advertised_update_interval(&re);
+        let advertised_interval = super::advertised_update_interval(&re, config.update_interval);
         assert_eq!(advertised_interval, super::INTERVAL_NOT_REPEATING);
 
         // Check that the interval is properly capped
@@ -1945,7 +2265,7 @@ mod tests {
         let re = super::RouteEntry::new(source, neighbor, metric, seqno, selected, expiration);
         // We can't match exactly here since everything takes a non instant amount of time to do,
         // but basically verify that the calculated interval is within expected parameters.
-        let advertised_interval = super::advertised_update_interval(&re);
-        assert_eq!(advertised_interval, super::UPDATE_INTERVAL);
+        let advertised_interval = super::advertised_update_interval(&re, config.update_interval);
+        assert_eq!(advertised_interval, config.update_interval);
     }
 }
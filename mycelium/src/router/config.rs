@@ -0,0 +1,304 @@
+//! Runtime configuration of the [`Router`](super::Router) timers.
+
+use std::{fmt, time::Duration};
+
+use super::dampening::{DampeningConfig, DampeningConfigError};
+
+/// Time between HELLO messages.
+const DEFAULT_HELLO_INTERVAL: Duration = Duration::from_secs(20);
+/// Time filled in in IHU packets.
+const DEFAULT_IHU_INTERVAL: Duration = Duration::from_secs(60);
+/// Max time used in UPDATE packets. For local (static) routes this is the timeout they are
+/// advertised with.
+const DEFAULT_UPDATE_INTERVAL: Duration = Duration::from_secs(300);
+/// We allow missing 1 hello, + some latency, so 2 HELLO's + 3 seconds for latency.
+const DEFAULT_DEAD_PEER_THRESHOLD: Duration = Duration::from_secs(43);
+/// Hold retracted routes for 1 minute before purging them from the routing table.
+const DEFAULT_RETRACTED_ROUTE_HOLD_TIME: Duration = Duration::from_secs(60);
+/// Amount of time to wait between consecutive seqno bumps of the local router seqno.
+const DEFAULT_SEQNO_BUMP_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Extra time allowed on top of the interval a peer advertised, to account for latency.
+const PEER_INTERVAL_GRACE: Duration = Duration::from_secs(3);
+
+/// Intervals in babel TLVs are encoded as a 16 bit amount of centiseconds, this is the largest
+/// interval which can be advertised.
+const MAX_ADVERTISED_INTERVAL: Duration = Duration::from_millis(u16::MAX as u64 * 10);
+/// The smallest interval which can be advertised, smaller intervals are encoded as 0.
+const MIN_ADVERTISED_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Configuration of the timers used by the [`Router`](super::Router).
+///
+/// The [`Default`] implementation gives the values which have historically been used. Values can
+/// be changed freely, but must be consistent with each other. Use [`RouterConfig::validate`] to
+/// check this, the router refuses to start with an invalid config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouterConfig {
+    /// Time between HELLO messages sent to peers. This is also the interval advertised in the
+    /// HELLO TLV.
+    pub hello_interval: Duration,
+    /// Interval advertised in IHU TLVs.
+    pub ihu_interval: Duration,
+    /// Time between periodic route propagation. This is also the interval advertised in UPDATE
+    /// TLVs.
+    pub update_interval: Duration,
+    /// Time without IHU after which a peer is considered dead. Since IHU's are sent in reply to
+    /// HELLO's, this must be larger than the `hello_interval`. Peers advertising slower timers
+    /// are given more time, see [`RouterConfig::peer_dead_threshold`].
+    pub dead_peer_threshold: Duration,
+    /// Time a retracted route is held in the routing table before it is purged.
+    pub retracted_route_hold_time: Duration,
+    /// Minimal time between consecutive seqno bumps of the local router seqno, and between
+    /// forwarding of the same seqno request.
+    pub seqno_bump_timeout: Duration,
+    /// Maximum difference between the metric of the selected route and another feasible route to
+    /// the same destination, for that route to also be used to forward data packets. Packets are
+    /// spread over these routes per flow. [`Option::None`] disables multipath forwarding, so only
+    /// the selected route is used.
+    pub ecmp_metric_delta: Option<u16>,
+    /// Configuration of route flap dampening. [`Option::None`] disables flap dampening, which is
+    /// the default.
+    pub flap_dampening: Option<DampeningConfig>,
+    /// Sign updates for routes originated by the local node, so other nodes can verify that an
+    /// intermediate node did not tamper with the sequence number. Nodes which don't support
+    /// signed updates can't decode them, so this should only be enabled once all nodes in the
+    /// network support them.
+    pub sign_updates: bool,
+    /// Reject updates which are not signed by their origin. Retractions are still accepted
+    /// without signature. If this is not set, unsigned updates are only rejected for sources
+    /// which have recently sent signed updates.
+    pub require_signed_updates: bool,
+}
+
+impl RouterConfig {
+    /// Check if the values in this `RouterConfig` are consistent.
+    pub fn validate(&self) -> Result<(), RouterConfigError> {
+        for (name, interval) in [
+            ("hello_interval", self.hello_interval),
+            ("ihu_interval", self.ihu_interval),
+            ("update_interval", self.update_interval),
+        ] {
+            if !(MIN_ADVERTISED_INTERVAL..=MAX_ADVERTISED_INTERVAL).contains(&interval) {
+                return Err(RouterConfigError::IntervalOutOfRange(name));
+            }
+        }
+
+        if self.ihu_interval < self.hello_interval {
+            return Err(RouterConfigError::IhuIntervalTooSmall);
+        }
+
+        if self.dead_peer_threshold <= self.hello_interval {
+            return Err(RouterConfigError::DeadPeerThresholdTooSmall);
+        }
+
+        if self.update_interval < self.hello_interval {
+            return Err(RouterConfigError::UpdateIntervalTooSmall);
+        }
+
+        if self.seqno_bump_timeout.is_zero() || self.seqno_bump_timeout >= self.update_interval {
+            return Err(RouterConfigError::SeqnoBumpTimeoutOutOfRange);
+        }
+
+        if self.retracted_route_hold_time.is_zero() {
+            return Err(RouterConfigError::RetractedRouteHoldTimeZero);
+        }
+
+        if let Some(dampening) = self.flap_dampening {
+            dampening.validate().map_err(RouterConfigError::Dampening)?;
+        }
+
+        Ok(())
+    }
+
+    /// Time without IHU after which a peer is considered dead, taking the HELLO and IHU
+    /// intervals advertised by that peer into account.
+    ///
+    /// A peer which advertised a HELLO interval is allowed to miss 1 HELLO, plus some latency,
+    /// similar to how the default dead peer threshold is derived. A peer which advertised an IHU
+    /// interval is given the IHU hold time from RFC 8966, i.e. 3.5 times the interval. If both are
+    /// known, the shortest of the 2 is used. The result is never lower than the configured
+    /// `dead_peer_threshold`, so peers running slower timers than the local node are not
+    /// considered dead prematurely.
+    pub fn peer_dead_threshold(
+        &self,
+        advertised_hello_interval: Option<Duration>,
+        advertised_ihu_interval: Option<Duration>,
+    ) -> Duration {
+        let from_hello = advertised_hello_interval.map(|hi| hi * 2 + PEER_INTERVAL_GRACE);
+        let from_ihu = advertised_ihu_interval.map(|ii| ii * 7 / 2);
+
+        let advertised = match (from_hello, from_ihu) {
+            (Some(h), Some(i)) => Some(h.min(i)),
+            (h, i) => h.or(i),
+        };
+
+        advertised.map_or(self.dead_peer_threshold, |threshold| {
+            threshold.max(self.dead_peer_threshold)
+        })
+    }
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            hello_interval: DEFAULT_HELLO_INTERVAL,
+            ihu_interval: DEFAULT_IHU_INTERVAL,
+            update_interval: DEFAULT_UPDATE_INTERVAL,
+            dead_peer_threshold: DEFAULT_DEAD_PEER_THRESHOLD,
+            retracted_route_hold_time: DEFAULT_RETRACTED_ROUTE_HOLD_TIME,
+            seqno_bump_timeout: DEFAULT_SEQNO_BUMP_TIMEOUT,
+            ecmp_metric_delta: None,
+            flap_dampening: None,
+            sign_updates: false,
+            require_signed_updates: false,
+        }
+    }
+}
+
+/// Reasons a [`RouterConfig`] is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouterConfigError {
+    /// The named interval can't be encoded in a babel TLV.
+    IntervalOutOfRange(&'static str),
+    /// The IHU interval is smaller than the HELLO interval.
+    IhuIntervalTooSmall,
+    /// The dead peer threshold is not larger than the HELLO interval.
+    DeadPeerThresholdTooSmall,
+    /// The UPDATE interval is smaller than the HELLO interval.
+    UpdateIntervalTooSmall,
+    /// The seqno bump timeout is 0, or not smaller than the UPDATE interval.
+    SeqnoBumpTimeoutOutOfRange,
+    /// The retracted route hold time is 0.
+    RetractedRouteHoldTimeZero,
+    /// The flap dampening configuration is invalid.
+    Dampening(DampeningConfigError),
+}
+
+impl fmt::Display for RouterConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouterConfigError::IntervalOutOfRange(name) => write!(
+                f,
+                "{name} must be between {}ms and {}ms",
+                MIN_ADVERTISED_INTERVAL.as_millis(),
+                MAX_ADVERTISED_INTERVAL.as_millis()
+            ),
+            RouterConfigError::IhuIntervalTooSmall => {
+                f.write_str("ihu_interval must not be smaller than hello_interval")
+            }
+            RouterConfigError::DeadPeerThresholdTooSmall => {
+                f.write_str("dead_peer_threshold must be larger than hello_interval")
+            }
+            RouterConfigError::UpdateIntervalTooSmall => {
+                f.write_str("update_interval must not be smaller than hello_interval")
+            }
+            RouterConfigError::SeqnoBumpTimeoutOutOfRange => f.write_str(
+                "seqno_bump_timeout must be larger than 0 and smaller than update_interval",
+            ),
+            RouterConfigError::RetractedRouteHoldTimeZero => {
+                f.write_str("retracted_route_hold_time must be larger than 0")
+            }
+            RouterConfigError::Dampening(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RouterConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{RouterConfig, RouterConfigError};
+
+    #[test]
+    fn default_is_valid() {
+        assert_eq!(RouterConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn dead_peer_threshold_exceeds_hello_interval() {
+        let config = RouterConfig {
+            hello_interval: Duration::from_secs(5),
+            dead_peer_threshold: Duration::from_secs(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(RouterConfigError::DeadPeerThresholdTooSmall)
+        );
+    }
+
+    #[test]
+    fn intervals_must_fit_tlv() {
+        let config = RouterConfig {
+            update_interval: Duration::from_secs(1000),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(RouterConfigError::IntervalOutOfRange("update_interval"))
+        );
+
+        let config = RouterConfig {
+            ihu_interval: Duration::from_millis(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(RouterConfigError::IntervalOutOfRange("ihu_interval"))
+        );
+    }
+
+    #[test]
+    fn fast_timers_are_valid() {
+        let config = RouterConfig {
+            hello_interval: Duration::from_secs(1),
+            ihu_interval: Duration::from_secs(3),
+            update_interval: Duration::from_secs(15),
+            dead_peer_threshold: Duration::from_millis(2500),
+            retracted_route_hold_time: Duration::from_secs(10),
+            seqno_bump_timeout: Duration::from_secs(1),
+            ecmp_metric_delta: Some(10),
+            flap_dampening: None,
+            sign_updates: true,
+            require_signed_updates: true,
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn peer_dead_threshold_honours_advertised_intervals() {
+        let config = RouterConfig::default();
+
+        // Nothing advertised yet.
+        assert_eq!(
+            config.peer_dead_threshold(None, None),
+            config.dead_peer_threshold
+        );
+        // A peer using the same timers gets the same threshold.
+        assert_eq!(
+            config.peer_dead_threshold(Some(config.hello_interval), Some(config.ihu_interval)),
+            config.dead_peer_threshold
+        );
+        // Peers with faster timers don't lower the threshold.
+        assert_eq!(
+            config.peer_dead_threshold(Some(Duration::from_secs(1)), Some(Duration::from_secs(3))),
+            config.dead_peer_threshold
+        );
+        // Slower peers extend it.
+        assert_eq!(
+            config.peer_dead_threshold(Some(Duration::from_secs(60)), None),
+            Duration::from_secs(123)
+        );
+        assert_eq!(
+            config.peer_dead_threshold(None, Some(Duration::from_secs(20))),
+            Duration::from_secs(70)
+        );
+        assert_eq!(
+            config
+                .peer_dead_threshold(Some(Duration::from_secs(60)), Some(Duration::from_secs(20))),
+            Duration::from_secs(70)
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Route flap dampening, based on [RFC 2439](https://datatracker.ietf.org/doc/html/rfc2439).
+//!
+//! Every time a source becomes unreachable or reachable again, a penalty is added to the source.
+//! The penalty decays exponentially over time. Once the penalty exceeds the suppress threshold,
+//! routes from the source are no longer selected, until the penalty decays below the reuse
+//! threshold.
+
+use std::{collections::HashMap, fmt, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::source_table::SourceKey;
+
+/// Penalty added when a source is retracted.
+pub const RETRACTION_PENALTY: u32 = 1000;
+/// Penalty added when a previously retracted source is announced again.
+pub const REANNOUNCEMENT_PENALTY: u32 = 500;
+
+/// Default half life of the penalty.
+const DEFAULT_HALF_LIFE: Duration = Duration::from_secs(60);
+/// Default penalty above which a source is suppressed.
+const DEFAULT_SUPPRESS_THRESHOLD: u32 = 2000;
+/// Default penalty below which a suppressed source is used again.
+const DEFAULT_REUSE_THRESHOLD: u32 = 750;
+/// Default maximum time a source can be suppressed after it stops flapping.
+const DEFAULT_MAX_SUPPRESS_TIME: Duration = Duration::from_secs(600);
+
+/// Configuration of route flap dampening.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DampeningConfig {
+    /// Time after which the penalty of a source is halved.
+    pub half_life: Duration,
+    /// Penalty above which routes from a source are suppressed.
+    pub suppress_threshold: u32,
+    /// Penalty below which routes from a suppressed source are used again.
+    pub reuse_threshold: u32,
+    /// Maximum time a source stays suppressed once it stops flapping. This caps the penalty of a
+    /// source.
+    pub max_suppress_time: Duration,
+}
+
+impl DampeningConfig {
+    /// Check if the values in this `DampeningConfig` are consistent.
+    pub fn validate(&self) -> Result<(), DampeningConfigError> {
+        if self.half_life.is_zero() {
+            return Err(DampeningConfigError::HalfLifeZero);
+        }
+
+        if self.reuse_threshold >= self.suppress_threshold {
+            return Err(DampeningConfigError::ReuseThresholdTooLarge);
+        }
+
+        if self.max_penalty() <= self.suppress_threshold as f64 {
+            return Err(DampeningConfigError::MaxSuppressTimeTooSmall);
+        }
+
+        Ok(())
+    }
+
+    /// Highest penalty a source can accumulate, so it decays below the reuse threshold within
+    /// the max suppress time.
+    fn max_penalty(&self) -> f64 {
+        self.reuse_threshold as f64
+            * 2f64.powf(self.max_suppress_time.as_secs_f64() / self.half_life.as_secs_f64())
+    }
+}
+
+impl Default for DampeningConfig {
+    fn default() -> Self {
+        Self {
+            half_life: DEFAULT_HALF_LIFE,
+            suppress_threshold: DEFAULT_SUPPRESS_THRESHOLD,
+            reuse_threshold: DEFAULT_REUSE_THRESHOLD,
+            max_suppress_time: DEFAULT_MAX_SUPPRESS_TIME,
+        }
+    }
+}
+
+/// Reasons a [`DampeningConfig`] is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DampeningConfigError {
+    /// The half life is 0.
+    HalfLifeZero,
+    /// The reuse threshold is not smaller than the suppress threshold.
+    ReuseThresholdTooLarge,
+    /// The max suppress time is too small for the penalty to ever exceed the suppress threshold.
+    MaxSuppressTimeTooSmall,
+}
+
+impl fmt::Display for DampeningConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DampeningConfigError::HalfLifeZero => {
+                f.write_str("dampening half life must be larger than 0")
+            }
+            DampeningConfigError::ReuseThresholdTooLarge => {
+                f.write_str("dampening reuse threshold must be smaller than the suppress threshold")
+            }
+            DampeningConfigError::MaxSuppressTimeTooSmall => f.write_str(
+                "dampening max suppress time is too small to ever reach the suppress threshold",
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DampeningConfigError {}
+
+/// Dampening state of a single source.
+#[derive(Debug, Clone, Copy)]
+struct DampeningState {
+    penalty: f64,
+    last_update: Instant,
+    suppressed: bool,
+}
+
+impl DampeningState {
+    /// Apply the decay since the last update to the penalty.
+    fn decay(&mut self, config: &DampeningConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_update);
+        self.penalty *= 0.5f64.powf(elapsed.as_secs_f64() / config.half_life.as_secs_f64());
+        self.last_update = now;
+        if self.suppressed && self.penalty < config.reuse_threshold as f64 {
+            self.suppressed = false;
+        }
+    }
+}
+
+/// Dampening state of all sources which flapped recently.
+pub struct DampeningTable {
+    config: DampeningConfig,
+    table: HashMap<SourceKey, DampeningState>,
+}
+
+impl DampeningTable {
+    /// Create a new, empty `DampeningTable`.
+    pub fn new(config: DampeningConfig) -> Self {
+        Self {
+            config,
+            table: HashMap::new(),
+        }
+    }
+
+    /// Record a flap of the given source, adding `penalty` to its current penalty. Returns true
+    /// if the source is suppressed as a result of this, and was not suppressed before.
+    pub fn record_flap(&mut self, source: SourceKey, penalty: u32, now: Instant) -> bool {
+        let state = self.table.entry(source).or_insert(DampeningState {
+            penalty: 0.,
+            last_update: now,
+            suppressed: false,
+        });
+        state.decay(&self.config, now);
+        state.penalty = (state.penalty + penalty as f64).min(self.config.max_penalty());
+
+        let was_suppressed = state.suppressed;
+        if state.penalty > self.config.suppress_threshold as f64 {
+            state.suppressed = true;
+        }
+
+        state.suppressed && !was_suppressed
+    }
+
+    /// Checks if routes from the given source are currently suppressed.
+    pub fn is_suppressed(&self, source: &SourceKey) -> bool {
+        self.table.get(source).is_some_and(|state| state.suppressed)
+    }
+
+    /// Apply decay to all tracked sources. Sources whose penalty decayed far enough to not matter
+    /// anymore are forgotten. Returns the sources which are no longer suppressed.
+    pub fn decay(&mut self, now: Instant) -> Vec<SourceKey> {
+        let mut reusable = Vec::new();
+        let forget_below = self.config.reuse_threshold as f64 / 2.;
+        self.table.retain(|source, state| {
+            let was_suppressed = state.suppressed;
+            state.decay(&self.config, now);
+            if was_suppressed && !state.suppressed {
+                reusable.push(*source);
+            }
+            state.suppressed || state.penalty >= forget_below
+        });
+        reusable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv6Addr, time::Duration};
+
+    use tokio::time::Instant;
+
+    use crate::{crypto::SecretKey, router_id::RouterId, source_table::SourceKey, subnet::Subnet};
+
+    use super::{
+        DampeningConfig, DampeningConfigError, DampeningTable, DEFAULT_HALF_LIFE,
+        REANNOUNCEMENT_PENALTY, RETRACTION_PENALTY,
+    };
+
+    fn source_key() -> SourceKey {
+        let sk = SecretKey::new();
+        SourceKey::new(
+            Subnet::new(Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 0).into(), 64).unwrap(),
+            RouterId::new((&sk).into()),
+        )
+    }
+
+    #[test]
+    fn single_flap_is_not_suppressed() {
+        let mut dt = DampeningTable::new(DampeningConfig::default());
+        let sk = source_key();
+        let now = Instant::now();
+
+        assert!(!dt.record_flap(sk, RETRACTION_PENALTY, now));
+        assert!(!dt.record_flap(sk, REANNOUNCEMENT_PENALTY, now));
+        assert!(!dt.is_suppressed(&sk));
+    }
+
+    #[test]
+    fn repeated_flaps_are_suppressed_until_decayed() {
+        let config = DampeningConfig::default();
+        let mut dt = DampeningTable::new(config);
+        let sk = source_key();
+        let mut now = Instant::now();
+
+        dt.record_flap(sk, RETRACTION_PENALTY, now);
+        dt.record_flap(sk, REANNOUNCEMENT_PENALTY, now);
+        now += Duration::from_secs(5);
+        assert!(dt.record_flap(sk, RETRACTION_PENALTY, now));
+        assert!(dt.is_suppressed(&sk));
+
+        // A single half life is not sufficient to go below the reuse threshold.
+        now += config.half_life;
+        assert!(dt.decay(now).is_empty());
+        assert!(dt.is_suppressed(&sk));
+
+        now += config.half_life * 2;
+        assert_eq!(dt.decay(now), vec![sk]);
+        assert!(!dt.is_suppressed(&sk));
+
+        // Eventually the source is forgotten.
+        now += config.half_life * 2;
+        dt.decay(now);
+        assert!(dt.table.is_empty());
+    }
+
+    #[test]
+    fn penalty_is_capped() {
+        let config = DampeningConfig::default();
+        let mut dt = DampeningTable::new(config);
+        let sk = source_key();
+        let mut now = Instant::now();
+
+        for _ in 0..1000 {
+            dt.record_flap(sk, RETRACTION_PENALTY, now);
+        }
+        assert!(dt.is_suppressed(&sk));
+
+        now += config.max_suppress_time + Duration::from_secs(1);
+        assert_eq!(dt.decay(now), vec![sk]);
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(DampeningConfig::default().validate(), Ok(()));
+        assert!(DampeningConfig {
+            reuse_threshold: 3000,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        // The penalty is capped at 750 * 2 = 1500, which never reaches the suppress threshold.
+        assert_eq!(
+            DampeningConfig {
+                max_suppress_time: DEFAULT_HALF_LIFE,
+                ..Default::default()
+            }
+            .validate(),
+            Err(DampeningConfigError::MaxSuppressTimeTooSmall)
+        );
+        // The penalty is capped at exactly the suppress threshold.
+        assert_eq!(
+            DampeningConfig {
+                reuse_threshold: 1000,
+                max_suppress_time: DEFAULT_HALF_LIFE,
+                ..Default::default()
+            }
+            .validate(),
+            Err(DampeningConfigError::MaxSuppressTimeTooSmall)
+        );
+    }
+}
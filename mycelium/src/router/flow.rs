@@ -0,0 +1,118 @@
+//! Identification of traffic flows, used to spread traffic over multiple equal cost paths while
+//! keeping all packets of a single flow on the same path.
+
+use std::net::Ipv6Addr;
+
+/// Size of a fixed IPv6 header.
+const IPV6_HEADER_SIZE: usize = 40;
+/// Next header value of TCP.
+const NEXT_HEADER_TCP: u8 = 6;
+/// Next header value of UDP.
+const NEXT_HEADER_UDP: u8 = 17;
+/// Next header value used when the flow is not derived from an actual IPv6 header.
+const NEXT_HEADER_NONE: u8 = 59;
+
+/// A flow of packets between 2 endpoints.
+///
+/// Packets which belong to the same flow should be forwarded over the same path, to avoid
+/// reordering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Flow {
+    src: Ipv6Addr,
+    dst: Ipv6Addr,
+    next_header: u8,
+    src_port: u16,
+    dst_port: u16,
+}
+
+impl Flow {
+    /// Create a new `Flow` which is only identified by source and destination address. This is
+    /// used if no further information about the packet is available, e.g. because it is
+    /// encrypted.
+    pub fn new(src: Ipv6Addr, dst: Ipv6Addr) -> Self {
+        Self {
+            src,
+            dst,
+            next_header: NEXT_HEADER_NONE,
+            src_port: 0,
+            dst_port: 0,
+        }
+    }
+
+    /// Extract a `Flow` from a plain IPv6 packet. For TCP and UDP packets, the ports are part of
+    /// the flow. Other protocols, including packets with extension headers, are only identified
+    /// by their addresses and next header value.
+    ///
+    /// Returns [`Option::None`] if the packet does not contain a full IPv6 header.
+    pub fn from_ipv6_packet(packet: &[u8]) -> Option<Self> {
+        if packet.len() < IPV6_HEADER_SIZE || packet[0] >> 4 != 6 {
+            return None;
+        }
+
+        let next_header = packet[6];
+        let src = Ipv6Addr::from(<[u8; 16]>::try_from(&packet[8..24]).ok()?);
+        let dst = Ipv6Addr::from(<[u8; 16]>::try_from(&packet[24..40]).ok()?);
+
+        let (src_port, dst_port) = match (next_header, packet.get(IPV6_HEADER_SIZE..)) {
+            (NEXT_HEADER_TCP | NEXT_HEADER_UDP, Some(l4)) if l4.len() >= 4 => (
+                u16::from_be_bytes([l4[0], l4[1]]),
+                u16::from_be_bytes([l4[2], l4[3]]),
+            ),
+            _ => (0, 0),
+        };
+
+        Some(Self {
+            src,
+            dst,
+            next_header,
+            src_port,
+            dst_port,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::Flow;
+
+    /// Build a minimal IPv6 packet with the given next header and payload.
+    fn packet(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; 40];
+        packet[0] = 0x60;
+        packet[6] = next_header;
+        packet[8..24].copy_from_slice(&Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 1).octets());
+        packet[24..40].copy_from_slice(&Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 2).octets());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn ports_are_part_of_tcp_and_udp_flows() {
+        let a = Flow::from_ipv6_packet(&packet(6, &[0, 80, 0x1f, 0x90])).unwrap();
+        let b = Flow::from_ipv6_packet(&packet(6, &[0, 81, 0x1f, 0x90])).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a.src_port, 80);
+        assert_eq!(a.dst_port, 8080);
+
+        let udp = Flow::from_ipv6_packet(&packet(17, &[0, 80, 0x1f, 0x90])).unwrap();
+        assert_ne!(a, udp);
+    }
+
+    #[test]
+    fn other_protocols_only_use_addresses() {
+        let a = Flow::from_ipv6_packet(&packet(58, &[1, 2, 3, 4])).unwrap();
+        let b = Flow::from_ipv6_packet(&packet(58, &[5, 6, 7, 8])).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn invalid_packets() {
+        assert_eq!(Flow::from_ipv6_packet(&[0x60; 20]), None);
+
+        let mut v4 = packet(6, &[]);
+        v4[0] = 0x45;
+        assert_eq!(Flow::from_ipv6_packet(&v4), None);
+    }
+}
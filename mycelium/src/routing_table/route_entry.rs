@@ -1,7 +1,7 @@
 use tokio::time::Instant;
 
 use crate::{
-    metric::Metric, peer::Peer, router_id::RouterId, sequence_number::SeqNo,
+    crypto::Signature, metric::Metric, peer::Peer, router_id::RouterId, sequence_number::SeqNo,
     source_table::SourceKey,
 };
 
@@ -15,6 +15,8 @@ pub struct RouteEntry {
     seqno: SeqNo,
     selected: bool,
     expires: Instant,
+    /// Signature of the origin of the route, so it can be included when the route is advertised.
+    signature: Option<Signature>,
 }
 
 impl RouteEntry {
@@ -34,6 +36,7 @@ impl RouteEntry {
             seqno,
             selected,
             expires,
+            signature: None,
         }
     }
 
@@ -68,6 +71,11 @@ impl RouteEntry {
         self.expires
     }
 
+    /// Return the [`Signature`] of the origin of this `RouteEntry`, if the route was signed.
+    pub fn signature(&self) -> Option<Signature> {
+        self.signature
+    }
+
     /// Set the [`SourceKey`] for this `RouteEntry`.
     pub fn set_source(&mut self, source: SourceKey) {
         self.source = source;
@@ -93,6 +101,12 @@ impl RouteEntry {
         self.seqno = seqno;
     }
 
+    /// Sets the [`Signature`] of the origin of this `RouteEntry`. This must match the router id,
+    /// subnet and sequence number of the entry.
+    pub fn set_signature(&mut self, signature: Option<Signature>) {
+        self.signature = signature;
+    }
+
     /// Sets if this `RouteEntry` is the selected route for the associated
     /// [`Subnet`](crate::subnet::Subnet).
     pub fn set_selected(&mut self, selected: bool) {
@@ -115,6 +129,7 @@ impl std::fmt::Debug for RouteEntry {
             .field("seqno", &self.seqno)
             .field("selected", &self.selected)
             .field("expires", &self.expires)
+            .field("signed", &self.signature.is_some())
             .finish()
     }
 }
@@ -5,10 +5,10 @@
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
-    time::{Duration, Instant},
+    time::Duration,
 };
 
-use tokio::time::MissedTickBehavior;
+use tokio::time::{Instant, MissedTickBehavior};
 use tracing::{debug, trace};
 
 use crate::{peer::Peer, router_id::RouterId, sequence_number::SeqNo, subnet::Subnet};
@@ -0,0 +1,674 @@
+//! Ephemeral session keys for end-to-end encryption of data packets.
+//!
+//! Without session keys, data packets are encrypted with the [`SharedSecret`] computed from the
+//! static keys of both nodes. If one of those keys is ever compromised, all recorded traffic
+//! between the nodes can be decrypted. To prevent this, nodes periodically exchange ephemeral keys
+//! in band, and derive a session key from them. The ephemeral secret keys are discarded as soon as
+//! the session key is derived, and session keys are discarded once they expire, so recorded
+//! traffic stays private even if a static key is compromised later.
+//!
+//! Key exchanges are encrypted with the static [`SharedSecret`], which authenticates them. While
+//! the first key exchange is in progress, or if the remote does not support session keys, packets
+//! are encrypted with the static [`SharedSecret`]. Once the remote used a session key, it is known
+//! to support them, and packets encrypted with the static [`SharedSecret`] are no longer exchanged
+//! with it. Otherwise an attacker who blocks key exchanges could force traffic back to the static
+//! [`SharedSecret`].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, trace};
+
+use crate::{
+    crypto::{PacketBuffer, PublicKey, SecretKey, SharedSecret},
+    replay_window::ReplayWindow,
+};
+
+/// Time after which a new session key is negotiated.
+const REKEY_AFTER_TIME: Duration = Duration::from_secs(120);
+
+/// Amount of packets encrypted with a session key after which a new session key is negotiated.
+const REKEY_AFTER_PACKETS: u64 = 1 << 24;
+
+/// Time after which a session key is discarded. This leaves some time after [`REKEY_AFTER_TIME`]
+/// to negotiate a new key, and for packets encrypted with the old key to arrive.
+const SESSION_KEY_LIFETIME: Duration = Duration::from_secs(180);
+
+/// Time to wait for a response to a key exchange, before starting a new one.
+const KEY_EXCHANGE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Amount of unanswered key exchanges after which a new key exchange is only started every
+/// [`REKEY_AFTER_TIME`]. This limits the overhead for remotes which don't support session keys.
+const MAX_KEY_EXCHANGE_ATTEMPTS: u8 = 3;
+
+/// Interval at which expired session keys are removed.
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Wire value of a [`KeyExchange::Init`].
+const KEY_EXCHANGE_INIT: u8 = 0;
+
+/// Wire value of a [`KeyExchange::Response`].
+const KEY_EXCHANGE_RESPONSE: u8 = 1;
+
+/// A message used to negotiate a session key. These are sent as the body of a data packet,
+/// encrypted with the static [`SharedSecret`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyExchange {
+    /// Start of a key exchange, containing the ephemeral [`PublicKey`] of the initiator.
+    Init { ephemeral: PublicKey },
+    /// Response to a [`KeyExchange::Init`], containing the ephemeral [`PublicKey`] of the
+    /// initiator which is answered, and the ephemeral [`PublicKey`] of the responder.
+    Response {
+        initiator: PublicKey,
+        ephemeral: PublicKey,
+    },
+}
+
+/// Keeps track of the session keys negotiated with remotes.
+#[derive(Clone)]
+pub struct SessionCache {
+    inner: Arc<SessionCacheInner>,
+}
+
+/// Actual implementation of the cache.
+struct SessionCacheInner {
+    /// The [`PublicKey`] of the local node. This is used to decide which key exchange proceeds if
+    /// both nodes start one at the same time.
+    local: PublicKey,
+    /// Session state of every remote we exchanged packets with.
+    sessions: Mutex<HashMap<PublicKey, Session>>,
+}
+
+/// Session state with a single remote.
+#[derive(Default)]
+struct Session {
+    /// Session key used to encrypt packets for the remote.
+    current: Option<SessionKey>,
+    /// Amount of packets encrypted with the current session key.
+    packets: u64,
+    /// Session key negotiated on request of the remote. This is only used to encrypt packets once
+    /// the remote used it, at which point we know the remote received our response.
+    next: Option<SessionKey>,
+    /// The session key used before the current one. Packets encrypted with it might still be in
+    /// flight.
+    previous: Option<SessionKey>,
+    /// Key exchange started by the local node, which is not answered yet.
+    pending: Option<PendingExchange>,
+    /// Amount of key exchanges started since the last session key was established.
+    attempts: u8,
+    /// Time at which the local node last started a key exchange.
+    last_attempt: Option<Instant>,
+    /// Set once the remote sent a packet encrypted with a session key. From then on packets
+    /// must not be encrypted with the static [`SharedSecret`], and key exchanges are retried
+    /// until a new session key is established.
+    secured: bool,
+    /// Highest replay counter of a [`KeyExchange::Init`] answered for the remote.
+    init_counter: Option<u64>,
+}
+
+/// A negotiated session key.
+struct SessionKey {
+    key: SharedSecret,
+    /// Time at which the key was negotiated.
+    established: Instant,
+    /// Replay counters of packets received with this key. Tying the window to the key means a
+    /// remote which restarts, and thus negotiates a new key, can start its counter anywhere.
+    replay_window: ReplayWindow,
+}
+
+/// Ephemeral key of a key exchange started by the local node.
+struct PendingExchange {
+    secret: SecretKey,
+    public: PublicKey,
+}
+
+impl KeyExchange {
+    /// Size of an encoded [`KeyExchange::Init`].
+    const INIT_SIZE: usize = 1 + 32;
+    /// Size of an encoded [`KeyExchange::Response`].
+    const RESPONSE_SIZE: usize = 1 + 32 + 32;
+
+    /// Decode a `KeyExchange` from the body of a data packet.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let key = |bytes: &[u8]| {
+            PublicKey::from(<[u8; 32]>::try_from(bytes).expect("Slice has the size of a key; qed"))
+        };
+
+        match (data.first().copied(), data.len()) {
+            (Some(KEY_EXCHANGE_INIT), Self::INIT_SIZE) => Some(KeyExchange::Init {
+                ephemeral: key(&data[1..33]),
+            }),
+            (Some(KEY_EXCHANGE_RESPONSE), Self::RESPONSE_SIZE) => Some(KeyExchange::Response {
+                initiator: key(&data[1..33]),
+                ephemeral: key(&data[33..65]),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Encode this `KeyExchange` in the given buffer, and return the amount of bytes written.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the buffer is too small to hold the encoded `KeyExchange`.
+    pub fn write_bytes(&self, dst: &mut [u8]) -> usize {
+        match self {
+            KeyExchange::Init { ephemeral } => {
+                dst[0] = KEY_EXCHANGE_INIT;
+                dst[1..33].copy_from_slice(ephemeral.as_bytes());
+                Self::INIT_SIZE
+            }
+            KeyExchange::Response {
+                initiator,
+                ephemeral,
+            } => {
+                dst[0] = KEY_EXCHANGE_RESPONSE;
+                dst[1..33].copy_from_slice(initiator.as_bytes());
+                dst[33..65].copy_from_slice(ephemeral.as_bytes());
+                Self::RESPONSE_SIZE
+            }
+        }
+    }
+}
+
+impl SessionCache {
+    /// Create a new `SessionCache` for the node with the given [`PublicKey`].
+    pub fn new(local: PublicKey) -> Self {
+        let inner = Arc::new(SessionCacheInner {
+            local,
+            sessions: Mutex::new(HashMap::new()),
+        });
+        // Spawn background cleanup task.
+        tokio::spawn(inner.clone().sweep_sessions());
+
+        Self { inner }
+    }
+
+    /// Get the session key to encrypt a packet for the remote, if one is established. Every call
+    /// counts as an encrypted packet for rekeying purposes.
+    pub fn encryption_key(&self, remote: &PublicKey) -> Option<SharedSecret> {
+        let mut sessions = self.inner.sessions.lock().unwrap();
+        let session = sessions.get_mut(remote)?;
+        let key = session
+            .current
+            .as_ref()
+            .filter(|key| !key.expired())?
+            .key
+            .clone();
+        session.packets += 1;
+
+        Some(key)
+    }
+
+    /// Start a key exchange with the remote if a new session key is needed. The returned
+    /// [`KeyExchange`] must be sent to the remote.
+    pub fn start_key_exchange(&self, remote: &PublicKey) -> Option<KeyExchange> {
+        let mut sessions = self.inner.sessions.lock().unwrap();
+        let session = sessions.entry(*remote).or_default();
+        if !session.needs_key_exchange() {
+            return None;
+        }
+
+        let secret = SecretKey::new();
+        let public = PublicKey::from(&secret);
+        session.pending = Some(PendingExchange { secret, public });
+        session.attempts = session.attempts.saturating_add(1);
+        session.last_attempt = Some(Instant::now());
+
+        trace!(%remote, attempt = session.attempts, "Starting session key exchange");
+
+        Some(KeyExchange::Init { ephemeral: public })
+    }
+
+    /// Checks if the remote is known to use session keys. Packets for, and from, such a remote
+    /// must not be encrypted with the static [`SharedSecret`], except for key exchanges.
+    pub fn is_secured(&self, remote: &PublicKey) -> bool {
+        self.inner
+            .sessions
+            .lock()
+            .unwrap()
+            .get(remote)
+            .is_some_and(|session| session.secured)
+    }
+
+    /// Process a [`KeyExchange`] received from the remote. `static_secret` is the static
+    /// [`SharedSecret`] with the remote, and `counter` the replay counter of the packet which
+    /// contained the key exchange, if any. If a response must be sent to the remote, it is
+    /// returned.
+    ///
+    /// Key exchanges are not checked against a replay window, so a remote which restarted with a
+    /// lower replay counter can always negotiate a new key. Instead, a [`KeyExchange::Init`] with
+    /// a counter which is not higher than the last answered one is ignored while a key exchange is
+    /// in progress, so a replayed init can't abort it.
+    pub fn handle_key_exchange(
+        &self,
+        remote: &PublicKey,
+        static_secret: &SharedSecret,
+        key_exchange: KeyExchange,
+        counter: Option<u64>,
+    ) -> Option<KeyExchange> {
+        let mut sessions = self.inner.sessions.lock().unwrap();
+        let session = sessions.entry(*remote).or_default();
+
+        match key_exchange {
+            KeyExchange::Init {
+                ephemeral: initiator,
+            } => {
+                let newer = counter
+                    .is_some_and(|counter| session.init_counter.is_none_or(|last| counter > last));
+                if !newer && session.exchange_in_progress() {
+                    trace!(%remote, "Ignoring key exchange which is not newer than the last one");
+                    return None;
+                }
+                // If both nodes start a key exchange at the same time, only the one started by
+                // the node with the highest public key proceeds.
+                if session.pending.is_some() && self.inner.local.as_bytes() > remote.as_bytes() {
+                    trace!(%remote, "Ignoring key exchange started at the same time as ours");
+                    return None;
+                }
+                session.pending = None;
+                session.init_counter = session.init_counter.max(counter);
+
+                let secret = SecretKey::new();
+                let ephemeral = PublicKey::from(&secret);
+                let key = static_secret.derive_session_key(
+                    &secret.shared_secret(&initiator),
+                    &initiator,
+                    &ephemeral,
+                );
+                session.next = Some(SessionKey::new(key));
+
+                trace!(%remote, "Answering session key exchange");
+
+                Some(KeyExchange::Response {
+                    initiator,
+                    ephemeral,
+                })
+            }
+            KeyExchange::Response {
+                initiator,
+                ephemeral,
+            } => {
+                let Some(pending) = session.pending.take_if(|p| p.public == initiator) else {
+                    trace!(%remote, "Ignoring response to unknown session key exchange");
+                    return None;
+                };
+
+                let key = static_secret.derive_session_key(
+                    &pending.secret.shared_secret(&ephemeral),
+                    &initiator,
+                    &ephemeral,
+                );
+                session.install(SessionKey::new(key));
+
+                debug!(%remote, "Established new session key");
+
+                None
+            }
+        }
+    }
+
+    /// Decrypt a packet from the remote with one of the session keys, and return the packet and
+    /// the key which decrypted it. If this is not possible, the data is returned unmodified, so it
+    /// can be decrypted with the static [`SharedSecret`].
+    pub fn decrypt(
+        &self,
+        remote: &PublicKey,
+        mut data: Vec<u8>,
+    ) -> Result<(PacketBuffer, SharedSecret), Vec<u8>> {
+        // Clone the keys so the lock is not held during decryption.
+        let (keys, next, secured) = {
+            let sessions = self.inner.sessions.lock().unwrap();
+            let Some(session) = sessions.get(remote) else {
+                return Err(data);
+            };
+            let usable = |key: &Option<SessionKey>| {
+                key.as_ref()
+                    .filter(|key| !key.expired())
+                    .map(|key| key.key.clone())
+            };
+            (
+                [usable(&session.current), usable(&session.previous)],
+                usable(&session.next),
+                session.secured,
+            )
+        };
+
+        for key in keys.into_iter().flatten() {
+            match key.try_decrypt(data) {
+                Ok(pb) => {
+                    if !secured {
+                        self.mark_secured(remote);
+                    }
+                    return Ok((pb, key));
+                }
+                Err(d) => data = d,
+            }
+        }
+
+        let Some(next) = next else {
+            return Err(data);
+        };
+        let pb = next.try_decrypt(data)?;
+
+        // The remote started using the key it negotiated with us, so we can use it as well.
+        let mut sessions = self.inner.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(remote) {
+            if let Some(key) = session.next.take_if(|key| *key.key == *next) {
+                session.install(key);
+                debug!(%remote, "Remote confirmed new session key");
+            }
+            session.secured = true;
+        }
+
+        Ok((pb, next))
+    }
+
+    /// Check if a packet from the remote, decrypted with the given session key, has a replay
+    /// counter which was not received before with that key. If the key is no longer known, the
+    /// packet is rejected.
+    pub fn accept_replay_counter(
+        &self,
+        remote: &PublicKey,
+        key: &SharedSecret,
+        counter: u64,
+    ) -> bool {
+        let mut sessions = self.inner.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(remote) else {
+            return false;
+        };
+
+        [
+            &mut session.current,
+            &mut session.previous,
+            &mut session.next,
+        ]
+        .into_iter()
+        .flatten()
+        .find(|session_key| *session_key.key == **key)
+        .is_some_and(|session_key| session_key.replay_window.accept(counter))
+    }
+
+    /// Remember that the remote uses session keys.
+    fn mark_secured(&self, remote: &PublicKey) {
+        if let Some(session) = self.inner.sessions.lock().unwrap().get_mut(remote) {
+            session.secured = true;
+        }
+    }
+}
+
+impl SessionCacheInner {
+    /// Periodic task to remove expired session keys, and sessions with remotes we no longer
+    /// communicate with.
+    async fn sweep_sessions(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(SESSION_SWEEP_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            trace!("Removing expired session keys");
+
+            let mut sessions = self.sessions.lock().unwrap();
+            sessions.retain(|_, session| {
+                session.remove_expired_keys();
+                !session.is_idle()
+            });
+        }
+    }
+}
+
+impl Session {
+    /// Checks if a new key exchange should be started now.
+    fn needs_key_exchange(&self) -> bool {
+        if self.completing_remote_exchange() {
+            return false;
+        }
+
+        let rekey = self.current.as_ref().is_none_or(|key| {
+            key.established.elapsed() >= REKEY_AFTER_TIME || self.packets >= REKEY_AFTER_PACKETS
+        });
+        // Remotes which are known to support session keys can't be downgraded to the static
+        // secret, so keep trying to establish a new key with them.
+        let retry_interval = if self.secured || self.attempts < MAX_KEY_EXCHANGE_ATTEMPTS {
+            KEY_EXCHANGE_RETRY_INTERVAL
+        } else {
+            REKEY_AFTER_TIME
+        };
+
+        rekey
+            && self
+                .last_attempt
+                .is_none_or(|last_attempt| last_attempt.elapsed() >= retry_interval)
+    }
+
+    /// Checks if a key exchange started by the remote is being completed.
+    fn completing_remote_exchange(&self) -> bool {
+        self.next
+            .as_ref()
+            .is_some_and(|key| key.established.elapsed() < KEY_EXCHANGE_RETRY_INTERVAL)
+    }
+
+    /// Checks if a key exchange started by either node is in progress.
+    fn exchange_in_progress(&self) -> bool {
+        self.pending.is_some() || self.completing_remote_exchange()
+    }
+
+    /// Use a newly negotiated key to encrypt packets.
+    fn install(&mut self, key: SessionKey) {
+        self.previous = self.current.replace(key);
+        self.packets = 0;
+        self.attempts = 0;
+    }
+
+    /// Remove all session keys which expired.
+    fn remove_expired_keys(&mut self) {
+        for key in [&mut self.current, &mut self.next, &mut self.previous] {
+            if key.as_ref().is_some_and(SessionKey::expired) {
+                *key = None;
+            }
+        }
+    }
+
+    /// Checks if there are no usable session keys, and no recent key exchanges. Once a session is
+    /// idle, it is forgotten that the remote supports session keys.
+    fn is_idle(&self) -> bool {
+        self.current.is_none()
+            && self.next.is_none()
+            && self.previous.is_none()
+            && self
+                .last_attempt
+                .is_none_or(|last_attempt| last_attempt.elapsed() >= REKEY_AFTER_TIME)
+    }
+}
+
+impl SessionKey {
+    /// Create a new `SessionKey` established now.
+    fn new(key: SharedSecret) -> Self {
+        Self {
+            key,
+            established: Instant::now(),
+            replay_window: ReplayWindow::new(),
+        }
+    }
+
+    /// Checks if this key should no longer be used.
+    fn expired(&self) -> bool {
+        self.established.elapsed() >= SESSION_KEY_LIFETIME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::{PacketBuffer, PublicKey, SecretKey};
+
+    use super::{KeyExchange, SessionCache};
+
+    /// Create a packet containing the given data.
+    fn packet(data: &[u8]) -> PacketBuffer {
+        let mut pb = PacketBuffer::new();
+        pb.buffer_mut()[..data.len()].copy_from_slice(data);
+        pb.set_size(data.len());
+        pb
+    }
+
+    #[test]
+    fn key_exchange_roundtrip() {
+        let init = KeyExchange::Init {
+            ephemeral: PublicKey::from(&SecretKey::new()),
+        };
+        let response = KeyExchange::Response {
+            initiator: PublicKey::from(&SecretKey::new()),
+            ephemeral: PublicKey::from(&SecretKey::new()),
+        };
+
+        for ke in [init, response] {
+            let mut buf = [0; 128];
+            let len = ke.write_bytes(&mut buf);
+            assert_eq!(KeyExchange::from_bytes(&buf[..len]), Some(ke));
+            assert_eq!(KeyExchange::from_bytes(&buf[..len - 1]), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn negotiate_session_key() {
+        let sk1 = SecretKey::new();
+        let sk2 = SecretKey::new();
+        let pk1 = PublicKey::from(&sk1);
+        let pk2 = PublicKey::from(&sk2);
+        let ss1 = sk1.shared_secret(&pk2);
+        let ss2 = sk2.shared_secret(&pk1);
+
+        let sc1 = SessionCache::new(pk1);
+        let sc2 = SessionCache::new(pk2);
+
+        assert!(sc1.encryption_key(&pk2).is_none());
+        let init = sc1.start_key_exchange(&pk2).expect("No session key yet");
+        // Only one key exchange at a time.
+        assert!(sc1.start_key_exchange(&pk2).is_none());
+
+        let response = sc2
+            .handle_key_exchange(&pk1, &ss2, init, Some(1))
+            .expect("Init is answered");
+        // The responder does not use the key until the initiator uses it.
+        assert!(sc2.encryption_key(&pk1).is_none());
+        assert!(sc1
+            .handle_key_exchange(&pk2, &ss1, response, Some(1))
+            .is_none());
+
+        let key = sc1
+            .encryption_key(&pk2)
+            .expect("Session key is established");
+        assert_ne!(*key, *ss1);
+        // The static secret can't decrypt packets encrypted with the session key.
+        let data = ss2
+            .try_decrypt(key.encrypt(packet(b"some data")))
+            .expect_err("Packet is encrypted with the session key");
+        assert!(!sc2.is_secured(&pk1));
+        let (pb, used) = sc2
+            .decrypt(&pk1, data)
+            .expect("Responder has the session key");
+        assert_eq!(&*pb, b"some data");
+        assert_eq!(*used, *key);
+        // The initiator used the session key, so the static secret must no longer be used.
+        assert!(sc2.is_secured(&pk1));
+        assert!(!sc1.is_secured(&pk2));
+
+        let key2 = sc2.encryption_key(&pk1).expect("Session key is confirmed");
+        assert_eq!(*key, *key2);
+        let (pb, _) = sc1
+            .decrypt(&pk2, key2.encrypt(packet(b"other data")))
+            .expect("Initiator has the session key");
+        assert_eq!(&*pb, b"other data");
+        assert!(sc1.is_secured(&pk2));
+
+        // Every session key has its own replay window.
+        assert!(sc2.accept_replay_counter(&pk1, &used, 10));
+        assert!(!sc2.accept_replay_counter(&pk1, &used, 10));
+        assert!(!sc2.accept_replay_counter(&pk1, &ss2, 10));
+
+        // Packets encrypted with the static secret are left for the caller.
+        assert!(sc1.decrypt(&pk2, ss2.encrypt(packet(b"data"))).is_err());
+    }
+
+    #[tokio::test]
+    async fn replayed_init_is_ignored() {
+        let sk1 = SecretKey::new();
+        let sk2 = SecretKey::new();
+        let pk1 = PublicKey::from(&sk1);
+        let pk2 = PublicKey::from(&sk2);
+        let ss1 = sk1.shared_secret(&pk2);
+        let ss2 = sk2.shared_secret(&pk1);
+
+        let sc1 = SessionCache::new(pk1);
+        let sc2 = SessionCache::new(pk2);
+
+        let init = sc1.start_key_exchange(&pk2).expect("No session key yet");
+        let response = sc2
+            .handle_key_exchange(&pk1, &ss2, init, Some(10))
+            .expect("Init is answered");
+        // The replay does not replace the key negotiated for the original init.
+        assert!(sc2
+            .handle_key_exchange(&pk1, &ss2, init, Some(10))
+            .is_none());
+        assert!(sc1
+            .handle_key_exchange(&pk2, &ss1, response, Some(11))
+            .is_none());
+
+        let key = sc1
+            .encryption_key(&pk2)
+            .expect("Session key is established");
+        let (pb, _) = sc2
+            .decrypt(&pk1, key.encrypt(packet(b"some data")))
+            .expect("Responder kept the negotiated key");
+        assert_eq!(&*pb, b"some data");
+
+        // A remote which restarted with a lower counter can still negotiate a new key once no key
+        // exchange is in progress.
+        let restarted = SessionCache::new(pk1);
+        let init = restarted
+            .start_key_exchange(&pk2)
+            .expect("No session key yet");
+        assert!(sc2.handle_key_exchange(&pk1, &ss2, init, Some(1)).is_some());
+    }
+
+    #[tokio::test]
+    async fn simultaneous_key_exchange() {
+        let sk1 = SecretKey::new();
+        let sk2 = SecretKey::new();
+        let pk1 = PublicKey::from(&sk1);
+        let pk2 = PublicKey::from(&sk2);
+        let ss1 = sk1.shared_secret(&pk2);
+        let ss2 = sk2.shared_secret(&pk1);
+
+        let sc1 = SessionCache::new(pk1);
+        let sc2 = SessionCache::new(pk2);
+
+        let init1 = sc1.start_key_exchange(&pk2).expect("No session key yet");
+        let init2 = sc2.start_key_exchange(&pk1).expect("No session key yet");
+
+        let response1 = sc1.handle_key_exchange(&pk2, &ss1, init2, Some(1));
+        let response2 = sc2.handle_key_exchange(&pk1, &ss2, init1, Some(1));
+
+        // Exactly one of the key exchanges proceeds.
+        match (response1, response2) {
+            (Some(response), None) => {
+                assert!(sc2
+                    .handle_key_exchange(&pk1, &ss2, response, Some(2))
+                    .is_none());
+                assert!(sc2.encryption_key(&pk1).is_some());
+            }
+            (None, Some(response)) => {
+                assert!(sc1
+                    .handle_key_exchange(&pk2, &ss1, response, Some(1))
+                    .is_none());
+                assert!(sc1.encryption_key(&pk2).is_some());
+            }
+            _ => panic!("Exactly one key exchange must be answered"),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! The signature cache keeps track of recently verified signatures on route updates. Since routes
+//! are advertised periodically with the same seqno, this avoids verifying the same signature over
+//! and over. It also remembers which sources sign their updates, so a node relaying the route
+//! can't simply strip the signature to get a forged seqno accepted.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, trace};
+
+use crate::{babel::Update, crypto::Signature, sequence_number::SeqNo, source_table::SourceKey};
+
+/// The amount of time to remember a verified signature, since it was last seen. This is
+/// sufficiently larger than the default update interval, so routes which are still advertised
+/// are not forgotten.
+const SIGNATURE_CACHE_TTL: Duration = Duration::from_secs(900);
+
+/// The last verified signature of a source.
+struct VerifiedSignature {
+    /// Sequence number signed by the signature.
+    seqno: SeqNo,
+    /// The actual signature.
+    signature: Signature,
+    /// Time at which the signature was last seen.
+    last_seen: Instant,
+}
+
+/// A cache for verified update signatures. Entries in the cache are automatically removed after a
+/// certain amount of time.
+#[derive(Clone)]
+pub struct SignatureCache {
+    /// Actual cache wrapped in an Arc to make it shareable.
+    inner: Arc<SignatureCacheInner>,
+}
+
+/// Actual implementation of the cache.
+struct SignatureCacheInner {
+    /// Actual cache, maps sources to the last signature verified for them.
+    cache: RwLock<HashMap<SourceKey, VerifiedSignature>>,
+}
+
+impl SignatureCache {
+    /// Create a new [`SignatureCache`].
+    pub fn new() -> Self {
+        trace!(capacity = 0, "Creating new signature cache");
+
+        let inner = Arc::new(SignatureCacheInner::new());
+        // Spawn background cleanup task.
+        tokio::spawn(inner.clone().sweep_entries());
+
+        Self { inner }
+    }
+
+    /// Checks if the given [`Update`] carries a valid signature. The signature is only verified if
+    /// it is not the last signature which was verified for the source of the update. Returns false
+    /// if the update is not signed.
+    pub fn verify(&self, update: &Update) -> bool {
+        let Some(signature) = update.signature() else {
+            return false;
+        };
+        let source_key = SourceKey::new(update.subnet(), update.router_id());
+
+        if let Some(verified) = self.inner.cache.write().unwrap().get_mut(&source_key) {
+            if verified.seqno == update.seqno() && verified.signature == signature {
+                verified.last_seen = Instant::now();
+                return true;
+            }
+        }
+
+        if !update.verify_signature() {
+            return false;
+        }
+
+        self.inner.cache.write().unwrap().insert(
+            source_key,
+            VerifiedSignature {
+                seqno: update.seqno(),
+                signature,
+                last_seen: Instant::now(),
+            },
+        );
+
+        true
+    }
+
+    /// Checks if a valid signature has recently been seen for the given source.
+    pub fn is_signed_source(&self, source_key: &SourceKey) -> bool {
+        self.inner.cache.read().unwrap().contains_key(source_key)
+    }
+}
+
+impl SignatureCacheInner {
+    /// Create a new empty `SignatureCacheInner`.
+    fn new() -> Self {
+        Self {
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Periodic task to clear old entries.
+    async fn sweep_entries(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(SIGNATURE_CACHE_TTL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            debug!("Cleaning up expired signatures from signature cache");
+
+            let mut cache = self.cache.write().unwrap();
+            let prev_entries = cache.len();
+            cache.retain(|_, verified| verified.last_seen.elapsed() <= SIGNATURE_CACHE_TTL);
+            cache.shrink_to_fit();
+
+            debug!(
+                cleaned_entries = prev_entries - cache.len(),
+                "Cleaned up stale signature cache entries"
+            );
+        }
+    }
+}
+
+impl Default for SignatureCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,311 @@
+//! In process network simulation, to test multiple [`Router`](crate::router::Router)s without
+//! using real sockets.
+//!
+//! A [`TopologyBuilder`] creates routers and wires them together using in memory
+//! [`links`](link), which have configurable latency, loss and bandwidth. The resulting
+//! [`Topology`] can be used to inspect the routing state of every node, and to modify or cut
+//! links to trigger failovers.
+//!
+//! This module is only available with the `test-support` feature.
+
+pub use link::{link, LinkConfig, LinkHandle, LinkStats, SimConnection};
+pub use topology::{NoMetrics, NodeId, SimNode, Topology, TopologyBuilder};
+
+mod link;
+mod topology;
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::Duration};
+
+    use bytes::BytesMut;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::Encoder;
+
+    use crate::{
+        babel::RouteRequest,
+        packet::{Codec, DataPacket, Packet},
+        router::RouterConfig,
+    };
+
+    use super::{LinkConfig, Topology};
+
+    /// Encode a single control packet.
+    fn control_packet() -> BytesMut {
+        let mut buf = BytesMut::new();
+        Codec::new()
+            .encode(
+                Packet::ControlPacket(RouteRequest::new(None).into()),
+                &mut buf,
+            )
+            .expect("Can encode control packet");
+        buf
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn link_applies_latency() {
+        let (mut a, mut b, handle) = super::link(LinkConfig {
+            latency: Duration::from_millis(50),
+            ..Default::default()
+        });
+
+        let packet = control_packet();
+        let start = tokio::time::Instant::now();
+        a.write_all(&packet).await.unwrap();
+
+        let mut buf = vec![0; packet.len()];
+        // The packet must not arrive before the latency has passed.
+        tokio::time::advance(Duration::from_millis(49)).await;
+        assert!(tokio::time::timeout(Duration::ZERO, b.read_exact(&mut buf))
+            .await
+            .is_err());
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        b.read_exact(&mut buf).await.unwrap();
+
+        assert_eq!(start.elapsed(), Duration::from_millis(50));
+        assert_eq!(&buf[..], &packet[..]);
+        assert_eq!(handle.stats().packets_delivered, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn link_drops_packets() {
+        let (mut a, _b, handle) = super::link(LinkConfig {
+            loss: 1.0,
+            ..Default::default()
+        });
+
+        let packet = control_packet();
+        for _ in 0..10 {
+            a.write_all(&packet).await.unwrap();
+        }
+
+        let stats = handle.stats();
+        assert_eq!(stats.packets_delivered, 0);
+        assert_eq!(stats.packets_dropped, 10);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn severed_link_is_closed() {
+        let (mut a, mut b, handle) = super::link(LinkConfig::default());
+
+        handle.sever();
+
+        let mut buf = [0; 16];
+        assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+        assert!(a.write_all(&control_packet()).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connecting_unknown_node_fails() {
+        let mut other = Topology::builder();
+        other.node();
+        let unknown = other.node();
+
+        let mut builder = Topology::builder();
+        let a = builder.node();
+        builder.link(a, unknown, LinkConfig::default());
+        assert!(builder.build().is_err());
+
+        let mut builder = Topology::builder();
+        let a = builder.node();
+        let mut topology = builder.build().unwrap();
+        assert!(topology.connect(a, unknown, LinkConfig::default()).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn line_topology_converges() {
+        let mut builder = Topology::builder();
+        let a = builder.node();
+        let b = builder.node();
+        let c = builder.node();
+        builder
+            .link(a, b, LinkConfig::default())
+            .link(b, c, LinkConfig::default());
+        let topology = builder.build().unwrap();
+
+        assert!(topology.converge(Duration::from_secs(5)).await);
+
+        let route = topology.selected_route(a, c).unwrap();
+        assert_eq!(
+            route.source().router_id(),
+            topology.node(c).router().router_id()
+        );
+        // The route to c must go through b, which is the only peer of a.
+        assert_eq!(topology.node(a).router().peer_interfaces().len(), 1);
+        assert_eq!(
+            route.neighbour(),
+            &topology.node(a).router().peer_interfaces()[0]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn signed_updates_converge() {
+        let mut builder = Topology::builder();
+        builder.router_config(RouterConfig {
+            sign_updates: true,
+            require_signed_updates: true,
+            ..Default::default()
+        });
+        let a = builder.node();
+        let b = builder.node();
+        let c = builder.node();
+        builder
+            .link(a, b, LinkConfig::default())
+            .link(b, c, LinkConfig::default());
+        let topology = builder.build().unwrap();
+
+        assert!(topology.converge(Duration::from_secs(5)).await);
+
+        // b forwards the signature of c to a.
+        let route = topology.selected_route(a, c).unwrap();
+        assert!(route.signature().is_some());
+        assert_eq!(
+            route.source().router_id(),
+            topology.node(c).router().router_id()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failover_after_link_severed() {
+        // a - b - d
+        // |       |
+        // c ----- d
+        let mut builder = Topology::builder();
+        let a = builder.node();
+        let b = builder.node();
+        let c = builder.node();
+        let d = builder.node();
+        builder
+            .link(a, b, LinkConfig::default())
+            .link(b, d, LinkConfig::default())
+            .link(a, c, LinkConfig::default())
+            .link(c, d, LinkConfig::default());
+        let topology = builder.build().unwrap();
+
+        assert!(topology.converge(Duration::from_secs(5)).await);
+
+        let initial = topology.selected_route(a, d).unwrap();
+        let initial_neighbour = initial.neighbour().clone();
+        // Cut the link which is currently used.
+        let via_b = topology.node(a).router().peer_interfaces()[0] == initial_neighbour;
+        let (x, y) = if via_b { (a, b) } else { (a, c) };
+        topology.link(x, y).unwrap().sever();
+
+        assert!(
+            topology
+                .wait_for(Duration::from_secs(5), |t| t
+                    .selected_route(a, d)
+                    .map(|re| re.neighbour() != &initial_neighbour)
+                    .unwrap_or(false))
+                .await
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn link_cost_offset_moves_selected_route() {
+        // a - b - d
+        // |       |
+        // c ----- d
+        let mut builder = Topology::builder();
+        let a = builder.node();
+        let b = builder.node();
+        let c = builder.node();
+        let d = builder.node();
+        builder
+            .link(a, b, LinkConfig::default())
+            .link(b, d, LinkConfig::default())
+            .link(a, c, LinkConfig::default())
+            .link(c, d, LinkConfig::default());
+        let topology = builder.build().unwrap();
+
+        assert!(topology.converge(Duration::from_secs(5)).await);
+
+        let initial_neighbour = topology.selected_route(a, d).unwrap().neighbour().clone();
+        initial_neighbour.set_link_cost_offset(1000);
+        topology
+            .node(a)
+            .router()
+            .peer_link_cost_changed(&initial_neighbour);
+
+        assert_ne!(
+            topology.selected_route(a, d).unwrap().neighbour(),
+            &initial_neighbour
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ecmp_spreads_flows() {
+        // a - b - d
+        // |       |
+        // c ----- d
+        let mut builder = Topology::builder();
+        builder.router_config(RouterConfig {
+            ecmp_metric_delta: Some(1000),
+            ..Default::default()
+        });
+        let a = builder.node();
+        let b = builder.node();
+        let c = builder.node();
+        let d = builder.node();
+        builder
+            .link(a, b, LinkConfig::default())
+            .link(b, d, LinkConfig::default())
+            .link(a, c, LinkConfig::default())
+            .link(c, d, LinkConfig::default());
+        let topology = builder.build().unwrap();
+
+        assert!(topology.converge(Duration::from_secs(5)).await);
+        // Wait until a has learned the route to d from both its peers.
+        assert!(
+            topology
+                .wait_for(Duration::from_secs(5), |t| t
+                    .node(a)
+                    .router()
+                    .load_fallback_routes()
+                    .iter()
+                    .any(|re| re.source().subnet() == t.node(d).subnet()))
+                .await
+        );
+
+        let IpAddr::V6(src) = topology.node(a).subnet().address() else {
+            unreachable!()
+        };
+        let IpAddr::V6(dst) = topology.node(d).subnet().address() else {
+            unreachable!()
+        };
+
+        let before_b = topology.link(a, b).unwrap().stats().packets_delivered;
+        let before_c = topology.link(a, c).unwrap().stats().packets_delivered;
+
+        const PACKETS: u16 = 64;
+        for i in 0..PACKETS {
+            let mut src = src.segments();
+            src[7] = i + 1;
+            topology.node(a).router().route_packet(DataPacket {
+                raw_data: vec![0; 64],
+                hop_limit: 64,
+                src_ip: src.into(),
+                dst_ip: dst,
+            });
+        }
+
+        let mut delivered = 0;
+        assert!(
+            topology
+                .wait_for(Duration::from_secs(5), |t| {
+                    while t.node(d).try_recv_delivered().is_some() {
+                        delivered += 1;
+                    }
+                    delivered == PACKETS
+                })
+                .await
+        );
+
+        // Flows are hashed, so both paths should carry a fair share of the packets.
+        let via_b = topology.link(a, b).unwrap().stats().packets_delivered - before_b;
+        let via_c = topology.link(a, c).unwrap().stats().packets_delivered - before_c;
+        assert!(via_b >= 10, "only {via_b} packets went via b");
+        assert!(via_c >= 10, "only {via_c} packets went via c");
+    }
+}
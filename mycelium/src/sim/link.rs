@@ -0,0 +1,380 @@
+//! An in memory [`Connection`] with configurable latency, loss and bandwidth.
+
+use std::{
+    future::Future,
+    io,
+    net::{Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc,
+    time::{Instant, Sleep},
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    connection::Connection,
+    endpoint::{Endpoint, Protocol},
+    packet,
+};
+
+/// Counter used to give every link a unique id, which shows up in the connection identifier.
+static LINK_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Port used in the synthetic endpoints of simulated links.
+const SIM_PORT: u16 = 9651;
+
+/// Properties of a simulated link. The same properties apply in both directions.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkConfig {
+    /// One way delay added to every packet.
+    pub latency: Duration,
+    /// Probability in the range [0, 1] that a packet is dropped.
+    pub loss: f64,
+    /// Capacity of the link in bytes per second, per direction. [`Option::None`] means the link
+    /// has unlimited capacity.
+    pub bandwidth: Option<u64>,
+    /// Static link cost reported by both sides of the link.
+    pub static_link_cost: u16,
+    /// Seed for the random generator deciding which packets are dropped, so runs are
+    /// reproducible.
+    pub seed: u64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(1),
+            loss: 0.0,
+            bandwidth: None,
+            static_link_cost: 1,
+            seed: 0,
+        }
+    }
+}
+
+/// Counters of a simulated link, summed over both directions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// Amount of packets which were (or will be) delivered to the remote.
+    pub packets_delivered: u64,
+    /// Amount of packets dropped, either because of simulated loss or because the link is down.
+    pub packets_dropped: u64,
+}
+
+/// A packet in flight, and the time it should be delivered to the remote.
+type InFlight = (Instant, Bytes);
+
+/// Create a new simulated link. This returns both ends of the link, as well as a
+/// [`handle`](LinkHandle) which can be used to modify the link at runtime.
+///
+/// The link is packet aware: writes are split in individual mycelium packets, and loss, latency
+/// and bandwidth are applied per packet. Writing anything which is not a valid mycelium packet
+/// is an error.
+pub fn link(config: LinkConfig) -> (SimConnection, SimConnection, LinkHandle) {
+    let id = LINK_ID.fetch_add(1, Ordering::Relaxed);
+    let (a_tx, a_rx) = mpsc::unbounded_channel();
+    let (b_tx, b_rx) = mpsc::unbounded_channel();
+
+    let state = Arc::new(LinkState {
+        inner: Mutex::new(LinkInner {
+            config,
+            rng: StdRng::seed_from_u64(config.seed),
+            up: true,
+            directions: [
+                Direction {
+                    tx: Some(a_tx),
+                    busy_until: Instant::now(),
+                },
+                Direction {
+                    tx: Some(b_tx),
+                    busy_until: Instant::now(),
+                },
+            ],
+        }),
+        packets_delivered: AtomicU64::new(0),
+        packets_dropped: AtomicU64::new(0),
+    });
+
+    // End `a` writes in direction 0 and reads from direction 1, end `b` does the opposite.
+    let a = SimConnection::new(
+        format!("sim #{id} a -> b"),
+        sim_endpoint(id, 1),
+        0,
+        b_rx,
+        state.clone(),
+    );
+    let b = SimConnection::new(
+        format!("sim #{id} b -> a"),
+        sim_endpoint(id, 0),
+        1,
+        a_rx,
+        state.clone(),
+    );
+
+    (a, b, LinkHandle { state })
+}
+
+/// Synthetic [`Endpoint`] of one side of a link. The address is taken from the discard only
+/// prefix, so it can never collide with a real endpoint.
+fn sim_endpoint(id: u64, side: u16) -> Endpoint {
+    let addr = Ipv6Addr::new(
+        0x100,
+        0,
+        0,
+        0,
+        (id >> 32) as u16,
+        (id >> 16) as u16,
+        id as u16,
+        side,
+    );
+    Endpoint::new(Protocol::Tcp, SocketAddr::new(addr.into(), SIM_PORT))
+}
+
+/// Shared state of both ends of a link.
+struct LinkState {
+    inner: Mutex<LinkInner>,
+    packets_delivered: AtomicU64,
+    packets_dropped: AtomicU64,
+}
+
+struct LinkInner {
+    config: LinkConfig,
+    rng: StdRng,
+    up: bool,
+    directions: [Direction; 2],
+}
+
+/// State of one direction of a link.
+struct Direction {
+    /// Sender for packets in flight. Once this is taken, the receiving end observes EOF.
+    tx: Option<mpsc::UnboundedSender<InFlight>>,
+    /// Time until which the link is busy transmitting previous packets in this direction.
+    busy_until: Instant,
+}
+
+impl LinkState {
+    /// Transmit a single packet in the given direction.
+    fn transmit(&self, direction: usize, packet: Bytes) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let LinkInner {
+            config,
+            rng,
+            up,
+            directions,
+        } = &mut *inner;
+        let dir = &mut directions[direction];
+
+        let Some(tx) = &dir.tx else {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        };
+
+        if !*up || (config.loss > 0.0 && rng.gen_bool(config.loss.min(1.0))) {
+            self.packets_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let start = dir.busy_until.max(Instant::now());
+        let transmit_time = match config.bandwidth {
+            Some(bw) => Duration::from_secs_f64(packet.len() as f64 / bw as f64),
+            None => Duration::ZERO,
+        };
+        dir.busy_until = start + transmit_time;
+
+        if tx.send((dir.busy_until + config.latency, packet)).is_err() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.packets_delivered.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Close the given direction, the reading side will observe EOF once all packets in flight
+    /// are delivered.
+    fn close(&self, direction: usize) {
+        self.inner.lock().unwrap().directions[direction].tx = None;
+    }
+}
+
+/// A handle to modify a simulated link after it has been created.
+#[derive(Clone)]
+pub struct LinkHandle {
+    state: Arc<LinkState>,
+}
+
+impl LinkHandle {
+    /// Get the current [`LinkConfig`].
+    pub fn config(&self) -> LinkConfig {
+        self.state.inner.lock().unwrap().config
+    }
+
+    /// Change the latency of the link. This only affects packets which are sent after this call.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.inner.lock().unwrap().config.latency = latency;
+    }
+
+    /// Change the loss probability of the link.
+    pub fn set_loss(&self, loss: f64) {
+        self.state.inner.lock().unwrap().config.loss = loss;
+    }
+
+    /// Change the bandwidth of the link.
+    pub fn set_bandwidth(&self, bandwidth: Option<u64>) {
+        self.state.inner.lock().unwrap().config.bandwidth = bandwidth;
+    }
+
+    /// Bring the link up or down. While the link is down, all packets are silently dropped, but
+    /// the connection stays open. This simulates a network outage which is only noticed by the
+    /// lack of traffic.
+    pub fn set_up(&self, up: bool) {
+        self.state.inner.lock().unwrap().up = up;
+    }
+
+    /// Close the link in both directions. This simulates a connection which is reset, both ends
+    /// observe EOF after packets in flight are delivered.
+    pub fn sever(&self) {
+        self.state.close(0);
+        self.state.close(1);
+    }
+
+    /// Get the current [`LinkStats`].
+    pub fn stats(&self) -> LinkStats {
+        LinkStats {
+            packets_delivered: self.state.packets_delivered.load(Ordering::Relaxed),
+            packets_dropped: self.state.packets_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// One end of a simulated link.
+pub struct SimConnection {
+    identifier: String,
+    /// Endpoint of the other end of the link.
+    remote: Endpoint,
+    /// Direction this end writes in.
+    direction: usize,
+    state: Arc<LinkState>,
+    /// Bytes written which don't form a complete packet yet.
+    write_buf: BytesMut,
+    codec: packet::Codec,
+    rx: mpsc::UnboundedReceiver<InFlight>,
+    /// Packet currently being read.
+    current: Option<InFlight>,
+    delay: Pin<Box<Sleep>>,
+}
+
+impl SimConnection {
+    fn new(
+        identifier: String,
+        remote: Endpoint,
+        direction: usize,
+        rx: mpsc::UnboundedReceiver<InFlight>,
+        state: Arc<LinkState>,
+    ) -> Self {
+        Self {
+            identifier,
+            remote,
+            direction,
+            state,
+            write_buf: BytesMut::new(),
+            codec: packet::Codec::new(),
+            rx,
+            current: None,
+            delay: Box::pin(tokio::time::sleep(Duration::ZERO)),
+        }
+    }
+}
+
+impl AsyncRead for SimConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if let Some((deliver_at, data)) = &mut this.current {
+                if *deliver_at > Instant::now() {
+                    this.delay.as_mut().reset(*deliver_at);
+                    ready!(this.delay.as_mut().poll(cx));
+                }
+
+                let n = buf.remaining().min(data.len());
+                buf.put_slice(&data[..n]);
+                data.advance(n);
+                if data.is_empty() {
+                    this.current = None;
+                }
+
+                return Poll::Ready(Ok(()));
+            }
+
+            match ready!(this.rx.poll_recv(cx)) {
+                Some(in_flight) => this.current = Some(in_flight),
+                // Link is closed, signal EOF by not filling the buffer.
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for SimConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = &mut *self;
+        this.write_buf.extend_from_slice(buf);
+
+        // Split written bytes in individual packets. Since the codec is stateful, decoding and
+        // encoding again is the easiest way to get the exact frame.
+        while let Some(packet) = this.codec.decode(&mut this.write_buf)? {
+            let mut frame = BytesMut::new();
+            this.codec.encode(packet, &mut frame)?;
+            this.state.transmit(this.direction, frame.freeze())?;
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.state.close(self.direction);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Connection for SimConnection {
+    fn identifier(&self) -> Result<String, io::Error> {
+        Ok(self.identifier.clone())
+    }
+
+    fn static_link_cost(&self) -> Result<u16, io::Error> {
+        Ok(self.state.inner.lock().unwrap().config.static_link_cost)
+    }
+
+    fn remote_endpoint(&self) -> Result<Endpoint, io::Error> {
+        Ok(self.remote.clone())
+    }
+}
+
+impl Drop for SimConnection {
+    fn drop(&mut self) {
+        // Dropping one end of a connection closes it, similar to a socket.
+        self.state.close(self.direction);
+    }
+}
@@ -0,0 +1,286 @@
+//! Wire multiple [`Router`]s together over simulated links.
+
+use std::{
+    error::Error,
+    sync::{atomic::AtomicU64, Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    crypto::{PublicKey, SecretKey},
+    filters,
+    metrics::Metrics,
+    packet::DataPacket,
+    peer::Peer,
+    router::{Router, RouterConfig},
+    routing_table::RouteEntry,
+    subnet::Subnet,
+    GLOBAL_SUBNET_ADDRESS, GLOBAL_SUBNET_PREFIX_LEN,
+};
+
+use super::link::{self, LinkConfig, LinkHandle};
+
+/// Interval at which conditions are checked while waiting for them to become true.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// [`Metrics`] implementation used by simulated nodes, which does nothing.
+#[derive(Clone)]
+pub struct NoMetrics;
+impl Metrics for NoMetrics {}
+
+/// Identifier of a node in a [`Topology`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// Builder for a [`Topology`].
+#[derive(Default)]
+pub struct TopologyBuilder {
+    router_config: RouterConfig,
+    nodes: Vec<Option<SecretKey>>,
+    links: Vec<(NodeId, NodeId, LinkConfig)>,
+}
+
+impl TopologyBuilder {
+    /// Create a new empty `TopologyBuilder`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the [`RouterConfig`] used by all routers in the topology.
+    pub fn router_config(&mut self, config: RouterConfig) -> &mut Self {
+        self.router_config = config;
+        self
+    }
+
+    /// Add a node with a random key.
+    pub fn node(&mut self) -> NodeId {
+        self.nodes.push(None);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Add a node with the given key.
+    pub fn node_with_key(&mut self, key: SecretKey) -> NodeId {
+        self.nodes.push(Some(key));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Connect 2 nodes with a link using the given [`LinkConfig`].
+    pub fn link(&mut self, a: NodeId, b: NodeId, config: LinkConfig) -> &mut Self {
+        self.links.push((a, b, config));
+        self
+    }
+
+    /// Create all nodes and links in the topology.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if not executed in the context of a tokio runtime.
+    pub fn build(self) -> Result<Topology, Box<dyn Error>> {
+        let mut topology = Topology {
+            router_config: self.router_config,
+            nodes: Vec::with_capacity(self.nodes.len()),
+            links: Vec::with_capacity(self.links.len()),
+        };
+
+        for key in self.nodes {
+            topology.add_node(key.unwrap_or_default())?;
+        }
+        for (a, b, config) in self.links {
+            topology.connect(a, b, config)?;
+        }
+
+        Ok(topology)
+    }
+}
+
+/// A single node in a [`Topology`].
+pub struct SimNode {
+    router: Router<NoMetrics>,
+    /// Data packets the router delivered to the local node.
+    delivered: Mutex<mpsc::UnboundedReceiver<DataPacket>>,
+}
+
+impl SimNode {
+    /// The [`Router`] of this node.
+    pub fn router(&self) -> &Router<NoMetrics> {
+        &self.router
+    }
+
+    /// The subnet owned by this node.
+    pub fn subnet(&self) -> Subnet {
+        self.router.node_tun_subnet()
+    }
+
+    /// Take the next data packet which was delivered to this node, if there is one.
+    pub fn try_recv_delivered(&self) -> Option<DataPacket> {
+        self.delivered.lock().unwrap().try_recv().ok()
+    }
+}
+
+/// A set of [`Router`]s connected by simulated links.
+pub struct Topology {
+    router_config: RouterConfig,
+    nodes: Vec<SimNode>,
+    links: Vec<(NodeId, NodeId, LinkHandle)>,
+}
+
+impl Topology {
+    /// Create a new [`TopologyBuilder`].
+    pub fn builder() -> TopologyBuilder {
+        TopologyBuilder::new()
+    }
+
+    /// Add a new node with the given key to the running topology.
+    pub fn add_node(&mut self, key: SecretKey) -> Result<NodeId, Box<dyn Error>> {
+        let pubkey = PublicKey::from(&key);
+        let subnet = Subnet::new(
+            Subnet::new(pubkey.address().into(), 64)
+                .expect("64 is a valid IPv6 prefix size; qed")
+                .network(),
+            64,
+        )
+        .expect("64 is a valid IPv6 prefix size; qed");
+        let (tun_tx, tun_rx) = mpsc::unbounded_channel();
+
+        let router = Router::new(
+            1,
+            self.router_config,
+            tun_tx,
+            subnet,
+            vec![subnet],
+            (key, pubkey),
+            vec![
+                Box::new(filters::AllowedSubnet::new(
+                    Subnet::new(GLOBAL_SUBNET_ADDRESS, GLOBAL_SUBNET_PREFIX_LEN)
+                        .expect("Global subnet is properly defined; qed"),
+                )),
+                Box::new(filters::MaxSubnetSize::<64>),
+                Box::new(filters::RouterIdOwnsSubnet),
+            ],
+            vec![],
+            // There is no peer manager in the simulation, drop the TLV's it would handle.
+            mpsc::unbounded_channel().0,
+            NoMetrics,
+        )?;
+
+        self.nodes.push(SimNode {
+            router,
+            delivered: Mutex::new(tun_rx),
+        });
+
+        Ok(NodeId(self.nodes.len() - 1))
+    }
+
+    /// Connect 2 nodes in the running topology. The returned [`LinkHandle`] can be used to
+    /// modify the link later on. This fails if either node is not part of this topology.
+    pub fn connect(
+        &mut self,
+        a: NodeId,
+        b: NodeId,
+        config: LinkConfig,
+    ) -> Result<LinkHandle, Box<dyn Error>> {
+        for id in [a, b] {
+            if id.0 >= self.nodes.len() {
+                return Err(format!("node {} is not part of this topology", id.0).into());
+            }
+        }
+
+        let (con_a, con_b, handle) = link::link(config);
+
+        let key_a = self.nodes[a.0].router.node_public_key();
+        let key_b = self.nodes[b.0].router.node_public_key();
+        let peer_a = Self::new_peer(&self.nodes[a.0].router, con_a, key_b)?;
+        let peer_b = Self::new_peer(&self.nodes[b.0].router, con_b, key_a)?;
+        self.nodes[a.0].router.add_peer_interface(peer_a);
+        self.nodes[b.0].router.add_peer_interface(peer_b);
+
+        self.links.push((a, b, handle.clone()));
+
+        Ok(handle)
+    }
+
+    /// Create a new [`Peer`] on the router for one end of a link. Links are not authenticated in
+    /// the simulation, the public key of the remote node is used as is.
+    fn new_peer(
+        router: &Router<NoMetrics>,
+        connection: link::SimConnection,
+        remote_public_key: PublicKey,
+    ) -> Result<Peer, Box<dyn Error>> {
+        Ok(Peer::new(
+            router.router_data_tx(),
+            router.router_control_tx(),
+            connection,
+            Some(remote_public_key),
+            router.dead_peer_sink().clone(),
+            Arc::new(AtomicU64::new(0)),
+            Arc::new(AtomicU64::new(0)),
+        )?)
+    }
+
+    /// Get a node in the topology.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the node is not part of this topology.
+    pub fn node(&self, id: NodeId) -> &SimNode {
+        &self.nodes[id.0]
+    }
+
+    /// Iterate over all nodes in the topology.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &SimNode)> {
+        self.nodes.iter().enumerate().map(|(i, n)| (NodeId(i), n))
+    }
+
+    /// Get the handle of the first link between 2 nodes, in either direction.
+    pub fn link(&self, a: NodeId, b: NodeId) -> Option<&LinkHandle> {
+        self.links
+            .iter()
+            .find(|(x, y, _)| (*x == a && *y == b) || (*x == b && *y == a))
+            .map(|(_, _, handle)| handle)
+    }
+
+    /// Get the selected route on node `from` for the subnet of node `to`.
+    pub fn selected_route(&self, from: NodeId, to: NodeId) -> Option<RouteEntry> {
+        let subnet = self.node(to).subnet();
+        self.node(from)
+            .router
+            .load_selected_routes()
+            .into_iter()
+            .find(|re| re.source().subnet() == subnet)
+    }
+
+    /// Checks if every node has a selected route to every other node.
+    pub fn is_converged(&self) -> bool {
+        self.nodes().all(|(from, _)| {
+            self.nodes()
+                .all(|(to, _)| from == to || self.selected_route(from, to).is_some())
+        })
+    }
+
+    /// Wait until `condition` returns true, or `timeout` expires. Returns the last result of
+    /// `condition`. With a paused tokio clock, the time spent waiting is virtual, so tests using
+    /// this don't depend on wall clock timing.
+    pub async fn wait_for<F: FnMut(&Self) -> bool>(
+        &self,
+        timeout: Duration,
+        mut condition: F,
+    ) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if condition(self) {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Wait until every node has a selected route to every other node, or `timeout` expires.
+    pub async fn converge(&self, timeout: Duration) -> bool {
+        self.wait_for(timeout, Self::is_converged).await
+    }
+}
@@ -169,7 +169,7 @@ mod tests {
 
     use crate::{
         babel,
-        crypto::SecretKey,
+        crypto::{PublicKey, SecretKey},
         metric::Metric,
         peer::Peer,
         router_id::RouterId,
@@ -379,6 +379,7 @@ mod tests {
             router_data_tx,
             router_control_tx,
             con1,
+            Some(PublicKey::from([0; 32])),
             dead_peer_sink,
             Arc::new(AtomicU64::new(0)),
             Arc::new(AtomicU64::new(0)),
@@ -424,6 +425,7 @@ mod tests {
             router_data_tx,
             router_control_tx,
             con1,
+            Some(PublicKey::from([0; 32])),
             dead_peer_sink,
             Arc::new(AtomicU64::new(0)),
             Arc::new(AtomicU64::new(0)),
@@ -469,6 +471,7 @@ mod tests {
             router_data_tx,
             router_control_tx,
             con1,
+            Some(PublicKey::from([0; 32])),
             dead_peer_sink,
             Arc::new(AtomicU64::new(0)),
             Arc::new(AtomicU64::new(0)),
@@ -9,9 +9,10 @@
 //! might not be optimal for other uses.
 
 use core::fmt;
-use std::{hash::Hash, net::IpAddr};
+use std::{hash::Hash, net::IpAddr, str::FromStr};
 
 use ipnet::IpNet;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 /// Representation of a subnet. A subnet can be either IPv4 or IPv6.
 #[derive(Debug, Clone, Copy, Eq, PartialOrd, Ord)]
@@ -25,6 +26,10 @@ pub struct Subnet {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PrefixLenError;
 
+/// An error returned when parsing a [`Subnet`] from a string fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubnetParseError;
+
 impl Subnet {
     /// Create a new `Subnet` from the given [`IpAddr`] and prefix length.
     pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Subnet, PrefixLenError> {
@@ -178,6 +183,38 @@ impl fmt::Display for PrefixLenError {
 
 impl std::error::Error for PrefixLenError {}
 
+impl FromStr for Subnet {
+    type Err = SubnetParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            inner: s.parse().map_err(|_| SubnetParseError)?,
+        })
+    }
+}
+
+impl fmt::Display for SubnetParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid subnet, expected an address and prefix length (e.g. 400::/64)")
+    }
+}
+
+impl std::error::Error for SubnetParseError {}
+
+impl Serialize for Subnet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Subnet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::net::{Ipv4Addr, Ipv6Addr};
@@ -220,4 +257,17 @@ mod tests {
         assert_eq!(subnet_1, subnet_5);
         assert_ne!(subnet_1, subnet_6);
     }
+
+    #[test]
+    fn parse_subnet() {
+        let subnet: Subnet = "400::/7".parse().unwrap();
+        assert_eq!(
+            subnet,
+            Subnet::new(Ipv6Addr::new(0x400, 0, 0, 0, 0, 0, 0, 0).into(), 7).unwrap()
+        );
+        assert_eq!(subnet.to_string(), "400::/7");
+
+        assert!("400::".parse::<Subnet>().is_err());
+        assert!("10.0.0.0/33".parse::<Subnet>().is_err());
+    }
 }
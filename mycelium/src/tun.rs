@@ -8,6 +8,11 @@ pub struct TunConfig {
     pub name: String,
     pub node_subnet: Subnet,
     pub route_subnet: Subnet,
+    /// MTU of the TUN interface. This is the largest packet the host can send over the overlay.
+    pub mtu: u16,
+    /// Use segmentation offload on the TUN interface. This is only supported on Linux, and ignored
+    /// on other platforms.
+    pub offload: bool,
 }
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
@@ -21,9 +21,6 @@ use crate::crypto::PacketBuffer;
 use crate::subnet::Subnet;
 use crate::tun::TunConfig;
 
-// TODO
-const LINK_MTU: i32 = 1400;
-
 /// The 4 byte packet header written before a packet is sent on the TUN
 // TODO: figure out structure and values, but for now this seems to work.
 const HEADER: [u8; 4] = [0, 0, 0, 30];
@@ -87,7 +84,7 @@ pub async fn new(
             "TUN device name must be of the form 'utunXXX...' where X is a digit",
         ))?;
     }
-    let mut tun = match create_tun_interface(&tun_config.name) {
+    let mut tun = match create_tun_interface(&tun_config.name, tun_config.mtu) {
         Ok(tun) => tun,
         Err(e) => {
             error!(
@@ -100,6 +97,9 @@ pub async fn new(
     let iface = Iface::by_name(&tun_config.name)?;
     iface.add_address(tun_config.node_subnet, tun_config.route_subnet)?;
 
+    // Reads include the packet header, so the buffer must have room for it.
+    let buf_size = tun_config.mtu as usize + HEADER.len();
+
     let (tun_sink, mut sink_receiver) = mpsc::channel::<PacketBuffer>(1000);
     let (tun_stream, stream_receiver) = mpsc::unbounded_channel();
 
@@ -110,7 +110,7 @@ pub async fn new(
             let mut buf = if let Some(buf) = buf_hold.take() {
                 buf
             } else {
-                PacketBuffer::new()
+                PacketBuffer::with_mtu(buf_size)
             };
 
             select! {
@@ -175,12 +175,15 @@ fn validate_utun_name(input: &str) -> bool {
 }
 
 /// Create a new TUN interface
-fn create_tun_interface(name: &str) -> Result<tun::AsyncDevice, Box<dyn std::error::Error>> {
+fn create_tun_interface(
+    name: &str,
+    mtu: u16,
+) -> Result<tun::AsyncDevice, Box<dyn std::error::Error>> {
     let mut config = tun::Configuration::default();
     config
         .name(name)
         .layer(tun::Layer::L3)
-        .mtu(LINK_MTU)
+        .mtu(mtu as i32)
         .queues(1)
         .up();
     let tun = tun::create_as_async(&config)?;
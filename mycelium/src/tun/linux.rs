@@ -12,8 +12,13 @@ use crate::crypto::PacketBuffer;
 use crate::subnet::Subnet;
 use crate::tun::TunConfig;
 
-// TODO
-const LINK_MTU: i32 = 1400;
+mod offload;
+
+/// A TUN interface, either managed by `tokio_tun`, or with segmentation offload.
+enum Device {
+    Plain(Tun),
+    Offload(offload::Tun),
+}
 
 /// Create a new tun interface and set required routes
 ///
@@ -29,8 +34,13 @@ pub async fn new(
     ),
     Box<dyn std::error::Error>,
 > {
-    let tun = match create_tun_interface(&tun_config.name) {
-        Ok(tun) => tun,
+    let device = if tun_config.offload {
+        offload::Tun::new(&tun_config.name).map(Device::Offload)
+    } else {
+        create_tun_interface(&tun_config.name, tun_config.mtu).map(Device::Plain)
+    };
+    let device = match device {
+        Ok(device) => device,
         Err(e) => {
             error!(
                 "Could not create tun device named \"{}\", make sure the name is not yet in use, and you have sufficient privileges to create a network device",
@@ -45,6 +55,14 @@ pub async fn new(
 
     let tun_index = link_index_by_name(handle.clone(), tun_config.name).await?;
 
+    // tokio_tun configures the interface itself, with offload it is done here.
+    if let Device::Offload(_) = device {
+        if let Err(e) = set_mtu_and_up(handle.clone(), tun_index, tun_config.mtu).await {
+            error!("Failed to set MTU of TUN interface and bring it up: {e}");
+            return Err(e);
+        }
+    }
+
     if let Err(e) = add_address(
         handle.clone(),
         tun_index,
@@ -66,47 +84,21 @@ pub async fn new(
     // We are done with our netlink connection, abort the task so we can properly clean up.
     netlink_task_handle.abort();
 
-    let (tun_sink, mut sink_receiver) = mpsc::channel::<PacketBuffer>(1000);
+    let (tun_sink, sink_receiver) = mpsc::channel::<PacketBuffer>(1000);
     let (tun_stream, stream_receiver) = mpsc::unbounded_channel();
 
+    let mtu = tun_config.mtu as usize;
+
     // Spawn a single task to manage the TUN interface
-    tokio::spawn(async move {
-        let mut buf_hold = None;
-        loop {
-            let mut buf = if let Some(buf) = buf_hold.take() {
-                buf
-            } else {
-                PacketBuffer::new()
-            };
-
-            select! {
-                data = sink_receiver.recv() => {
-                    match data {
-                        None => return,
-                        Some(data) => {
-                            if let Err(e) = tun.send(&data).await {
-                                error!("Failed to send data to tun interface {e}");
-                            }
-                        }
-                    }
-                    // Save the buffer as we didn't  use it
-                    buf_hold = Some(buf);
-                }
-                read_result = tun.recv(buf.buffer_mut()) => {
-                    let rr = read_result.map(|n| {
-                        buf.set_size(n);
-                        buf
-                    });
-
-                    if tun_stream.send(rr).is_err() {
-                        error!("Could not forward data to tun stream, receiver is gone");
-                        break;
-                    };
-                }
-            }
+    match device {
+        Device::Plain(tun) => {
+            tokio::spawn(run(tun, mtu, sink_receiver, tun_stream));
         }
-        info!("Stop reading from / writing to tun interface");
-    });
+        Device::Offload(tun) => {
+            info!("Using segmentation offload on TUN interface");
+            tokio::spawn(offload::run(tun, mtu, sink_receiver, tun_stream));
+        }
+    }
 
     Ok((
         tokio_stream::wrappers::UnboundedReceiverStream::new(stream_receiver),
@@ -114,12 +106,57 @@ pub async fn new(
     ))
 }
 
+/// Read packets from, and write packets to, the TUN interface until the sink is closed, or the
+/// stream is dropped.
+async fn run(
+    tun: Tun,
+    mtu: usize,
+    mut sink_receiver: mpsc::Receiver<PacketBuffer>,
+    tun_stream: mpsc::UnboundedSender<io::Result<PacketBuffer>>,
+) {
+    let mut buf_hold = None;
+    loop {
+        let mut buf = if let Some(buf) = buf_hold.take() {
+            buf
+        } else {
+            PacketBuffer::with_mtu(mtu)
+        };
+
+        select! {
+            data = sink_receiver.recv() => {
+                match data {
+                    None => return,
+                    Some(data) => {
+                        if let Err(e) = tun.send(&data).await {
+                            error!("Failed to send data to tun interface {e}");
+                        }
+                    }
+                }
+                // Save the buffer as we didn't  use it
+                buf_hold = Some(buf);
+            }
+            read_result = tun.recv(buf.buffer_mut()) => {
+                let rr = read_result.map(|n| {
+                    buf.set_size(n);
+                    buf
+                });
+
+                if tun_stream.send(rr).is_err() {
+                    error!("Could not forward data to tun stream, receiver is gone");
+                    break;
+                };
+            }
+        }
+    }
+    info!("Stop reading from / writing to tun interface");
+}
+
 /// Create a new TUN interface
-fn create_tun_interface(name: &str) -> Result<Tun, Box<dyn std::error::Error>> {
+fn create_tun_interface(name: &str, mtu: u16) -> Result<Tun, Box<dyn std::error::Error>> {
     let tun = TunBuilder::new()
         .name(name)
         .tap(false)
-        .mtu(LINK_MTU)
+        .mtu(mtu as i32)
         .packet_info(false)
         .up()
         .try_build()?;
@@ -127,6 +164,21 @@ fn create_tun_interface(name: &str) -> Result<Tun, Box<dyn std::error::Error>> {
     Ok(tun)
 }
 
+/// Set the MTU of an interface, and bring it up.
+async fn set_mtu_and_up(
+    handle: Handle,
+    link_index: u32,
+    mtu: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(handle
+        .link()
+        .set(link_index)
+        .mtu(mtu as u32)
+        .up()
+        .execute()
+        .await?)
+}
+
 /// Retrieve the link index of an interface with the given name
 async fn link_index_by_name(
     handle: Handle,
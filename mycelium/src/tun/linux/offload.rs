@@ -0,0 +1,612 @@
+//! TUN interface with segmentation offload.
+//!
+//! The interface is created with `IFF_VNET_HDR`, so every packet read from or written to it is
+//! prefixed with a `virtio_net_hdr`. With TSO enabled on the interface, the kernel hands us TCP
+//! segments of up to 64KiB, which are split here in packets which fit the MTU of the interface
+//! (GSO). In the other direction, consecutive TCP segments of the same connection received from
+//! the overlay are coalesced in a single large segment before they are written (GRO). This
+//! significantly reduces the amount of syscalls, and the amount of packets the kernel network
+//! stack processes, at high throughput.
+//!
+//! Only IPv6 packets without extension headers are segmented or coalesced, since the overlay only
+//! carries IPv6.
+
+use std::{
+    ffi::CString,
+    fs::{File, OpenOptions},
+    io::{self, IoSlice, Read, Write},
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+};
+
+use nix::libc;
+use tokio::{io::unix::AsyncFd, select, sync::mpsc};
+use tracing::{debug, error, info};
+
+use crate::buffer_pool;
+use crate::crypto::PacketBuffer;
+
+/// Path of the TUN clone device.
+const TUN_CLONE_DEVICE: &str = "/dev/net/tun";
+
+/// Flag to create a TUN (L3) interface instead of a TAP interface.
+const IFF_TUN: libc::c_short = 0x0001;
+/// Flag to not prefix packets with packet information.
+const IFF_NO_PI: libc::c_short = 0x1000;
+/// Flag to prefix packets with a `virtio_net_hdr`.
+const IFF_VNET_HDR: libc::c_short = 0x4000;
+
+/// Offload flag indicating we can handle packets with a partial checksum.
+const TUN_F_CSUM: libc::c_uint = 0x01;
+/// Offload flag indicating we can handle IPv6 TCP segments larger than the MTU.
+const TUN_F_TSO6: libc::c_uint = 0x04;
+
+/// Size of a `virtio_net_hdr`.
+const VIRTIO_NET_HDR_SIZE: usize = 10;
+/// `virtio_net_hdr` flag indicating the checksum of the packet must still be completed.
+const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+/// `virtio_net_hdr` GSO type of a packet which is not segmented.
+const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+/// `virtio_net_hdr` GSO type of an IPv6 TCP segment.
+const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+/// `virtio_net_hdr` GSO type bit set if the segment has ECN bits set.
+const VIRTIO_NET_HDR_GSO_ECN: u8 = 0x80;
+
+/// Largest packet the kernel hands us, this is the maximum size of an IPv6 packet without jumbo
+/// payload option.
+const MAX_SEGMENT_SIZE: usize = 40 + u16::MAX as usize;
+
+/// Size of an IPv6 header.
+const IPV6_HEADER_SIZE: usize = 40;
+/// Next header value of TCP in an IPv6 header.
+const IPV6_NEXT_HEADER_TCP: u8 = 6;
+/// Minimum size of a TCP header.
+const TCP_MIN_HEADER_SIZE: usize = 20;
+/// Offset of the checksum in a TCP header.
+const TCP_CHECKSUM_OFFSET: usize = 16;
+
+/// TCP FIN flag.
+const TCP_FLAG_FIN: u8 = 0x01;
+/// TCP PSH flag.
+const TCP_FLAG_PSH: u8 = 0x08;
+/// TCP ACK flag.
+const TCP_FLAG_ACK: u8 = 0x10;
+/// TCP CWR flag.
+const TCP_FLAG_CWR: u8 = 0x80;
+
+/// Maximum amount of packets taken from the sink at once to coalesce.
+const MAX_WRITE_BATCH: usize = 64;
+
+/// `virtio_net_hdr` of a packet which needs no offload.
+const EMPTY_VIRTIO_NET_HDR: [u8; VIRTIO_NET_HDR_SIZE] = [0; VIRTIO_NET_HDR_SIZE];
+
+/// Request layout used by `TUNSETIFF`.
+#[repr(C)]
+struct IfReq {
+    name: [libc::c_char; libc::IFNAMSIZ],
+    flags: libc::c_short,
+    _pad: [u8; 22],
+}
+
+nix::ioctl_write_ptr_bad!(
+    /// Create, or attach to, a TUN interface.
+    tunsetiff,
+    nix::request_code_write!(b'T', 202, std::mem::size_of::<libc::c_int>()),
+    IfReq
+);
+
+nix::ioctl_write_int!(
+    /// Set the offloads supported by the reader of the TUN interface.
+    tunsetoffload,
+    b'T',
+    208
+);
+
+/// A TUN interface created with `IFF_VNET_HDR`.
+pub struct Tun {
+    fd: AsyncFd<File>,
+}
+
+/// A packet to write to the TUN interface.
+enum Outgoing {
+    /// A single packet, which needs no offload.
+    Packet(PacketBuffer),
+    /// Coalesced TCP segments, with the `virtio_net_hdr` describing how to split them.
+    Segment([u8; VIRTIO_NET_HDR_SIZE], Vec<u8>),
+}
+
+impl Tun {
+    /// Create a new TUN interface with the given name, and enable checksum and TCP segmentation
+    /// offload on it. The interface still needs to be brought up, and its MTU set.
+    pub fn new(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(TUN_CLONE_DEVICE)?;
+
+        let raw_name = CString::new(name)?;
+        let name_bytes = raw_name.as_bytes_with_nul();
+        if name_bytes.len() > libc::IFNAMSIZ {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "interface name too long").into(),
+            );
+        }
+        let mut req = IfReq {
+            name: [0; libc::IFNAMSIZ],
+            flags: IFF_TUN | IFF_NO_PI | IFF_VNET_HDR,
+            _pad: [0; 22],
+        };
+        for (dst, src) in req.name.iter_mut().zip(name_bytes) {
+            *dst = *src as libc::c_char;
+        }
+
+        // SAFETY: The fd is a valid TUN clone device, and req has the layout of an ifreq.
+        unsafe { tunsetiff(file.as_raw_fd(), &req) }?;
+        // SAFETY: The fd is attached to a TUN interface.
+        unsafe { tunsetoffload(file.as_raw_fd(), (TUN_F_CSUM | TUN_F_TSO6) as _) }?;
+
+        Ok(Self {
+            fd: AsyncFd::new(file)?,
+        })
+    }
+
+    /// Read a single packet, including the `virtio_net_hdr`.
+    async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            match guard.try_io(|file| file.get_ref().read(buf)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Write a single packet, prefixed with the given `virtio_net_hdr`.
+    async fn write(&self, header: &[u8; VIRTIO_NET_HDR_SIZE], packet: &[u8]) -> io::Result<()> {
+        let bufs = [IoSlice::new(header), IoSlice::new(packet)];
+        loop {
+            let mut guard = self.fd.writable().await?;
+            match guard.try_io(|file| file.get_ref().write_vectored(&bufs)) {
+                Ok(result) => return result.map(|_| ()),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Read packets from, and write packets to, the TUN interface until the sink is closed, or the
+/// stream is dropped.
+pub async fn run(
+    tun: Tun,
+    mtu: usize,
+    mut sink_receiver: mpsc::Receiver<PacketBuffer>,
+    tun_stream: mpsc::UnboundedSender<io::Result<PacketBuffer>>,
+) {
+    let mut read_buf = vec![0; VIRTIO_NET_HDR_SIZE + MAX_SEGMENT_SIZE];
+    let mut batch = Vec::with_capacity(MAX_WRITE_BATCH);
+
+    loop {
+        select! {
+            received = sink_receiver.recv_many(&mut batch, MAX_WRITE_BATCH) => {
+                if received == 0 {
+                    return;
+                }
+                for outgoing in coalesce(batch.drain(..)) {
+                    let (header, packet) = outgoing.parts();
+                    if let Err(e) = tun.write(header, packet).await {
+                        error!("Failed to send data to tun interface {e}");
+                    }
+                    if let Outgoing::Segment(_, data) = outgoing {
+                        buffer_pool::put(data);
+                    }
+                }
+            }
+            read_result = tun.read(&mut read_buf) => {
+                let packets = match read_result {
+                    Ok(n) => match segment(&read_buf[..n], mtu) {
+                        Some(packets) => packets.into_iter().map(Ok).collect(),
+                        None => {
+                            debug!("Dropping packet from tun interface which can't be segmented");
+                            continue;
+                        }
+                    },
+                    Err(e) => vec![Err(e)],
+                };
+
+                for packet in packets {
+                    if tun_stream.send(packet).is_err() {
+                        error!("Could not forward data to tun stream, receiver is gone");
+                        info!("Stop reading from / writing to tun interface");
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Split a packet read from the TUN interface, including the `virtio_net_hdr`, in packets which
+/// fit the MTU, and complete their checksum. [`None`] is returned if the packet is malformed, or
+/// it is a segment we can't split.
+fn segment(data: &[u8], mtu: usize) -> Option<Vec<PacketBuffer>> {
+    if data.len() < VIRTIO_NET_HDR_SIZE {
+        return None;
+    }
+    let (header, packet) = data.split_at(VIRTIO_NET_HDR_SIZE);
+    let flags = header[0];
+    let gso_type = header[1] & !VIRTIO_NET_HDR_GSO_ECN;
+    let gso_size = u16::from_ne_bytes([header[4], header[5]]) as usize;
+    let csum_start = u16::from_ne_bytes([header[6], header[7]]) as usize;
+    let csum_offset = u16::from_ne_bytes([header[8], header[9]]) as usize;
+
+    if gso_type == VIRTIO_NET_HDR_GSO_NONE {
+        if packet.len() > mtu {
+            return None;
+        }
+        let mut pb = PacketBuffer::with_mtu(mtu);
+        pb.buffer_mut()[..packet.len()].copy_from_slice(packet);
+        pb.set_size(packet.len());
+        if flags & VIRTIO_NET_HDR_F_NEEDS_CSUM != 0 {
+            // The checksum field contains the checksum of the pseudo header, complete it with the
+            // checksum of the data.
+            let csum_pos = csum_start + csum_offset;
+            if csum_pos + 2 > packet.len() {
+                return None;
+            }
+            let buf = pb.buffer_mut();
+            let csum = fold_checksum(checksum(&buf[csum_start..packet.len()], 0));
+            buf[csum_pos..csum_pos + 2].copy_from_slice(&(!csum).to_be_bytes());
+        }
+        return Some(vec![pb]);
+    }
+
+    if gso_type != VIRTIO_NET_HDR_GSO_TCPV6 || gso_size == 0 {
+        return None;
+    }
+    let tcp_header_size = tcp_header_size(packet)?;
+    let header_size = IPV6_HEADER_SIZE + tcp_header_size;
+    if header_size + gso_size > mtu {
+        return None;
+    }
+
+    let seq = u32::from_be_bytes(
+        packet[IPV6_HEADER_SIZE + 4..IPV6_HEADER_SIZE + 8]
+            .try_into()
+            .ok()?,
+    );
+    let tcp_flags = packet[IPV6_HEADER_SIZE + 13];
+    let payload = &packet[header_size..];
+    let segments = payload.len().div_ceil(gso_size);
+
+    let mut packets = Vec::with_capacity(segments);
+    for (index, chunk) in payload.chunks(gso_size).enumerate() {
+        let mut pb = PacketBuffer::with_mtu(mtu);
+        let buf = pb.buffer_mut();
+        buf[..header_size].copy_from_slice(&packet[..header_size]);
+        buf[header_size..header_size + chunk.len()].copy_from_slice(chunk);
+
+        // Payload length of the IPv6 header.
+        buf[4..6].copy_from_slice(&((tcp_header_size + chunk.len()) as u16).to_be_bytes());
+        let tcp = &mut buf[IPV6_HEADER_SIZE..header_size + chunk.len()];
+        tcp[4..8].copy_from_slice(&seq.wrapping_add((index * gso_size) as u32).to_be_bytes());
+        let mut segment_flags = tcp_flags;
+        if index != 0 {
+            segment_flags &= !TCP_FLAG_CWR;
+        }
+        if index != segments - 1 {
+            segment_flags &= !(TCP_FLAG_FIN | TCP_FLAG_PSH);
+        }
+        tcp[13] = segment_flags;
+
+        set_tcp_checksum(buf, header_size + chunk.len());
+        pb.set_size(header_size + chunk.len());
+        packets.push(pb);
+    }
+
+    Some(packets)
+}
+
+/// Coalesce consecutive TCP segments of the same connection in a single segment. Packets which
+/// can't be coalesced are returned as is.
+fn coalesce(packets: impl Iterator<Item = PacketBuffer>) -> Vec<Outgoing> {
+    let mut out = Vec::new();
+    let mut current: Vec<PacketBuffer> = Vec::new();
+
+    for packet in packets {
+        if !current.is_empty() && can_append(&current, &packet) {
+            current.push(packet);
+            continue;
+        }
+        if !current.is_empty() {
+            out.push(merge(std::mem::take(&mut current)));
+        }
+        if coalescable(&packet) {
+            current.push(packet);
+        } else {
+            out.push(Outgoing::Packet(packet));
+        }
+    }
+    if !current.is_empty() {
+        out.push(merge(current));
+    }
+
+    out
+}
+
+/// Check if a packet is a TCP segment with data, which can be coalesced with other segments.
+fn coalescable(packet: &[u8]) -> bool {
+    let Some(tcp_header_size) = tcp_header_size(packet) else {
+        return false;
+    };
+    let flags = packet[IPV6_HEADER_SIZE + 13];
+    packet.len() > IPV6_HEADER_SIZE + tcp_header_size && flags & !TCP_FLAG_PSH == TCP_FLAG_ACK
+}
+
+/// Check if a packet continues the segments in `current`.
+fn can_append(current: &[PacketBuffer], packet: &[u8]) -> bool {
+    let first = &current[0];
+    let last = &current[current.len() - 1];
+    if !coalescable(packet) || first.len() < packet.len() {
+        return false;
+    }
+    // Segments can only follow a full sized segment without PSH.
+    if last.len() != first.len() || last[IPV6_HEADER_SIZE + 13] & TCP_FLAG_PSH != 0 {
+        return false;
+    }
+
+    let tcp_size = tcp_header_size(first).expect("Segment is coalescable; qed");
+    let header_size = IPV6_HEADER_SIZE + tcp_size;
+    if tcp_header_size(packet) != Some(tcp_size) {
+        return false;
+    }
+    let payload_size: usize = current.iter().map(|p| p.len() - header_size).sum();
+    if payload_size + packet.len() > MAX_SEGMENT_SIZE {
+        return false;
+    }
+
+    // Version, traffic class, flow label, next header, hop limit and addresses must match.
+    if first[..4] != packet[..4] || first[6..IPV6_HEADER_SIZE] != packet[6..IPV6_HEADER_SIZE] {
+        return false;
+    }
+    let first_tcp = &first[IPV6_HEADER_SIZE..header_size];
+    let tcp = &packet[IPV6_HEADER_SIZE..header_size];
+    // Ports, acknowledgement number, data offset, window, urgent pointer and options must match.
+    if first_tcp[..4] != tcp[..4]
+        || first_tcp[8..13] != tcp[8..13]
+        || first_tcp[14..16] != tcp[14..16]
+        || first_tcp[18..] != tcp[18..]
+    {
+        return false;
+    }
+
+    let next_seq = u32::from_be_bytes(
+        last[IPV6_HEADER_SIZE + 4..IPV6_HEADER_SIZE + 8]
+            .try_into()
+            .expect("Static range bounds on slice are correct length"),
+    )
+    .wrapping_add((last.len() - header_size) as u32);
+    u32::from_be_bytes(
+        tcp[4..8]
+            .try_into()
+            .expect("Static range bounds on slice are correct length"),
+    ) == next_seq
+}
+
+/// Merge coalesced segments in a single segment, with a `virtio_net_hdr` describing how the
+/// kernel can split it again. A single segment is returned as is.
+fn merge(mut segments: Vec<PacketBuffer>) -> Outgoing {
+    if segments.len() == 1 {
+        return Outgoing::Packet(segments.pop().expect("There is exactly 1 segment; qed"));
+    }
+
+    let first = &segments[0];
+    let tcp_header_size = tcp_header_size(first).expect("Segment is coalescable; qed");
+    let header_size = IPV6_HEADER_SIZE + tcp_header_size;
+    let gso_size = first.len() - header_size;
+    let payload_size: usize = segments.iter().map(|p| p.len() - header_size).sum();
+
+    let mut packet = buffer_pool::get(header_size + payload_size);
+    packet.extend_from_slice(&first[..header_size]);
+    for segment in &segments {
+        packet.extend_from_slice(&segment[header_size..]);
+    }
+    // The last segment might have PSH set.
+    packet[IPV6_HEADER_SIZE + 13] = segments[segments.len() - 1][IPV6_HEADER_SIZE + 13];
+    packet[4..6].copy_from_slice(&((tcp_header_size + payload_size) as u16).to_be_bytes());
+
+    // The kernel completes the checksum, starting from the checksum of the pseudo header.
+    let csum_pos = IPV6_HEADER_SIZE + TCP_CHECKSUM_OFFSET;
+    let csum = fold_checksum(pseudo_header_checksum(
+        &packet,
+        tcp_header_size + payload_size,
+    ));
+    packet[csum_pos..csum_pos + 2].copy_from_slice(&csum.to_be_bytes());
+
+    let mut header = [0; VIRTIO_NET_HDR_SIZE];
+    header[0] = VIRTIO_NET_HDR_F_NEEDS_CSUM;
+    header[1] = VIRTIO_NET_HDR_GSO_TCPV6;
+    header[2..4].copy_from_slice(&(header_size as u16).to_ne_bytes());
+    header[4..6].copy_from_slice(&(gso_size as u16).to_ne_bytes());
+    header[6..8].copy_from_slice(&(IPV6_HEADER_SIZE as u16).to_ne_bytes());
+    header[8..10].copy_from_slice(&(TCP_CHECKSUM_OFFSET as u16).to_ne_bytes());
+
+    Outgoing::Segment(header, packet)
+}
+
+impl Outgoing {
+    /// Get the `virtio_net_hdr` and the data of the packet.
+    fn parts(&self) -> (&[u8; VIRTIO_NET_HDR_SIZE], &[u8]) {
+        match self {
+            Outgoing::Packet(packet) => (&EMPTY_VIRTIO_NET_HDR, packet),
+            Outgoing::Segment(header, data) => (header, data),
+        }
+    }
+}
+
+/// Get the size of the TCP header of an IPv6 packet, if the packet is a TCP segment without
+/// extension headers.
+fn tcp_header_size(packet: &[u8]) -> Option<usize> {
+    if packet.len() < IPV6_HEADER_SIZE + TCP_MIN_HEADER_SIZE
+        || packet[0] >> 4 != 6
+        || packet[6] != IPV6_NEXT_HEADER_TCP
+    {
+        return None;
+    }
+    let size = (packet[IPV6_HEADER_SIZE + 12] >> 4) as usize * 4;
+    if size < TCP_MIN_HEADER_SIZE || packet.len() < IPV6_HEADER_SIZE + size {
+        return None;
+    }
+
+    Some(size)
+}
+
+/// Compute and set the TCP checksum of an IPv6 packet of the given length.
+fn set_tcp_checksum(packet: &mut [u8], len: usize) {
+    let csum_pos = IPV6_HEADER_SIZE + TCP_CHECKSUM_OFFSET;
+    packet[csum_pos..csum_pos + 2].copy_from_slice(&[0, 0]);
+    let sum = pseudo_header_checksum(packet, len - IPV6_HEADER_SIZE);
+    let csum = fold_checksum(checksum(&packet[IPV6_HEADER_SIZE..len], sum));
+    packet[csum_pos..csum_pos + 2].copy_from_slice(&(!csum).to_be_bytes());
+}
+
+/// Checksum of the IPv6 pseudo header of a TCP segment, see
+/// https://www.rfc-editor.org/rfc/rfc8200#section-8.1.
+fn pseudo_header_checksum(packet: &[u8], tcp_len: usize) -> u32 {
+    let sum = checksum(&packet[8..IPV6_HEADER_SIZE], 0);
+    sum + (tcp_len >> 16) as u32 + (tcp_len & 0xFFFF) as u32 + IPV6_NEXT_HEADER_TCP as u32
+}
+
+/// Add the 16 bit words of data to a ones' complement sum.
+fn checksum(data: &[u8], initial: u32) -> u32 {
+    let mut sum = initial as u64;
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u64;
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u64) << 8;
+    }
+    while sum > u32::MAX as u64 {
+        sum = (sum & 0xFFFF_FFFF) + (sum >> 32);
+    }
+
+    sum as u32
+}
+
+/// Fold a ones' complement sum to 16 bits.
+fn fold_checksum(mut sum: u32) -> u16 {
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::PacketBuffer;
+
+    use super::{
+        coalesce, segment, set_tcp_checksum, EMPTY_VIRTIO_NET_HDR, IPV6_HEADER_SIZE, TCP_FLAG_ACK,
+        TCP_FLAG_PSH, VIRTIO_NET_HDR_F_NEEDS_CSUM, VIRTIO_NET_HDR_GSO_TCPV6, VIRTIO_NET_HDR_SIZE,
+    };
+
+    /// Create an IPv6 TCP segment with the given sequence number, flags and payload.
+    fn tcp_segment(seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; IPV6_HEADER_SIZE + 20];
+        packet[0] = 0x60;
+        packet[4..6].copy_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+        packet[6] = 6;
+        packet[7] = 64;
+        packet[8] = 0x04;
+        packet[23] = 1;
+        packet[24] = 0x05;
+        packet[39] = 2;
+        let tcp = &mut packet[IPV6_HEADER_SIZE..];
+        tcp[..2].copy_from_slice(&1234u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&80u16.to_be_bytes());
+        tcp[4..8].copy_from_slice(&seq.to_be_bytes());
+        tcp[8..12].copy_from_slice(&42u32.to_be_bytes());
+        tcp[12] = 5 << 4;
+        tcp[13] = flags;
+        tcp[14..16].copy_from_slice(&1000u16.to_be_bytes());
+        packet.extend_from_slice(payload);
+        let len = packet.len();
+        set_tcp_checksum(&mut packet, len);
+        packet
+    }
+
+    fn packet_buffer(data: &[u8]) -> PacketBuffer {
+        let mut pb = PacketBuffer::with_mtu(data.len());
+        pb.buffer_mut().copy_from_slice(data);
+        pb.set_size(data.len());
+        pb
+    }
+
+    #[test]
+    fn coalesce_and_segment_roundtrip() {
+        let payload = (0..=255).cycle().take(3000).collect::<Vec<u8>>();
+        let segments = [
+            tcp_segment(100, TCP_FLAG_ACK, &payload[..1200]),
+            tcp_segment(1300, TCP_FLAG_ACK, &payload[1200..2400]),
+            tcp_segment(2500, TCP_FLAG_ACK | TCP_FLAG_PSH, &payload[2400..]),
+        ];
+
+        let coalesced = coalesce(segments.iter().map(|s| packet_buffer(s)));
+        assert_eq!(coalesced.len(), 1);
+        let (header, packet) = coalesced[0].parts();
+        assert_eq!(header[0], VIRTIO_NET_HDR_F_NEEDS_CSUM);
+        assert_eq!(header[1], VIRTIO_NET_HDR_GSO_TCPV6);
+        assert_eq!(packet.len(), IPV6_HEADER_SIZE + 20 + payload.len());
+        assert_eq!(&packet[IPV6_HEADER_SIZE + 20..], &payload[..]);
+
+        let mut data = header.to_vec();
+        data.extend_from_slice(packet);
+        let split = segment(&data, 1280).unwrap();
+        assert_eq!(split.len(), 3);
+        for (split, original) in split.iter().zip(&segments) {
+            assert_eq!(&split[..], &original[..]);
+        }
+    }
+
+    #[test]
+    fn unrelated_packets_are_not_coalesced() {
+        let first = tcp_segment(100, TCP_FLAG_ACK, &[1; 100]);
+        // Sequence number does not follow the first segment.
+        let second = tcp_segment(300, TCP_FLAG_ACK, &[2; 100]);
+        // No data.
+        let third = tcp_segment(400, TCP_FLAG_ACK, &[]);
+
+        let coalesced = coalesce(
+            [&first, &second, &third]
+                .into_iter()
+                .map(|s| packet_buffer(s)),
+        );
+        assert_eq!(coalesced.len(), 3);
+        for (outgoing, original) in coalesced.iter().zip([&first, &second, &third]) {
+            let (header, packet) = outgoing.parts();
+            assert_eq!(header, &EMPTY_VIRTIO_NET_HDR);
+            assert_eq!(packet, &original[..]);
+        }
+    }
+
+    #[test]
+    fn partial_checksum_is_completed() {
+        let original = tcp_segment(100, TCP_FLAG_ACK, &[3; 99]);
+        let mut partial = original.clone();
+        // Replace the checksum with the checksum of the pseudo header only.
+        let sum = super::pseudo_header_checksum(&partial, partial.len() - IPV6_HEADER_SIZE);
+        partial[IPV6_HEADER_SIZE + 16..IPV6_HEADER_SIZE + 18]
+            .copy_from_slice(&super::fold_checksum(sum).to_be_bytes());
+
+        let mut data = vec![0; VIRTIO_NET_HDR_SIZE];
+        data[0] = VIRTIO_NET_HDR_F_NEEDS_CSUM;
+        data[6..8].copy_from_slice(&(IPV6_HEADER_SIZE as u16).to_ne_bytes());
+        data[8..10].copy_from_slice(&16u16.to_ne_bytes());
+        data.extend_from_slice(&partial);
+
+        let packets = segment(&data, 1280).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(&packets[0][..], &original[..]);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io;
 use std::net::Ipv4Addr;
 use std::path::Path;
@@ -5,6 +6,7 @@ use std::{
     error::Error,
     net::{IpAddr, SocketAddr},
     path::PathBuf,
+    time::Duration,
 };
 use std::{fmt::Display, str::FromStr};
 
@@ -18,7 +20,11 @@ use tracing::{debug, error, info, warn};
 
 use crypto::PublicKey;
 use mycelium::endpoint::Endpoint;
-use mycelium::{crypto, Node};
+use mycelium::peer_acl::PeerAcl;
+use mycelium::policy::RoutePolicy;
+use mycelium::proxy::HttpProxy;
+use mycelium::router::{DampeningConfig, RouterConfig};
+use mycelium::{crypto, Node, DEFAULT_TUN_MTU};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
@@ -189,9 +195,17 @@ pub enum PeersCommand {
         json: bool,
     },
     /// Add peer(s)
-    Add { peers: Vec<String> },
+    Add {
+        peers: Vec<String>,
+        /// Cost added to the link cost of the peer(s), to make routes through them less
+        /// preferable.
+        #[arg(long = "link-cost-offset", default_value_t = 0)]
+        link_cost_offset: u16,
+    },
     /// Remove peer(s)
     Remove { peers: Vec<String> },
+    /// Change the cost added to the link cost of a peer
+    SetCost { peer: String, link_cost_offset: u16 },
 }
 
 #[derive(Debug, Subcommand)]
@@ -216,6 +230,10 @@ pub struct NodeArguments {
     #[arg(long = "peers", num_args = 1..)]
     static_peers: Vec<Endpoint>,
 
+    /// Cost added to the link cost of static peers. This can only be set in the config file.
+    #[arg(skip)]
+    peer_link_cost_offsets: HashMap<Endpoint, u16>,
+
     /// Port to listen on for tcp connections.
     #[arg(short = 't', long = "tcp-listen-port", default_value_t = DEFAULT_TCP_LISTEN_PORT)]
     tcp_listen_port: u16,
@@ -228,6 +246,21 @@ pub struct NodeArguments {
     #[arg(short = 'q', long = "quic-listen-port", default_value_t = DEFAULT_QUIC_LISTEN_PORT)]
     quic_listen_port: u16,
 
+    /// Port to listen on for WebSocket connections. WebSockets are not accepted if this is not
+    /// set. Peers connecting over `wss://` need a reverse proxy in front of this port which
+    /// terminates TLS.
+    #[arg(long = "ws-listen-port")]
+    ws_listen_port: Option<u16>,
+
+    /// HTTP proxy to tunnel outbound WebSocket connections through, e.g.
+    /// `http://proxy.example.org:3128`.
+    ///
+    /// Use an `https://` proxy to connect to the proxy itself over TLS. If this is not set, the
+    /// `HTTP_PROXY` (for `ws://` peers) and `HTTPS_PROXY` (for `wss://` peers) environment
+    /// variables are used, except for the hosts in `NO_PROXY`.
+    #[arg(long = "ws-proxy")]
+    ws_proxy: Option<HttpProxy>,
+
     /// Port to use for link local peer discovery. This uses the UDP protocol.
     #[arg(long = "peer-discovery-port", default_value_t = DEFAULT_PEER_DISCOVERY_PORT)]
     peer_discovery_port: u16,
@@ -240,6 +273,30 @@ pub struct NodeArguments {
     #[arg(long = "disable-peer-discovery", default_value_t = false)]
     disable_peer_discovery: bool,
 
+    /// Disable UDP hole punching.
+    ///
+    /// By default, neighbours are asked to introduce this node to remotes which are only reachable
+    /// through them, so both can connect to each other directly over quic, even if they are
+    /// behind NAT. Introductions for other nodes are sent as well. This flag disables both.
+    #[arg(long = "disable-hole-punching", default_value_t = false)]
+    disable_hole_punching: bool,
+
+    /// Refuse peers which don't support the link handshake.
+    ///
+    /// Nodes running an older version don't authenticate their node key when connecting. By
+    /// default, such peers are still accepted without a verified key, and connected to without
+    /// the handshake. This flag rejects them. It will be the default in a future release.
+    #[arg(long = "disable-legacy-peers", default_value_t = false)]
+    disable_legacy_peers: bool,
+
+    /// Enable peer exchange, and keep at least this many outbound peers connected.
+    ///
+    /// With peer exchange, the endpoints of outbound peers with a public address are shared with
+    /// neighbours. If there are less outbound peers than this target, endpoints shared by
+    /// neighbours are connected to, preferring peers in different networks.
+    #[arg(long = "peer-exchange-target")]
+    peer_exchange_target: Option<usize>,
+
     /// Address of the HTTP API server.
     #[arg(long = "api-addr", default_value_t = DEFAULT_HTTP_API_SERVER_ADDRESS)]
     api_addr: SocketAddr,
@@ -260,6 +317,22 @@ pub struct NodeArguments {
     #[arg(long = "tun-name", default_value = TUN_NAME)]
     tun_name: String,
 
+    /// MTU of the TUN interface, if one is created.
+    ///
+    /// This is the largest packet which can be sent over the overlay. Packets from remotes which
+    /// are larger than this are answered with an ICMPv6 Packet Too Big, so the sender lowers its
+    /// MTU. Must be at least 1280, the minimum MTU of IPv6.
+    #[arg(long = "tun-mtu", default_value_t = DEFAULT_TUN_MTU)]
+    tun_mtu: u16,
+
+    /// Use TCP segmentation offload on the TUN interface. Only supported on Linux.
+    ///
+    /// The kernel hands over TCP segments of up to 64KiB, which are split in packets which fit
+    /// the MTU, and consecutive TCP segments received from remotes are coalesced before they are
+    /// handed to the kernel. This reduces the amount of syscalls, and increases throughput.
+    #[arg(long = "tun-offload", default_value_t = false)]
+    tun_offload: bool,
+
     /// Enable a private network, with this name.
     ///
     /// If this flag is set, the system will run in "private network mode", and use Tls connections
@@ -301,18 +374,51 @@ pub struct NodeArguments {
     /// increased to process updates in parallel.
     #[arg(long = "update-workers", default_value_t = 1)]
     update_workers: usize,
+
+    /// The amount of additional subnets, derived from the node key, to announce.
+    ///
+    /// Traffic for these subnets is delivered on the TUN interface, from where the host can route
+    /// it further, e.g. to containers or VMs. The announced subnets are logged on startup and
+    /// listed in the node info of the API.
+    #[arg(long = "derived-subnets", default_value_t = 0)]
+    derived_subnets: u16,
+
+    /// Timers used by the router. These can only be set in the config file.
+    #[arg(skip)]
+    router_config: RouterConfig,
+
+    /// Route import and export policy. This can only be set in the config file.
+    #[arg(skip)]
+    route_policy: RoutePolicy,
+
+    /// Access control list for inbound peer connections. This can only be set in the config
+    /// file.
+    #[arg(skip)]
+    peer_acl: PeerAcl,
 }
 
 #[derive(Debug, Deserialize, Default)]
 struct MyceliumConfig {
     #[serde(deserialize_with = "deserialize_optional_endpoint_str_from_toml")]
     peers: Option<Vec<Endpoint>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_endpoint_map_from_toml"
+    )]
+    peer_link_cost: Option<HashMap<Endpoint, u16>>,
     tcp_listen_port: Option<u16>,
     disable_quic: Option<bool>,
     quic_listen_port: Option<u16>,
+    ws_listen_port: Option<u16>,
+    ws_proxy: Option<HttpProxy>,
     no_tun: Option<bool>,
     tun_name: Option<String>,
+    tun_mtu: Option<u16>,
+    tun_offload: Option<bool>,
     disable_peer_discovery: Option<bool>,
+    disable_hole_punching: Option<bool>,
+    disable_legacy_peers: Option<bool>,
+    peer_exchange_target: Option<usize>,
     peer_discovery_port: Option<u16>,
     api_addr: Option<SocketAddr>,
     metrics_api_address: Option<SocketAddr>,
@@ -320,6 +426,54 @@ struct MyceliumConfig {
     network_key_file: Option<PathBuf>,
     firewall_mark: Option<u32>,
     update_workers: Option<usize>,
+    derived_subnets: Option<u16>,
+    router: Option<RouterFileConfig>,
+    policy: Option<RoutePolicy>,
+    peer_acl: Option<PeerAcl>,
+}
+
+/// Router settings in the config file. Timers are expressed in seconds. Settings which are not set
+/// keep their default value.
+#[derive(Debug, Deserialize, Default)]
+struct RouterFileConfig {
+    hello_interval: Option<u64>,
+    ihu_interval: Option<u64>,
+    update_interval: Option<u64>,
+    dead_peer_threshold: Option<u64>,
+    retracted_route_hold_time: Option<u64>,
+    seqno_bump_timeout: Option<u64>,
+    ecmp_metric_delta: Option<u16>,
+    flap_dampening: Option<bool>,
+    sign_updates: Option<bool>,
+    require_signed_updates: Option<bool>,
+}
+
+impl From<RouterFileConfig> for RouterConfig {
+    fn from(value: RouterFileConfig) -> Self {
+        let default = RouterConfig::default();
+        let secs_or = |secs: Option<u64>, default| secs.map(Duration::from_secs).unwrap_or(default);
+        RouterConfig {
+            hello_interval: secs_or(value.hello_interval, default.hello_interval),
+            ihu_interval: secs_or(value.ihu_interval, default.ihu_interval),
+            update_interval: secs_or(value.update_interval, default.update_interval),
+            dead_peer_threshold: secs_or(value.dead_peer_threshold, default.dead_peer_threshold),
+            retracted_route_hold_time: secs_or(
+                value.retracted_route_hold_time,
+                default.retracted_route_hold_time,
+            ),
+            seqno_bump_timeout: secs_or(value.seqno_bump_timeout, default.seqno_bump_timeout),
+            ecmp_metric_delta: value.ecmp_metric_delta,
+            flap_dampening: match value.flap_dampening {
+                Some(true) => Some(DampeningConfig::default()),
+                Some(false) => None,
+                None => default.flap_dampening,
+            },
+            sign_updates: value.sign_updates.unwrap_or(default.sign_updates),
+            require_signed_updates: value
+                .require_signed_updates
+                .unwrap_or(default.require_signed_updates),
+        }
+    }
 }
 
 #[tokio::main]
@@ -437,23 +591,35 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 let config = mycelium::Config {
                     node_key: node_secret_key,
                     peers: merged_config.static_peers,
+                    peer_link_cost_offsets: merged_config.peer_link_cost_offsets,
                     no_tun: merged_config.no_tun,
                     tcp_listen_port: merged_config.tcp_listen_port,
                     quic_listen_port: if merged_config.disable_quic {
                         None
                     } else {
-                        Some(merged_config.peer_discovery_port)
+                        Some(merged_config.quic_listen_port)
                     },
+                    ws_listen_port: merged_config.ws_listen_port,
+                    ws_proxy: merged_config.ws_proxy,
                     peer_discovery_port: if merged_config.disable_peer_discovery {
                         None
                     } else {
                         Some(merged_config.peer_discovery_port)
                     },
+                    hole_punching: !merged_config.disable_hole_punching,
+                    legacy_peers: !merged_config.disable_legacy_peers,
+                    peer_exchange_target: merged_config.peer_exchange_target,
                     tun_name: merged_config.tun_name,
+                    tun_mtu: merged_config.tun_mtu,
+                    tun_offload: merged_config.tun_offload,
                     private_network_config,
                     metrics: metrics.clone(),
                     firewall_mark: merged_config.firewall_mark,
                     update_workers: merged_config.update_workers,
+                    derived_subnets: merged_config.derived_subnets,
+                    router_config: merged_config.router_config,
+                    route_policy: merged_config.route_policy,
+                    peer_acl: merged_config.peer_acl,
                 };
                 metrics.spawn(metrics_api_addr);
                 let node = Node::new(config).await?;
@@ -462,23 +628,35 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 let config = mycelium::Config {
                     node_key: node_secret_key,
                     peers: merged_config.static_peers,
+                    peer_link_cost_offsets: merged_config.peer_link_cost_offsets,
                     no_tun: merged_config.no_tun,
                     tcp_listen_port: merged_config.tcp_listen_port,
                     quic_listen_port: if merged_config.disable_quic {
                         None
                     } else {
-                        Some(merged_config.peer_discovery_port)
+                        Some(merged_config.quic_listen_port)
                     },
+                    ws_listen_port: merged_config.ws_listen_port,
+                    ws_proxy: merged_config.ws_proxy,
                     peer_discovery_port: if merged_config.disable_peer_discovery {
                         None
                     } else {
                         Some(merged_config.peer_discovery_port)
                     },
+                    hole_punching: !merged_config.disable_hole_punching,
+                    legacy_peers: !merged_config.disable_legacy_peers,
+                    peer_exchange_target: merged_config.peer_exchange_target,
                     tun_name: merged_config.tun_name,
+                    tun_mtu: merged_config.tun_mtu,
+                    tun_offload: merged_config.tun_offload,
                     private_network_config,
                     metrics: mycelium_metrics::NoMetrics,
                     firewall_mark: merged_config.firewall_mark,
                     update_workers: merged_config.update_workers,
+                    derived_subnets: merged_config.derived_subnets,
+                    router_config: merged_config.router_config,
+                    route_policy: merged_config.route_policy,
+                    peer_acl: merged_config.peer_acl,
                 };
                 let node = Node::new(config).await?;
                 mycelium_api::Http::spawn(node, merged_config.api_addr)
@@ -565,12 +743,31 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 PeersCommand::List { json } => {
                     return mycelium_cli::list_peers(cli.node_args.api_addr, json).await;
                 }
-                PeersCommand::Add { peers } => {
-                    return mycelium_cli::add_peers(cli.node_args.api_addr, peers).await;
+                PeersCommand::Add {
+                    peers,
+                    link_cost_offset,
+                } => {
+                    return mycelium_cli::add_peers(
+                        cli.node_args.api_addr,
+                        peers,
+                        link_cost_offset,
+                    )
+                    .await;
                 }
                 PeersCommand::Remove { peers } => {
                     return mycelium_cli::remove_peers(cli.node_args.api_addr, peers).await;
                 }
+                PeersCommand::SetCost {
+                    peer,
+                    link_cost_offset,
+                } => {
+                    return mycelium_cli::set_peer_link_cost_offset(
+                        cli.node_args.api_addr,
+                        peer,
+                        link_cost_offset,
+                    )
+                    .await;
+                }
             },
             Command::Routes { command } => match command {
                 RoutesCommand::Selected { json } => {
@@ -640,6 +837,7 @@ fn merge_config(cli_args: NodeArguments, file_config: MyceliumConfig) -> NodeArg
         } else {
             file_config.peers.unwrap_or_default()
         },
+        peer_link_cost_offsets: file_config.peer_link_cost.unwrap_or_default(),
         tcp_listen_port: if cli_args.tcp_listen_port != DEFAULT_TCP_LISTEN_PORT {
             cli_args.tcp_listen_port
         } else {
@@ -655,6 +853,8 @@ fn merge_config(cli_args: NodeArguments, file_config: MyceliumConfig) -> NodeArg
                 .quic_listen_port
                 .unwrap_or(DEFAULT_QUIC_LISTEN_PORT)
         },
+        ws_listen_port: cli_args.ws_listen_port.or(file_config.ws_listen_port),
+        ws_proxy: cli_args.ws_proxy.or(file_config.ws_proxy),
         peer_discovery_port: if cli_args.peer_discovery_port != DEFAULT_PEER_DISCOVERY_PORT {
             cli_args.peer_discovery_port
         } else {
@@ -664,6 +864,13 @@ fn merge_config(cli_args: NodeArguments, file_config: MyceliumConfig) -> NodeArg
         },
         disable_peer_discovery: cli_args.disable_peer_discovery
             || file_config.disable_peer_discovery.unwrap_or(false),
+        disable_hole_punching: cli_args.disable_hole_punching
+            || file_config.disable_hole_punching.unwrap_or(false),
+        disable_legacy_peers: cli_args.disable_legacy_peers
+            || file_config.disable_legacy_peers.unwrap_or(false),
+        peer_exchange_target: cli_args
+            .peer_exchange_target
+            .or(file_config.peer_exchange_target),
         api_addr: if cli_args.api_addr != DEFAULT_HTTP_API_SERVER_ADDRESS {
             cli_args.api_addr
         } else {
@@ -677,6 +884,12 @@ fn merge_config(cli_args: NodeArguments, file_config: MyceliumConfig) -> NodeArg
         } else {
             file_config.tun_name.unwrap_or_else(|| TUN_NAME.to_string())
         },
+        tun_mtu: if cli_args.tun_mtu != DEFAULT_TUN_MTU {
+            cli_args.tun_mtu
+        } else {
+            file_config.tun_mtu.unwrap_or(DEFAULT_TUN_MTU)
+        },
+        tun_offload: cli_args.tun_offload || file_config.tun_offload.unwrap_or(false),
         metrics_api_address: cli_args
             .metrics_api_address
             .or(file_config.metrics_api_address),
@@ -688,6 +901,14 @@ fn merge_config(cli_args: NodeArguments, file_config: MyceliumConfig) -> NodeArg
         } else {
             file_config.update_workers.unwrap_or(1)
         },
+        derived_subnets: if cli_args.derived_subnets != 0 {
+            cli_args.derived_subnets
+        } else {
+            file_config.derived_subnets.unwrap_or(0)
+        },
+        router_config: file_config.router.map(Into::into).unwrap_or_default(),
+        route_policy: file_config.policy.unwrap_or_default(),
+        peer_acl: file_config.peer_acl.unwrap_or_default(),
     }
 }
 
@@ -721,3 +942,25 @@ where
         None => None,
     })
 }
+
+/// Deserialize an optional map of endpoints to a value from TOML format. The keys are the string
+/// representation of the endpoints. If no value is provided, it returns None.
+fn deserialize_optional_endpoint_map_from_toml<'de, D, T>(
+    deserializer: D,
+) -> Result<Option<HashMap<Endpoint, T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<HashMap<String, T>>::deserialize(deserializer)?
+        .map(|m| {
+            m.into_iter()
+                .map(|(k, v)| {
+                    <Endpoint as std::str::FromStr>::from_str(&k)
+                        .map(|ep| (ep, v))
+                        .map_err(serde::de::Error::custom)
+                })
+                .collect()
+        })
+        .transpose()
+}